@@ -2,7 +2,12 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use freeghost::core::crypto::{
     key_manager::KeyManager,
     quantum::QuantumResistantProcessor,
+    ntt::{NTTContext, NttBackend},
 };
+use std::time::Instant;
+
+// Associated data bound into every benchmark ciphertext
+const BENCH_AAD: &[u8] = b"bench";
 
 fn bench_key_manager(c: &mut Criterion) {
     let key_manager = KeyManager::new("test_encryption_key").unwrap();
@@ -10,14 +15,14 @@ fn bench_key_manager(c: &mut Criterion) {
 
     c.bench_function("encrypt 1KB", |b| {
         b.iter(|| {
-            key_manager.encrypt(black_box(&test_data)).unwrap();
+            key_manager.encrypt(black_box(&test_data), BENCH_AAD).unwrap();
         })
     });
 
-    let encrypted = key_manager.encrypt(&test_data).unwrap();
+    let encrypted = key_manager.encrypt(&test_data, BENCH_AAD).unwrap();
     c.bench_function("decrypt 1KB", |b| {
         b.iter(|| {
-            key_manager.decrypt(black_box(&encrypted)).unwrap();
+            key_manager.decrypt(black_box(&encrypted), BENCH_AAD).unwrap();
         })
     });
 
@@ -82,14 +87,14 @@ fn bench_large_data(c: &mut Criterion) {
         
         group.bench_function(format!("encrypt {}KB", size / 1024), |b| {
             b.iter(|| {
-                key_manager.encrypt(black_box(&test_data)).unwrap();
+                key_manager.encrypt(black_box(&test_data), BENCH_AAD).unwrap();
             })
         });
 
-        let encrypted = key_manager.encrypt(&test_data).unwrap();
+        let encrypted = key_manager.encrypt(&test_data, BENCH_AAD).unwrap();
         group.bench_function(format!("decrypt {}KB", size / 1024), |b| {
             b.iter(|| {
-                key_manager.decrypt(black_box(&encrypted)).unwrap();
+                key_manager.decrypt(black_box(&encrypted), BENCH_AAD).unwrap();
             })
         });
     }
@@ -118,7 +123,7 @@ fn bench_concurrent_operations(c: &mut Criterion) {
                     let data = test_data.clone();
                     let km = km.clone();
                     handles.push(tokio::spawn(async move {
-                        km.encrypt(&data).unwrap()
+                        km.encrypt(&data, BENCH_AAD).unwrap()
                     }));
                 }
 
@@ -130,8 +135,66 @@ fn bench_concurrent_operations(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_ntt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ntt");
+
+    let scalar = NTTContext::scalar();
+    let detected = NTTContext::new();
+    let mut poly = [0i16; 256];
+    for (i, coeff) in poly.iter_mut().enumerate() {
+        *coeff = (i as i16 * 13) % 3329;
+    }
+
+    for (name, ctx) in [("scalar", &scalar), ("detected", &detected)] {
+        group.bench_function(format!("forward {}", name), |b| {
+            b.iter(|| {
+                let mut a = black_box(poly);
+                ctx.forward(&mut a);
+                a
+            })
+        });
+
+        group.bench_function(format!("inverse {}", name), |b| {
+            b.iter(|| {
+                let mut a = black_box(poly);
+                ctx.inverse(&mut a);
+                a
+            })
+        });
+
+        group.bench_function(format!("basemul {}", name), |b| {
+            b.iter(|| ctx.basemul(black_box(&poly), black_box(&poly)))
+        });
+    }
+
+    group.finish();
+
+    // Criterion reports each backend separately; print the ratio as well
+    if detected.backend() != NttBackend::Scalar {
+        let round_trip = |ctx: &NTTContext| {
+            let start = Instant::now();
+            for _ in 0..10_000 {
+                let mut a = black_box(poly);
+                ctx.forward(&mut a);
+                let a = ctx.basemul(&a, &poly);
+                let mut a = black_box(a);
+                ctx.inverse(&mut a);
+            }
+            start.elapsed()
+        };
+        let scalar_time = round_trip(&scalar);
+        let simd_time = round_trip(&detected);
+        println!(
+            "NTT forward+basemul+inverse speedup ({:?} vs Scalar): {:.2}x",
+            detected.backend(),
+            scalar_time.as_secs_f64() / simd_time.as_secs_f64()
+        );
+    }
+}
+
 criterion_group!(
     benches,
+    bench_ntt,
     bench_key_manager,
     bench_quantum_resistant,
     bench_large_data,
@@ -5,16 +5,15 @@ use freeghost::{
         services::identity::IdentityService,
         crypto::quantum::ZeroKnowledgeProof,
     },
-    storage::encrypted::EncryptedStore,
+    storage::{encrypted::EncryptedStore, kv::SharedStore},
     utils::config::{Config, StorageConfig, SecurityConfig},
 };
 use std::sync::Arc;
 use tempfile::tempdir;
 use tokio::runtime::Runtime;
-use tokio::sync::RwLock;
 use uuid::Uuid;
 
-async fn setup_test_environment() -> (IdentityService, Arc<RwLock<EncryptedStore>>) {
+async fn setup_test_environment() -> (IdentityService, SharedStore) {
     let temp_dir = tempdir().unwrap();
     
     let config = Config {
@@ -38,9 +37,9 @@ async fn setup_test_environment() -> (IdentityService, Arc<RwLock<EncryptedStore
         },
     };
 
-    let storage = Arc::new(RwLock::new(
+    let storage: SharedStore = Arc::new(
         EncryptedStore::new(&config.storage).await.unwrap()
-    ));
+    );
     
     let service = IdentityService::new(&config, storage.clone()).await.unwrap();
     
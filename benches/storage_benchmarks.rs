@@ -1,12 +1,16 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use freeghost::{
-    storage::encrypted::EncryptedStore,
+    storage::{
+        encrypted::{BackupTarget, EncryptedStore, LocalTarget},
+        kv::{KvBatch, KvStore, KvStoreExt},
+    },
     utils::config::StorageConfig,
     core::identity::types::{Identity, BiometricTemplate},
 };
 use tempfile::tempdir;
 use uuid::Uuid;
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::runtime::Runtime;
 
 fn setup_test_store() -> (EncryptedStore, PathBuf) {
@@ -45,20 +49,20 @@ fn bench_storage_operations(c: &mut Criterion) {
     c.bench_function("store_identity", |b| {
         b.iter(|| {
             rt.block_on(async {
-                store.store_identity(black_box(&identity)).await.unwrap();
+                store.store_identity(black_box(&identity)).unwrap();
             });
         })
     });
 
     // Store the identity first
     rt.block_on(async {
-        store.store_identity(&identity).await.unwrap();
+        store.store_identity(&identity).unwrap();
     });
 
     c.bench_function("get_identity", |b| {
         b.iter(|| {
             rt.block_on(async {
-                store.get_identity(black_box(&identity.id)).await.unwrap();
+                store.get_identity(black_box(&identity.id)).unwrap();
             });
         })
     });
@@ -66,7 +70,7 @@ fn bench_storage_operations(c: &mut Criterion) {
     c.bench_function("delete_identity", |b| {
         b.iter(|| {
             rt.block_on(async {
-                store.delete_identity(black_box(&identity.id)).await.unwrap();
+                store.delete_identity(black_box(&identity.id)).unwrap();
             });
         })
     });
@@ -83,19 +87,11 @@ fn bench_batch_operations(c: &mut Criterion) {
 
         group.bench_with_input(BenchmarkId::new("store_batch", size), &identities, |b, ids| {
             b.iter(|| {
-                rt.block_on(async {
-                    store.batch_operation(|batch| {
-                        for id in ids {
-                            batch.put(
-                                format!("identity:{}", id.id).as_bytes(),
-                                serde_json::to_vec(id).unwrap(),
-                            );
-                        }
-                        Ok(())
-                    })
-                    .await
-                    .unwrap();
-                });
+                let mut batch = KvBatch::new();
+                for id in ids {
+                    batch.put_record(id.id.as_bytes().to_vec(), id).unwrap();
+                }
+                store.write(batch).unwrap();
             });
         });
     }
@@ -117,7 +113,7 @@ fn bench_concurrent_access(c: &mut Criterion) {
         // Store identities first
         rt.block_on(async {
             for identity in &identities {
-                store.store_identity(identity).await.unwrap();
+                store.store_identity(identity).unwrap();
             }
         });
 
@@ -134,7 +130,7 @@ fn bench_concurrent_access(c: &mut Criterion) {
                             let store = store.clone();
                             let id = id.id;
                             handles.push(tokio::spawn(async move {
-                                store.get_identity(&id).await.unwrap();
+                                store.get_identity(&id).unwrap();
                             }));
                         }
 
@@ -156,36 +152,34 @@ fn bench_backup_restore(c: &mut Criterion) {
     // Test with different amounts of data
     for num_identities in [100, 1000].iter() {
         let (store, path) = setup_test_store();
-        let backup_path = path.join("backup");
+        let targets: Vec<Arc<dyn BackupTarget>> = vec![Arc::new(LocalTarget::new(path.join("backup")))];
         let identities: Vec<_> = (0..*num_identities).map(|_| create_test_identity()).collect();
 
         // Store test data
         rt.block_on(async {
             for identity in &identities {
-                store.store_identity(identity).await.unwrap();
+                store.store_identity(identity).unwrap();
             }
         });
 
         group.bench_with_input(
             BenchmarkId::new("backup", num_identities),
-            &backup_path,
-            |b, backup_path| {
+            &targets,
+            |b, targets| {
                 b.iter(|| {
                     rt.block_on(async {
-                        store.backup(black_box(backup_path)).await.unwrap();
+                        store.backup(black_box(targets)).await.unwrap();
                     });
                 });
             },
         );
 
         group.bench_with_input(
-            BenchmarkId::new("restore", num_identities),
-            &backup_path,
-            |b, backup_path| {
+            BenchmarkId::new("test_restore", num_identities),
+            &targets,
+            |b, targets| {
                 b.iter(|| {
-                    rt.block_on(async {
-                        store.restore(black_box(backup_path)).await.unwrap();
-                    });
+                    store.test_restore(black_box(targets[0].as_ref()), None).unwrap();
                 });
             },
         );
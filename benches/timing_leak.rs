@@ -0,0 +1,228 @@
+//! dudect-style statistical timing-leak harness
+//!
+//! Runs each target on two input classes -- one fixed input and freshly
+//! random inputs -- interleaved in random order, and applies Welch's t-test
+//! to the execution-time distributions. A |t| above `T_THRESHOLD` means the
+//! running time depends on the data and the target is reported as leaking.
+//!
+//! Run with `cargo bench --bench timing_leak`. The number of measurements
+//! per target can be set with `DUDECT_MEASUREMENTS`.
+
+use std::time::Instant;
+
+use freeghost::core::crypto::{
+    dilithium::Dilithium,
+    kyber::{KyberKEM, add_coeffs, decode_message},
+    serialization::{serialize_ciphertext, deserialize_ciphertext},
+};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Same threshold dudect uses to declare a definite leak
+const T_THRESHOLD: f64 = 4.5;
+const DEFAULT_MEASUREMENTS: usize = 20_000;
+/// Percentiles at which the slow tail is cropped before testing
+const CROP_PERCENTILES: [f64; 4] = [1.0, 0.99, 0.95, 0.90];
+
+/// Online mean/variance accumulator (Welford)
+#[derive(Default, Clone, Copy)]
+struct Moments {
+    n: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    fn push(&mut self, x: f64) {
+        self.n += 1.0;
+        let delta = x - self.mean;
+        self.mean += delta / self.n;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        if self.n < 2.0 {
+            0.0
+        } else {
+            self.m2 / (self.n - 1.0)
+        }
+    }
+}
+
+/// Welch's t statistic between the two classes
+fn welch_t(a: &Moments, b: &Moments) -> f64 {
+    let denom = (a.variance() / a.n + b.variance() / b.n).sqrt();
+    if denom == 0.0 {
+        0.0
+    } else {
+        (a.mean - b.mean) / denom
+    }
+}
+
+fn percentile(sorted: &[u128], p: f64) -> u128 {
+    let idx = ((sorted.len() - 1) as f64 * p) as usize;
+    sorted[idx]
+}
+
+/// Measure `op` on the two classes and return the largest |t| over all crops
+fn measure<F>(name: &str, measurements: usize, rng: &SystemRandom, mut op: F) -> f64
+where
+    F: FnMut(usize),
+{
+    let mut classes = vec![0u8; measurements];
+    rng.fill(&mut classes).expect("failed to draw class assignments");
+
+    // Warm caches and branch predictors before recording anything
+    for &class in classes.iter().take(100) {
+        op((class & 1) as usize);
+    }
+
+    let mut samples = Vec::with_capacity(measurements);
+    for &class in &classes {
+        let class = (class & 1) as usize;
+        let start = Instant::now();
+        op(class);
+        samples.push((class, start.elapsed().as_nanos()));
+    }
+
+    let mut sorted: Vec<u128> = samples.iter().map(|&(_, t)| t).collect();
+    sorted.sort_unstable();
+
+    let mut max_t: f64 = 0.0;
+    for &p in CROP_PERCENTILES.iter() {
+        let cutoff = percentile(&sorted, p);
+        let mut moments = [Moments::default(); 2];
+        for &(class, t) in samples.iter().filter(|&&(_, t)| t <= cutoff) {
+            moments[class].push(t as f64);
+        }
+        let t = welch_t(&moments[0], &moments[1]);
+        if t.abs() > max_t.abs() {
+            max_t = t;
+        }
+    }
+
+    let verdict = if max_t.abs() > T_THRESHOLD { "POSSIBLE LEAK" } else { "ok" };
+    println!(
+        "{:<24} n={:<8} max |t| = {:>8.3}  {}",
+        name, measurements, max_t.abs(), verdict
+    );
+    max_t
+}
+
+fn bench_kyber_decapsulate(measurements: usize, rng: &SystemRandom) -> f64 {
+    let (pk, sk) = KyberKEM::keygen().expect("keygen failed");
+
+    // Class 0: the same ciphertext every time. Class 1: a fresh one per call.
+    let (_, fixed) = KyberKEM::encapsulate(&pk).expect("encapsulation failed");
+    let fixed = serialize_ciphertext(&fixed).expect("serialization failed");
+    let random: Vec<Vec<u8>> = (0..measurements)
+        .map(|_| {
+            let (_, ct) = KyberKEM::encapsulate(&pk).expect("encapsulation failed");
+            serialize_ciphertext(&ct).expect("serialization failed")
+        })
+        .collect();
+
+    let mut next = 0;
+    measure("KyberKEM::decapsulate", measurements, rng, |class| {
+        let bytes = if class == 0 {
+            &fixed
+        } else {
+            next = (next + 1) % random.len();
+            &random[next]
+        };
+        let ct = deserialize_ciphertext(bytes).expect("deserialization failed");
+        std::hint::black_box(KyberKEM::decapsulate(&sk, &ct).expect("decapsulation failed"));
+    })
+}
+
+fn bench_kyber_decode_message(measurements: usize, rng: &SystemRandom) -> f64 {
+    // Class 0: all-zero coefficients. Class 1: fresh coefficients in (-q, q),
+    // which is where a division by q would show. Both classes read from
+    // pools of the same size so cache misses do not tell them apart.
+    let fixed = vec![[0i16; 256]; measurements];
+    let mut bytes = vec![0u8; measurements * 512];
+    rng.fill(&mut bytes).expect("failed to draw coefficients");
+    let random: Vec<[i16; 256]> = bytes
+        .chunks_exact(512)
+        .map(|chunk| {
+            let mut coeffs = [0i16; 256];
+            for (coeff, pair) in coeffs.iter_mut().zip(chunk.chunks_exact(2)) {
+                *coeff = (u16::from_le_bytes([pair[0], pair[1]]) % 6657) as i16 - 3328;
+            }
+            coeffs
+        })
+        .collect();
+
+    let mut next = 0;
+    measure("kyber::decode_message", measurements, rng, |class| {
+        next = (next + 1) % measurements;
+        let coeffs = if class == 0 { &fixed[next] } else { &random[next] };
+        std::hint::black_box(decode_message(std::hint::black_box(coeffs)));
+    })
+}
+
+fn bench_kyber_add(measurements: usize, rng: &SystemRandom) -> f64 {
+    // Class 0: all-zero coefficients. Class 1: fresh coefficients in [0, q),
+    // where a reduction by `%` would show. Both classes read from pools of
+    // the same size so cache misses do not tell them apart.
+    let fixed = vec![[0i16; 256]; measurements];
+    let mut bytes = vec![0u8; measurements * 512];
+    rng.fill(&mut bytes).expect("failed to draw coefficients");
+    let random: Vec<[i16; 256]> = bytes
+        .chunks_exact(512)
+        .map(|chunk| {
+            let mut coeffs = [0i16; 256];
+            for (coeff, pair) in coeffs.iter_mut().zip(chunk.chunks_exact(2)) {
+                *coeff = (u16::from_le_bytes([pair[0], pair[1]]) % 3329) as i16;
+            }
+            coeffs
+        })
+        .collect();
+
+    let mut next = 0;
+    measure("kyber::add_coeffs", measurements, rng, |class| {
+        next = (next + 1) % measurements;
+        let coeffs = if class == 0 { &fixed[next] } else { &random[next] };
+        std::hint::black_box(add_coeffs(std::hint::black_box(coeffs), std::hint::black_box(coeffs)));
+    })
+}
+
+fn bench_dilithium_sign(measurements: usize, rng: &SystemRandom) -> f64 {
+    let (_, sk) = Dilithium::keygen().expect("keygen failed");
+
+    let fixed = [0u8; 64];
+    let mut random = vec![[0u8; 64]; measurements];
+    for msg in random.iter_mut() {
+        rng.fill(msg).expect("failed to draw message");
+    }
+
+    let mut next = 0;
+    measure("Dilithium::sign", measurements, rng, |class| {
+        let msg = if class == 0 {
+            &fixed
+        } else {
+            next = (next + 1) % random.len();
+            &random[next]
+        };
+        std::hint::black_box(Dilithium::sign(&sk, msg).expect("signing failed"));
+    })
+}
+
+fn main() {
+    let measurements = std::env::var("DUDECT_MEASUREMENTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MEASUREMENTS);
+    let rng = SystemRandom::new();
+
+    let results = [
+        bench_kyber_decapsulate(measurements, &rng),
+        bench_kyber_decode_message(measurements, &rng),
+        bench_kyber_add(measurements, &rng),
+        bench_dilithium_sign(measurements, &rng),
+    ];
+
+    if results.iter().any(|t| t.abs() > T_THRESHOLD) {
+        eprintln!("data-dependent timing detected (|t| > {})", T_THRESHOLD);
+        std::process::exit(1);
+    }
+}
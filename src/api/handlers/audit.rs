@@ -0,0 +1,206 @@
+use actix_web::{
+    web::{self, Data, Query},
+    HttpRequest, HttpResponse, Scope,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+use tracing::error;
+
+use crate::{
+    utils::config::Config,
+    core::crypto::{
+        audit::{AuditFilter, AuditSystem},
+        audit_export::{format_event, ExportFormat},
+    },
+    api::handlers::bearer::BearerGuard,
+};
+
+const DEFAULT_LIMIT: usize = 1000;
+const MAX_LIMIT: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub event_type: Option<String>,
+    pub component_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    /// RFC 3339
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+    /// "json" (default), "jsonl", "cef" or "syslog"
+    pub format: Option<String>,
+}
+
+/// Access settings for the audit endpoint
+pub struct AuditApi {
+    guard: BearerGuard,
+    hostname: String,
+}
+
+impl AuditApi {
+    /// Without a token the endpoint answers 404
+    pub fn new(token: Option<&str>, hostname: &str) -> Self {
+        Self {
+            guard: BearerGuard::new("Audit API", "/audit", token),
+            hostname: hostname.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.security.audit_api_token.as_deref(), &config.node.id)
+    }
+}
+
+pub fn scope() -> Scope {
+    web::scope("/audit")
+        .service(
+            web::resource("")
+                .route(web::get().to(query_events))
+        )
+}
+
+async fn query_events(
+    request: HttpRequest,
+    api: Data<AuditApi>,
+    audit: Data<AuditSystem>,
+    query: Query<AuditQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    api.guard.check(&request, &audit, None).await?;
+
+    let query = query.into_inner();
+    let format = match query.format.as_deref() {
+        None | Some("json") => None,
+        Some(other) => Some(other.parse::<ExportFormat>().map_err(actix_web::error::ErrorBadRequest)?),
+    };
+    let filter = AuditFilter {
+        event_type: query.event_type,
+        component_id: query.component_id,
+        session_id: query.session_id,
+        start: query.start,
+        end: query.end,
+        limit: Some(query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)),
+    };
+
+    let events = audit.query(&filter).await.map_err(|e| {
+        error!("Audit query failed: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+
+    let Some(format) = format else {
+        return Ok(HttpResponse::Ok().json(json!({
+            "count": events.len(),
+            "events": events,
+        })));
+    };
+
+    let mut body = String::new();
+    for event in &events {
+        let line = format_event(event, format, &api.hostname)
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        body.push_str(&line);
+        body.push('\n');
+    }
+    let content_type = if format == ExportFormat::JsonLines { "application/x-ndjson" } else { "text/plain; charset=utf-8" };
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::{header, StatusCode}, test, App};
+    use crate::core::crypto::{audit::AuditEventType, quantum::SecurityLevel};
+
+    const TOKEN: &str = "audit-token-for-tests";
+
+    async fn audit_system() -> Data<AuditSystem> {
+        let audit = AuditSystem::new(30, SecurityLevel::Standard);
+        audit.record_event(AuditEventType::KeyRotation, None, None).await.unwrap();
+        audit.record_event(AuditEventType::AuthenticationAttempt { success: true }, Some(Uuid::new_v4()), None).await.unwrap();
+        Data::new(audit)
+    }
+
+    #[actix_web::test]
+    async fn test_rejects_missing_token() {
+        let audit = audit_system().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(AuditApi::new(Some(TOKEN), "node-1")))
+                .app_data(audit.clone())
+                .service(scope())
+        ).await;
+
+        let request = test::TestRequest::get().uri("/audit").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::UNAUTHORIZED);
+
+        let request = test::TestRequest::get()
+            .uri("/audit")
+            .insert_header((header::AUTHORIZATION, "Bearer wrong-token"))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::UNAUTHORIZED);
+
+        // The first attempt is logged; later ones are counted into the next
+        // event instead of flooding the log
+        for _ in 0..10 {
+            let request = test::TestRequest::get().uri("/audit").to_request();
+            assert_eq!(test::call_service(&app, request).await.status(), StatusCode::UNAUTHORIZED);
+        }
+        let failures = audit.query(&AuditFilter {
+            event_type: Some("AuthenticationAttempt".into()),
+            ..AuditFilter::default()
+        }).await.unwrap();
+        let logged: Vec<_> = failures.iter().filter_map(|e| e.metadata.as_ref()).collect();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0]["endpoint"], "/audit");
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_without_token() {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(AuditApi::new(None, "node-1")))
+                .app_data(audit_system().await)
+                .service(scope())
+        ).await;
+
+        let request = test::TestRequest::get()
+            .uri("/audit")
+            .insert_header((header::AUTHORIZATION, "Bearer "))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_filtered_query() {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(AuditApi::new(Some(TOKEN), "node-1")))
+                .app_data(audit_system().await)
+                .service(scope())
+        ).await;
+
+        let request = test::TestRequest::get()
+            .uri("/audit?event_type=KeyRotation&start=2000-01-01T00:00:00Z")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", TOKEN)))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["count"], 1);
+        assert_eq!(body["events"][0]["event_type"], "KeyRotation");
+
+        let request = test::TestRequest::get()
+            .uri("/audit?format=cef&limit=2")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", TOKEN)))
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body.lines().count(), 2);
+        assert!(body.lines().all(|line| line.starts_with("CEF:0|FreeGhost|node|")));
+
+        let request = test::TestRequest::get()
+            .uri("/audit?format=xml")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", TOKEN)))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::BAD_REQUEST);
+    }
+}
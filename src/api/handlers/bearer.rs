@@ -0,0 +1,103 @@
+//! Bearer-token guard for endpoints that are off unless a token is configured
+//!
+//! Rejected requests are written to the audit log, but at most one event per
+//! `AUDIT_INTERVAL`; the rejections in between are counted into the next
+//! event, so unauthenticated clients cannot flood the log and its
+//! checkpoints.
+
+use std::time::{Duration, Instant};
+use actix_web::{http::header, HttpRequest};
+use parking_lot::Mutex;
+use ring::constant_time::verify_slices_are_equal;
+use serde_json::json;
+use sha3::{Digest, Sha3_256};
+use uuid::Uuid;
+use tracing::{warn, error};
+
+use crate::core::crypto::audit::{AuditEventType, AuditSystem};
+
+/// Least time between audit events for rejected requests
+const AUDIT_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct BearerGuard {
+    // What the endpoint is called in errors, e.g. "Audit API"
+    name: &'static str,
+    endpoint: &'static str,
+    // Only the digest is kept so comparisons take the same time for any token
+    token_digest: Option<[u8; 32]>,
+    rejections: Mutex<Rejections>,
+}
+
+#[derive(Default)]
+struct Rejections {
+    last_audited: Option<Instant>,
+    unaudited: u64,
+}
+
+impl BearerGuard {
+    /// Without a token every request is answered with 404
+    pub fn new(name: &'static str, endpoint: &'static str, token: Option<&str>) -> Self {
+        Self {
+            name,
+            endpoint,
+            token_digest: token.map(digest),
+            rejections: Mutex::new(Rejections::default()),
+        }
+    }
+
+    /// Let `request` through if it carries the token; 404 if no token is
+    /// configured, 401 otherwise. `component_id` is what the request was
+    /// about, for the audit event.
+    pub async fn check(
+        &self,
+        request: &HttpRequest,
+        audit: &AuditSystem,
+        component_id: Option<Uuid>,
+    ) -> Result<(), actix_web::Error> {
+        let Some(expected) = &self.token_digest else {
+            return Err(actix_web::error::ErrorNotFound(format!("{} is disabled", self.name)));
+        };
+        let authorized = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| verify_slices_are_equal(&digest(token), expected).is_ok())
+            .unwrap_or(false);
+        if authorized {
+            return Ok(());
+        }
+
+        warn!("Rejected request to {} from {:?}", self.endpoint, request.peer_addr());
+        if let Some(unaudited) = self.due_for_audit() {
+            let metadata = json!({
+                "endpoint": self.endpoint,
+                "peer": request.peer_addr().map(|addr| addr.to_string()),
+                "unaudited_rejections": unaudited,
+            });
+            if let Err(e) = audit
+                .record_event(AuditEventType::AuthenticationAttempt { success: false }, component_id, Some(metadata))
+                .await
+            {
+                error!("Failed to record rejected request to {}: {}", self.endpoint, e);
+            }
+        }
+        Err(actix_web::error::ErrorUnauthorized(format!("Invalid {} token", self.name)))
+    }
+
+    /// Rejections counted since the last audit event if another one is due
+    fn due_for_audit(&self) -> Option<u64> {
+        let mut rejections = self.rejections.lock();
+        let now = Instant::now();
+        if rejections.last_audited.is_some_and(|last| now.duration_since(last) < AUDIT_INTERVAL) {
+            rejections.unaudited += 1;
+            return None;
+        }
+        rejections.last_audited = Some(now);
+        Some(std::mem::take(&mut rejections.unaudited))
+    }
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha3_256::digest(token.as_bytes()).into()
+}
@@ -0,0 +1,125 @@
+use actix_web::{
+    web::{self, Data, Json, Path},
+    HttpRequest, HttpResponse, Scope,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+use tracing::{info, warn, error};
+
+use crate::{
+    utils::config::Config,
+    core::{
+        crypto::{
+            audit::AuditSystem,
+            credentials::{IssuanceRequest, Presentation, PresentationRequest, VerifiedClaims},
+        },
+        services::credentials::CredentialService,
+    },
+    api::handlers::bearer::BearerGuard,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyPresentationRequest {
+    pub presentation: Presentation,
+    /// The relying party's original request; its nonce binds the presentation
+    pub request: PresentationRequest,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyPresentationResponse {
+    pub valid: bool,
+    pub satisfied: bool,
+    pub claims: Option<VerifiedClaims>,
+}
+
+/// Access settings for credential issuance. Issuing vouches for the
+/// identity, so only the enrollment frontend that checked it may ask.
+pub struct CredentialApi {
+    guard: BearerGuard,
+}
+
+impl CredentialApi {
+    /// Without a token issuance answers 404
+    pub fn new(token: Option<&str>) -> Self {
+        Self {
+            guard: BearerGuard::new("Credential API", "/credentials/{id}/issue", token),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.security.credential_api_token.as_deref())
+    }
+}
+
+pub fn scope() -> Scope {
+    web::scope("/credentials")
+        .service(
+            web::resource("/issuer")
+                .route(web::get().to(issuer_public_key))
+        )
+        .service(
+            web::resource("/presentations/verify")
+                .route(web::post().to(verify_presentation))
+        )
+        .service(
+            web::resource("/{id}/issue")
+                .route(web::post().to(issue_credential))
+        )
+}
+
+async fn issuer_public_key(
+    service: Data<CredentialService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let public_key = service.issuer_public_key();
+    Ok(HttpResponse::Ok().json(json!({
+        "key_id": hex::encode(public_key.key_id()),
+        "public_key": public_key,
+    })))
+}
+
+async fn issue_credential(
+    http_request: HttpRequest,
+    api: Data<CredentialApi>,
+    audit: Data<AuditSystem>,
+    service: Data<CredentialService>,
+    id: Path<Uuid>,
+    request: Json<IssuanceRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    api.guard.check(&http_request, &audit, Some(*id)).await?;
+
+    info!("Received credential issuance request for identity: {}", id);
+
+    let issued = service
+        .issue_credential(*id, request.into_inner())
+        .await
+        .map_err(|e| {
+            error!("Credential issuance failed for identity {}: {}", id, e);
+            actix_web::error::ErrorBadRequest(e)
+        })?;
+
+    Ok(HttpResponse::Created().json(issued))
+}
+
+async fn verify_presentation(
+    service: Data<CredentialService>,
+    request: Json<VerifyPresentationRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let response = match service.verify_presentation(&request.presentation, &request.request.nonce) {
+        Ok(claims) => VerifyPresentationResponse {
+            valid: true,
+            satisfied: claims.satisfies(&request.request),
+            claims: Some(claims),
+        },
+        Err(e) => {
+            warn!("Presentation rejected: {}", e);
+            VerifyPresentationResponse {
+                valid: false,
+                satisfied: false,
+                claims: None,
+            }
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,108 @@
+//! Refusing requests while the node's session is suspended
+//!
+//! Key material is registered with the memory poison detector under the
+//! audit session of the running node. With `PoisonResponse::SuspendSessions`
+//! an integrity alert suspends that session, and every request is answered
+//! with 503 until it is resumed.
+
+use std::future::Future;
+use std::sync::Arc;
+use actix_web::{
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
+    HttpResponse,
+};
+use serde_json::json;
+use uuid::Uuid;
+use tracing::warn;
+
+use crate::core::crypto::poisoning::MemoryPoisonDetector;
+
+#[derive(Clone)]
+pub struct SessionGuard {
+    detector: Arc<MemoryPoisonDetector>,
+    session: Uuid,
+}
+
+impl SessionGuard {
+    pub fn new(detector: Arc<MemoryPoisonDetector>, session: Uuid) -> Self {
+        Self { detector, session }
+    }
+
+    pub fn suspended(&self) -> bool {
+        self.detector.is_session_suspended(&self.session)
+    }
+
+    /// Middleware for `App::wrap_fn`: hand `request` to `service` unless
+    /// the session is suspended
+    pub fn filter<S, B>(
+        &self,
+        request: ServiceRequest,
+        service: &S,
+    ) -> impl Future<Output = Result<ServiceResponse<EitherBody<B>>, actix_web::Error>>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    {
+        let call = if self.suspended() {
+            warn!("Refused request to {} while session {} is suspended", request.path(), self.session);
+            Err(request)
+        } else {
+            Ok(service.call(request))
+        };
+
+        async move {
+            match call {
+                Ok(response) => Ok(response.await?.map_into_left_body()),
+                Err(request) => Ok(request
+                    .into_response(HttpResponse::ServiceUnavailable().json(json!({
+                        "error": "Session suspended after a memory integrity alert",
+                    })))
+                    .map_into_right_body()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App};
+    use parking_lot::RwLock;
+    use crate::core::crypto::{
+        audit::AuditSystem,
+        poisoning::{AuditAlertHandler, MemoryRegionType, PoisonResponse},
+        quantum::SecurityLevel,
+        secure_memory::Secret,
+    };
+
+    #[actix_web::test]
+    async fn test_corrupted_region_suspends_requests() {
+        let audit = Arc::new(AuditSystem::new(30, SecurityLevel::Standard));
+        let detector = Arc::new(
+            MemoryPoisonDetector::new(Box::new(AuditAlertHandler::new(audit.clone())))
+                .with_response(PoisonResponse::SuspendSessions),
+        );
+        let session = audit.get_current_session();
+        let key = Arc::new(RwLock::new(Secret::new([7u8; 32]).unwrap()));
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, Some(session)).unwrap();
+
+        let guard = SessionGuard::new(detector.clone(), session);
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(move |request, service| guard.filter(request, service))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        ).await;
+
+        let request = test::TestRequest::get().uri("/").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::OK);
+
+        key.read().tamper(0, 0xFF);
+        assert!(!detector.check_memory().await);
+        let request = test::TestRequest::get().uri("/").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        detector.resume_session(&session);
+        let request = test::TestRequest::get().uri("/").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::OK);
+    }
+}
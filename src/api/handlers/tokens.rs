@@ -0,0 +1,91 @@
+use actix_web::{
+    web::{self, Data, Json, Path},
+    HttpResponse, Scope,
+};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+use tracing::{info, error};
+
+use crate::core::{
+    crypto::{
+        credentials::Presentation,
+        tokens::{Token, TokenRequest},
+    },
+    services::tokens::TokenService,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct IssueTokensRequest {
+    pub request: TokenRequest,
+    /// The identity's credential, presented for
+    /// `TokenService::presentation_request`
+    pub presentation: Presentation,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedeemTokenRequest {
+    pub token: Token,
+    /// Scope the redeeming service expects the token to be bound to
+    pub scope: String,
+}
+
+pub fn scope() -> Scope {
+    web::scope("/tokens")
+        .service(
+            web::resource("/key")
+                .route(web::get().to(current_key))
+        )
+        .service(
+            web::resource("/redeem")
+                .route(web::post().to(redeem_token))
+        )
+        .service(
+            web::resource("/{id}/issue")
+                .route(web::post().to(issue_tokens))
+        )
+}
+
+async fn current_key(
+    service: Data<TokenService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let public_key = service.current_public_key();
+    Ok(HttpResponse::Ok().json(json!({
+        "key_id": hex::encode(public_key.key_id()),
+        "public_key": public_key,
+    })))
+}
+
+async fn issue_tokens(
+    service: Data<TokenService>,
+    id: Path<Uuid>,
+    request: Json<IssueTokensRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    info!("Received token issuance request for identity: {}", id);
+
+    let IssueTokensRequest { request, presentation } = request.into_inner();
+    let response = service
+        .issue_tokens(*id, request, &presentation)
+        .await
+        .map_err(|e| {
+            error!("Token issuance failed for identity {}: {}", id, e);
+            actix_web::error::ErrorBadRequest(e)
+        })?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn redeem_token(
+    service: Data<TokenService>,
+    request: Json<RedeemTokenRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = service
+        .redeem_token(&request.token, &request.scope)
+        .await
+        .map_err(|e| {
+            error!("Token redemption failed: {}", e);
+            actix_web::error::ErrorInternalServerError(e)
+        })?;
+
+    Ok(HttpResponse::Ok().json(outcome))
+}
@@ -0,0 +1,115 @@
+use actix_web::{
+    web::{self, Data, Json},
+    HttpResponse, HttpServer, App, Scope,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::{
+    utils::error::NodeError,
+    core::crypto::{
+        key_manager::{KeyManager, KeyShare},
+        unseal::UnsealSession,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitShareRequest {
+    /// Share in its text form (`fgks1-...`)
+    pub share: String,
+}
+
+pub struct UnsealState {
+    session: Mutex<UnsealSession>,
+    unsealed: Mutex<Option<oneshot::Sender<KeyManager>>>,
+}
+
+pub fn scope() -> Scope {
+    web::scope("/unseal")
+        .service(
+            web::resource("")
+                .route(web::get().to(unseal_status))
+                .route(web::post().to(submit_share))
+        )
+}
+
+/// Serve the unseal endpoint on a loopback address until enough shares
+/// have been submitted, then shut it down and return the unlocked keyring
+pub async fn serve(
+    address: &str,
+    session: UnsealSession,
+) -> crate::utils::error::Result<KeyManager> {
+    let address: SocketAddr = address.parse()
+        .map_err(|_| NodeError::Config("Invalid unseal_address".into()))?;
+    if !address.ip().is_loopback() {
+        return Err(NodeError::Config("unseal_address must be a loopback address".into()));
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let state = Data::new(UnsealState {
+        session: Mutex::new(session),
+        unsealed: Mutex::new(Some(tx)),
+    });
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .service(scope())
+    })
+    .workers(1)
+    .bind(address)
+    .map_err(|e| NodeError::Init(format!("Failed to bind unseal endpoint: {}", e)))?
+    .run();
+    let handle = server.handle();
+    tokio::spawn(server);
+
+    info!("Keyring is sealed; waiting for key shares on http://{}/unseal", address);
+    let key_manager = rx.await
+        .map_err(|_| NodeError::Init("Unseal endpoint stopped".into()))?;
+    handle.stop(true).await;
+
+    Ok(key_manager)
+}
+
+async fn unseal_status(
+    state: Data<UnsealState>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let progress = state.session.lock().unwrap().progress();
+    Ok(HttpResponse::Ok().json(json!({
+        "sealed": true,
+        "progress": progress,
+    })))
+}
+
+async fn submit_share(
+    state: Data<UnsealState>,
+    request: Json<SubmitShareRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut session = state.session.lock().unwrap();
+
+    let result = request.share
+        .parse::<KeyShare>()
+        .and_then(|share| session.submit(share));
+
+    match result {
+        Ok(Some(key_manager)) => {
+            if let Some(tx) = state.unsealed.lock().unwrap().take() {
+                let _ = tx.send(key_manager);
+            }
+            info!("Keyring unsealed");
+            Ok(HttpResponse::Ok().json(json!({ "sealed": false })))
+        }
+        Ok(None) => Ok(HttpResponse::Accepted().json(json!({
+            "sealed": true,
+            "progress": session.progress(),
+        }))),
+        Err(e) => {
+            warn!("Key share rejected: {}", e);
+            Err(actix_web::error::ErrorBadRequest(e))
+        }
+    }
+}
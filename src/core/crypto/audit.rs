@@ -1,13 +1,45 @@
 // src/core/crypto/audit.rs
 
+//! Tamper-evident audit log
+//!
+//! Each event is stored with `hash = SHA3-256(previous hash || sequence ||
+//! event)`, so changing or removing a record breaks the chain from there
+//! on. Every `checkpoint_interval` events the head hash is signed with
+//! Dilithium; the signature pins down everything before it, which the chain
+//! alone cannot do against someone who rewrites all later hashes too.
+//! Records after the last checkpoint are only covered by the chain.
+//!
+//! `open` keeps the log in the `audit` column family of the node database,
+//! `new` keeps it in memory. Retention drops whole checkpointed segments and
+//! keeps the checkpoint each one ends at, so what is left still verifies.
+//! A log that starts after record 0 only verifies if it starts right after
+//! the oldest checkpoint and that checkpoint is past the retention period;
+//! cutting off a recent prefix is reported as missing records.
+
 use chrono::{DateTime, Utc};
+use rocksdb::{DB, Direction, IteratorMode, WriteBatch};
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
 use super::types::{SecurityLevel, CryptoMetadata};
+use super::dilithium::{Dilithium, SecretKey as DilithiumSecretKey, Signature as DilithiumSignature};
 use thiserror::Error;
 
+/// Column family holding the audit log
+pub const AUDIT_CF: &str = "audit";
+
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 256;
+// Events buffered per live subscriber before it starts missing some
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+const EVENT_PREFIX: &[u8] = b"event:";
+const CHECKPOINT_PREFIX: &[u8] = b"checkpoint:";
+// Secondary index by time: `time:<millis><seq>`, both big-endian
+const TIME_PREFIX: &[u8] = b"time:";
+
 #[derive(Debug, Error)]
 pub enum AuditError {
     #[error("Failed to record audit event")]
@@ -18,6 +50,18 @@ pub enum AuditError {
     InvalidPeriod,
     #[error("Storage error: {0}")]
     StorageError(String),
+    #[error("Signing error: {0}")]
+    SigningFailed(String),
+    #[error("Audit record {0} is missing")]
+    RecordMissing(u64),
+    #[error("Audit record {0} was modified")]
+    RecordModified(u64),
+    #[error("Audit chain does not match the checkpoint at record {0}")]
+    CheckpointMismatch(u64),
+    #[error("Invalid checkpoint signature at record {0}")]
+    InvalidCheckpoint(u64),
+    #[error("Export error: {0}")]
+    ExportFailed(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +79,25 @@ pub enum AuditEventType {
     SystemShutdown,
 }
 
+impl AuditEventType {
+    /// Variant name, as used for filtering and in exported formats
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditEventType::KeyGeneration => "KeyGeneration",
+            AuditEventType::KeyRotation => "KeyRotation",
+            AuditEventType::SignatureCreation => "SignatureCreation",
+            AuditEventType::SignatureVerification => "SignatureVerification",
+            AuditEventType::TemplateGeneration => "TemplateGeneration",
+            AuditEventType::TemplateVerification => "TemplateVerification",
+            AuditEventType::SecurityLevelChange => "SecurityLevelChange",
+            AuditEventType::AuthenticationAttempt { .. } => "AuthenticationAttempt",
+            AuditEventType::AnomalyDetected { .. } => "AnomalyDetected",
+            AuditEventType::SystemStartup => "SystemStartup",
+            AuditEventType::SystemShutdown => "SystemShutdown",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum AnomalySeverity {
     Low,
@@ -64,42 +127,139 @@ pub struct AuditSummary {
     pub security_level_changes: usize,
 }
 
+/// Criteria for `AuditSystem::query`; unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    /// Variant name, e.g. "AuthenticationAttempt"
+    pub event_type: Option<String>,
+    pub component_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.event_type.as_deref().is_none_or(|name| event.event_type.name() == name)
+            && self.component_id.is_none_or(|id| event.component_id == Some(id))
+            && self.session_id.is_none_or(|id| event.session_id == Some(id))
+            && self.start.is_none_or(|start| event.timestamp >= start)
+            && self.end.is_none_or(|end| event.timestamp <= end)
+    }
+}
+
+/// Signed head of the chain after record `seq`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditCheckpoint {
+    pub seq: u64,
+    pub hash: String,
+    pub timestamp: DateTime<Utc>,
+    pub signature: String,
+}
+
+/// Outcome of a successful `verify_chain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainVerification {
+    /// First record still retained
+    pub first_seq: Option<u64>,
+    pub records: u64,
+    pub checkpoints: u64,
+    /// Records after the last checkpoint, covered by the chain only
+    pub unsigned_records: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChainedRecord {
+    seq: u64,
+    hash: String,
+    event: AuditEvent,
+}
+
 pub struct AuditSystem {
     storage: Arc<RwLock<AuditStorage>>,
     retention_period: chrono::Duration,
+    checkpoint_interval: u64,
+    signing_key: DilithiumSecretKey,
     current_session: Uuid,
     metadata: CryptoMetadata,
+    subscribers: broadcast::Sender<AuditEvent>,
 }
 
 struct AuditStorage {
-    events: Vec<AuditEvent>,
-    index: std::collections::BTreeMap<DateTime<Utc>, Vec<usize>>,
+    backend: Backend,
+    next_seq: u64,
+    head: [u8; 32],
+    since_checkpoint: u64,
+}
+
+enum Backend {
+    Memory(BTreeMap<Vec<u8>, Vec<u8>>),
+    RocksDb(Arc<DB>),
 }
 
 impl AuditSystem {
+    /// In-memory log signed with a key generated for this instance
     pub fn new(retention_days: i64, security_level: SecurityLevel) -> Self {
-        let storage = Arc::new(RwLock::new(AuditStorage {
-            events: Vec::new(),
-            index: std::collections::BTreeMap::new(),
-        }));
+        let (_, signing_key) = Dilithium::keygen()
+            .expect("Failed to generate audit signing key");
+        let storage = AuditStorage::load(Backend::Memory(BTreeMap::new()))
+            .expect("Empty in-memory audit log");
 
-        let system = Self {
-            storage,
-            retention_period: chrono::Duration::days(retention_days),
-            current_session: Uuid::new_v4(),
-            metadata: CryptoMetadata::new(security_level),
-        };
+        Self::init(storage, signing_key, retention_days, DEFAULT_CHECKPOINT_INTERVAL, security_level)
+            .expect("Failed to record startup in in-memory audit log")
+    }
+
+    /// Log persisted in the `AUDIT_CF` column family of `db`. The same
+    /// signing key has to be supplied on every open for old checkpoints to
+    /// verify.
+    pub fn open(
+        db: Arc<DB>,
+        signing_key: DilithiumSecretKey,
+        retention_days: i64,
+        checkpoint_interval: u64,
+        security_level: SecurityLevel,
+    ) -> Result<Self, AuditError> {
+        let storage = AuditStorage::load(Backend::RocksDb(db))?;
+        Self::init(storage, signing_key, retention_days, checkpoint_interval.max(1), security_level)
+    }
+
+    fn init(
+        mut storage: AuditStorage,
+        signing_key: DilithiumSecretKey,
+        retention_days: i64,
+        checkpoint_interval: u64,
+        security_level: SecurityLevel,
+    ) -> Result<Self, AuditError> {
+        let current_session = Uuid::new_v4();
+        let metadata = CryptoMetadata::new(security_level);
 
         // Record system startup
-        tokio::spawn(async move {
-            let _ = system.record_event(
-                AuditEventType::SystemStartup,
-                None,
-                None,
-            ).await;
-        });
+        storage.append(&AuditEvent {
+            id: Uuid::new_v4(),
+            event_type: AuditEventType::SystemStartup,
+            timestamp: Utc::now(),
+            security_level: metadata.security_level,
+            component_id: None,
+            metadata: None,
+            session_id: Some(current_session),
+        })?;
 
-        system
+        Ok(Self {
+            storage: Arc::new(RwLock::new(storage)),
+            retention_period: chrono::Duration::days(retention_days),
+            checkpoint_interval,
+            signing_key,
+            current_session,
+            metadata,
+            subscribers: broadcast::channel(SUBSCRIBER_BUFFER).0,
+        })
+    }
+
+    /// Live feed of events as they are recorded, e.g. for streaming export.
+    /// A subscriber that falls more than a buffer behind skips events.
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditEvent> {
+        self.subscribers.subscribe()
     }
 
     pub async fn record_event(
@@ -119,23 +279,31 @@ impl AuditSystem {
         };
 
         let mut storage = self.storage.write().await;
-        
-        // Add to main storage
-        let event_index = storage.events.len();
-        storage.events.push(event.clone());
+        storage.append(&event)?;
 
-        // Update time index
-        storage.index
-            .entry(event.timestamp.date().and_hms(0, 0, 0))
-            .or_insert_with(Vec::new)
-            .push(event_index);
+        if storage.since_checkpoint >= self.checkpoint_interval {
+            self.write_checkpoint(&mut storage, Utc::now())?;
+            // Retention works on checkpointed segments
+            self.cleanup_old_events(&mut storage).await?;
+        }
+        drop(storage);
 
-        // Cleanup old events
-        self.cleanup_old_events(&mut storage).await;
+        // Nobody listening is fine
+        let _ = self.subscribers.send(event.clone());
 
         Ok(event.id)
     }
 
+    /// Sign the current head now rather than at the next interval. Returns
+    /// `None` if there is nothing new to sign.
+    pub async fn checkpoint(&self) -> Result<Option<AuditCheckpoint>, AuditError> {
+        let mut storage = self.storage.write().await;
+        if storage.since_checkpoint == 0 {
+            return Ok(None);
+        }
+        self.write_checkpoint(&mut storage, Utc::now()).map(Some)
+    }
+
     pub async fn get_events(
         &self,
         start_time: DateTime<Utc>,
@@ -145,18 +313,40 @@ impl AuditSystem {
             return Err(AuditError::InvalidPeriod);
         }
 
+        self.query(&AuditFilter {
+            start: Some(start_time),
+            end: Some(end_time),
+            ..AuditFilter::default()
+        }).await
+    }
+
+    /// Events matching `filter`, oldest first
+    pub async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>, AuditError> {
+        let start_time = filter.start.unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+        let end_time = filter.end.unwrap_or_else(Utc::now);
+        if end_time < start_time {
+            return Err(AuditError::InvalidPeriod);
+        }
+        let limit = filter.limit.unwrap_or(usize::MAX);
+
         let storage = self.storage.read().await;
-        let mut events = Vec::new();
+        let from = time_key(start_time, 0);
+        let to = time_key(end_time + chrono::Duration::milliseconds(1), 0);
 
-        for (date, indices) in storage.index.range(start_time..=end_time) {
-            if date >= &start_time && date <= &end_time {
-                for &index in indices {
-                    if let Some(event) = storage.events.get(index) {
-                        if event.timestamp >= start_time && event.timestamp <= end_time {
-                            events.push(event.clone());
-                        }
-                    }
-                }
+        let mut seqs = Vec::new();
+        storage.backend.scan(&from, &to, |key, _| {
+            seqs.push(seq_from_key(key)?);
+            Ok(())
+        })?;
+
+        let mut events = Vec::new();
+        for seq in seqs {
+            if events.len() >= limit {
+                break;
+            }
+            let record = storage.record(seq)?.ok_or(AuditError::RetrievalFailed)?;
+            if filter.matches(&record.event) {
+                events.push(record.event);
             }
         }
 
@@ -194,22 +384,152 @@ impl AuditSystem {
         })
     }
 
-    async fn cleanup_old_events(&self, storage: &mut AuditStorage) {
+    /// Walk the whole retained log, recomputing every hash and checking
+    /// every checkpoint signature. Fails on the first record that was
+    /// removed or altered.
+    pub async fn verify_chain(&self) -> Result<ChainVerification, AuditError> {
+        let storage = self.storage.read().await;
+        let public_key = self.signing_key.public_key();
+        // Retention only cuts at checkpoints older than this
+        let cutoff = Utc::now() - self.retention_period;
+
+        let mut checkpoints = BTreeMap::new();
+        storage.backend.scan(CHECKPOINT_PREFIX, &prefix_end(CHECKPOINT_PREFIX), |_, value| {
+            let checkpoint: AuditCheckpoint = decode(value)?;
+            let hash = decode_hash(&checkpoint.hash)?;
+            let signature = hex::decode(&checkpoint.signature)
+                .ok()
+                .and_then(|bytes| DilithiumSignature::from_bytes(&bytes).ok())
+                .ok_or(AuditError::InvalidCheckpoint(checkpoint.seq))?;
+            let message = checkpoint_message(checkpoint.seq, &hash, checkpoint.timestamp);
+            if !Dilithium::verify(public_key, &message, &signature).unwrap_or(false) {
+                return Err(AuditError::InvalidCheckpoint(checkpoint.seq));
+            }
+            checkpoints.insert(checkpoint.seq, (hash, checkpoint.timestamp));
+            Ok(())
+        })?;
+
+        let mut first_seq = None;
+        let mut next_seq = None;
+        let mut previous = [0u8; 32];
+        let mut records = 0;
+
+        storage.backend.scan(EVENT_PREFIX, &prefix_end(EVENT_PREFIX), |_, value| {
+            let record: ChainedRecord = decode(value)?;
+            match next_seq {
+                // A pruned log starts right after the checkpoint it was cut
+                // at, which is the oldest one left and past retention
+                None if record.seq > 0 => {
+                    previous = match checkpoints.first_key_value() {
+                        Some((&seq, &(hash, timestamp))) if seq == record.seq - 1 && timestamp < cutoff => hash,
+                        _ => return Err(AuditError::RecordMissing(record.seq - 1)),
+                    };
+                }
+                Some(expected) if record.seq != expected => {
+                    return Err(AuditError::RecordMissing(expected));
+                }
+                _ => {}
+            }
+
+            let hash = chain_hash(&previous, record.seq, &record.event)?;
+            if hex::encode(hash) != record.hash {
+                return Err(AuditError::RecordModified(record.seq));
+            }
+            if checkpoints.get(&record.seq).is_some_and(|(signed, _)| signed != &hash) {
+                return Err(AuditError::CheckpointMismatch(record.seq));
+            }
+
+            first_seq.get_or_insert(record.seq);
+            next_seq = Some(record.seq + 1);
+            previous = hash;
+            records += 1;
+            Ok(())
+        })?;
+
+        let last_checkpoint = checkpoints.keys().next_back().copied();
+        match (next_seq, last_checkpoint) {
+            // Signed records past the end were deleted
+            (Some(next), Some(signed)) if signed >= next => return Err(AuditError::RecordMissing(next)),
+            // Everything up to the only checkpoint was pruned; more than one
+            // checkpoint means records after the first are gone
+            (None, Some(_)) if checkpoints.len() > 1 => {
+                let first = *checkpoints.keys().next().unwrap();
+                return Err(AuditError::RecordMissing(first + 1));
+            }
+            // Retention would not have cut at a recent checkpoint
+            (None, Some(signed)) if checkpoints[&signed].1 >= cutoff => {
+                return Err(AuditError::RecordMissing(signed));
+            }
+            _ => {}
+        }
+
+        let unsigned_records = match (next_seq, last_checkpoint) {
+            (Some(next), Some(signed)) => next - (signed + 1).max(first_seq.unwrap_or(0)),
+            (Some(_), None) => records,
+            (None, _) => 0,
+        };
+
+        Ok(ChainVerification {
+            first_seq,
+            records,
+            checkpoints: checkpoints.len() as u64,
+            unsigned_records,
+        })
+    }
+
+    fn write_checkpoint(
+        &self,
+        storage: &mut AuditStorage,
+        timestamp: DateTime<Utc>,
+    ) -> Result<AuditCheckpoint, AuditError> {
+        let seq = storage.next_seq
+            .checked_sub(1)
+            .ok_or(AuditError::RecordingFailed)?;
+        let message = checkpoint_message(seq, &storage.head, timestamp);
+        let signature = Dilithium::sign(&self.signing_key, &message)
+            .map_err(|e| AuditError::SigningFailed(e.to_string()))?;
+
+        let checkpoint = AuditCheckpoint {
+            seq,
+            hash: hex::encode(storage.head),
+            timestamp,
+            signature: hex::encode(signature.to_bytes()),
+        };
+        storage.backend.write(vec![(key(CHECKPOINT_PREFIX, seq), encode(&checkpoint)?)], Vec::new())?;
+        storage.since_checkpoint = 0;
+
+        Ok(checkpoint)
+    }
+
+    /// Drop every record up to the newest checkpoint older than the
+    /// retention period, keeping that checkpoint as the new start of the
+    /// chain
+    async fn cleanup_old_events(&self, storage: &mut AuditStorage) -> Result<(), AuditError> {
         let cutoff = Utc::now() - self.retention_period;
-        
-        // Remove old events
-        storage.events.retain(|event| event.timestamp >= cutoff);
-        
-        // Update index
-        storage.index.retain(|date, indices| {
-            if date < &cutoff.date().and_hms(0, 0, 0) {
-                false
-            } else {
-                // Update indices to account for removed events
-                indices.retain(|&index| index < storage.events.len());
-                !indices.is_empty()
+
+        let mut cut = None;
+        let mut deletes = Vec::new();
+        storage.backend.scan(CHECKPOINT_PREFIX, &prefix_end(CHECKPOINT_PREFIX), |_, value| {
+            let checkpoint: AuditCheckpoint = decode(value)?;
+            if checkpoint.timestamp < cutoff {
+                if let Some(previous) = cut.replace(checkpoint.seq) {
+                    deletes.push(key(CHECKPOINT_PREFIX, previous));
+                }
             }
-        });
+            Ok(())
+        })?;
+        let Some(cut) = cut else {
+            return Ok(());
+        };
+
+        storage.backend.scan(EVENT_PREFIX, &key(EVENT_PREFIX, cut + 1), |event_key, value| {
+            let record: ChainedRecord = decode(value)?;
+            deletes.push(time_key(record.event.timestamp, record.seq));
+            deletes.push(event_key.to_vec());
+            Ok(())
+        })?;
+
+        storage.backend.write(Vec::new(), deletes)
     }
 
     pub fn get_current_session(&self) -> Uuid {
@@ -230,31 +550,246 @@ impl Drop for AuditSystem {
             session_id: Some(self.current_session),
         };
 
-        if let Ok(mut storage) = futures::executor::block_on(self.storage.write()) {
-            storage.events.push(shutdown_event);
+        // Never block here; a drop can run inside the runtime
+        if let Ok(mut storage) = self.storage.try_write() {
+            if storage.append(&shutdown_event).is_ok() {
+                let _ = self.write_checkpoint(&mut storage, Utc::now());
+            }
         }
     }
 }
 
+impl AuditStorage {
+    /// Pick up the head of an existing log
+    fn load(backend: Backend) -> Result<Self, AuditError> {
+        let last_checkpoint: Option<AuditCheckpoint> = backend
+            .last(CHECKPOINT_PREFIX)?
+            .map(|value| decode(&value))
+            .transpose()?;
+        let last_record: Option<ChainedRecord> = backend
+            .last(EVENT_PREFIX)?
+            .map(|value| decode(&value))
+            .transpose()?;
+
+        let (next_seq, head) = match (&last_record, &last_checkpoint) {
+            (Some(record), _) => (record.seq + 1, decode_hash(&record.hash)?),
+            // Everything up to the checkpoint was pruned
+            (None, Some(checkpoint)) => (checkpoint.seq + 1, decode_hash(&checkpoint.hash)?),
+            (None, None) => (0, [0u8; 32]),
+        };
+        let since_checkpoint = next_seq - last_checkpoint.map_or(0, |checkpoint| checkpoint.seq + 1);
+
+        Ok(Self {
+            backend,
+            next_seq,
+            head,
+            since_checkpoint,
+        })
+    }
+
+    fn append(&mut self, event: &AuditEvent) -> Result<(), AuditError> {
+        let seq = self.next_seq;
+        let hash = chain_hash(&self.head, seq, event)?;
+        let record = ChainedRecord {
+            seq,
+            hash: hex::encode(hash),
+            event: event.clone(),
+        };
+
+        self.backend.write(
+            vec![
+                (key(EVENT_PREFIX, seq), encode(&record)?),
+                (time_key(event.timestamp, seq), Vec::new()),
+            ],
+            Vec::new(),
+        )?;
+
+        self.next_seq += 1;
+        self.head = hash;
+        self.since_checkpoint += 1;
+        Ok(())
+    }
+
+    fn record(&self, seq: u64) -> Result<Option<ChainedRecord>, AuditError> {
+        self.backend
+            .get(&key(EVENT_PREFIX, seq))?
+            .map(|value| decode(&value))
+            .transpose()
+    }
+}
+
+impl Backend {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, AuditError> {
+        match self {
+            Backend::Memory(map) => Ok(map.get(key).cloned()),
+            Backend::RocksDb(db) => db
+                .get_cf(column_family(db)?, key)
+                .map_err(|e| AuditError::StorageError(e.to_string())),
+        }
+    }
+
+    /// Visit keys in `[from, to)` in order
+    fn scan<F>(&self, from: &[u8], to: &[u8], mut visit: F) -> Result<(), AuditError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), AuditError>,
+    {
+        match self {
+            Backend::Memory(map) => {
+                for (key, value) in map.range(from.to_vec()..to.to_vec()) {
+                    visit(key, value)?;
+                }
+            }
+            Backend::RocksDb(db) => {
+                let iter = db.iterator_cf(column_family(db)?, IteratorMode::From(from, Direction::Forward));
+                for item in iter {
+                    let (key, value) = item
+                        .map_err(|e| AuditError::StorageError(e.to_string()))?;
+                    if key.as_ref() >= to {
+                        break;
+                    }
+                    visit(&key, &value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of the greatest key starting with `prefix`
+    fn last(&self, prefix: &[u8]) -> Result<Option<Vec<u8>>, AuditError> {
+        let end = prefix_end(prefix);
+        let entry = match self {
+            Backend::Memory(map) => map
+                .range(prefix.to_vec()..end)
+                .next_back()
+                .map(|(key, value)| (key.clone(), value.clone())),
+            Backend::RocksDb(db) => db
+                .iterator_cf(column_family(db)?, IteratorMode::From(&end, Direction::Reverse))
+                .next()
+                .transpose()
+                .map_err(|e| AuditError::StorageError(e.to_string()))?
+                .map(|(key, value)| (key.to_vec(), value.to_vec())),
+        };
+        Ok(entry
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(_, value)| value))
+    }
+
+    /// Apply all puts and deletes atomically
+    fn write(&mut self, puts: Vec<(Vec<u8>, Vec<u8>)>, deletes: Vec<Vec<u8>>) -> Result<(), AuditError> {
+        match self {
+            Backend::Memory(map) => {
+                for key in deletes {
+                    map.remove(&key);
+                }
+                map.extend(puts);
+            }
+            Backend::RocksDb(db) => {
+                let cf = column_family(db)?;
+                let mut batch = WriteBatch::default();
+                for key in deletes {
+                    batch.delete_cf(cf, key);
+                }
+                for (key, value) in puts {
+                    batch.put_cf(cf, key, value);
+                }
+                db.write(batch)
+                    .map_err(|e| AuditError::StorageError(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn column_family(db: &DB) -> Result<&rocksdb::ColumnFamily, AuditError> {
+    db.cf_handle(AUDIT_CF)
+        .ok_or_else(|| AuditError::StorageError(format!("Missing column family {}", AUDIT_CF)))
+}
+
+fn chain_hash(previous: &[u8; 32], seq: u64, event: &AuditEvent) -> Result<[u8; 32], AuditError> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(previous);
+    hasher.update(seq.to_be_bytes());
+    hasher.update(encode(event)?);
+    Ok(hasher.finalize().into())
+}
+
+fn checkpoint_message(seq: u64, hash: &[u8; 32], timestamp: DateTime<Utc>) -> Vec<u8> {
+    let mut message = b"freeghost-audit-checkpoint".to_vec();
+    message.extend_from_slice(&seq.to_be_bytes());
+    message.extend_from_slice(hash);
+    message.extend_from_slice(&timestamp.timestamp_millis().to_be_bytes());
+    message
+}
+
+fn key(prefix: &[u8], seq: u64) -> Vec<u8> {
+    [prefix, &seq.to_be_bytes()].concat()
+}
+
+fn time_key(timestamp: DateTime<Utc>, seq: u64) -> Vec<u8> {
+    let millis = timestamp.timestamp_millis().max(0) as u64;
+    [TIME_PREFIX, &millis.to_be_bytes(), &seq.to_be_bytes()].concat()
+}
+
+fn seq_from_key(key: &[u8]) -> Result<u64, AuditError> {
+    key.len()
+        .checked_sub(8)
+        .and_then(|start| key[start..].try_into().ok())
+        .map(u64::from_be_bytes)
+        .ok_or_else(|| AuditError::StorageError("Invalid audit key".into()))
+}
+
+/// Smallest key greater than every key starting with `prefix`
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    *end.last_mut().expect("non-empty prefix") += 1;
+    end
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, AuditError> {
+    serde_json::to_vec(value).map_err(|e| AuditError::StorageError(e.to_string()))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, AuditError> {
+    serde_json::from_slice(bytes).map_err(|e| AuditError::StorageError(e.to_string()))
+}
+
+fn decode_hash(hash: &str) -> Result<[u8; 32], AuditError> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| AuditError::StorageError("Invalid audit hash".into()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Read-modify-write the raw record `seq` in the in-memory backend
+    async fn tamper(audit_system: &AuditSystem, seq: u64, change: impl FnOnce(&mut Option<Vec<u8>>)) {
+        let mut storage = audit_system.storage.write().await;
+        let Backend::Memory(map) = &mut storage.backend else { unreachable!() };
+        let mut value = map.remove(&key(EVENT_PREFIX, seq));
+        change(&mut value);
+        if let Some(value) = value {
+            map.insert(key(EVENT_PREFIX, seq), value);
+        }
+    }
+
     #[tokio::test]
     async fn test_audit_event_recording() {
         let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
-        
+
         let event_id = audit_system.record_event(
             AuditEventType::KeyGeneration,
             Some(Uuid::new_v4()),
             None,
         ).await.unwrap();
-        
+
         let events = audit_system.get_events(
             Utc::now() - chrono::Duration::hours(1),
             Utc::now(),
         ).await.unwrap();
-        
+
         assert!(!events.is_empty());
         assert!(events.iter().any(|e| e.id == event_id));
     }
@@ -262,7 +797,7 @@ mod tests {
     #[tokio::test]
     async fn test_audit_summary() {
         let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
-        
+
         // Record multiple events
         for _ in 0..5 {
             audit_system.record_event(
@@ -271,48 +806,211 @@ mod tests {
                 None,
             ).await.unwrap();
         }
-        
+
         audit_system.record_event(
             AuditEventType::AnomalyDetected { severity: AnomalySeverity::High },
             None,
             None,
         ).await.unwrap();
-        
+
         let summary = audit_system.get_summary(
             Utc::now() - chrono::Duration::hours(1),
             Utc::now(),
         ).await.unwrap();
-        
-        assert_eq!(summary.total_events, 6);
+
+        // Plus the startup event
+        assert_eq!(summary.total_events, 7);
         assert_eq!(summary.anomalies_detected, 1);
     }
 
+    #[tokio::test]
+    async fn test_query_filters() {
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        let component = Uuid::new_v4();
+        let mut feed = audit_system.subscribe();
+
+        audit_system.record_event(AuditEventType::AuthenticationAttempt { success: false }, Some(component), None).await.unwrap();
+        audit_system.record_event(AuditEventType::AuthenticationAttempt { success: true }, None, None).await.unwrap();
+        audit_system.record_event(AuditEventType::KeyRotation, Some(component), None).await.unwrap();
+
+        let by_type = audit_system.query(&AuditFilter {
+            event_type: Some("AuthenticationAttempt".into()),
+            ..AuditFilter::default()
+        }).await.unwrap();
+        assert_eq!(by_type.len(), 2);
+
+        let by_component = audit_system.query(&AuditFilter {
+            component_id: Some(component),
+            limit: Some(1),
+            ..AuditFilter::default()
+        }).await.unwrap();
+        assert_eq!(by_component.len(), 1);
+        assert_eq!(by_component[0].event_type.name(), "AuthenticationAttempt");
+
+        let other_session = audit_system.query(&AuditFilter {
+            session_id: Some(Uuid::new_v4()),
+            ..AuditFilter::default()
+        }).await.unwrap();
+        assert!(other_session.is_empty());
+
+        assert_eq!(feed.recv().await.unwrap().component_id, Some(component));
+    }
+
     #[tokio::test]
     async fn test_retention_period() {
         let audit_system = AuditSystem::new(1, SecurityLevel::Standard);
-        
-        // Record old event
+
+        // Record old events and a checkpoint from before the retention period
         let old_time = Utc::now() - chrono::Duration::days(2);
-        let mut storage = audit_system.storage.write().await;
-        storage.events.push(AuditEvent {
-            id: Uuid::new_v4(),
-            event_type: AuditEventType::KeyGeneration,
-            timestamp: old_time,
-            security_level: SecurityLevel::Standard,
-            component_id: None,
-            metadata: None,
-            session_id: None,
-        });
-        
+        {
+            let mut storage = audit_system.storage.write().await;
+            for _ in 0..3 {
+                storage.append(&AuditEvent {
+                    id: Uuid::new_v4(),
+                    event_type: AuditEventType::KeyGeneration,
+                    timestamp: old_time,
+                    security_level: SecurityLevel::Standard,
+                    component_id: None,
+                    metadata: None,
+                    session_id: None,
+                }).unwrap();
+            }
+            audit_system.write_checkpoint(&mut storage, old_time).unwrap();
+        }
+        audit_system.record_event(AuditEventType::KeyRotation, None, None).await.unwrap();
+
         // Trigger cleanup
-        audit_system.cleanup_old_events(&mut storage).await;
-        
-        // Verify old event was removed
+        let mut storage = audit_system.storage.write().await;
+        audit_system.cleanup_old_events(&mut storage).await.unwrap();
+        drop(storage);
+
+        // Verify old events were removed and the rest still verifies
         let events = audit_system.get_events(
             old_time,
             Utc::now(),
         ).await.unwrap();
-        
-        assert!(events.is_empty());
+
+        assert_eq!(events.len(), 1);
+        let verification = audit_system.verify_chain().await.unwrap();
+        assert_eq!(verification.first_seq, Some(4));
+        assert_eq!(verification.records, 1);
+    }
+
+    #[tokio::test]
+    async fn test_chain_verifies() {
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        for _ in 0..4 {
+            audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+        }
+        audit_system.checkpoint().await.unwrap().unwrap();
+        audit_system.record_event(AuditEventType::KeyRotation, None, None).await.unwrap();
+
+        let verification = audit_system.verify_chain().await.unwrap();
+        assert_eq!(verification.records, 6);
+        assert_eq!(verification.checkpoints, 1);
+        assert_eq!(verification.unsigned_records, 1);
+    }
+
+    #[tokio::test]
+    async fn test_modified_record_detected() {
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        for _ in 0..4 {
+            audit_system.record_event(AuditEventType::AuthenticationAttempt { success: false }, None, None).await.unwrap();
+        }
+
+        tamper(&audit_system, 2, |value| {
+            let mut record: ChainedRecord = decode(value.as_ref().unwrap()).unwrap();
+            record.event.event_type = AuditEventType::AuthenticationAttempt { success: true };
+            *value = Some(encode(&record).unwrap());
+        }).await;
+
+        assert!(matches!(audit_system.verify_chain().await, Err(AuditError::RecordModified(2))));
+    }
+
+    #[tokio::test]
+    async fn test_rehashed_records_fail_checkpoint() {
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        for _ in 0..4 {
+            audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+        }
+        audit_system.checkpoint().await.unwrap();
+
+        // Rewrite the signed record and fix up its hash, so the chain alone
+        // is consistent
+        let previous = {
+            let storage = audit_system.storage.read().await;
+            decode_hash(&storage.record(3).unwrap().unwrap().hash).unwrap()
+        };
+        tamper(&audit_system, 4, |value| {
+            let mut record: ChainedRecord = decode(value.as_ref().unwrap()).unwrap();
+            record.event.event_type = AuditEventType::KeyRotation;
+            record.hash = hex::encode(chain_hash(&previous, 4, &record.event).unwrap());
+            *value = Some(encode(&record).unwrap());
+        }).await;
+
+        assert!(matches!(audit_system.verify_chain().await, Err(AuditError::CheckpointMismatch(4))));
+    }
+
+    #[tokio::test]
+    async fn test_deleted_record_detected() {
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        for _ in 0..4 {
+            audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+        }
+        audit_system.checkpoint().await.unwrap();
+
+        tamper(&audit_system, 1, |value| *value = None).await;
+        assert!(matches!(audit_system.verify_chain().await, Err(AuditError::RecordMissing(1))));
+
+        // Deleting the signed tail is caught by the checkpoint
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+        audit_system.checkpoint().await.unwrap();
+        tamper(&audit_system, 1, |value| *value = None).await;
+        assert!(matches!(audit_system.verify_chain().await, Err(AuditError::RecordMissing(1))));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_deleted_recent_prefix_detected() {
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        for _ in 0..4 {
+            audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+        }
+        let checkpoint = audit_system.checkpoint().await.unwrap().unwrap();
+        audit_system.record_event(AuditEventType::KeyRotation, None, None).await.unwrap();
+
+        // Cut at a checkpoint that is still within retention, as if pruned
+        for seq in 0..=checkpoint.seq {
+            tamper(&audit_system, seq, |value| *value = None).await;
+        }
+        assert!(matches!(
+            audit_system.verify_chain().await,
+            Err(AuditError::RecordMissing(seq)) if seq == checkpoint.seq
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_persisted_log_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let open_db = || {
+            let mut opts = rocksdb::Options::default();
+            opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+            Arc::new(DB::open_cf(&opts, dir.path(), [AUDIT_CF]).unwrap())
+        };
+        let (_, signing_key) = Dilithium::keygen().unwrap();
+
+        {
+            let audit_system = AuditSystem::open(open_db(), signing_key.clone(), 30, 2, SecurityLevel::Standard).unwrap();
+            for _ in 0..3 {
+                audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+            }
+        }
+
+        let audit_system = AuditSystem::open(open_db(), signing_key, 30, 2, SecurityLevel::Standard).unwrap();
+        let verification = audit_system.verify_chain().await.unwrap();
+        // Startup, three events, shutdown, and the new startup
+        assert_eq!(verification.records, 6);
+        assert!(verification.checkpoints >= 2);
+    }
+}
@@ -0,0 +1,410 @@
+// src/core/crypto/audit_export.rs
+
+//! Shipping audit events to external collectors
+//!
+//! Events are rendered as JSON Lines, ArcSight CEF or RFC 5424 syslog and
+//! written to a file, a UDP syslog receiver (one datagram per event) or a
+//! TCP one (RFC 6587 octet counting). `AuditExporter::stream` follows
+//! `AuditSystem::subscribe` so a SIEM sees events as they are recorded.
+
+use chrono::SecondsFormat;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+use super::audit::{AnomalySeverity, AuditError, AuditEvent, AuditEventType};
+
+const CEF_VENDOR: &str = "FreeGhost";
+const CEF_PRODUCT: &str = "node";
+const APP_NAME: &str = "freeghost";
+// Enterprise number reserved for documentation (RFC 5612)
+const SD_ID: &str = "freeghost@32473";
+// log audit
+const SYSLOG_FACILITY: u8 = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    JsonLines,
+    Cef,
+    Syslog,
+}
+
+impl FromStr for ExportFormat {
+    type Err = AuditError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" | "json" => Ok(ExportFormat::JsonLines),
+            "cef" => Ok(ExportFormat::Cef),
+            "syslog" => Ok(ExportFormat::Syslog),
+            other => Err(AuditError::ExportFailed(format!("Unknown export format {}", other))),
+        }
+    }
+}
+
+/// Where exported events go: `file:<path>`, `udp://host:port` or
+/// `tcp://host:port`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    File(PathBuf),
+    Udp(String),
+    Tcp(String),
+}
+
+impl FromStr for ExportTarget {
+    type Err = AuditError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("file:") {
+            Ok(ExportTarget::File(PathBuf::from(path)))
+        } else if let Some(addr) = s.strip_prefix("udp://") {
+            Ok(ExportTarget::Udp(addr.to_string()))
+        } else if let Some(addr) = s.strip_prefix("tcp://") {
+            Ok(ExportTarget::Tcp(addr.to_string()))
+        } else {
+            Err(AuditError::ExportFailed(format!("Unknown export target {}", s)))
+        }
+    }
+}
+
+/// Render one event without a trailing newline
+pub fn format_event(event: &AuditEvent, format: ExportFormat, hostname: &str) -> Result<String, AuditError> {
+    match format {
+        ExportFormat::JsonLines => serde_json::to_string(event)
+            .map_err(|e| AuditError::ExportFailed(e.to_string())),
+        ExportFormat::Cef => Ok(format_cef(event, hostname)),
+        ExportFormat::Syslog => Ok(format_syslog(event, hostname)),
+    }
+}
+
+fn format_cef(event: &AuditEvent, hostname: &str) -> String {
+    let name = event.event_type.name();
+    let mut extensions = vec![
+        format!("rt={}", event.timestamp.timestamp_millis()),
+        format!("externalId={}", event.id),
+        format!("dvchost={}", cef_value(hostname)),
+        format!("cs1Label=sessionId cs1={}", optional(event.session_id)),
+        format!("cs2Label=componentId cs2={}", optional(event.component_id)),
+        format!("cs3Label=securityLevel cs3={:?}", event.security_level),
+    ];
+    if let AuditEventType::AuthenticationAttempt { success } = event.event_type {
+        extensions.push(format!("outcome={}", if success { "success" } else { "failure" }));
+    }
+    if let Some(metadata) = &event.metadata {
+        extensions.push(format!("msg={}", cef_value(&metadata.to_string())));
+    }
+
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        CEF_VENDOR,
+        CEF_PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        cef_header(name),
+        cef_header(name),
+        cef_severity(&event.event_type),
+        extensions.join(" "),
+    )
+}
+
+fn format_syslog(event: &AuditEvent, hostname: &str) -> String {
+    let priority = SYSLOG_FACILITY * 8 + syslog_severity(&event.event_type);
+    let hostname = syslog_name(hostname);
+    let mut line = format!(
+        "<{}>1 {} {} {} {} {} [{} id=\"{}\" session=\"{}\" component=\"{}\" level=\"{:?}\"]",
+        priority,
+        event.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        hostname,
+        APP_NAME,
+        std::process::id(),
+        event.event_type.name(),
+        SD_ID,
+        event.id,
+        sd_value(&optional(event.session_id)),
+        sd_value(&optional(event.component_id)),
+        event.security_level,
+    );
+    if let Some(metadata) = &event.metadata {
+        line.push(' ');
+        line.push_str(&metadata.to_string());
+    }
+    line
+}
+
+/// CEF severity, 0 (lowest) to 10
+fn cef_severity(event_type: &AuditEventType) -> u8 {
+    match event_type {
+        AuditEventType::AnomalyDetected { severity } => match severity {
+            AnomalySeverity::Critical => 10,
+            AnomalySeverity::High => 8,
+            AnomalySeverity::Medium => 5,
+            AnomalySeverity::Low => 3,
+        },
+        AuditEventType::AuthenticationAttempt { success: false } => 5,
+        AuditEventType::KeyGeneration
+        | AuditEventType::KeyRotation
+        | AuditEventType::SecurityLevelChange => 3,
+        _ => 1,
+    }
+}
+
+/// RFC 5424 severity, 0 (emergency) to 7 (debug)
+fn syslog_severity(event_type: &AuditEventType) -> u8 {
+    match event_type {
+        AuditEventType::AnomalyDetected { severity } => match severity {
+            AnomalySeverity::Critical => 2,
+            AnomalySeverity::High => 3,
+            AnomalySeverity::Medium => 4,
+            AnomalySeverity::Low => 5,
+        },
+        AuditEventType::AuthenticationAttempt { success: false } => 4,
+        _ => 6,
+    }
+}
+
+fn optional(id: Option<uuid::Uuid>) -> String {
+    id.map(|id| id.to_string()).unwrap_or_else(|| "-".into())
+}
+
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+fn sd_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+/// HOSTNAME is printable ASCII without spaces, at most 255 characters
+fn syslog_name(value: &str) -> String {
+    let name: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(255)
+        .collect();
+    if name.is_empty() { "-".into() } else { name }
+}
+
+enum Sink {
+    File(File),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Sink {
+    async fn connect(target: &ExportTarget) -> Result<Self, AuditError> {
+        let sink = match target {
+            ExportTarget::File(path) => Sink::File(
+                OpenOptions::new().create(true).append(true).open(path).await.map_err(export_error)?,
+            ),
+            ExportTarget::Udp(addr) => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(export_error)?;
+                socket.connect(addr).await.map_err(export_error)?;
+                Sink::Udp(socket)
+            }
+            ExportTarget::Tcp(addr) => Sink::Tcp(TcpStream::connect(addr).await.map_err(export_error)?),
+        };
+        Ok(sink)
+    }
+
+    async fn write(&mut self, message: &str) -> Result<(), AuditError> {
+        match self {
+            Sink::File(file) => {
+                file.write_all(format!("{}\n", message).as_bytes()).await.map_err(export_error)?;
+                file.flush().await.map_err(export_error)
+            }
+            Sink::Udp(socket) => socket.send(message.as_bytes()).await.map(|_| ()).map_err(export_error),
+            // RFC 6587 octet counting: `<length> <message>`
+            Sink::Tcp(stream) => stream
+                .write_all(format!("{} {}", message.len(), message).as_bytes())
+                .await
+                .map_err(export_error),
+        }
+    }
+}
+
+pub struct AuditExporter {
+    target: ExportTarget,
+    format: ExportFormat,
+    hostname: String,
+    sink: Sink,
+}
+
+impl AuditExporter {
+    pub async fn connect(target: ExportTarget, format: ExportFormat, hostname: &str) -> Result<Self, AuditError> {
+        let sink = Sink::connect(&target).await?;
+        Ok(Self {
+            target,
+            format,
+            hostname: hostname.to_string(),
+            sink,
+        })
+    }
+
+    /// Send one event, reconnecting once if the sink has gone away
+    pub async fn send(&mut self, event: &AuditEvent) -> Result<(), AuditError> {
+        let message = format_event(event, self.format, &self.hostname)?;
+        if self.sink.write(&message).await.is_ok() {
+            return Ok(());
+        }
+        self.sink = Sink::connect(&self.target).await?;
+        self.sink.write(&message).await
+    }
+
+    pub async fn export(&mut self, events: &[AuditEvent]) -> Result<(), AuditError> {
+        for event in events {
+            self.send(event).await?;
+        }
+        Ok(())
+    }
+
+    /// Forward events from `feed` until the audit system goes away
+    pub fn stream(mut self, mut feed: broadcast::Receiver<AuditEvent>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match feed.recv().await {
+                    Ok(event) => {
+                        if let Err(e) = self.send(&event).await {
+                            error!("Failed to export audit event {}: {}", event.id, e);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Audit export fell behind, {} events not exported", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+fn export_error(e: std::io::Error) -> AuditError {
+    AuditError::ExportFailed(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::crypto::audit::AuditSystem;
+    use crate::core::crypto::quantum::SecurityLevel;
+    use chrono::{TimeZone, Utc};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    fn event(event_type: AuditEventType) -> AuditEvent {
+        AuditEvent {
+            id: Uuid::new_v4(),
+            event_type,
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+            security_level: SecurityLevel::Standard,
+            component_id: None,
+            metadata: Some(serde_json::json!({ "reason": "a=b|c" })),
+            session_id: Some(Uuid::nil()),
+        }
+    }
+
+    #[test]
+    fn test_cef_format() {
+        let line = format_event(&event(AuditEventType::AuthenticationAttempt { success: false }), ExportFormat::Cef, "node-1").unwrap();
+        assert!(line.starts_with("CEF:0|FreeGhost|node|"));
+        assert!(line.contains("|AuthenticationAttempt|AuthenticationAttempt|5|"));
+        assert!(line.contains("rt=1709294400000"));
+        assert!(line.contains("outcome=failure"));
+        assert!(line.contains("cs2=-"));
+        // `=` in extension values is escaped, `|` is not
+        assert!(line.contains(r#"msg={"reason":"a\=b|c"}"#));
+    }
+
+    #[test]
+    fn test_syslog_format() {
+        let anomaly = event(AuditEventType::AnomalyDetected { severity: AnomalySeverity::Critical });
+        let line = format_event(&anomaly, ExportFormat::Syslog, "node 1").unwrap();
+        // facility 13 * 8 + crit
+        assert!(line.starts_with("<106>1 2024-03-01T12:00:00.000Z node1 freeghost "));
+        assert!(line.contains(" AnomalyDetected [freeghost@32473 id="));
+        assert!(line.contains("session=\"00000000-0000-0000-0000-000000000000\""));
+        assert!(line.ends_with(r#"] {"reason":"a=b|c"}"#));
+
+        let json = format_event(&anomaly, ExportFormat::JsonLines, "node 1").unwrap();
+        assert_eq!(serde_json::from_str::<AuditEvent>(&json).unwrap().id, anomaly.id);
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!("udp://127.0.0.1:514".parse::<ExportTarget>().unwrap(), ExportTarget::Udp("127.0.0.1:514".into()));
+        assert_eq!("file:/var/log/audit.log".parse::<ExportTarget>().unwrap(), ExportTarget::File("/var/log/audit.log".into()));
+        assert!("http://collector".parse::<ExportTarget>().is_err());
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_udp_export() {
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = ExportTarget::Udp(listener.local_addr().unwrap().to_string());
+        let mut exporter = AuditExporter::connect(target, ExportFormat::Syslog, "node-1").await.unwrap();
+
+        exporter.export(&[event(AuditEventType::KeyRotation), event(AuditEventType::SystemStartup)]).await.unwrap();
+
+        let mut buf = [0u8; 2048];
+        let len = listener.recv(&mut buf).await.unwrap();
+        assert!(std::str::from_utf8(&buf[..len]).unwrap().contains(" KeyRotation "));
+        let len = listener.recv(&mut buf).await.unwrap();
+        assert!(std::str::from_utf8(&buf[..len]).unwrap().contains(" SystemStartup "));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_stream_export() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = ExportTarget::Tcp(listener.local_addr().unwrap().to_string());
+
+        let audit_system = AuditSystem::new(30, SecurityLevel::Standard);
+        let exporter = AuditExporter::connect(target, ExportFormat::Cef, "node-1").await.unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        exporter.stream(audit_system.subscribe());
+
+        audit_system.record_event(AuditEventType::KeyGeneration, None, None).await.unwrap();
+
+        // Octet-counted frame
+        let mut frame = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            stream.read_exact(&mut byte).await.unwrap();
+            if byte[0] == b' ' {
+                break;
+            }
+            frame.push(byte[0]);
+        }
+        let len: usize = std::str::from_utf8(&frame).unwrap().parse().unwrap();
+        let mut message = vec![0u8; len];
+        stream.read_exact(&mut message).await.unwrap();
+        let message = String::from_utf8(message).unwrap();
+        assert!(message.starts_with("CEF:0|FreeGhost|node|"));
+        assert!(message.contains("|KeyGeneration|KeyGeneration|3|"));
+    }
+
+    #[tokio::test]
+    async fn test_file_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+
+        let mut exporter = AuditExporter::connect(ExportTarget::File(path.clone()), ExportFormat::JsonLines, "node-1").await.unwrap();
+        exporter.export(&[event(AuditEventType::KeyRotation), event(AuditEventType::SystemShutdown)]).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(serde_json::from_str::<AuditEvent>(lines[1]).is_ok());
+    }
+}
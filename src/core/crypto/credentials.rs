@@ -0,0 +1,809 @@
+//! Anonymous credentials with selective disclosure
+//!
+//! The node (issuer) signs a batch of credential instances for a verified
+//! identity. Each instance carries salted commitments to the attributes and a
+//! fresh holder key; the issuer signature is a Fiat-Shamir lattice proof from
+//! `sigma.rs` over the instance body, and the holder proves possession of the
+//! instance key with the same protocol bound to the relying party's nonce.
+//!
+//! A presentation opens only the attributes the holder chooses. Integer
+//! attributes also support range predicates through hash chains: the issuer
+//! commits to `H^v(s_ge)` and `H^(max-v)(s_le)`, and the holder proves
+//! `v >= k` by revealing `H^(v-k)(s_ge)` (or `v <= k` with the other chain)
+//! without revealing `v`.
+//!
+//! Instances contain no identifier and every value in them is fresh, so
+//! presentations made from different instances cannot be linked by relying
+//! parties. Each instance is used once; `CredentialHolder::present` consumes
+//! it. The issuer sees instance bodies at issuance and could link them to
+//! the identity if it colluded with a relying party.
+
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Serialize, Deserialize};
+use sha3::{Sha3_256, Digest};
+use thiserror::Error;
+
+use super::rng::system_rng;
+use super::sigma::{self, Statement, Witness, SigmaProof};
+
+const DIGEST_LEN: usize = 32;
+const SALT_LEN: usize = 32;
+/// Largest number of instances issued in one batch
+pub const MAX_BATCH: usize = 64;
+/// Upper bound on `max` for integer attributes; also bounds chain length
+pub const MAX_INTEGER: u32 = 1 << 16;
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error("Invalid issuance request: {0}")]
+    InvalidRequest(String),
+    #[error("Issuance failed: {0}")]
+    IssuanceFailed(String),
+    #[error("Invalid presentation: {0}")]
+    InvalidPresentation(String),
+    #[error("Credential expired")]
+    Expired,
+    #[error("Unknown attribute: {0}")]
+    UnknownAttribute(String),
+    #[error("Predicate not satisfied: {0}")]
+    PredicateNotSatisfied(String),
+    #[error("No unused credential instances left")]
+    Exhausted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeValue {
+    Bool(bool),
+    Text(String),
+    /// Integer in `0..=max`; supports range predicates
+    Integer { value: u32, max: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: String,
+    pub value: AttributeValue,
+}
+
+/// Claim about an integer attribute proven without disclosing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Predicate {
+    AtLeast { attribute: String, bound: u32 },
+    AtMost { attribute: String, bound: u32 },
+}
+
+impl Predicate {
+    pub fn attribute(&self) -> &str {
+        match self {
+            Predicate::AtLeast { attribute, .. } | Predicate::AtMost { attribute, .. } => attribute,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RangeCommitment {
+    pub max: u32,
+    /// `H^value(s_ge)`
+    pub ge_anchor: [u8; DIGEST_LEN],
+    /// `H^(max - value)(s_le)`
+    pub le_anchor: [u8; DIGEST_LEN],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeCommitment {
+    pub name: String,
+    pub digest: [u8; DIGEST_LEN],
+    pub range: Option<RangeCommitment>,
+}
+
+/// Signed part of a credential instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialBody {
+    pub issuer_key_id: [u8; DIGEST_LEN],
+    /// Serialized sigma statement of the instance's holder key
+    pub holder_key: Vec<u8>,
+    /// Sorted by name
+    pub attributes: Vec<AttributeCommitment>,
+    pub expires_at: i64,
+}
+
+impl CredentialBody {
+    fn signing_bytes(&self) -> Result<Vec<u8>, CredentialError> {
+        let mut out = b"freeghost-credential-v1".to_vec();
+        serde_json::to_writer(&mut out, self)
+            .map_err(|e| CredentialError::IssuanceFailed(e.to_string()))?;
+        Ok(out)
+    }
+}
+
+/// Secret openings of one attribute commitment, known to the holder only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeOpening {
+    pub name: String,
+    pub value: AttributeValue,
+    salt: [u8; SALT_LEN],
+    /// `(s_ge, s_le)` for integer attributes
+    chain_seeds: Option<([u8; DIGEST_LEN], [u8; DIGEST_LEN])>,
+}
+
+/// One signed instance, as sent from the issuer to the holder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedInstance {
+    pub body: CredentialBody,
+    pub signature: Vec<u8>,
+    pub openings: Vec<AttributeOpening>,
+}
+
+/// Holder keys to be certified, one per requested instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuanceRequest {
+    pub holder_keys: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuerPublicKey {
+    pub statement: Vec<u8>,
+}
+
+impl IssuerPublicKey {
+    pub fn key_id(&self) -> [u8; DIGEST_LEN] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"freeghost-credential-issuer");
+        hasher.update(&self.statement);
+        hasher.finalize().into()
+    }
+}
+
+/// What the relying party asks for
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresentationRequest {
+    pub nonce: Vec<u8>,
+    pub disclose: Vec<String>,
+    pub predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisclosedAttribute {
+    pub name: String,
+    pub value: AttributeValue,
+    pub salt: [u8; SALT_LEN],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredicateProof {
+    pub predicate: Predicate,
+    /// Intermediate chain value; hashing it forward reaches the anchor
+    pub chain_value: [u8; DIGEST_LEN],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Presentation {
+    pub body: CredentialBody,
+    pub issuer_signature: Vec<u8>,
+    pub disclosed: Vec<DisclosedAttribute>,
+    pub predicates: Vec<PredicateProof>,
+    pub holder_proof: Vec<u8>,
+}
+
+/// Claims established by a valid presentation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedClaims {
+    pub disclosed: BTreeMap<String, AttributeValue>,
+    pub predicates: Vec<Predicate>,
+    pub expires_at: i64,
+}
+
+impl VerifiedClaims {
+    /// Check that every disclosure and predicate in `request` was proven
+    pub fn satisfies(&self, request: &PresentationRequest) -> bool {
+        request.disclose.iter().all(|name| self.disclosed.contains_key(name))
+            && request.predicates.iter().all(|p| self.predicates.contains(p))
+    }
+}
+
+pub struct CredentialIssuer {
+    witness: Witness,
+    statement: Statement,
+    public_key: IssuerPublicKey,
+}
+
+impl CredentialIssuer {
+    /// Create an issuer with a fresh random key
+    pub fn generate() -> Result<Self, CredentialError> {
+        Self::from_seed(&random_bytes::<32>()?)
+    }
+
+    /// Deterministically derive the issuer key from secret seed material
+    pub fn from_seed(seed: &[u8]) -> Result<Self, CredentialError> {
+        let witness = Witness::derive(seed)
+            .map_err(|e| CredentialError::IssuanceFailed(e.to_string()))?;
+
+        let mut matrix_seed = [0u8; sigma::SEED_LEN];
+        matrix_seed.copy_from_slice(&Sha3_256::new()
+            .chain_update(b"freeghost-credential-issuer-matrix")
+            .chain_update(seed)
+            .finalize());
+
+        let statement = witness.statement(matrix_seed);
+        let public_key = IssuerPublicKey { statement: statement.to_bytes() };
+
+        Ok(Self { witness, statement, public_key })
+    }
+
+    pub fn public_key(&self) -> &IssuerPublicKey {
+        &self.public_key
+    }
+
+    /// Sign one instance per holder key in `request`, all carrying `attributes`
+    pub fn issue(
+        &self,
+        request: &IssuanceRequest,
+        attributes: &[Attribute],
+        expires_at: i64,
+    ) -> Result<Vec<IssuedInstance>, CredentialError> {
+        if request.holder_keys.is_empty() || request.holder_keys.len() > MAX_BATCH {
+            return Err(CredentialError::InvalidRequest(format!(
+                "Batch size must be between 1 and {}",
+                MAX_BATCH
+            )));
+        }
+        validate_attributes(attributes)?;
+
+        let mut sorted = attributes.to_vec();
+        sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+        request.holder_keys
+            .iter()
+            .map(|holder_key| {
+                Statement::from_bytes(holder_key)
+                    .map_err(|e| CredentialError::InvalidRequest(e.to_string()))?;
+
+                let openings = sorted.iter()
+                    .map(AttributeOpening::random)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let body = CredentialBody {
+                    issuer_key_id: self.public_key.key_id(),
+                    holder_key: holder_key.clone(),
+                    attributes: openings.iter().map(AttributeOpening::commitment).collect(),
+                    expires_at,
+                };
+
+                let signature = sigma::prove(&self.statement, &self.witness, &body.signing_bytes()?)
+                    .map_err(|e| CredentialError::IssuanceFailed(e.to_string()))?
+                    .to_bytes();
+
+                Ok(IssuedInstance { body, signature, openings })
+            })
+            .collect()
+    }
+}
+
+struct HeldInstance {
+    key_material: [u8; 32],
+    body: CredentialBody,
+    signature: Vec<u8>,
+    openings: Vec<AttributeOpening>,
+}
+
+/// Holder side: requests, stores and presents credential instances
+#[derive(Default)]
+pub struct CredentialHolder {
+    pending: Vec<([u8; 32], Vec<u8>)>,
+    instances: Vec<HeldInstance>,
+}
+
+impl CredentialHolder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate `count` fresh holder keys to be certified
+    pub fn issuance_request(&mut self, count: usize) -> Result<IssuanceRequest, CredentialError> {
+        let mut holder_keys = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let key_material = random_bytes::<32>()?;
+            let witness = Witness::derive(&key_material)
+                .map_err(|e| CredentialError::InvalidRequest(e.to_string()))?;
+            let statement = witness.statement(random_bytes::<{ sigma::SEED_LEN }>()?).to_bytes();
+
+            self.pending.push((key_material, statement.clone()));
+            holder_keys.push(statement);
+        }
+
+        Ok(IssuanceRequest { holder_keys })
+    }
+
+    /// Check and store issued instances. Instances that were not requested,
+    /// are not signed by `issuer` or whose openings do not match are rejected.
+    pub fn accept(
+        &mut self,
+        issuer: &IssuerPublicKey,
+        issued: Vec<IssuedInstance>,
+    ) -> Result<(), CredentialError> {
+        for instance in issued {
+            let position = self.pending
+                .iter()
+                .position(|(_, key)| *key == instance.body.holder_key)
+                .ok_or_else(|| CredentialError::InvalidRequest("Instance was not requested".into()))?;
+
+            verify_issuer_signature(issuer, &instance.body, &instance.signature)?;
+
+            let matches = instance.openings.len() == instance.body.attributes.len()
+                && instance.openings
+                    .iter()
+                    .zip(&instance.body.attributes)
+                    .all(|(opening, commitment)| opening.commitment().matches(commitment));
+            if !matches {
+                return Err(CredentialError::InvalidPresentation("Openings do not match commitments".into()));
+            }
+
+            let (key_material, _) = self.pending.swap_remove(position);
+            self.instances.push(HeldInstance {
+                key_material,
+                body: instance.body,
+                signature: instance.signature,
+                openings: instance.openings,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Unused instances left
+    pub fn remaining(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Build a presentation for `request`, consuming one instance
+    pub fn present(&mut self, request: &PresentationRequest) -> Result<Presentation, CredentialError> {
+        let instance = self.instances.last().ok_or(CredentialError::Exhausted)?;
+
+        let opening = |name: &str| {
+            instance.openings
+                .iter()
+                .find(|o| o.name == name)
+                .ok_or_else(|| CredentialError::UnknownAttribute(name.to_string()))
+        };
+
+        let disclosed = request.disclose
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| {
+                let o = opening(name)?;
+                Ok(DisclosedAttribute { name: o.name.clone(), value: o.value.clone(), salt: o.salt })
+            })
+            .collect::<Result<Vec<_>, CredentialError>>()?;
+
+        let predicates = request.predicates
+            .iter()
+            .map(|predicate| {
+                let chain_value = opening(predicate.attribute())?.prove(predicate)?;
+                Ok(PredicateProof { predicate: predicate.clone(), chain_value })
+            })
+            .collect::<Result<Vec<_>, CredentialError>>()?;
+
+        let witness = Witness::derive(&instance.key_material)
+            .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+        let holder_statement = Statement::from_bytes(&instance.body.holder_key)
+            .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+
+        let mut presentation = Presentation {
+            body: instance.body.clone(),
+            issuer_signature: instance.signature.clone(),
+            disclosed,
+            predicates,
+            holder_proof: Vec::new(),
+        };
+
+        let context = presentation_context(&presentation, &request.nonce)?;
+        presentation.holder_proof = sigma::prove(&holder_statement, &witness, &context)
+            .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?
+            .to_bytes();
+
+        // Each instance is shown once so presentations stay unlinkable
+        self.instances.pop();
+        Ok(presentation)
+    }
+}
+
+/// Verify a presentation against the issuer key and the nonce the relying
+/// party sent. Returns the claims it establishes.
+pub fn verify_presentation(
+    presentation: &Presentation,
+    issuer: &IssuerPublicKey,
+    nonce: &[u8],
+    now: i64,
+) -> Result<VerifiedClaims, CredentialError> {
+    let body = &presentation.body;
+
+    if now > body.expires_at {
+        return Err(CredentialError::Expired);
+    }
+    verify_issuer_signature(issuer, body, &presentation.issuer_signature)?;
+
+    let commitments: BTreeMap<&str, &AttributeCommitment> = body.attributes
+        .iter()
+        .map(|c| (c.name.as_str(), c))
+        .collect();
+    let commitment = |name: &str| {
+        commitments
+            .get(name)
+            .copied()
+            .ok_or_else(|| CredentialError::UnknownAttribute(name.to_string()))
+    };
+
+    let mut disclosed = BTreeMap::new();
+    for attribute in &presentation.disclosed {
+        let expected = attribute_digest(&attribute.name, &attribute.value, &attribute.salt);
+        if commitment(&attribute.name)?.digest != expected {
+            return Err(CredentialError::InvalidPresentation(format!(
+                "Opening of {} does not match", attribute.name
+            )));
+        }
+        if disclosed.insert(attribute.name.clone(), attribute.value.clone()).is_some() {
+            return Err(CredentialError::InvalidPresentation(format!(
+                "{} disclosed twice", attribute.name
+            )));
+        }
+    }
+
+    for proof in &presentation.predicates {
+        let name = proof.predicate.attribute();
+        let range = commitment(name)?
+            .range
+            .as_ref()
+            .ok_or_else(|| CredentialError::InvalidPresentation(format!("{} is not an integer", name)))?;
+
+        let (steps, anchor) = match proof.predicate {
+            Predicate::AtLeast { bound, .. } if bound <= range.max => (bound, &range.ge_anchor),
+            Predicate::AtMost { bound, .. } if bound <= range.max => (range.max - bound, &range.le_anchor),
+            _ => return Err(CredentialError::InvalidPresentation("Bound out of range".into())),
+        };
+        if hash_chain(&proof.chain_value, steps) != *anchor {
+            return Err(CredentialError::InvalidPresentation(format!(
+                "Predicate on {} does not hold", name
+            )));
+        }
+    }
+
+    let holder_statement = Statement::from_bytes(&body.holder_key)
+        .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+    let holder_proof = SigmaProof::from_bytes(&presentation.holder_proof)
+        .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+    let context = presentation_context(presentation, nonce)?;
+
+    if !sigma::verify(&holder_statement, &holder_proof, &context) {
+        return Err(CredentialError::InvalidPresentation("Holder proof rejected".into()));
+    }
+
+    Ok(VerifiedClaims {
+        disclosed,
+        predicates: presentation.predicates.iter().map(|p| p.predicate.clone()).collect(),
+        expires_at: body.expires_at,
+    })
+}
+
+impl AttributeOpening {
+    fn random(attribute: &Attribute) -> Result<Self, CredentialError> {
+        let chain_seeds = match attribute.value {
+            AttributeValue::Integer { .. } => Some((random_bytes()?, random_bytes()?)),
+            _ => None,
+        };
+
+        Ok(Self {
+            name: attribute.name.clone(),
+            value: attribute.value.clone(),
+            salt: random_bytes()?,
+            chain_seeds,
+        })
+    }
+
+    fn commitment(&self) -> AttributeCommitment {
+        let range = match (&self.value, &self.chain_seeds) {
+            (AttributeValue::Integer { value, max }, Some((ge_seed, le_seed))) => Some(RangeCommitment {
+                max: *max,
+                ge_anchor: hash_chain(ge_seed, *value),
+                le_anchor: hash_chain(le_seed, max - value),
+            }),
+            _ => None,
+        };
+
+        AttributeCommitment {
+            name: self.name.clone(),
+            digest: attribute_digest(&self.name, &self.value, &self.salt),
+            range,
+        }
+    }
+
+    /// Chain value proving `predicate`, if it holds for this attribute
+    fn prove(&self, predicate: &Predicate) -> Result<[u8; DIGEST_LEN], CredentialError> {
+        let (value, max, (ge_seed, le_seed)) = match (&self.value, &self.chain_seeds) {
+            (AttributeValue::Integer { value, max }, Some(seeds)) => (*value, *max, seeds),
+            _ => return Err(CredentialError::PredicateNotSatisfied(format!("{} is not an integer", self.name))),
+        };
+
+        match *predicate {
+            Predicate::AtLeast { bound, .. } if bound <= value => Ok(hash_chain(ge_seed, value - bound)),
+            Predicate::AtMost { bound, .. } if value <= bound && bound <= max => Ok(hash_chain(le_seed, bound - value)),
+            _ => Err(CredentialError::PredicateNotSatisfied(format!("{:?}", predicate))),
+        }
+    }
+}
+
+impl AttributeCommitment {
+    fn matches(&self, other: &Self) -> bool {
+        let range_matches = match (&self.range, &other.range) {
+            (Some(a), Some(b)) => a.max == b.max && a.ge_anchor == b.ge_anchor && a.le_anchor == b.le_anchor,
+            (None, None) => true,
+            _ => false,
+        };
+        self.name == other.name && self.digest == other.digest && range_matches
+    }
+}
+
+fn validate_attributes(attributes: &[Attribute]) -> Result<(), CredentialError> {
+    if attributes.is_empty() {
+        return Err(CredentialError::InvalidRequest("No attributes".into()));
+    }
+
+    let mut names = BTreeSet::new();
+    for attribute in attributes {
+        if !names.insert(attribute.name.as_str()) {
+            return Err(CredentialError::InvalidRequest(format!("Duplicate attribute {}", attribute.name)));
+        }
+        if let AttributeValue::Integer { value, max } = attribute.value {
+            if max > MAX_INTEGER || value > max {
+                return Err(CredentialError::InvalidRequest(format!("{} out of range", attribute.name)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_issuer_signature(
+    issuer: &IssuerPublicKey,
+    body: &CredentialBody,
+    signature: &[u8],
+) -> Result<(), CredentialError> {
+    if body.issuer_key_id != issuer.key_id() {
+        return Err(CredentialError::InvalidPresentation("Unknown issuer".into()));
+    }
+
+    let statement = Statement::from_bytes(&issuer.statement)
+        .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+    let signature = SigmaProof::from_bytes(signature)
+        .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+
+    if !sigma::verify(&statement, &signature, &body.signing_bytes()?) {
+        return Err(CredentialError::InvalidPresentation("Issuer signature rejected".into()));
+    }
+    Ok(())
+}
+
+/// Fiat-Shamir context for the holder proof: everything shown plus the nonce
+fn presentation_context(presentation: &Presentation, nonce: &[u8]) -> Result<Vec<u8>, CredentialError> {
+    let shown = serde_json::to_vec(&(&presentation.disclosed, &presentation.predicates))
+        .map_err(|e| CredentialError::InvalidPresentation(e.to_string()))?;
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"freeghost-credential-presentation-v1");
+    hasher.update(&(nonce.len() as u64).to_le_bytes());
+    hasher.update(nonce);
+    hasher.update(&presentation.body.signing_bytes()?);
+    hasher.update(&presentation.issuer_signature);
+    hasher.update(&shown);
+    Ok(hasher.finalize().to_vec())
+}
+
+fn attribute_digest(name: &str, value: &AttributeValue, salt: &[u8; SALT_LEN]) -> [u8; DIGEST_LEN] {
+    let value = serde_json::to_vec(value).unwrap_or_default();
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"freeghost-credential-attribute");
+    hasher.update(&(name.len() as u64).to_le_bytes());
+    hasher.update(name.as_bytes());
+    hasher.update(&(value.len() as u64).to_le_bytes());
+    hasher.update(&value);
+    hasher.update(salt);
+    hasher.finalize().into()
+}
+
+/// `H^steps(start)`
+fn hash_chain(start: &[u8; DIGEST_LEN], steps: u32) -> [u8; DIGEST_LEN] {
+    let mut current = *start;
+    for _ in 0..steps {
+        current = Sha3_256::new()
+            .chain_update(b"freeghost-credential-chain")
+            .chain_update(current)
+            .finalize()
+            .into();
+    }
+    current
+}
+
+fn random_bytes<const LEN: usize>() -> Result<[u8; LEN], CredentialError> {
+    let mut out = [0u8; LEN];
+    system_rng()
+        .fill(&mut out)
+        .map_err(|_| CredentialError::IssuanceFailed("Failed to generate randomness".into()))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn attributes() -> Vec<Attribute> {
+        vec![
+            Attribute { name: "verified_human".into(), value: AttributeValue::Bool(true) },
+            Attribute { name: "enrolled_day".into(), value: AttributeValue::Integer { value: 120, max: 1000 } },
+            Attribute { name: "risk_percent".into(), value: AttributeValue::Integer { value: 12, max: 100 } },
+            Attribute { name: "region".into(), value: AttributeValue::Text("eu".into()) },
+        ]
+    }
+
+    fn issue(issuer: &CredentialIssuer, count: usize) -> CredentialHolder {
+        let mut holder = CredentialHolder::new();
+        let request = holder.issuance_request(count).unwrap();
+        let issued = issuer.issue(&request, &attributes(), NOW + 3600).unwrap();
+        holder.accept(issuer.public_key(), issued).unwrap();
+        holder
+    }
+
+    fn request() -> PresentationRequest {
+        PresentationRequest {
+            nonce: b"relying party nonce".to_vec(),
+            disclose: vec!["verified_human".into()],
+            predicates: vec![
+                Predicate::AtMost { attribute: "enrolled_day".into(), bound: 200 },
+                Predicate::AtMost { attribute: "risk_percent".into(), bound: 20 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_selective_disclosure() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let mut holder = issue(&issuer, 1);
+
+        let request = request();
+        let presentation = holder.present(&request).unwrap();
+        let claims = verify_presentation(&presentation, issuer.public_key(), &request.nonce, NOW).unwrap();
+
+        assert!(claims.satisfies(&request));
+        assert_eq!(claims.disclosed.len(), 1);
+        assert_eq!(claims.disclosed["verified_human"], AttributeValue::Bool(true));
+
+        // Undisclosed values never appear in the presentation
+        let encoded = serde_json::to_string(&presentation).unwrap();
+        assert!(!encoded.contains("\"eu\""));
+    }
+
+    #[test]
+    fn test_range_predicates() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let mut holder = issue(&issuer, 2);
+
+        let ok = PresentationRequest {
+            nonce: vec![1],
+            disclose: vec![],
+            predicates: vec![
+                Predicate::AtLeast { attribute: "enrolled_day".into(), bound: 120 },
+                Predicate::AtMost { attribute: "enrolled_day".into(), bound: 120 },
+            ],
+        };
+        let presentation = holder.present(&ok).unwrap();
+        assert!(verify_presentation(&presentation, issuer.public_key(), &[1], NOW).unwrap().satisfies(&ok));
+
+        // The holder cannot prove a false predicate
+        let false_claim = PresentationRequest {
+            nonce: vec![2],
+            disclose: vec![],
+            predicates: vec![Predicate::AtMost { attribute: "risk_percent".into(), bound: 11 }],
+        };
+        assert!(matches!(holder.present(&false_claim), Err(CredentialError::PredicateNotSatisfied(_))));
+
+        // Nor stretch a true one
+        let mut forged = holder.present(&request()).unwrap();
+        forged.predicates[1].predicate = Predicate::AtMost { attribute: "risk_percent".into(), bound: 5 };
+        assert!(verify_presentation(&forged, issuer.public_key(), &request().nonce, NOW).is_err());
+    }
+
+    #[test]
+    fn test_presentations_are_unlinkable() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let mut holder = issue(&issuer, 2);
+
+        let a = holder.present(&request()).unwrap();
+        let b = holder.present(&request()).unwrap();
+
+        assert_ne!(a.body.holder_key, b.body.holder_key);
+        assert_ne!(a.issuer_signature, b.issuer_signature);
+        assert_ne!(a.disclosed[0].salt, b.disclosed[0].salt);
+        for (x, y) in a.body.attributes.iter().zip(&b.body.attributes) {
+            assert_ne!(x.digest, y.digest);
+        }
+        assert_eq!(holder.remaining(), 0);
+        assert!(matches!(holder.present(&request()), Err(CredentialError::Exhausted)));
+    }
+
+    #[test]
+    fn test_wrong_nonce_rejected() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let mut holder = issue(&issuer, 1);
+
+        let presentation = holder.present(&request()).unwrap();
+        assert!(verify_presentation(&presentation, issuer.public_key(), b"other nonce", NOW).is_err());
+    }
+
+    #[test]
+    fn test_tampering_rejected() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let mut holder = issue(&issuer, 2);
+        let nonce = request().nonce;
+
+        let mut presentation = holder.present(&request()).unwrap();
+        presentation.disclosed[0].value = AttributeValue::Bool(false);
+        assert!(verify_presentation(&presentation, issuer.public_key(), &nonce, NOW).is_err());
+
+        let mut presentation = holder.present(&request()).unwrap();
+        presentation.body.expires_at += 1;
+        assert!(verify_presentation(&presentation, issuer.public_key(), &nonce, NOW).is_err());
+    }
+
+    #[test]
+    fn test_issuer_and_expiry_checked() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let other = CredentialIssuer::generate().unwrap();
+        let mut holder = issue(&issuer, 1);
+        let nonce = request().nonce;
+
+        let presentation = holder.present(&request()).unwrap();
+        assert!(verify_presentation(&presentation, other.public_key(), &nonce, NOW).is_err());
+        assert!(matches!(
+            verify_presentation(&presentation, issuer.public_key(), &nonce, NOW + 7200),
+            Err(CredentialError::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_holder_rejects_foreign_instances() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let other = CredentialIssuer::generate().unwrap();
+
+        let mut holder = CredentialHolder::new();
+        let request = holder.issuance_request(1).unwrap();
+        let issued = other.issue(&request, &attributes(), NOW).unwrap();
+
+        assert!(holder.accept(issuer.public_key(), issued).is_err());
+        assert_eq!(holder.remaining(), 0);
+    }
+
+    #[test]
+    fn test_invalid_issuance_requests() {
+        let issuer = CredentialIssuer::generate().unwrap();
+        let mut holder = CredentialHolder::new();
+        let request = holder.issuance_request(1).unwrap();
+
+        let mut duplicate = attributes();
+        duplicate.push(duplicate[0].clone());
+        assert!(issuer.issue(&request, &duplicate, NOW).is_err());
+
+        let out_of_range = vec![Attribute {
+            name: "n".into(),
+            value: AttributeValue::Integer { value: 5, max: 4 },
+        }];
+        assert!(issuer.issue(&request, &out_of_range, NOW).is_err());
+
+        let empty = IssuanceRequest { holder_keys: vec![] };
+        assert!(issuer.issue(&empty, &attributes(), NOW).is_err());
+    }
+}
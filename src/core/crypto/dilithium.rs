@@ -1,138 +1,190 @@
 //! CRYSTALS-Dilithium implementation for post-quantum digital signatures
 //! Based on the specification: https://pq-crystals.org/dilithium/
+//!
+//! Dilithium2 parameters over R_q = Z_q[X]/(X^256 + 1) with q = 8380417.
+//! Key generation and signing follow the specification, with hedged
+//! signing randomness. The one departure is that the public key holds `t`
+//! whole rather than its high bits `t1`: the verifier recomputes `w - c·s2`
+//! exactly, so signatures carry no hint vector. Keys are larger as a
+//! result, and encodings do not interoperate with other implementations.
+//!
+//! Arithmetic on secrets is free of data-dependent branches. The number of
+//! signing attempts depends only on rejected candidates, which are never
+//! released.
 
-use crate::utils::error::{Result, NodeError};
-use ring::rand::SystemRandom;
-use sha3::{Sha3_256, Shake256, digest::{Update, ExtendableOutput, XofReader}};
-use std::convert::TryInto;
-
-// Dilithium parameters for different security levels
-const DILITHIUM_N: usize = 256;
-const DILITHIUM_Q: i16 = 8380417;
-const DILITHIUM_K: usize = 4; // Dilithium3
-const DILITHIUM_L: usize = 4;
-const DILITHIUM_ETA: u8 = 2;
-const DILITHIUM_TAU: u8 = 39;
-const DILITHIUM_BETA: u8 = 78;
-const DILITHIUM_OMEGA: u8 = 80;
-
-/// Represents a polynomial in R_q = Z_q[X]/(X^n + 1)
-#[derive(Clone, Debug, PartialEq)]
-pub struct Polynomial {
-    coeffs: [i16; DILITHIUM_N],
-    is_ntt: bool,  // Track if polynomial is in NTT form
+use sha3::{Shake128, Shake256, digest::{Update, ExtendableOutput, XofReader}};
+use zeroize::Zeroize;
+
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::rng::{CryptoRng, system_rng},
+};
+
+const N: usize = 256;
+const Q: i32 = 8_380_417;
+/// Rows of A: length of s2, t and w
+const K: usize = 4;
+/// Columns of A: length of s1, y and z
+const L: usize = 4;
+/// Secret coefficients lie in [-ETA, ETA]
+const ETA: i32 = 2;
+/// Number of ±1 coefficients in a challenge
+const TAU: usize = 39;
+/// Upper bound on ||c·s||∞, TAU·ETA
+const BETA: i32 = 78;
+/// Masking coefficients lie in (-GAMMA1, GAMMA1]
+const GAMMA1: i32 = 1 << 17;
+/// Half the rounding range that splits w into high and low bits
+const GAMMA2: i32 = (Q - 1) / 88;
+/// Primitive 512th root of unity mod q
+const ROOT: i64 = 1753;
+/// 256^(-1) mod q, the inverse NTT's scaling
+const N_INV: i64 = 8_347_681;
+/// Give up signing after this many rejected attempts (about 4.3 expected)
+const MAX_ATTEMPTS: u16 = 1000;
+
+const SEED_LEN: usize = 32;
+/// Length of tr and μ
+const CRH_LEN: usize = 64;
+
+// Bits per packed coefficient
+const T_BITS: u32 = 23;
+const ETA_BITS: u32 = 3;
+const Z_BITS: u32 = 18;
+const W1_BITS: u32 = 6;
+
+pub const PUBLIC_KEY_LEN: usize = SEED_LEN + K * packed_len(T_BITS);
+pub const SECRET_KEY_LEN: usize = SEED_LEN + CRH_LEN + (L + K) * packed_len(ETA_BITS) + PUBLIC_KEY_LEN;
+pub const SIGNATURE_LEN: usize = SEED_LEN + L * packed_len(Z_BITS);
+
+/// Powers of ROOT in bit-reversed order, as the NTT consumes them
+const ZETAS: [i64; N] = zetas();
+
+/// Polynomial in R_q with coefficients in [0, q)
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Poly {
+    coeffs: [i32; N],
 }
 
 /// Public key for Dilithium
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PublicKey {
-    // Matrix A (k x l)
-    a: Vec<Vec<Polynomial>>,
-    // Vector t1
-    t1: Vec<Polynomial>,
+    // Seed of the matrix A
+    rho: [u8; SEED_LEN],
+    // t = A·s1 + s2
+    t: Vec<Poly>,
 }
 
 /// Secret key for Dilithium
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SecretKey {
-    // Vectors s1, s2
-    s1: Vec<Polynomial>,
-    s2: Vec<Polynomial>,
-    // Cached public key for verification
+    // Seed of the signing randomness
+    key: [u8; SEED_LEN],
+    // Hash of the public key, bound into every signed message
+    tr: [u8; CRH_LEN],
+    s1: Vec<Poly>,
+    s2: Vec<Poly>,
     public_key: PublicKey,
 }
 
 /// Signature for Dilithium
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Signature {
-    // Vector z
-    z: Vec<Polynomial>,
-    // Scalar h
-    h: Vec<u8>,
-    // Scalar c
-    c: [u8; 32],
+    // Challenge seed
+    c: [u8; SEED_LEN],
+    // Response y + c·s1
+    z: Vec<Poly>,
 }
 
-impl Polynomial {
-    /// Create a new polynomial with zero coefficients
-    fn new() -> Self {
-        Self {
-            coeffs: [0; DILITHIUM_N],
-            is_ntt: false,
-        }
+impl Poly {
+    fn zero() -> Self {
+        Self { coeffs: [0; N] }
     }
 
-    /// Create a polynomial from coefficients
-    fn from_coeffs(coeffs: [i16; DILITHIUM_N], is_ntt: bool) -> Self {
-        Self { coeffs, is_ntt }
+    fn add(&self, other: &Self) -> Self {
+        let mut out = Self::zero();
+        for i in 0..N {
+            out.coeffs[i] = reduce_once(self.coeffs[i] + other.coeffs[i]);
+        }
+        out
     }
 
-    /// Add two polynomials in R_q
-    fn add(&self, other: &Self) -> Self {
-        assert_eq!(self.is_ntt, other.is_ntt, "Polynomials must be in same form");
-        let mut result = Self::new();
-        for i in 0..DILITHIUM_N {
-            result.coeffs[i] = (self.coeffs[i] + other.coeffs[i]) % DILITHIUM_Q;
+    fn sub(&self, other: &Self) -> Self {
+        let mut out = Self::zero();
+        for i in 0..N {
+            out.coeffs[i] = reduce_once(self.coeffs[i] - other.coeffs[i] + Q);
         }
-        result.is_ntt = self.is_ntt;
-        result
+        out
     }
 
-    /// Multiply two polynomials using NTT
-    fn multiply(&self, other: &Self, ctx: &NTTContext) -> Result<Self> {
-        // Ensure both polynomials are in NTT form
+    /// Forward NTT. The result is in bit-reversed order, where products
+    /// are coefficient-wise.
+    fn ntt(&self) -> Self {
         let mut a = self.clone();
-        let mut b = other.clone();
-        
-        if !a.is_ntt {
-            a.to_ntt(ctx);
-        }
-        if !b.is_ntt {
-            b.to_ntt(ctx);
+        let mut k = 0;
+        let mut len = N / 2;
+        while len > 0 {
+            for start in (0..N).step_by(2 * len) {
+                k += 1;
+                for j in start..start + len {
+                    let t = mul(ZETAS[k], a.coeffs[j + len]);
+                    a.coeffs[j + len] = reduce_once(a.coeffs[j] - t + Q);
+                    a.coeffs[j] = reduce_once(a.coeffs[j] + t);
+                }
+            }
+            len >>= 1;
         }
+        a
+    }
 
-        // Pointwise multiplication in NTT domain
-        let mut result = Self::new();
-        for i in 0..DILITHIUM_N {
-            result.coeffs[i] = ((a.coeffs[i] as i32 * b.coeffs[i] as i32) % DILITHIUM_Q as i32) as i16;
+    /// Inverse of `ntt`, including the 1/256 scaling
+    fn inv_ntt(&self) -> Self {
+        let mut a = self.clone();
+        let mut k = N;
+        let mut len = 1;
+        while len < N {
+            for start in (0..N).step_by(2 * len) {
+                k -= 1;
+                let zeta = Q as i64 - ZETAS[k];
+                for j in start..start + len {
+                    let t = a.coeffs[j];
+                    a.coeffs[j] = reduce_once(t + a.coeffs[j + len]);
+                    a.coeffs[j + len] = mul(zeta, t - a.coeffs[j + len] + Q);
+                }
+            }
+            len <<= 1;
         }
-        result.is_ntt = true;
-        
-        Ok(result)
+        for coeff in a.coeffs.iter_mut() {
+            *coeff = mul(N_INV, *coeff);
+        }
+        a
     }
 
-    /// Convert polynomial to NTT form
-    fn to_ntt(&mut self, ctx: &NTTContext) {
-        if !self.is_ntt {
-            ctx.forward(&mut self.coeffs);
-            self.is_ntt = true;
+    /// Coefficient-wise product of two polynomials in NTT form
+    fn pointwise(&self, other: &Self) -> Self {
+        let mut out = Self::zero();
+        for i in 0..N {
+            out.coeffs[i] = mul(self.coeffs[i] as i64, other.coeffs[i]);
         }
+        out
     }
 
-    /// Convert polynomial from NTT form
-    fn from_ntt(&mut self, ctx: &NTTContext) {
-        if self.is_ntt {
-            ctx.inverse(&mut self.coeffs);
-            self.is_ntt = false;
-        }
+    /// Infinity norm of the centered representatives
+    fn norm_inf(&self) -> i32 {
+        self.coeffs.iter().map(|&c| abs(centered(c))).max().unwrap_or(0)
     }
 
-    /// Sample a polynomial with small coefficients
-    fn sample_noise(eta: u8) -> Result<Self> {
-        Ok(Self {
-            coeffs: sample_cbd(eta)?.try_into().map_err(|_| 
-                NodeError::Crypto("Invalid coefficient count".into()))?,
-            is_ntt: false,
-        })
+    fn pack(&self, out: &mut Vec<u8>, bits: u32, encode: impl Fn(i32) -> u32) {
+        pack(out, self.coeffs.iter().map(|&c| encode(c)), bits);
     }
 
-    /// Generate a random polynomial
-    fn random() -> Result<Self> {
-        Ok(Self {
-            coeffs: random_poly(DILITHIUM_Q)?.try_into().map_err(|_| 
-                NodeError::Crypto("Invalid coefficient count".into()))?,
-            is_ntt: false,
-        })
+    /// Decode `packed_len(bits)` bytes, failing if `decode` rejects a value
+    fn unpack(bytes: &[u8], bits: u32, decode: impl Fn(u32) -> Option<i32>) -> Result<Self> {
+        let mut poly = Self::zero();
+        for (coeff, value) in poly.coeffs.iter_mut().zip(unpack(bytes, bits)) {
+            *coeff = decode(value).ok_or_else(|| NodeError::Crypto("Invalid Dilithium coefficient".into()))?;
+        }
+        Ok(poly)
     }
 }
 
@@ -141,189 +193,581 @@ pub struct Dilithium;
 impl Dilithium {
     /// Generate a new key pair
     pub fn keygen() -> Result<(PublicKey, SecretKey)> {
-        let ctx = NTTContext::new();
-        let rng = SystemRandom::new();
-        
-        // Generate random seed for matrix A
-        let mut seed = [0u8; 32];
-        rng.fill(&mut seed)
-            .map_err(|_| NodeError::Crypto("Failed to generate random seed".into()))?;
-        
-        // Generate matrix A using SHAKE-256
-        let a_matrix = expand_a(&seed, DILITHIUM_K)?;
-        let mut a = vec![vec![Polynomial::new(); DILITHIUM_L]; DILITHIUM_K];
-        for i in 0..DILITHIUM_K {
-            for j in 0..DILITHIUM_L {
-                a[i][j] = Polynomial::from_coeffs(
-                    a_matrix[i][j].try_into()
-                        .map_err(|_| NodeError::Crypto("Invalid matrix dimensions".into()))?,
-                    false
-                );
-                a[i][j].to_ntt(&ctx);
+        Self::keygen_with_rng(&*system_rng())
+    }
+
+    /// Generate a key pair drawing all randomness from `rng`
+    pub fn keygen_with_rng(rng: &dyn CryptoRng) -> Result<(PublicKey, SecretKey)> {
+        let mut zeta = [0u8; SEED_LEN];
+        rng.fill(&mut zeta)?;
+
+        // ρ for A, ρ' for the secret vectors, K for signing
+        let mut seeds = [0u8; SEED_LEN + CRH_LEN + SEED_LEN];
+        shake256(&[&zeta], &mut seeds);
+        let mut rho = [0u8; SEED_LEN];
+        let mut rho_prime = [0u8; CRH_LEN];
+        let mut key = [0u8; SEED_LEN];
+        rho.copy_from_slice(&seeds[..SEED_LEN]);
+        rho_prime.copy_from_slice(&seeds[SEED_LEN..SEED_LEN + CRH_LEN]);
+        key.copy_from_slice(&seeds[SEED_LEN + CRH_LEN..]);
+
+        let s1: Vec<_> = (0..L).map(|i| sample_eta(&rho_prime, i as u16)).collect();
+        let s2: Vec<_> = (0..K).map(|i| sample_eta(&rho_prime, (L + i) as u16)).collect();
+        zeta.zeroize();
+        seeds.zeroize();
+        rho_prime.zeroize();
+
+        // t = A·s1 + s2
+        let t = mat_vec(&expand_a(&rho), &s1).iter().zip(&s2).map(|(as1, s2)| as1.add(s2)).collect();
+        let public_key = PublicKey { rho, t };
+        let tr = public_key.tr();
+
+        Ok((public_key.clone(), SecretKey { key, tr, s1, s2, public_key }))
+    }
+
+    /// Sign a message
+    pub fn sign(sk: &SecretKey, message: &[u8]) -> Result<Signature> {
+        Self::sign_with_rng(sk, message, &*system_rng())
+    }
+
+    /// Sign drawing the signing randomness from `rng`
+    pub fn sign_with_rng(sk: &SecretKey, message: &[u8], rng: &dyn CryptoRng) -> Result<Signature> {
+        let a = expand_a(&sk.public_key.rho);
+        let mu = crh(&[&sk.tr, message]);
+
+        let mut rnd = [0u8; SEED_LEN];
+        rng.fill(&mut rnd)?;
+        let mut rho_prime = crh(&[&sk.key, &rnd, &mu]);
+        rnd.zeroize();
+
+        let s1: Vec<_> = sk.s1.iter().map(Poly::ntt).collect();
+        let s2: Vec<_> = sk.s2.iter().map(Poly::ntt).collect();
+
+        for attempt in 0..MAX_ATTEMPTS {
+            // Commitment w = A·y for a fresh masking vector y
+            let y: Vec<_> = (0..L).map(|i| sample_mask(&rho_prime, attempt * L as u16 + i as u16)).collect();
+            let w = mat_vec(&a, &y);
+            let w1: Vec<_> = w.iter().map(high_bits).collect();
+
+            let c = challenge(&mu, &w1);
+            let c_hat = sample_in_ball(&c).ntt();
+
+            // z = y + c·s1 must not reveal s1, and w - c·s2 must round to
+            // the same high bits so the verifier recomputes w1
+            let z: Vec<_> = y.iter().zip(&s1).map(|(y, s1)| y.add(&c_hat.pointwise(s1).inv_ntt())).collect();
+            if z.iter().any(|z| z.norm_inf() >= GAMMA1 - BETA) {
+                continue;
+            }
+            let low_bits_fit = w.iter().zip(&s2).all(|(w, s2)| {
+                let r = w.sub(&c_hat.pointwise(s2).inv_ntt());
+                r.coeffs.iter().all(|&coeff| abs(decompose(coeff).1) < GAMMA2 - BETA)
+            });
+            if !low_bits_fit {
+                continue;
             }
+
+            rho_prime.zeroize();
+            return Ok(Signature { c, z });
+        }
+
+        rho_prime.zeroize();
+        Err(NodeError::Crypto("Signing exceeded rejection sampling attempts".into()))
+    }
+
+    /// Verify a signature
+    pub fn verify(pk: &PublicKey, message: &[u8], signature: &Signature) -> Result<bool> {
+        if pk.t.len() != K || signature.z.len() != L {
+            return Ok(false);
         }
-        
-        // Sample secret vectors s1, s2
-        let mut s1 = Vec::with_capacity(DILITHIUM_L);
-        for _ in 0..DILITHIUM_L {
-            let mut s1i = Polynomial::sample_noise(DILITHIUM_ETA)?;
-            s1i.to_ntt(&ctx);
-            s1.push(s1i);
+        if signature.z.iter().any(|z| z.norm_inf() >= GAMMA1 - BETA) {
+            return Ok(false);
         }
-        
-        let mut s2 = Vec::with_capacity(DILITHIUM_K);
-        for _ in 0..DILITHIUM_K {
-            let mut s2i = Polynomial::sample_noise(DILITHIUM_ETA)?;
-            s2i.to_ntt(&ctx);
-            s2.push(s2i);
+
+        // A·z - c·t = A·y - c·s2, which has the signer's w1 as high bits
+        let mu = crh(&[&pk.tr(), message]);
+        let c_hat = sample_in_ball(&signature.c).ntt();
+        let w1: Vec<_> = mat_vec(&expand_a(&pk.rho), &signature.z)
+            .iter()
+            .zip(&pk.t)
+            .map(|(az, t)| high_bits(&az.sub(&c_hat.pointwise(&t.ntt()).inv_ntt())))
+            .collect();
+
+        Ok(challenge(&mu, &w1) == signature.c)
+    }
+}
+
+impl PublicKey {
+    /// ρ, then t at 23 bits per coefficient
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(PUBLIC_KEY_LEN);
+        out.extend_from_slice(&self.rho);
+        for t in &self.t {
+            t.pack(&mut out, T_BITS, |c| c as u32);
         }
-        
-        // Compute t1 = As1 + s2
-        let mut t1 = Vec::with_capacity(DILITHIUM_K);
-        for i in 0..DILITHIUM_K {
-            let mut t1i = Polynomial::new();
-            for j in 0..DILITHIUM_L {
-                let prod = a[i][j].multiply(&s1[j], &ctx)?;
-                t1i = t1i.add(&prod);
-            }
-            let mut s2i = s2[i].clone();
-            s2i.to_ntt(&ctx);
-            t1i = t1i.add(&s2i);
-            t1.push(t1i);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != PUBLIC_KEY_LEN {
+            return Err(NodeError::Crypto("Invalid Dilithium public key length".into()));
         }
-        
-        let pk = PublicKey { a, t1 };
-        let sk = SecretKey {
-            s1,
-            s2,
-            public_key: pk.clone(),
-        };
-        
-        Ok((pk, sk))
+        let (rho, t) = bytes.split_at(SEED_LEN);
+        let t = t
+            .chunks_exact(packed_len(T_BITS))
+            .map(|chunk| Poly::unpack(chunk, T_BITS, |v| (v < Q as u32).then_some(v as i32)))
+            .collect::<Result<_>>()?;
+        Ok(Self { rho: rho.try_into().expect("split at SEED_LEN"), t })
     }
 
-    /// Sign a message
-    pub fn sign(sk: &SecretKey, message: &[u8]) -> Result<Signature> {
-        let ctx = NTTContext::new();
-        
-        // Hash message
-        let mut hasher = Sha3_256::new();
-        hasher.update(message);
-        let mut c = [0u8; 32];
-        c.copy_from_slice(&hasher.finalize());
-        
-        // Sample y
-        let mut y = Vec::with_capacity(DILITHIUM_L);
-        for _ in 0..DILITHIUM_L {
-            let mut yi = Polynomial::sample_noise(DILITHIUM_ETA)?;
-            yi.to_ntt(&ctx);
-            y.push(yi);
+    fn tr(&self) -> [u8; CRH_LEN] {
+        crh(&[&self.to_bytes()])
+    }
+}
+
+impl SecretKey {
+    /// K, tr, s1 and s2 at 3 bits per coefficient, then the public key
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SECRET_KEY_LEN);
+        out.extend_from_slice(&self.key);
+        out.extend_from_slice(&self.tr);
+        for s in self.s1.iter().chain(&self.s2) {
+            s.pack(&mut out, ETA_BITS, |c| (ETA - centered(c)) as u32);
         }
-        
-        // Compute w = Ay
-        let mut w = Vec::with_capacity(DILITHIUM_K);
-        for i in 0..DILITHIUM_K {
-            let mut wi = Polynomial::new();
-            for j in 0..DILITHIUM_L {
-                let prod = sk.public_key.a[i][j].multiply(&y[j], &ctx)?;
-                wi = wi.add(&prod);
-            }
-            wi.from_ntt(&ctx);
-            w.push(wi);
+        out.extend_from_slice(&self.public_key.to_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != SECRET_KEY_LEN {
+            return Err(NodeError::Crypto("Invalid Dilithium secret key length".into()));
         }
-        
-        // Compute z = y + cs1
-        let mut z = Vec::with_capacity(DILITHIUM_L);
-        for i in 0..DILITHIUM_L {
-            let mut zi = y[i].clone();
-            let mut cs1i = sk.s1[i].clone();
-            cs1i.to_ntt(&ctx);
-            zi = zi.add(&cs1i);
-            z.push(zi);
+        let (key, rest) = bytes.split_at(SEED_LEN);
+        let (tr, rest) = rest.split_at(CRH_LEN);
+        let (secrets, public_key) = rest.split_at((L + K) * packed_len(ETA_BITS));
+        let mut secrets = secrets
+            .chunks_exact(packed_len(ETA_BITS))
+            .map(|chunk| Poly::unpack(chunk, ETA_BITS, |v| (v <= 2 * ETA as u32).then(|| reduce_once(ETA - v as i32 + Q))))
+            .collect::<Result<Vec<_>>>()?;
+        let s2 = secrets.split_off(L);
+
+        let public_key = PublicKey::from_bytes(public_key)?;
+        let tr: [u8; CRH_LEN] = tr.try_into().expect("split at CRH_LEN");
+        if tr != public_key.tr() {
+            return Err(NodeError::Crypto("Dilithium secret key does not match its public key".into()));
         }
-        
-        // Compute h
-        let mut h = vec![0u8; DILITHIUM_K];
-        for i in 0..DILITHIUM_K {
-            h[i] = (w[i].coeffs[0] % 2) as u8;
+        Ok(Self { key: key.try_into().expect("split at SEED_LEN"), tr, s1: secrets, s2, public_key })
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        for s in self.s1.iter_mut().chain(self.s2.iter_mut()) {
+            s.coeffs.zeroize();
         }
-        
-        Ok(Signature { z, h, c })
     }
+}
 
-    /// Verify a signature
-    pub fn verify(pk: &PublicKey, message: &[u8], signature: &Signature) -> Result<bool> {
-        let ctx = NTTContext::new();
-        
-        // Hash message
-        let mut hasher = Sha3_256::new();
-        hasher.update(message);
-        let mut c = [0u8; 32];
-        c.copy_from_slice(&hasher.finalize());
-        
-        // Compute Az
-        let mut az = Vec::with_capacity(DILITHIUM_K);
-        for i in 0..DILITHIUM_K {
-            let mut azi = Polynomial::new();
-            for j in 0..DILITHIUM_L {
-                let prod = pk.a[i][j].multiply(&signature.z[j], &ctx)?;
-                azi = azi.add(&prod);
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretKey").field("public_key", &self.public_key).finish_non_exhaustive()
+    }
+}
+
+impl Signature {
+    /// Challenge seed, then z at 18 bits per coefficient
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SIGNATURE_LEN);
+        out.extend_from_slice(&self.c);
+        for z in &self.z {
+            z.pack(&mut out, Z_BITS, |c| (GAMMA1 - centered(c)) as u32);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != SIGNATURE_LEN {
+            return Err(NodeError::Crypto("Invalid signature length".into()));
+        }
+        let (c, z) = bytes.split_at(SEED_LEN);
+        let z = z
+            .chunks_exact(packed_len(Z_BITS))
+            .map(|chunk| Poly::unpack(chunk, Z_BITS, |v| Some(reduce_once(GAMMA1 - v as i32 + Q))))
+            .collect::<Result<_>>()?;
+        Ok(Self { c: c.try_into().expect("split at SEED_LEN"), z })
+    }
+}
+
+const fn packed_len(bits: u32) -> usize {
+    N * bits as usize / 8
+}
+
+const fn zetas() -> [i64; N] {
+    let mut zetas = [0; N];
+    let mut k = 0;
+    while k < N {
+        let mut exp = (k as u8).reverse_bits();
+        let mut base = ROOT;
+        let mut acc = 1;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = acc * base % Q as i64;
             }
-            azi.from_ntt(&ctx);
-            az.push(azi);
+            base = base * base % Q as i64;
+            exp >>= 1;
         }
-        
-        // Verify h
-        for i in 0..DILITHIUM_K {
-            if (az[i].coeffs[0] % 2) as u8 != signature.h[i] {
-                return Ok(false);
+        zetas[k] = acc;
+        k += 1;
+    }
+    zetas
+}
+
+/// `a·b mod q` for `a` and `b` in [0, q)
+fn mul(a: i64, b: i32) -> i32 {
+    (a * b as i64 % Q as i64) as i32
+}
+
+/// Reduce a value in [0, 2q) into [0, q) without branching
+fn reduce_once(a: i32) -> i32 {
+    let r = a - Q;
+    r + ((r >> 31) & Q)
+}
+
+/// Representative in (-(q-1)/2, (q-1)/2] of a value in [0, q)
+fn centered(a: i32) -> i32 {
+    a - (((Q - 1) / 2 - a) >> 31 & Q)
+}
+
+fn abs(a: i32) -> i32 {
+    let mask = a >> 31;
+    (a ^ mask) - mask
+}
+
+/// Split `r` in [0, q) into `(r1, r0)` with `r = r1·2·GAMMA2 + r0 mod q`,
+/// `r1` in [0, 43] and `r0` in [-GAMMA2, GAMMA2]
+fn decompose(r: i32) -> (i32, i32) {
+    let mut r1 = (r + 127) >> 7;
+    r1 = (r1 * 11275 + (1 << 23)) >> 24;
+    // 44 wraps to 0, r0 absorbing the difference
+    r1 ^= ((43 - r1) >> 31) & r1;
+    let mut r0 = r - r1 * 2 * GAMMA2;
+    r0 -= (((Q - 1) / 2 - r0) >> 31) & Q;
+    (r1, r0)
+}
+
+fn high_bits(poly: &Poly) -> [i32; N] {
+    poly.coeffs.map(|coeff| decompose(coeff).0)
+}
+
+/// A·v for `a` from `expand_a`, in normal form
+fn mat_vec(a: &[Vec<Poly>], v: &[Poly]) -> Vec<Poly> {
+    let v: Vec<_> = v.iter().map(Poly::ntt).collect();
+    a.iter()
+        .map(|row| row.iter().zip(&v).fold(Poly::zero(), |acc, (aij, vj)| acc.add(&aij.pointwise(vj))).inv_ntt())
+        .collect()
+}
+
+/// Expand the K x L matrix A, in NTT form, from ρ by rejection sampling
+fn expand_a(rho: &[u8; SEED_LEN]) -> Vec<Vec<Poly>> {
+    (0..K)
+        .map(|i| {
+            (0..L)
+                .map(|j| {
+                    let mut shake = Shake128::default();
+                    shake.update(rho);
+                    shake.update(&[j as u8, i as u8]);
+                    let mut reader = shake.finalize_xof();
+
+                    let mut poly = Poly::zero();
+                    let mut buf = [0u8; 3];
+                    for coeff in poly.coeffs.iter_mut() {
+                        *coeff = loop {
+                            reader.read(&mut buf);
+                            let value = u32::from_le_bytes([buf[0], buf[1], buf[2] & 0x7F, 0]);
+                            if value < Q as u32 {
+                                break value as i32;
+                            }
+                        };
+                    }
+                    poly
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Secret polynomial with coefficients uniform in [-ETA, ETA]
+fn sample_eta(seed: &[u8; CRH_LEN], nonce: u16) -> Poly {
+    let mut shake = Shake256::default();
+    shake.update(seed);
+    shake.update(&nonce.to_le_bytes());
+    let mut reader = shake.finalize_xof();
+
+    let mut poly = Poly::zero();
+    let mut filled = 0;
+    let mut byte = [0u8; 1];
+    while filled < N {
+        reader.read(&mut byte);
+        for nibble in [byte[0] & 0x0F, byte[0] >> 4] {
+            // 15 nibble values map evenly onto the 5 coefficients
+            if nibble < 15 && filled < N {
+                let nibble = nibble as i32;
+                poly.coeffs[filled] = reduce_once(ETA - (nibble - ((205 * nibble) >> 10) * 5) + Q);
+                filled += 1;
             }
         }
-        
-        Ok(true)
     }
+    poly
+}
+
+/// Masking polynomial with coefficients uniform in (-GAMMA1, GAMMA1]
+fn sample_mask(seed: &[u8; CRH_LEN], nonce: u16) -> Poly {
+    let mut shake = Shake256::default();
+    shake.update(seed);
+    shake.update(&nonce.to_le_bytes());
+    let mut bytes = [0u8; packed_len(Z_BITS)];
+    shake.finalize_xof().read(&mut bytes);
+
+    let mut poly = Poly::zero();
+    for (coeff, value) in poly.coeffs.iter_mut().zip(unpack(&bytes, Z_BITS)) {
+        *coeff = reduce_once(GAMMA1 - value as i32 + Q);
+    }
+    poly
+}
+
+/// Polynomial with TAU coefficients in {-1, 1} and the rest zero
+fn sample_in_ball(seed: &[u8; SEED_LEN]) -> Poly {
+    let mut shake = Shake256::default();
+    shake.update(seed);
+    let mut reader = shake.finalize_xof();
+
+    let mut signs = [0u8; 8];
+    reader.read(&mut signs);
+    let mut signs = u64::from_le_bytes(signs);
+
+    let mut c = Poly::zero();
+    let mut byte = [0u8; 1];
+    for i in (N - TAU)..N {
+        let j = loop {
+            reader.read(&mut byte);
+            if (byte[0] as usize) <= i {
+                break byte[0] as usize;
+            }
+        };
+        c.coeffs[i] = c.coeffs[j];
+        c.coeffs[j] = if signs & 1 == 1 { Q - 1 } else { 1 };
+        signs >>= 1;
+    }
+    c
+}
+
+/// Challenge seed H(μ || w1)
+fn challenge(mu: &[u8; CRH_LEN], w1: &[[i32; N]]) -> [u8; SEED_LEN] {
+    let mut packed = Vec::with_capacity(K * packed_len(W1_BITS));
+    for poly in w1 {
+        pack(&mut packed, poly.iter().map(|&c| c as u32), W1_BITS);
+    }
+    let mut out = [0u8; SEED_LEN];
+    shake256(&[mu, &packed], &mut out);
+    out
+}
+
+fn crh(parts: &[&[u8]]) -> [u8; CRH_LEN] {
+    let mut out = [0u8; CRH_LEN];
+    shake256(parts, &mut out);
+    out
+}
+
+fn shake256(parts: &[&[u8]], out: &mut [u8]) {
+    let mut shake = Shake256::default();
+    for part in parts {
+        shake.update(part);
+    }
+    shake.finalize_xof().read(out);
+}
+
+/// Little-endian bit packing of `bits`-bit values
+fn pack(out: &mut Vec<u8>, values: impl Iterator<Item = u32>, bits: u32) {
+    let mut acc = 0u64;
+    let mut filled = 0;
+    for value in values {
+        acc |= (value as u64) << filled;
+        filled += bits;
+        while filled >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            filled -= 8;
+        }
+    }
+}
+
+fn unpack(bytes: &[u8], bits: u32) -> impl Iterator<Item = u32> + '_ {
+    let mut acc = 0u64;
+    let mut filled = 0;
+    let mut bytes = bytes.iter();
+    std::iter::from_fn(move || {
+        while filled < bits {
+            acc |= (*bytes.next()? as u64) << filled;
+            filled += 8;
+        }
+        let value = (acc & ((1 << bits) - 1)) as u32;
+        acc >>= bits;
+        filled -= bits;
+        Some(value)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::crypto::rng::DeterministicRng;
+
+    fn random_poly(rng: &DeterministicRng) -> Poly {
+        let mut bytes = [0u8; N * 4];
+        rng.fill(&mut bytes).unwrap();
+        let mut poly = Poly::zero();
+        for (coeff, chunk) in poly.coeffs.iter_mut().zip(bytes.chunks_exact(4)) {
+            *coeff = (u32::from_le_bytes(chunk.try_into().unwrap()) % Q as u32) as i32;
+        }
+        poly
+    }
+
+    /// Negacyclic schoolbook product: X^N = -1
+    fn schoolbook(a: &Poly, b: &Poly) -> Poly {
+        let mut out = Poly::zero();
+        for i in 0..N {
+            for j in 0..N {
+                let product = a.coeffs[i] as i64 * b.coeffs[j] as i64 % Q as i64;
+                let k = (i + j) % N;
+                let product = if i + j < N { product } else { Q as i64 - product };
+                out.coeffs[k] = ((out.coeffs[k] as i64 + product) % Q as i64) as i32;
+            }
+        }
+        out
+    }
 
     #[test]
     fn test_dilithium_correctness() {
         // Generate keys
         let (pk, sk) = Dilithium::keygen().unwrap();
-        
+
         // Sign message
         let message = b"test message";
         let signature = Dilithium::sign(&sk, message).unwrap();
-        
+
         // Verify signature
         let valid = Dilithium::verify(&pk, message, &signature).unwrap();
-        
+
         assert!(valid);
     }
 
+    #[test]
+    fn test_forgeries_rejected() {
+        let (pk, sk) = Dilithium::keygen().unwrap();
+        let (other_pk, _) = Dilithium::keygen().unwrap();
+        let signature = Dilithium::sign(&sk, b"test message").unwrap();
+
+        assert!(!Dilithium::verify(&pk, b"other message", &signature).unwrap());
+        assert!(!Dilithium::verify(&other_pk, b"test message", &signature).unwrap());
+
+        let mut tampered = signature.clone();
+        tampered.c[0] ^= 1;
+        assert!(!Dilithium::verify(&pk, b"test message", &tampered).unwrap());
+
+        let mut tampered = signature.clone();
+        tampered.z[0].coeffs[0] = reduce_once(tampered.z[0].coeffs[0] + 1);
+        assert!(!Dilithium::verify(&pk, b"test message", &tampered).unwrap());
+
+        // A response too large to hide s1 is refused outright
+        let mut tampered = signature;
+        tampered.z[0].coeffs[0] = GAMMA1;
+        assert!(!Dilithium::verify(&pk, b"test message", &tampered).unwrap());
+    }
+
+    #[test]
+    fn test_encoding_roundtrip() {
+        let (pk, sk) = Dilithium::keygen().unwrap();
+        let signature = Dilithium::sign(&sk, b"test message").unwrap();
+        assert_eq!(pk.to_bytes().len(), PUBLIC_KEY_LEN);
+        assert_eq!(sk.to_bytes().len(), SECRET_KEY_LEN);
+        assert_eq!(signature.to_bytes().len(), SIGNATURE_LEN);
+
+        let pk = PublicKey::from_bytes(&pk.to_bytes()).unwrap();
+        let sk = SecretKey::from_bytes(&sk.to_bytes()).unwrap();
+        let signature = Signature::from_bytes(&signature.to_bytes()).unwrap();
+        assert!(Dilithium::verify(&pk, b"test message", &signature).unwrap());
+        assert!(Dilithium::verify(sk.public_key(), b"test message", &signature).unwrap());
+        let resigned = Dilithium::sign(&sk, b"test message").unwrap();
+        assert!(Dilithium::verify(&pk, b"test message", &resigned).unwrap());
+
+        assert!(Signature::from_bytes(&signature.to_bytes()[1..]).is_err());
+        // t coefficients must be below q
+        let mut bytes = pk.to_bytes();
+        bytes[SEED_LEN..SEED_LEN + 3].copy_from_slice(&[0xFF, 0xFF, 0x7F]);
+        assert!(PublicKey::from_bytes(&bytes).is_err());
+        // The secret key must belong to its public key
+        let mut bytes = sk.to_bytes();
+        bytes[SEED_LEN] ^= 1;
+        assert!(SecretKey::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_seeded_signing_is_reproducible() {
+        let sign = |seed: &[u8]| {
+            let rng = DeterministicRng::from_seed(seed);
+            let (pk, sk) = Dilithium::keygen_with_rng(&rng).unwrap();
+            let signature = Dilithium::sign_with_rng(&sk, b"test message", &rng).unwrap();
+            (pk.to_bytes(), signature.to_bytes())
+        };
+
+        assert_eq!(sign(b"dilithium-kat"), sign(b"dilithium-kat"));
+        assert_ne!(sign(b"dilithium-kat").0, sign(b"another seed").0);
+    }
+
     #[test]
     fn test_polynomial_operations() {
-        let p1 = Polynomial::new();
-        let p2 = Polynomial::new();
-        
-        // Test addition
-        let p3 = p1.add(&p2);
-        assert_eq!(p3.coeffs, [0; DILITHIUM_N]);
-        
-        // Test multiplication
-        let p4 = p1.multiply(&p2);
-        assert_eq!(p4.coeffs, [0; DILITHIUM_N]);
+        let rng = DeterministicRng::from_seed(b"dilithium-ntt");
+        for _ in 0..4 {
+            let a = random_poly(&rng);
+            let b = random_poly(&rng);
+            assert_eq!(a.ntt().inv_ntt(), a);
+            assert_eq!(a.ntt().pointwise(&b.ntt()).inv_ntt(), schoolbook(&a, &b));
+            assert_eq!(a.add(&b).sub(&b), a);
+        }
+
+        // X · X^255 = X^256 = -1
+        let mut x = Poly::zero();
+        x.coeffs[1] = 1;
+        let mut x255 = Poly::zero();
+        x255.coeffs[255] = 1;
+        let mut minus_one = Poly::zero();
+        minus_one.coeffs[0] = Q - 1;
+        assert_eq!(x.ntt().pointwise(&x255.ntt()).inv_ntt(), minus_one);
+    }
+
+    #[test]
+    fn test_decompose() {
+        for r in (0..Q).step_by(997).chain([0, GAMMA2, 2 * GAMMA2, Q - GAMMA2, Q - 1]) {
+            let (r1, r0) = decompose(r);
+            assert!((0..44).contains(&r1), "r1 = {} for {}", r1, r);
+            assert!(abs(r0) <= GAMMA2, "r0 = {} for {}", r0, r);
+            assert_eq!((r1 as i64 * 2 * GAMMA2 as i64 + r0 as i64).rem_euclid(Q as i64), r as i64);
+        }
     }
 
     #[test]
     fn test_noise_sampling() {
-        let p = Polynomial::sample_noise(DILITHIUM_ETA).unwrap();
-        
+        let (_, sk) = Dilithium::keygen().unwrap();
+
         // Verify coefficients are within bounds
-        for coeff in &p.coeffs {
-            assert!(coeff.abs() <= DILITHIUM_ETA as i16);
+        for s in sk.s1.iter().chain(&sk.s2) {
+            assert!(s.norm_inf() <= ETA);
         }
+        let y = sample_mask(&[7; CRH_LEN], 0);
+        assert!(y.norm_inf() <= GAMMA1);
     }
 }
@@ -1,86 +1,810 @@
-use std::sync::RwLock;
-use ring::{aead, digest, pbkdf2};
+//! Key hierarchy for data at rest
+//!
+//! The passphrase derives a key-encryption key (KEK) with Argon2id; the salt
+//! and cost parameters are stored next to the keys, so the same passphrase
+//! opens the keyring after a restart. The KEK only wraps other keys:
+//!
+//! - a root secret used for `derive_key` and `hash_features`, which must stay
+//!   stable across rotations
+//! - versioned data-encryption keys (DEKs); the newest one encrypts, every
+//!   version keeps decrypting
+//!
+//! Every ciphertext starts with a format byte and the big-endian DEK version.
+//! The AES-GCM tag covers both together with associated data supplied by the
+//! caller, so a ciphertext only decrypts in the context it was written for.
+//! Ciphertexts of the first format carry no associated data; they can only
+//! be read through `decrypt_legacy`, to migrate them.
+//!
+//! Keyrings written with PBKDF2, or with Argon2id parameters other than the
+//! configured ones, are re-wrapped on the first successful unlock. Only the
+//! wrapping changes; DEKs and therefore stored data stay the same.
+//!
+//! Instead of a passphrase the KEK can be a random key split into M-of-N
+//! Shamir shares (`split_kek`), so no single operator can unlock the node.
+//! Shares can be encrypted to each custodian's Kyber key, and `reshare`
+//! hands out a fresh set for the same KEK. Old shares still combine to the
+//! KEK mathematically; replacing a custodian for good needs `split_kek`.
+//!
+//! Alternatively the KEK can live in a `KeyStore` (`open_with_store`), e.g.
+//! an HSM, in which case it never enters process memory and every wrap and
+//! unwrap is a call into the store.
+//!
+//! The unwrapped root secret and DEKs are kept in guarded memory, and a
+//! cipher is set up from them per call. `watch_memory` registers them with
+//! the memory poison detector, including DEKs added by later rotations.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::info;
+use argon2::{Algorithm, Argon2, Params, Version};
+use parking_lot::RwLock as SyncRwLock;
+use ring::{digest, pbkdf2};
+use serde::{Serialize, Deserialize};
 use sha3::{Sha3_256, Digest};
+use uuid::Uuid;
+use zeroize::Zeroize;
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
 
-use crate::utils::error::{Result, NodeError};
+use crate::{
+    utils::{
+        config::StorageConfig,
+        error::{Result, NodeError},
+    },
+    core::crypto::{
+        keystore::KeyStore,
+        poisoning::{MemoryPoisonDetector, MemoryRegionType},
+        rng::system_rng,
+        secure_memory::Secret,
+        kyber::{KyberKEM, PublicKey as KyberPublicKey, SecretKey as KyberSecretKey},
+        serialization::{serialize_ciphertext, deserialize_ciphertext},
+        shamir::{self, Share},
+    },
+};
 
-const PBKDF2_ITERATIONS: u32 = 100_000;
 const KEY_LEN: usize = 32;
 const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// First byte of every ciphertext produced by `encrypt`
+const CIPHERTEXT_FORMAT: u8 = 2;
+/// Format of ciphertexts written before `encrypt` took associated data
+const LEGACY_CIPHERTEXT_FORMAT: u8 = 1;
+/// Format byte followed by the DEK version
+const HEADER_LEN: usize = 1 + 4;
+
+pub(crate) const KEYRING_FILE: &str = "keyring.json";
+const KEYRING_FORMAT: u32 = 1;
+/// Prefix of the text form of a key share
+const SHARE_PREFIX: &str = "fgks1";
+/// Label of the KEK inside a key store
+const STORE_KEK_LABEL: &str = "freeghost-keyring-kek";
+/// Detector region of the root secret; DEKs are `keyring-dek-<version>`
+const ROOT_REGION: &str = "keyring-root";
+
+/// Argon2id cost parameters for deriving the KEK
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory in KiB
+    pub memory_kib: u32,
+    /// Number of passes
+    pub iterations: u32,
+    /// Degree of parallelism (lanes)
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// OWASP-recommended baseline: 64 MiB, 3 passes, 1 lane
+    fn default() -> Self {
+        Self {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+impl From<&StorageConfig> for KdfParams {
+    fn from(config: &StorageConfig) -> Self {
+        Self {
+            memory_kib: config.kdf_memory_kib,
+            iterations: config.kdf_iterations,
+            parallelism: config.kdf_parallelism,
+        }
+    }
+}
+
+impl KdfParams {
+    fn argon2(&self) -> Result<Argon2<'static>> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(KEY_LEN))
+            .map_err(|e| NodeError::Crypto(format!("Invalid KDF parameters: {}", e)))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    /// Derive a 256-bit key from `passphrase`
+    pub(crate) fn derive(&self, passphrase: &str, salt: &[u8]) -> Result<Vec<u8>> {
+        let mut key = vec![0u8; KEY_LEN];
+        self.argon2()?
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| NodeError::Crypto(format!("Key derivation failed: {}", e)))?;
+        Ok(key)
+    }
+}
+
+/// Serialized keyring; holds only wrapped key material
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyringRecord {
+    format: u32,
+    kdf: KdfRecord,
+    root: String,
+    active_version: u32,
+    deks: Vec<WrappedDek>,
+}
+
+/// KDF used for the stored KEK, with its salt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm")]
+enum KdfRecord {
+    /// Legacy; only read, never written
+    #[serde(rename = "pbkdf2-hmac-sha256")]
+    Pbkdf2 { iterations: u32, salt: String },
+    #[serde(rename = "argon2id")]
+    Argon2id {
+        #[serde(flatten)]
+        params: KdfParams,
+        salt: String,
+    },
+    /// Random KEK split into Shamir shares. `shares` holds a digest of every
+    /// current share by index, so stale or corrupted shares are caught before
+    /// they are combined.
+    #[serde(rename = "shamir")]
+    Shamir {
+        threshold: u8,
+        generation: u32,
+        shares: Vec<String>,
+    },
+    /// KEK held by a key store under `label`
+    #[serde(rename = "key-store")]
+    KeyStore { backend: String, label: String },
+}
+
+impl KdfRecord {
+    fn argon2id(params: KdfParams) -> Result<Self> {
+        Ok(KdfRecord::Argon2id {
+            params,
+            salt: hex::encode(random_bytes(SALT_LEN)?),
+        })
+    }
+
+    fn uses(&self, params: &KdfParams) -> bool {
+        matches!(self, KdfRecord::Argon2id { params: current, .. } if current == params)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WrappedDek {
+    version: u32,
+    created_at: i64,
+    wrapped: String,
+}
+
+/// Unwrapped key in its own guarded region
+type GuardedKey = Arc<SyncRwLock<Secret<[u8; KEY_LEN]>>>;
+
+struct KeyState {
+    record: KeyringRecord,
+    root: GuardedKey,
+    deks: BTreeMap<u32, GuardedKey>,
+}
+
+/// Key-encryption key
+enum Kek {
+    /// Derived from a passphrase or combined from shares. The raw bytes are
+    /// kept so a share-sealed KEK can be split again without reconstructing it.
+    Local { secret: Vec<u8>, cipher: Aes256Gcm },
+    /// Held by a key store; wrapping is delegated to it
+    Store { store: Arc<dyn KeyStore>, label: String },
+}
+
+impl Kek {
+    fn new(secret: Vec<u8>) -> Result<Self> {
+        Ok(Kek::Local {
+            cipher: new_cipher(&secret)?,
+            secret,
+        })
+    }
+}
+
+impl Drop for Kek {
+    fn drop(&mut self) {
+        if let Kek::Local { secret, .. } = self {
+            for byte in secret.iter_mut() {
+                // Volatile so the wipe is not optimised away
+                unsafe { std::ptr::write_volatile(byte, 0) };
+            }
+        }
+    }
+}
+
+/// One custodian's share of a share-sealed KEK. The text form
+/// (`fgks1-<generation>-<threshold>-<index>-<hex>`) is what gets typed in at
+/// the unseal prompt.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyShare {
+    pub generation: u32,
+    pub threshold: u8,
+    pub index: u8,
+    value: String,
+}
+
+impl KeyShare {
+    fn digest(&self) -> Result<String> {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"freeghost-key-share");
+        hasher.update(self.generation.to_be_bytes());
+        hasher.update([self.index]);
+        hasher.update(decode_hex(&self.value)?);
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// Encrypt the share to a custodian's Kyber public key
+    pub fn seal(&self, custodian: &KyberPublicKey) -> Result<SealedShare> {
+        let (shared_secret, encapsulation) = KyberKEM::encapsulate(custodian)?;
+        let cipher = new_cipher(&shared_secret)?;
+        let aad = share_aad(self.generation, self.index);
+
+        Ok(SealedShare {
+            generation: self.generation,
+            index: self.index,
+            encapsulation: hex::encode(serialize_ciphertext(&encapsulation)?),
+            ciphertext: hex::encode(wrap_with(&cipher, self.to_string().as_bytes(), &aad)?),
+        })
+    }
+}
+
+impl std::fmt::Display for KeyShare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            SHARE_PREFIX, self.generation, self.threshold, self.index, self.value
+        )
+    }
+}
+
+impl std::fmt::Debug for KeyShare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("generation", &self.generation)
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for KeyShare {
+    type Err = NodeError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || NodeError::Crypto("Invalid key share".into());
+
+        let parts: Vec<&str> = s.trim().split('-').collect();
+        if parts.len() != 5 || parts[0] != SHARE_PREFIX {
+            return Err(invalid());
+        }
+        let share = KeyShare {
+            generation: parts[1].parse().map_err(|_| invalid())?,
+            threshold: parts[2].parse().map_err(|_| invalid())?,
+            index: parts[3].parse().map_err(|_| invalid())?,
+            value: parts[4].to_ascii_lowercase(),
+        };
+        if share.index == 0 || decode_hex(&share.value)?.len() != KEY_LEN {
+            return Err(invalid());
+        }
+        Ok(share)
+    }
+}
+
+/// A key share encrypted to a custodian's Kyber public key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedShare {
+    pub generation: u32,
+    pub index: u8,
+    encapsulation: String,
+    ciphertext: String,
+}
+
+impl SealedShare {
+    pub fn open(&self, secret_key: &KyberSecretKey) -> Result<KeyShare> {
+        let encapsulation = deserialize_ciphertext(&decode_hex(&self.encapsulation)?)?;
+        let shared_secret = KyberKEM::decapsulate(secret_key, &encapsulation)?;
+        let aad = share_aad(self.generation, self.index);
+
+        let plaintext = unwrap_with(&new_cipher(&shared_secret)?, &decode_hex(&self.ciphertext)?, &aad)?;
+        let share: KeyShare = String::from_utf8(plaintext)
+            .map_err(|_| NodeError::Crypto("Invalid key share".into()))?
+            .parse()?;
+
+        if share.generation != self.generation || share.index != self.index {
+            return Err(NodeError::Crypto("Sealed share does not match its header".into()));
+        }
+        Ok(share)
+    }
+}
+
+/// Seal state of a share-sealed keyring
+#[derive(Debug, Clone)]
+pub struct SealStatus {
+    pub threshold: u8,
+    pub shares: u8,
+    pub generation: u32,
+    digests: Vec<String>,
+}
+
+impl SealStatus {
+    /// Check a share against the keyring before it is combined
+    pub fn verify_share(&self, share: &KeyShare) -> Result<()> {
+        if share.generation != self.generation {
+            return Err(NodeError::Crypto(format!(
+                "Share is from generation {}, keyring is at generation {}",
+                share.generation, self.generation
+            )));
+        }
+        let expected = share
+            .index
+            .checked_sub(1)
+            .and_then(|i| self.digests.get(i as usize))
+            .ok_or_else(|| NodeError::Crypto(format!("Unknown share index {}", share.index)))?;
+
+        if share.threshold != self.threshold || &share.digest()? != expected {
+            return Err(NodeError::Crypto(format!("Share {} does not match the keyring", share.index)));
+        }
+        Ok(())
+    }
+}
 
 pub struct KeyManager {
-    master_key: RwLock<Vec<u8>>,
-    encryption_key: RwLock<Aes256Gcm>,
+    kek: Kek,
+    state: RwLock<KeyState>,
+    // Keyring location; `None` keeps everything in memory
+    path: Option<PathBuf>,
+    // Encryptions under the active version, for rotation by usage
+    uses: AtomicU64,
+    // Detector and session the keys are registered under, once watched
+    watcher: OnceLock<(Arc<MemoryPoisonDetector>, Uuid)>,
 }
 
 impl KeyManager {
+    /// Create a volatile keyring that lives only as long as this instance.
+    /// Use `open` for anything that has to survive a restart.
     pub fn new(encryption_key: &str) -> Result<Self> {
-        if encryption_key.is_empty() {
-            return Err(NodeError::Crypto("Encryption key cannot be empty".into()));
-        }
-
-        // Generate master key using PBKDF2
-        let mut salt = [0u8; SALT_LEN];
-        ring::rand::SystemRandom::new()
-            .fill(&mut salt)
-            .map_err(|_| NodeError::Crypto("Failed to generate salt".into()))?;
-
-        let mut master_key = vec![0u8; KEY_LEN];
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            std::num::NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
-            &salt,
-            encryption_key.as_bytes(),
-            &mut master_key,
-        );
+        Self::new_with_params(encryption_key, &KdfParams::default())
+    }
+
+    pub fn new_with_params(encryption_key: &str, params: &KdfParams) -> Result<Self> {
+        let (kek, state) = Self::create_keyring(encryption_key, KdfRecord::argon2id(*params)?)?;
+        Ok(Self {
+            kek,
+            state: RwLock::new(state),
+            path: None,
+            uses: AtomicU64::new(0),
+            watcher: OnceLock::new(),
+        })
+    }
+
+    /// Open the keyring stored in `dir`, creating it on first use. A keyring
+    /// wrapped with another KDF or other parameters is re-wrapped with
+    /// `params` once the passphrase has been verified.
+    pub fn open(dir: &Path, encryption_key: &str, params: &KdfParams) -> Result<Self> {
+        let path = dir.join(KEYRING_FILE);
+
+        let (kek, state) = if path.exists() {
+            let record = read_record(&path)?;
+            match &record.kdf {
+                KdfRecord::Shamir { threshold, .. } => {
+                    return Err(NodeError::Crypto(format!(
+                        "Keyring is sealed; unseal it with {} key shares",
+                        threshold
+                    )));
+                }
+                KdfRecord::KeyStore { backend, .. } => {
+                    return Err(NodeError::Crypto(format!(
+                        "Keyring KEK is held by the {} key store",
+                        backend
+                    )));
+                }
+                _ => {}
+            }
+            let kek = derive_kek(encryption_key, &record.kdf)?;
+            let state = Self::unlock(&kek, record)?;
+
+            if state.record.kdf.uses(params) {
+                (kek, state)
+            } else {
+                let kdf = KdfRecord::argon2id(*params)?;
+                let new_kek = derive_kek(encryption_key, &kdf)?;
+                let record = rewrap(&kek, &new_kek, &state, kdf)?;
+                persist(&path, &record)?;
+                info!("Re-wrapped keyring with Argon2id ({:?})", params);
+                (new_kek, KeyState { record, ..state })
+            }
+        } else {
+            fs::create_dir_all(dir)
+                .map_err(|e| NodeError::Crypto(format!("Failed to create keyring directory: {}", e)))?;
+            let (kek, state) = Self::create_keyring(encryption_key, KdfRecord::argon2id(*params)?)?;
+            persist(&path, &state.record)?;
+            (kek, state)
+        };
+
+        Ok(Self {
+            kek,
+            state: RwLock::new(state),
+            path: Some(path),
+            uses: AtomicU64::new(0),
+            watcher: OnceLock::new(),
+        })
+    }
+
+    /// Seal state of the keyring in `dir`; `None` if it is unlocked by
+    /// passphrase or does not exist yet
+    pub fn seal_status(dir: &Path) -> Result<Option<SealStatus>> {
+        let path = dir.join(KEYRING_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(match read_record(&path)?.kdf {
+            KdfRecord::Shamir { threshold, generation, shares } => Some(SealStatus {
+                threshold,
+                shares: shares.len() as u8,
+                generation,
+                digests: shares,
+            }),
+            _ => None,
+        })
+    }
+
+    /// Whether the keyring in `dir` exists and is unlocked by passphrase
+    pub fn uses_passphrase(dir: &Path) -> Result<bool> {
+        let path = dir.join(KEYRING_FILE);
+        if !path.exists() {
+            return Ok(false);
+        }
+        Ok(matches!(
+            read_record(&path)?.kdf,
+            KdfRecord::Pbkdf2 { .. } | KdfRecord::Argon2id { .. }
+        ))
+    }
+
+    /// Unlock a share-sealed keyring with at least `threshold` of its shares
+    pub fn open_with_shares(dir: &Path, shares: &[KeyShare]) -> Result<Self> {
+        let path = dir.join(KEYRING_FILE);
+        let status = Self::seal_status(dir)?
+            .ok_or_else(|| NodeError::Crypto("Keyring is not sealed with key shares".into()))?;
+
+        let mut points: Vec<Share> = Vec::with_capacity(shares.len());
+        for share in shares {
+            status.verify_share(share)?;
+            if points.iter().all(|point| point.index != share.index) {
+                points.push(Share {
+                    index: share.index,
+                    value: decode_hex(&share.value)?,
+                });
+            }
+        }
+
+        let secret = shamir::combine(&points, status.threshold)
+            .map_err(|e| NodeError::Crypto(format!("Failed to combine key shares: {}", e)))?;
+        let kek = Kek::new(secret)?;
+        let state = Self::unlock(&kek, read_record(&path)?)?;
+
+        Ok(Self {
+            kek,
+            state: RwLock::new(state),
+            path: Some(path),
+            uses: AtomicU64::new(0),
+            watcher: OnceLock::new(),
+        })
+    }
+
+    /// Replace the KEK with a random key split into `count` shares, any
+    /// `threshold` of which unseal the keyring. The passphrase no longer
+    /// opens it afterwards. DEKs are re-wrapped; stored data is unchanged.
+    pub fn split_kek(&mut self, threshold: u8, count: u8) -> Result<Vec<KeyShare>> {
+        let kek = Kek::new(random_bytes(KEY_LEN)?)?;
+        let state = self.state.get_mut().unwrap();
+
+        let generation = match &state.record.kdf {
+            KdfRecord::Shamir { generation, .. } => generation + 1,
+            _ => 1,
+        };
+        let (kdf, shares) = split_shares(&kek, threshold, count, generation)?;
+
+        let record = rewrap(&self.kek, &kek, state, kdf)?;
+        if let Some(path) = &self.path {
+            persist(path, &record)?;
+        }
+        state.record = record;
+        self.kek = kek;
+
+        info!("Sealed keyring with {}-of-{} key shares", threshold, count);
+        Ok(shares)
+    }
+
+    /// Issue a new set of shares for the current share-sealed KEK. Nothing
+    /// is re-wrapped; shares from earlier generations are refused at unseal.
+    pub fn reshare(&self, threshold: u8, count: u8) -> Result<Vec<KeyShare>> {
+        let mut state = self.state.write().unwrap();
+        let generation = match &state.record.kdf {
+            KdfRecord::Shamir { generation, .. } => generation + 1,
+            _ => return Err(NodeError::Crypto("Keyring is not sealed with key shares".into())),
+        };
+        let (kdf, shares) = split_shares(&self.kek, threshold, count, generation)?;
+
+        let mut record = state.record.clone();
+        record.kdf = kdf;
+        if let Some(path) = &self.path {
+            persist(path, &record)?;
+        }
+        state.record = record;
+
+        info!("Re-shared keyring as {}-of-{} (generation {})", threshold, count, generation);
+        Ok(shares)
+    }
 
-        // Initialize AES-GCM cipher
-        let cipher = Aes256Gcm::new_from_slice(&master_key)
-            .map_err(|e| NodeError::Crypto(format!("Failed to initialize cipher: {}", e)))?;
+    /// Open the keyring in `dir` with its KEK held by `store`, creating
+    /// both on first use. The KEK never leaves the store.
+    pub fn open_with_store(dir: &Path, store: Arc<dyn KeyStore>) -> Result<Self> {
+        let path = dir.join(KEYRING_FILE);
+
+        let (kek, state) = if path.exists() {
+            let record = read_record(&path)?;
+            let label = match &record.kdf {
+                KdfRecord::KeyStore { backend, label } if backend == store.backend() => label.clone(),
+                KdfRecord::KeyStore { backend, .. } => {
+                    return Err(NodeError::Crypto(format!(
+                        "Keyring KEK is held by the {} key store, not {}",
+                        backend,
+                        store.backend()
+                    )));
+                }
+                _ => {
+                    return Err(NodeError::Crypto(
+                        "Keyring is not held by a key store; open it and call move_kek_to_store".into(),
+                    ));
+                }
+            };
+            let kek = Kek::Store { store, label };
+            let state = Self::unlock(&kek, record)?;
+            (kek, state)
+        } else {
+            fs::create_dir_all(dir)
+                .map_err(|e| NodeError::Crypto(format!("Failed to create keyring directory: {}", e)))?;
+            store.ensure_key(STORE_KEK_LABEL)?;
+            let kdf = KdfRecord::KeyStore {
+                backend: store.backend().to_string(),
+                label: STORE_KEK_LABEL.to_string(),
+            };
+            let kek = Kek::Store { store, label: STORE_KEK_LABEL.to_string() };
+            let state = Self::new_keyring(&kek, kdf)?;
+            persist(&path, &state.record)?;
+            (kek, state)
+        };
 
         Ok(Self {
-            master_key: RwLock::new(master_key),
-            encryption_key: RwLock::new(cipher),
+            kek,
+            state: RwLock::new(state),
+            path: Some(path),
+            uses: AtomicU64::new(0),
+            watcher: OnceLock::new(),
         })
     }
 
-    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        let mut nonce = [0u8; 12];
-        ring::rand::SystemRandom::new()
-            .fill(&mut nonce)
-            .map_err(|_| NodeError::Crypto("Failed to generate nonce".into()))?;
+    /// Re-wrap the keyring under a KEK held by `store`. Afterwards only
+    /// `open_with_store` opens it; stored data is unchanged.
+    pub fn move_kek_to_store(&mut self, store: Arc<dyn KeyStore>) -> Result<()> {
+        store.ensure_key(STORE_KEK_LABEL)?;
+        let kdf = KdfRecord::KeyStore {
+            backend: store.backend().to_string(),
+            label: STORE_KEK_LABEL.to_string(),
+        };
+        let kek = Kek::Store { store, label: STORE_KEK_LABEL.to_string() };
+
+        let state = self.state.get_mut().unwrap();
+        let record = rewrap(&self.kek, &kek, state, kdf)?;
+        if let Some(path) = &self.path {
+            persist(path, &record)?;
+        }
+        state.record = record;
+        self.kek = kek;
+
+        info!("Moved keyring KEK into the key store");
+        Ok(())
+    }
+
+    fn create_keyring(encryption_key: &str, kdf: KdfRecord) -> Result<(Kek, KeyState)> {
+        let kek = derive_kek(encryption_key, &kdf)?;
+        let state = Self::new_keyring(&kek, kdf)?;
+        Ok((kek, state))
+    }
 
-        let nonce = Nonce::from_slice(&nonce);
-        
-        let cipher = self.encryption_key.read().unwrap();
-        let ciphertext = cipher
-            .encrypt(nonce, data)
-            .map_err(|e| NodeError::Crypto(format!("Encryption failed: {}", e)))?;
+    fn new_keyring(kek: &Kek, kdf: KdfRecord) -> Result<KeyState> {
+        let root = random_bytes(KEY_LEN)?;
+        let dek = random_bytes(KEY_LEN)?;
 
-        // Combine nonce and ciphertext
-        let mut result = Vec::with_capacity(nonce.len() + ciphertext.len());
-        result.extend_from_slice(nonce);
+        let record = KeyringRecord {
+            format: KEYRING_FORMAT,
+            kdf,
+            root: hex::encode(wrap(kek, &root, b"freeghost-root")?),
+            active_version: 1,
+            deks: vec![WrappedDek {
+                version: 1,
+                created_at: chrono::Utc::now().timestamp(),
+                wrapped: hex::encode(wrap(kek, &dek, &dek_aad(1))?),
+            }],
+        };
+
+        let mut deks = BTreeMap::new();
+        deks.insert(1, guard_key(dek)?);
+
+        Ok(KeyState { record, root: guard_key(root)?, deks })
+    }
+
+    fn unlock(kek: &Kek, record: KeyringRecord) -> Result<KeyState> {
+        if record.format != KEYRING_FORMAT {
+            return Err(NodeError::Crypto(format!("Unsupported keyring format {}", record.format)));
+        }
+
+        let root = guard_key(unwrap(kek, &decode_hex(&record.root)?, b"freeghost-root")?)?;
+
+        let mut deks = BTreeMap::new();
+        for dek in &record.deks {
+            let key = unwrap(kek, &decode_hex(&dek.wrapped)?, &dek_aad(dek.version))?;
+            deks.insert(dek.version, guard_key(key)?);
+        }
+        if !deks.contains_key(&record.active_version) {
+            return Err(NodeError::Crypto("Active key version missing from keyring".into()));
+        }
+
+        Ok(KeyState { record, root, deks })
+    }
+
+    /// KDF algorithm and parameters the keyring is currently wrapped with
+    pub fn kdf_params(&self) -> Option<KdfParams> {
+        match self.state.read().unwrap().record.kdf {
+            KdfRecord::Argon2id { params, .. } => Some(params),
+            _ => None,
+        }
+    }
+
+    /// Encrypt under the active DEK. `aad` is authenticated but not stored:
+    /// `decrypt` needs the same bytes.
+    pub fn encrypt(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = random_bytes(NONCE_LEN)?;
+
+        let state = self.state.read().unwrap();
+        let version = state.record.active_version;
+        let header = header(CIPHERTEXT_FORMAT, version);
+
+        let ciphertext = with_key(&state.deks[&version], |key| {
+            new_cipher(key)?
+                .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad: &[&header[..], aad].concat() })
+                .map_err(|e| NodeError::Crypto(format!("Encryption failed: {}", e)))
+        })?;
+        self.uses.fetch_add(1, Ordering::Relaxed);
+
+        // Combine header, nonce and ciphertext
+        let mut result = Vec::with_capacity(HEADER_LEN + NONCE_LEN + ciphertext.len());
+        result.extend_from_slice(&header);
+        result.extend_from_slice(&nonce);
         result.extend_from_slice(&ciphertext);
 
         Ok(result)
     }
 
-    pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
-        if encrypted_data.len() < 12 {
+    /// Fails unless `aad` is exactly what the data was encrypted with
+    pub fn decrypt(&self, encrypted_data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if Self::is_legacy(encrypted_data) {
+            return Err(NodeError::Crypto("Ciphertext is not bound to its context; migrate it first".into()));
+        }
+        self.decrypt_with(encrypted_data, aad)
+    }
+
+    /// Decrypt a ciphertext of the format without associated data. Only for
+    /// migrating old data: nothing ties it to where it was found.
+    pub fn decrypt_legacy(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+        if !Self::is_legacy(encrypted_data) {
+            return Err(NodeError::Crypto("Not a legacy ciphertext".into()));
+        }
+        self.decrypt_with(encrypted_data, &[])
+    }
+
+    fn decrypt_with(&self, encrypted_data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let version = Self::key_version(encrypted_data)?;
+        if encrypted_data.len() < HEADER_LEN + NONCE_LEN {
             return Err(NodeError::Crypto("Invalid encrypted data".into()));
         }
 
-        let (nonce, ciphertext) = encrypted_data.split_at(12);
-        let nonce = Nonce::from_slice(nonce);
+        let (header, rest) = encrypted_data.split_at(HEADER_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let state = self.state.read().unwrap();
+        let key = state.deks
+            .get(&version)
+            .ok_or_else(|| NodeError::Crypto(format!("Unknown key version {}", version)))?;
+
+        with_key(key, |key| {
+            new_cipher(key)?
+                .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &[header, aad].concat() })
+                .map_err(|e| NodeError::Crypto(format!("Decryption failed: {}", e)))
+        })
+    }
+
+    /// Ciphertext in the format without associated data, to test migrations
+    #[cfg(test)]
+    pub(crate) fn encrypt_legacy(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce = random_bytes(NONCE_LEN)?;
+        let state = self.state.read().unwrap();
+        let header = header(LEGACY_CIPHERTEXT_FORMAT, state.record.active_version);
+        let ciphertext = with_key(&state.deks[&state.record.active_version], |key| {
+            new_cipher(key)?
+                .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad: &header })
+                .map_err(|e| NodeError::Crypto(format!("Encryption failed: {}", e)))
+        })?;
+        Ok([&header[..], &nonce, &ciphertext].concat())
+    }
+
+    /// Whether `encrypted_data` predates associated data
+    pub fn is_legacy(encrypted_data: &[u8]) -> bool {
+        encrypted_data.first() == Some(&LEGACY_CIPHERTEXT_FORMAT)
+    }
+
+    /// Version of the DEK that produced `encrypted_data`
+    pub fn key_version(encrypted_data: &[u8]) -> Result<u32> {
+        let known = matches!(encrypted_data.first(), Some(&(CIPHERTEXT_FORMAT | LEGACY_CIPHERTEXT_FORMAT)));
+        if encrypted_data.len() < HEADER_LEN || !known {
+            return Err(NodeError::Crypto("Invalid encrypted data".into()));
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&encrypted_data[1..HEADER_LEN]);
+        Ok(u32::from_be_bytes(version))
+    }
+
+    /// Version new data is encrypted with
+    pub fn active_version(&self) -> u32 {
+        self.state.read().unwrap().record.active_version
+    }
+
+    /// Every version that can still decrypt
+    pub fn versions(&self) -> Vec<u32> {
+        self.state.read().unwrap().deks.keys().copied().collect()
+    }
+
+    /// Unix time the active version was created
+    pub fn active_created_at(&self) -> i64 {
+        let state = self.state.read().unwrap();
+        state.record.deks
+            .iter()
+            .find(|dek| dek.version == state.record.active_version)
+            .map(|dek| dek.created_at)
+            .unwrap_or_default()
+    }
+
+    /// Encryptions under the active version since it became active. Kept in
+    /// memory only; callers that need it across restarts persist it and hand
+    /// it back with `add_uses`.
+    pub fn active_uses(&self) -> u64 {
+        self.uses.load(Ordering::Relaxed)
+    }
 
-        let cipher = self.encryption_key.read().unwrap();
-        cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|e| NodeError::Crypto(format!("Decryption failed: {}", e)))
+    pub fn add_uses(&self, count: u64) {
+        self.uses.fetch_add(count, Ordering::Relaxed);
     }
 
     pub fn hash_features(&self, features: &[f32]) -> Result<String> {
@@ -90,9 +814,12 @@ impl KeyManager {
             bytes.extend_from_slice(&feature.to_le_bytes());
         }
 
-        // Add master key as salt
-        let master_key = self.master_key.read().unwrap();
-        bytes.extend_from_slice(&master_key);
+        // Add root secret as salt
+        let state = self.state.read().unwrap();
+        with_key(&state.root, |root| {
+            bytes.extend_from_slice(root);
+            Ok(())
+        })?;
 
         // Use SHA3-256 for hashing
         let mut hasher = Sha3_256::new();
@@ -102,73 +829,628 @@ impl KeyManager {
         Ok(hex::encode(result))
     }
 
-    pub fn rotate_keys(&self) -> Result<()> {
-        let mut new_key = vec![0u8; KEY_LEN];
-        ring::rand::SystemRandom::new()
-            .fill(&mut new_key)
-            .map_err(|_| NodeError::Crypto("Failed to generate new key".into()))?;
+    /// Add a new DEK version and make it active. Data encrypted under older
+    /// versions stays readable. Returns the new version.
+    pub fn rotate_keys(&self) -> Result<u32> {
+        let new_key = random_bytes(KEY_LEN)?;
+
+        let mut state = self.state.write().unwrap();
+        let version = state.deks.keys().next_back().copied().unwrap_or(0) + 1;
 
-        let new_cipher = Aes256Gcm::new_from_slice(&new_key)
-            .map_err(|e| NodeError::Crypto(format!("Failed to initialize new cipher: {}", e)))?;
+        let mut record = state.record.clone();
+        record.deks.push(WrappedDek {
+            version,
+            created_at: chrono::Utc::now().timestamp(),
+            wrapped: hex::encode(wrap(&self.kek, &new_key, &dek_aad(version))?),
+        });
+        record.active_version = version;
 
-        // Update keys atomically
-        {
-            let mut master_key = self.master_key.write().unwrap();
-            let mut encryption_key = self.encryption_key.write().unwrap();
-            *master_key = new_key;
-            *encryption_key = new_cipher;
+        let key = guard_key(new_key)?;
+        if let Some((detector, session)) = self.watcher.get() {
+            watch(detector, *session, &key, dek_region(version))?;
         }
 
-        Ok(())
+        // Persist before switching, so nothing is encrypted under a key that
+        // would be lost on restart
+        if let Some(path) = &self.path {
+            persist(path, &record)?;
+        }
+
+        state.deks.insert(version, key);
+        state.record = record;
+        self.uses.store(0, Ordering::Relaxed);
+
+        Ok(version)
     }
 
     pub fn derive_key(&self, purpose: &str) -> Result<Vec<u8>> {
-        let master_key = self.master_key.read().unwrap();
-        
+        let state = self.state.read().unwrap();
+
         let mut context = digest::Context::new(&digest::SHA256);
-        context.update(&master_key);
+        with_key(&state.root, |root| {
+            context.update(root);
+            Ok(())
+        })?;
         context.update(purpose.as_bytes());
-        
+
         let derived = context.finish();
         Ok(derived.as_ref().to_vec())
     }
+
+    /// Register the root secret and every DEK with `detector`, and DEKs
+    /// added by later rotations as they are created. An alert suspends
+    /// `session`.
+    pub fn watch_memory(&self, detector: Arc<MemoryPoisonDetector>, session: Uuid) -> Result<()> {
+        // Held throughout so no rotation slips in between
+        let state = self.state.read().unwrap();
+        if self.watcher.get().is_some() {
+            return Err(NodeError::Crypto("Keyring memory is already watched".into()));
+        }
+
+        watch(&detector, session, &state.root, ROOT_REGION.to_string())?;
+        for (version, key) in &state.deks {
+            watch(&detector, session, key, dek_region(*version))?;
+        }
+        let _ = self.watcher.set((detector, session));
+        Ok(())
+    }
+}
+
+/// Wrap the unlocked root and DEKs under `new_kek`, recording `kdf`
+fn rewrap(old_kek: &Kek, new_kek: &Kek, state: &KeyState, kdf: KdfRecord) -> Result<KeyringRecord> {
+    let mut record = state.record.clone();
+    record.kdf = kdf;
+    record.root = hex::encode(with_key(&state.root, |root| wrap(new_kek, root, b"freeghost-root"))?);
+    for dek in record.deks.iter_mut() {
+        let aad = dek_aad(dek.version);
+        let key = unwrap(old_kek, &decode_hex(&dek.wrapped)?, &aad)?;
+        dek.wrapped = hex::encode(wrap(new_kek, &key, &aad)?);
+    }
+    Ok(record)
+}
+
+fn split_shares(kek: &Kek, threshold: u8, count: u8, generation: u32) -> Result<(KdfRecord, Vec<KeyShare>)> {
+    if threshold < 2 {
+        return Err(NodeError::Crypto("Key share threshold must be at least 2".into()));
+    }
+    let Kek::Local { secret, .. } = kek else {
+        return Err(NodeError::Crypto("A KEK held by a key store cannot be split".into()));
+    };
+    let shares: Vec<KeyShare> = shamir::split(secret, threshold, count)
+        .map_err(|e| NodeError::Crypto(format!("Failed to split key: {}", e)))?
+        .into_iter()
+        .map(|share| KeyShare {
+            generation,
+            threshold,
+            index: share.index,
+            value: hex::encode(&share.value),
+        })
+        .collect();
+
+    let digests = shares.iter().map(KeyShare::digest).collect::<Result<Vec<_>>>()?;
+    Ok((KdfRecord::Shamir { threshold, generation, shares: digests }, shares))
+}
+
+fn share_aad(generation: u32, index: u8) -> Vec<u8> {
+    [b"freeghost-key-share".as_slice(), &generation.to_be_bytes(), &[index]].concat()
+}
+
+fn derive_kek(encryption_key: &str, kdf: &KdfRecord) -> Result<Kek> {
+    if encryption_key.is_empty() {
+        return Err(NodeError::Crypto("Encryption key cannot be empty".into()));
+    }
+
+    let mut kek = [0u8; KEY_LEN];
+    match kdf {
+        KdfRecord::Argon2id { params, salt } => {
+            kek.copy_from_slice(&params.derive(encryption_key, &decode_hex(salt)?)?);
+        }
+        KdfRecord::Pbkdf2 { iterations, salt } => {
+            let iterations = std::num::NonZeroU32::new(*iterations)
+                .ok_or_else(|| NodeError::Crypto("Invalid KDF iterations".into()))?;
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                iterations,
+                &decode_hex(salt)?,
+                encryption_key.as_bytes(),
+                &mut kek,
+            );
+        }
+        KdfRecord::Shamir { .. } => {
+            return Err(NodeError::Crypto("Keyring is sealed with key shares".into()));
+        }
+        KdfRecord::KeyStore { .. } => {
+            return Err(NodeError::Crypto("Keyring KEK is held by a key store".into()));
+        }
+    }
+
+    Kek::new(kek.to_vec())
+}
+
+fn wrap(kek: &Kek, key: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    match kek {
+        Kek::Local { cipher, .. } => wrap_with(cipher, key, aad),
+        Kek::Store { store, label } => store.encrypt(label, key, aad),
+    }
+}
+
+fn unwrap(kek: &Kek, wrapped: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    match kek {
+        Kek::Local { cipher, .. } => unwrap_with(cipher, wrapped, aad),
+        Kek::Store { store, label } => store
+            .decrypt(label, wrapped, aad)
+            .map_err(|_| NodeError::Crypto("Key store could not unwrap the keyring".into())),
+    }
+}
+
+fn wrap_with(cipher: &Aes256Gcm, key: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = random_bytes(NONCE_LEN)?;
+    let wrapped = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: key, aad })
+        .map_err(|e| NodeError::Crypto(format!("Key wrapping failed: {}", e)))?;
+    Ok([nonce, wrapped].concat())
+}
+
+fn unwrap_with(cipher: &Aes256Gcm, wrapped: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if wrapped.len() < NONCE_LEN {
+        return Err(NodeError::Crypto("Invalid wrapped key".into()));
+    }
+    let (nonce, ciphertext) = wrapped.split_at(NONCE_LEN);
+    cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| NodeError::Crypto("Wrong encryption key or corrupted keyring".into()))
+}
+
+/// Move `key` into guarded memory, wiping the copy it came in
+fn guard_key(mut key: Vec<u8>) -> Result<GuardedKey> {
+    let mut bytes = [0u8; KEY_LEN];
+    let valid = key.len() == KEY_LEN;
+    if valid {
+        bytes.copy_from_slice(&key);
+    }
+    key.zeroize();
+    if !valid {
+        return Err(NodeError::Crypto("Invalid key length".into()));
+    }
+
+    let secret = Secret::new(bytes);
+    bytes.zeroize();
+    let secret = secret.map_err(|e| NodeError::Crypto(format!("Failed to guard key: {}", e)))?;
+    Ok(Arc::new(SyncRwLock::new(secret)))
+}
+
+/// Run `f` on a guarded key. A key wiped by the poison detector is refused
+/// rather than used as an all-zero key.
+fn with_key<T>(key: &GuardedKey, f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
+    let key = key.read();
+    let bytes = key.expose()
+        .map_err(|e| NodeError::Crypto(format!("Failed to access key: {}", e)))?;
+    if bytes.iter().all(|&byte| byte == 0) {
+        return Err(NodeError::Crypto("Key was wiped after a memory integrity alert".into()));
+    }
+    f(&bytes[..])
+}
+
+fn watch(detector: &MemoryPoisonDetector, session: Uuid, key: &GuardedKey, region_id: String) -> Result<()> {
+    detector
+        .protect_region(key, region_id, MemoryRegionType::KeyMaterial, Some(session))
+        .map_err(|e| NodeError::Crypto(format!("Failed to watch key memory: {}", e)))
+}
+
+fn dek_region(version: u32) -> String {
+    format!("keyring-dek-{}", version)
+}
+
+fn dek_aad(version: u32) -> Vec<u8> {
+    [b"freeghost-dek".as_slice(), &version.to_be_bytes()].concat()
+}
+
+fn header(format: u8, version: u32) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[0] = format;
+    header[1..].copy_from_slice(&version.to_be_bytes());
+    header
+}
+
+fn new_cipher(key: &[u8]) -> Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(key)
+        .map_err(|e| NodeError::Crypto(format!("Failed to initialize cipher: {}", e)))
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    system_rng()
+        .fill(&mut bytes)
+        .map_err(|_| NodeError::Crypto("Failed to generate random bytes".into()))?;
+    Ok(bytes)
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    hex::decode(value).map_err(|e| NodeError::Crypto(format!("Invalid keyring encoding: {}", e)))
+}
+
+fn read_record(path: &Path) -> Result<KeyringRecord> {
+    let contents = fs::read(path)
+        .map_err(|e| NodeError::Crypto(format!("Failed to read keyring: {}", e)))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| NodeError::Crypto(format!("Invalid keyring: {}", e)))
+}
+
+/// Write a key file atomically: temp file, fsync, rename
+pub(crate) fn persist<T: Serialize>(path: &Path, record: &T) -> Result<()> {
+    let contents = serde_json::to_vec_pretty(record)
+        .map_err(|e| NodeError::Crypto(format!("Failed to serialize keyring: {}", e)))?;
+    write_key_file(path, &contents)
+}
+
+/// Write `contents` to `path` atomically, readable by the owner only
+pub(crate) fn write_key_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(&tmp)
+        .map_err(|e| NodeError::Crypto(format!("Failed to write keyring: {}", e)))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| NodeError::Crypto(format!("Failed to write keyring: {}", e)))?;
+
+    fs::rename(&tmp, path)
+        .map_err(|e| NodeError::Crypto(format!("Failed to write keyring: {}", e)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::tempdir;
+
+    /// Cheap parameters so tests stay fast
+    fn test_params() -> KdfParams {
+        KdfParams { memory_kib: 256, iterations: 1, parallelism: 1 }
+    }
+
+    fn test_key_manager() -> KeyManager {
+        KeyManager::new_with_params("test_key", &test_params()).unwrap()
+    }
 
     #[test]
     fn test_encryption_decryption() {
-        let key_manager = KeyManager::new("test_key").unwrap();
+        let key_manager = test_key_manager();
         let data = b"test data";
-        
-        let encrypted = key_manager.encrypt(data).unwrap();
-        let decrypted = key_manager.decrypt(&encrypted).unwrap();
-        
+
+        let encrypted = key_manager.encrypt(data, b"").unwrap();
+        let decrypted = key_manager.decrypt(&encrypted, b"").unwrap();
+
         assert_eq!(data.to_vec(), decrypted);
     }
 
     #[test]
     fn test_feature_hashing() {
-        let key_manager = KeyManager::new("test_key").unwrap();
+        let key_manager = test_key_manager();
         let features = vec![0.1, 0.2, 0.3];
-        
+
         let hash1 = key_manager.hash_features(&features).unwrap();
         let hash2 = key_manager.hash_features(&features).unwrap();
-        
+
         assert_eq!(hash1, hash2);
     }
 
     #[test]
     fn test_key_rotation() {
-        let key_manager = KeyManager::new("test_key").unwrap();
+        let key_manager = test_key_manager();
         let data = b"test data";
-        
-        let encrypted = key_manager.encrypt(data).unwrap();
+
+        let encrypted = key_manager.encrypt(data, b"").unwrap();
+        assert_eq!(key_manager.active_uses(), 1);
+        let version = key_manager.rotate_keys().unwrap();
+        assert_eq!(key_manager.active_uses(), 0);
+        assert!(key_manager.active_created_at() > 0);
+
+        // Previous encrypted data stays decryptable after rotation
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), data.to_vec());
+
+        // New data uses the new version
+        let reencrypted = key_manager.encrypt(data, b"").unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(KeyManager::key_version(&encrypted).unwrap(), 1);
+        assert_eq!(KeyManager::key_version(&reencrypted).unwrap(), 2);
+        assert_eq!(key_manager.versions(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_keyring_survives_restart() {
+        let dir = tempdir().unwrap();
+
+        let (old, features_hash, derived) = {
+            let key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+            let old = key_manager.encrypt(b"before rotation", b"").unwrap();
+            key_manager.rotate_keys().unwrap();
+            (
+                old,
+                key_manager.hash_features(&[0.5, 0.25]).unwrap(),
+                key_manager.derive_key("purpose").unwrap(),
+            )
+        };
+
+        let key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+        assert_eq!(key_manager.active_version(), 2);
+        assert_eq!(key_manager.decrypt(&old, b"").unwrap(), b"before rotation".to_vec());
+        assert_eq!(key_manager.hash_features(&[0.5, 0.25]).unwrap(), features_hash);
+        assert_eq!(key_manager.derive_key("purpose").unwrap(), derived);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_rejected() {
+        let dir = tempdir().unwrap();
+        KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+
+        assert!(KeyManager::open(dir.path(), "other_key", &test_params()).is_err());
+        assert!(KeyManager::open(dir.path(), "", &test_params()).is_err());
+    }
+
+    #[test]
+    fn test_key_version_is_authenticated() {
+        let key_manager = test_key_manager();
         key_manager.rotate_keys().unwrap();
-        
-        // Previous encrypted data should not be decryptable after rotation
-        assert!(key_manager.decrypt(&encrypted).is_err());
+
+        let mut encrypted = key_manager.encrypt(b"test data", b"").unwrap();
+        encrypted[4] = 1;
+        assert!(key_manager.decrypt(&encrypted, b"").is_err());
+
+        encrypted[0] = 0;
+        assert!(KeyManager::key_version(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_associated_data_is_bound() {
+        let key_manager = test_key_manager();
+
+        let encrypted = key_manager.encrypt(b"test data", b"identity:a").unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"identity:a").unwrap(), b"test data".to_vec());
+        assert!(key_manager.decrypt(&encrypted, b"identity:b").is_err());
+        assert!(key_manager.decrypt(&encrypted, b"").is_err());
+
+        // Relabelling a ciphertext as legacy does not strip the binding
+        let mut downgraded = encrypted.clone();
+        downgraded[0] = LEGACY_CIPHERTEXT_FORMAT;
+        assert!(key_manager.decrypt_legacy(&downgraded).is_err());
+
+        // Legacy ciphertexts only open through the migration path
+        let legacy = key_manager.encrypt_legacy(b"test data").unwrap();
+        assert!(KeyManager::is_legacy(&legacy));
+        assert_eq!(KeyManager::key_version(&legacy).unwrap(), 1);
+        assert!(key_manager.decrypt(&legacy, b"").is_err());
+        assert_eq!(key_manager.decrypt_legacy(&legacy).unwrap(), b"test data".to_vec());
+    }
+
+    #[test]
+    fn test_keyring_holds_no_plaintext_keys() {
+        let dir = tempdir().unwrap();
+        let key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+
+        let contents = fs::read_to_string(dir.path().join(KEYRING_FILE)).unwrap();
+        let root = hex::encode(*key_manager.state.read().unwrap().root.read().expose().unwrap());
+        assert!(!contents.contains(&root));
+    }
+
+    #[test]
+    fn test_pbkdf2_keyring_migrated_on_unlock() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(KEYRING_FILE);
+
+        // Keyring as written by the PBKDF2 version
+        let legacy = KdfRecord::Pbkdf2 {
+            iterations: 1000,
+            salt: hex::encode(random_bytes(SALT_LEN).unwrap()),
+        };
+        let (_, state) = KeyManager::create_keyring("test_key", legacy).unwrap();
+        persist(&path, &state.record).unwrap();
+        let wrapped_before = state.record.deks[0].wrapped.clone();
+
+        let encrypted = {
+            let key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+            assert_eq!(key_manager.kdf_params(), Some(test_params()));
+            key_manager.encrypt(b"test data", b"").unwrap()
+        };
+
+        // Rewritten with Argon2id; the DEK itself is unchanged
+        let record: KeyringRecord = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert!(record.kdf.uses(&test_params()));
+        assert_ne!(record.deks[0].wrapped, wrapped_before);
+
+        let key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+        assert_eq!(KeyManager::key_version(&encrypted).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_changed_params_rewrap() {
+        let dir = tempdir().unwrap();
+        let encrypted = KeyManager::open(dir.path(), "test_key", &test_params())
+            .unwrap()
+            .encrypt(b"test data", b"")
+            .unwrap();
+
+        let stronger = KdfParams { memory_kib: 512, iterations: 2, parallelism: 2 };
+        let key_manager = KeyManager::open(dir.path(), "test_key", &stronger).unwrap();
+        assert_eq!(key_manager.kdf_params(), Some(stronger));
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+
+        // A wrong passphrase must not trigger a re-wrap
+        assert!(KeyManager::open(dir.path(), "other_key", &test_params()).is_err());
+        assert_eq!(
+            KeyManager::open(dir.path(), "test_key", &stronger).unwrap().kdf_params(),
+            Some(stronger)
+        );
+    }
+
+    #[test]
+    fn test_invalid_params_rejected() {
+        let params = KdfParams { memory_kib: 1, iterations: 0, parallelism: 1 };
+        assert!(KeyManager::new_with_params("test_key", &params).is_err());
+    }
+
+    #[test]
+    fn test_split_kek_unseals_with_threshold() {
+        let dir = tempdir().unwrap();
+        let (encrypted, shares) = {
+            let mut key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+            let encrypted = key_manager.encrypt(b"test data", b"").unwrap();
+            (encrypted, key_manager.split_kek(2, 3).unwrap())
+        };
+
+        let status = KeyManager::seal_status(dir.path()).unwrap().unwrap();
+        assert_eq!((status.threshold, status.shares, status.generation), (2, 3, 1));
+
+        // The passphrase is no longer enough
+        assert!(KeyManager::open(dir.path(), "test_key", &test_params()).is_err());
+        assert!(KeyManager::open_with_shares(dir.path(), &shares[..1]).is_err());
+
+        for pair in [[0, 1], [0, 2], [1, 2]] {
+            let subset = [shares[pair[0]].clone(), shares[pair[1]].clone()];
+            let key_manager = KeyManager::open_with_shares(dir.path(), &subset).unwrap();
+            assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+        }
+    }
+
+    #[test]
+    fn test_share_text_form() {
+        let mut key_manager = test_key_manager();
+        let shares = key_manager.split_kek(2, 2).unwrap();
+
+        let text = shares[0].to_string();
+        assert!(text.starts_with("fgks1-1-2-1-"));
+        assert_eq!(text.parse::<KeyShare>().unwrap(), shares[0]);
+        assert!(!format!("{:?}", shares[0]).contains(&shares[0].value));
+        assert!("fgks1-1-2-0-00".parse::<KeyShare>().is_err());
+    }
+
+    #[test]
+    fn test_tampered_share_rejected() {
+        let dir = tempdir().unwrap();
+        let shares = KeyManager::open(dir.path(), "test_key", &test_params())
+            .unwrap()
+            .split_kek(2, 3)
+            .unwrap();
+
+        let mut tampered = shares[1].clone();
+        let mut value = hex::decode(&tampered.value).unwrap();
+        value[0] ^= 1;
+        tampered.value = hex::encode(value);
+
+        let status = KeyManager::seal_status(dir.path()).unwrap().unwrap();
+        assert!(status.verify_share(&shares[1]).is_ok());
+        assert!(status.verify_share(&tampered).is_err());
+        assert!(KeyManager::open_with_shares(dir.path(), &[shares[0].clone(), tampered]).is_err());
+    }
+
+    #[test]
+    fn test_reshare_keeps_key() {
+        let dir = tempdir().unwrap();
+        let mut key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+        let old_shares = key_manager.split_kek(2, 3).unwrap();
+        let encrypted = key_manager.encrypt(b"test data", b"").unwrap();
+
+        let new_shares = key_manager.reshare(3, 5).unwrap();
+        assert_eq!(new_shares[0].generation, 2);
+        drop(key_manager);
+
+        // Earlier generation is refused even though it would combine
+        assert!(KeyManager::open_with_shares(dir.path(), &old_shares[..2]).is_err());
+        assert!(KeyManager::open_with_shares(dir.path(), &new_shares[..2]).is_err());
+
+        let key_manager = KeyManager::open_with_shares(dir.path(), &new_shares[2..]).unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+    }
+
+    #[test]
+    fn test_sealed_share_roundtrip() {
+        let mut key_manager = test_key_manager();
+        let shares = key_manager.split_kek(2, 2).unwrap();
+        let (public_key, secret_key) = KyberKEM::keygen().unwrap();
+        let (_, other_secret_key) = KyberKEM::keygen().unwrap();
+
+        let sealed = shares[1].seal(&public_key).unwrap();
+        assert_eq!(sealed.index, 2);
+        assert_eq!(sealed.open(&secret_key).unwrap(), shares[1]);
+        assert!(sealed.open(&other_secret_key).is_err());
+    }
+
+    #[test]
+    fn test_kek_held_by_key_store() {
+        use crate::core::crypto::keystore::MemoryKeyStore;
+
+        let dir = tempdir().unwrap();
+        let store: Arc<dyn KeyStore> = Arc::new(MemoryKeyStore::new());
+
+        let encrypted = KeyManager::open_with_store(dir.path(), store.clone())
+            .unwrap()
+            .encrypt(b"test data", b"")
+            .unwrap();
+        assert!(store.has_key(STORE_KEK_LABEL).unwrap());
+
+        let key_manager = KeyManager::open_with_store(dir.path(), store).unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+
+        // Neither a passphrase nor a different store opens it
+        assert!(KeyManager::open(dir.path(), "test_key", &test_params()).is_err());
+        assert!(KeyManager::open_with_store(dir.path(), Arc::new(MemoryKeyStore::new())).is_err());
+    }
+
+    #[test]
+    fn test_move_kek_to_store() {
+        use crate::core::crypto::keystore::MemoryKeyStore;
+
+        let dir = tempdir().unwrap();
+        let store: Arc<dyn KeyStore> = Arc::new(MemoryKeyStore::new());
+
+        let encrypted = {
+            let mut key_manager = KeyManager::open(dir.path(), "test_key", &test_params()).unwrap();
+            let encrypted = key_manager.encrypt(b"test data", b"").unwrap();
+            key_manager.move_kek_to_store(store.clone()).unwrap();
+            encrypted
+        };
+
+        assert!(KeyManager::open(dir.path(), "test_key", &test_params()).is_err());
+        let key_manager = KeyManager::open_with_store(dir.path(), store).unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_key_memory_watched() {
+        use crate::core::crypto::{
+            audit::AuditSystem,
+            poisoning::{AuditAlertHandler, PoisonResponse},
+            quantum::SecurityLevel,
+        };
+
+        let audit = Arc::new(AuditSystem::new(30, SecurityLevel::Standard));
+        let detector = Arc::new(
+            MemoryPoisonDetector::new(Box::new(AuditAlertHandler::new(audit)))
+                .with_response(PoisonResponse::ZeroizeKeys),
+        );
+        let session = Uuid::new_v4();
+        let key_manager = test_key_manager();
+        let encrypted = key_manager.encrypt(b"test data", b"").unwrap();
+
+        key_manager.watch_memory(detector.clone(), session).unwrap();
+        assert!(key_manager.watch_memory(detector.clone(), session).is_err());
+        assert!(detector.check_memory().await);
+
+        // DEKs from later rotations are watched as well
+        let version = key_manager.rotate_keys().unwrap();
+        key_manager.state.read().unwrap().deks[&version].read().tamper(0, 0xFF);
+        assert!(!detector.check_memory().await);
+
+        // Wiped keys are refused instead of used as zero keys
+        assert!(key_manager.encrypt(b"test data", b"").is_err());
+        assert!(key_manager.decrypt(&encrypted, b"").is_err());
+        assert!(key_manager.derive_key("test").is_err());
     }
 }
@@ -0,0 +1,183 @@
+//! Key store backed by a local file
+//!
+//! Keys are kept in process memory once opened and written to disk wrapped
+//! under an Argon2id-derived master key, each bound to its label. The file
+//! also holds a check value so a wrong passphrase fails at open rather than
+//! on first use.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use serde::{Serialize, Deserialize};
+
+use super::{KeyStore, KEY_LEN, seal, open, random_bytes};
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::key_manager::{KdfParams, persist},
+};
+
+const FILE_FORMAT: u32 = 1;
+const SALT_LEN: usize = 16;
+const CHECK_VALUE: &[u8] = b"freeghost-keystore";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    format: u32,
+    #[serde(flatten)]
+    params: KdfParams,
+    salt: String,
+    check: String,
+    keys: BTreeMap<String, String>,
+}
+
+pub struct FileKeyStore {
+    path: PathBuf,
+    master: Vec<u8>,
+    state: RwLock<(KeyFile, HashMap<String, Vec<u8>>)>,
+}
+
+impl FileKeyStore {
+    /// Open the key file at `path`, creating it on first use
+    pub fn open(path: &Path, passphrase: &str, params: &KdfParams) -> Result<Self> {
+        if passphrase.is_empty() {
+            return Err(NodeError::Crypto("Key store passphrase cannot be empty".into()));
+        }
+
+        let (file, master, keys) = if path.exists() {
+            let contents = std::fs::read(path)
+                .map_err(|e| NodeError::Crypto(format!("Failed to read key store: {}", e)))?;
+            let file: KeyFile = serde_json::from_slice(&contents)
+                .map_err(|e| NodeError::Crypto(format!("Invalid key store: {}", e)))?;
+            if file.format != FILE_FORMAT {
+                return Err(NodeError::Crypto(format!("Unsupported key store format {}", file.format)));
+            }
+
+            let master = file.params.derive(passphrase, &decode_hex(&file.salt)?)?;
+            open(&master, &decode_hex(&file.check)?, CHECK_VALUE)
+                .map_err(|_| NodeError::Crypto("Wrong key store passphrase".into()))?;
+
+            let mut keys = HashMap::new();
+            for (label, wrapped) in &file.keys {
+                keys.insert(label.clone(), open(&master, &decode_hex(wrapped)?, label.as_bytes())?);
+            }
+            (file, master, keys)
+        } else {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| NodeError::Crypto(format!("Failed to create key store directory: {}", e)))?;
+            }
+            let salt = random_bytes(SALT_LEN)?;
+            let master = params.derive(passphrase, &salt)?;
+            let file = KeyFile {
+                format: FILE_FORMAT,
+                params: *params,
+                salt: hex::encode(&salt),
+                check: hex::encode(seal(&master, &[], CHECK_VALUE)?),
+                keys: BTreeMap::new(),
+            };
+            persist(path, &file)?;
+            (file, master, HashMap::new())
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            master,
+            state: RwLock::new((file, keys)),
+        })
+    }
+
+    fn key(&self, label: &str) -> Result<Vec<u8>> {
+        self.state.read().unwrap().1
+            .get(label)
+            .cloned()
+            .ok_or_else(|| NodeError::Crypto(format!("No key with label {}", label)))
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn backend(&self) -> &'static str {
+        "file"
+    }
+
+    fn has_key(&self, label: &str) -> Result<bool> {
+        Ok(self.state.read().unwrap().1.contains_key(label))
+    }
+
+    fn ensure_key(&self, label: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        if state.1.contains_key(label) {
+            return Ok(());
+        }
+
+        let key = random_bytes(KEY_LEN)?;
+        let mut file = state.0.clone();
+        file.keys.insert(label.to_string(), hex::encode(seal(&self.master, &key, label.as_bytes())?));
+        // Persist first; a key that only exists in memory would be lost
+        persist(&self.path, &file)?;
+
+        state.0 = file;
+        state.1.insert(label.to_string(), key);
+        Ok(())
+    }
+
+    fn encrypt(&self, label: &str, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        seal(&self.key(label)?, plaintext, aad)
+    }
+
+    fn decrypt(&self, label: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        open(&self.key(label)?, ciphertext, aad)
+    }
+
+    fn delete_key(&self, label: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let mut file = state.0.clone();
+        if file.keys.remove(label).is_some() {
+            persist(&self.path, &file)?;
+        }
+        state.0 = file;
+        state.1.remove(label);
+        Ok(())
+    }
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    hex::decode(value).map_err(|e| NodeError::Crypto(format!("Invalid key store encoding: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn test_params() -> KdfParams {
+        KdfParams { memory_kib: 256, iterations: 1, parallelism: 1 }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = tempdir().unwrap();
+        let store = FileKeyStore::open(&dir.path().join("keys.json"), "test_key", &test_params()).unwrap();
+        super::super::exercise(&store);
+    }
+
+    #[test]
+    fn test_keys_survive_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+
+        let ciphertext = {
+            let store = FileKeyStore::open(&path, "test_key", &test_params()).unwrap();
+            store.ensure_key("kek").unwrap();
+            store.encrypt("kek", b"data", b"").unwrap()
+        };
+
+        assert!(FileKeyStore::open(&path, "other_key", &test_params()).is_err());
+
+        let store = FileKeyStore::open(&path, "test_key", &test_params()).unwrap();
+        assert_eq!(store.decrypt("kek", &ciphertext, b"").unwrap(), b"data".to_vec());
+
+        // Nothing in the file is usable without the passphrase
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&hex::encode(store.key("kek").unwrap())));
+    }
+}
@@ -0,0 +1,457 @@
+//! Key store backed by a remote KMS
+//!
+//! Speaks a KMIP-style operation set (Create, Locate, Encrypt, Decrypt,
+//! Destroy) as JSON over HTTP POST to `<url>/kmip`. Keys are created and
+//! used inside the KMS; only plaintexts and ciphertexts cross the wire. This
+//! is the JSON shape our KMS gateway accepts, not the KMIP TTLV encoding.
+//!
+//! The client only does plain HTTP, so plaintexts and the bearer token
+//! cross the wire unencrypted. It therefore only talks to a gateway or
+//! TLS-terminating sidecar on a loopback address; other hosts and
+//! `https://` URLs are refused rather than silently downgraded.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::RwLock;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+
+use super::KeyStore;
+use crate::utils::error::{Result, NodeError};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest response accepted from the KMS
+const MAX_RESPONSE: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "operation")]
+enum Request<'a> {
+    Create {
+        name: &'a str,
+        algorithm: &'a str,
+        length: u32,
+    },
+    Locate {
+        name: &'a str,
+    },
+    Encrypt {
+        unique_identifier: &'a str,
+        data: String,
+        aad: String,
+    },
+    Decrypt {
+        unique_identifier: &'a str,
+        data: String,
+        iv: String,
+        aad: String,
+    },
+    Destroy {
+        unique_identifier: &'a str,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Response {
+    unique_identifier: Option<String>,
+    #[serde(default)]
+    unique_identifiers: Vec<String>,
+    data: Option<String>,
+    iv: Option<String>,
+    result_message: Option<String>,
+}
+
+pub struct KmsKeyStore {
+    addr: SocketAddr,
+    host: String,
+    path: String,
+    token: Option<String>,
+    // Label -> KMS unique identifier
+    ids: RwLock<HashMap<String, String>>,
+}
+
+impl KmsKeyStore {
+    pub fn new(url: &str, token: Option<String>) -> Result<Self> {
+        if url.starts_with("https://") {
+            return Err(NodeError::Config(
+                "KMS client speaks plain HTTP; terminate TLS in a local gateway".into(),
+            ));
+        }
+        let rest = url.strip_prefix("http://")
+            .ok_or_else(|| NodeError::Config(format!("Invalid KMS URL {}", url)))?;
+        let (host, base) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let addr = loopback_addr(host, "kms_url")?;
+
+        Ok(Self {
+            addr,
+            host: host.to_string(),
+            path: format!("{}/kmip", base),
+            token,
+            ids: RwLock::new(HashMap::new()),
+        })
+    }
+
+    fn locate(&self, label: &str) -> Result<Option<String>> {
+        if let Some(id) = self.ids.read().unwrap().get(label) {
+            return Ok(Some(id.clone()));
+        }
+        let id = self.call(&Request::Locate { name: label })?
+            .unique_identifiers
+            .into_iter()
+            .next();
+        if let Some(id) = &id {
+            self.ids.write().unwrap().insert(label.to_string(), id.clone());
+        }
+        Ok(id)
+    }
+
+    fn id(&self, label: &str) -> Result<String> {
+        self.locate(label)?
+            .ok_or_else(|| NodeError::Crypto(format!("No key with label {}", label)))
+    }
+
+    fn call(&self, request: &Request) -> Result<Response> {
+        let body = serde_json::to_vec(request)
+            .map_err(|e| NodeError::Crypto(format!("Failed to encode KMS request: {}", e)))?;
+        let (status, body) = self.post(&body)
+            .map_err(|e| NodeError::Crypto(format!("KMS request failed: {}", e)))?;
+
+        let response: Response = serde_json::from_slice(&body).unwrap_or_default();
+        if status != 200 {
+            return Err(NodeError::Crypto(format!(
+                "KMS returned {}: {}",
+                status,
+                response.result_message.unwrap_or_default()
+            )));
+        }
+        Ok(response)
+    }
+
+    fn post(&self, body: &[u8]) -> std::io::Result<(u16, Vec<u8>)> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.host,
+            body.len()
+        );
+        if let Some(token) = &self.token {
+            head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+
+        let mut response = Vec::new();
+        stream.take(MAX_RESPONSE).read_to_end(&mut response)?;
+        parse_response(&response)
+    }
+}
+
+impl KeyStore for KmsKeyStore {
+    fn backend(&self) -> &'static str {
+        "kms"
+    }
+
+    fn has_key(&self, label: &str) -> Result<bool> {
+        Ok(self.locate(label)?.is_some())
+    }
+
+    fn ensure_key(&self, label: &str) -> Result<()> {
+        if self.locate(label)?.is_some() {
+            return Ok(());
+        }
+        let id = self.call(&Request::Create { name: label, algorithm: "AES", length: 256 })?
+            .unique_identifier
+            .ok_or_else(|| NodeError::Crypto("KMS did not return a key identifier".into()))?;
+        self.ids.write().unwrap().insert(label.to_string(), id);
+        Ok(())
+    }
+
+    /// Ciphertext layout: `[iv length][iv][ciphertext]`
+    fn encrypt(&self, label: &str, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let id = self.id(label)?;
+        let response = self.call(&Request::Encrypt {
+            unique_identifier: &id,
+            data: hex::encode(plaintext),
+            aad: hex::encode(aad),
+        })?;
+
+        let iv = decode_field(response.iv)?;
+        let data = decode_field(response.data)?;
+        let iv_len = u8::try_from(iv.len())
+            .map_err(|_| NodeError::Crypto("KMS returned an oversized IV".into()))?;
+        Ok([&[iv_len][..], &iv, &data].concat())
+    }
+
+    fn decrypt(&self, label: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let (&iv_len, rest) = ciphertext.split_first()
+            .ok_or_else(|| NodeError::Crypto("Invalid ciphertext".into()))?;
+        if rest.len() < iv_len as usize {
+            return Err(NodeError::Crypto("Invalid ciphertext".into()));
+        }
+        let (iv, data) = rest.split_at(iv_len as usize);
+
+        let id = self.id(label)?;
+        let response = self.call(&Request::Decrypt {
+            unique_identifier: &id,
+            data: hex::encode(data),
+            iv: hex::encode(iv),
+            aad: hex::encode(aad),
+        })?;
+        decode_field(response.data)
+    }
+
+    fn delete_key(&self, label: &str) -> Result<()> {
+        if let Some(id) = self.locate(label)? {
+            self.call(&Request::Destroy { unique_identifier: &id })?;
+        }
+        self.ids.write().unwrap().remove(label);
+        Ok(())
+    }
+}
+
+fn decode_field(value: Option<String>) -> Result<Vec<u8>> {
+    let value = value.ok_or_else(|| NodeError::Crypto("Incomplete KMS response".into()))?;
+    hex::decode(value).map_err(|_| NodeError::Crypto("Invalid KMS response encoding".into()))
+}
+
+/// Resolve `host`, with port 80 unless it names one, to a loopback address.
+/// Clients speaking plain HTTP only connect to a local gateway or sidecar,
+/// so nothing they send crosses the network in the clear.
+pub(crate) fn loopback_addr(host: &str, setting: &str) -> Result<SocketAddr> {
+    let authority = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let addrs: Vec<SocketAddr> = authority
+        .to_socket_addrs()
+        .map_err(|_| NodeError::Config(format!("Cannot resolve host {}", host)))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(NodeError::Config(format!("Cannot resolve host {}", host)));
+    }
+    addrs
+        .into_iter()
+        .find(|addr| addr.ip().is_loopback())
+        .ok_or_else(|| NodeError::Config(format!(
+            "{} must be a loopback address; reach remote hosts through a local TLS sidecar",
+            setting
+        )))
+}
+
+/// Status code and body of an HTTP/1.1 response read to connection close
+pub(crate) fn parse_response(response: &[u8]) -> std::io::Result<(u16, Vec<u8>)> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed HTTP response");
+
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(invalid)?;
+    let head = std::str::from_utf8(&response[..split]).map_err(|_| invalid())?;
+    let mut body = response[split + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(invalid)?;
+
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(invalid)?;
+        if name.eq_ignore_ascii_case("transfer-encoding") && value.trim().eq_ignore_ascii_case("chunked") {
+            body = dechunk(&body).ok_or_else(invalid)?;
+        }
+    }
+
+    Ok((status, body))
+}
+
+fn dechunk(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let end = data.windows(2).position(|w| w == b"\r\n")?;
+        let size_line = std::str::from_utf8(&data[..end]).ok()?;
+        let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+        data = &data[end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(data.get(..size)?);
+        data = data.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use crate::core::crypto::keystore::{open, random_bytes, seal};
+
+    /// Key id -> (name, key)
+    type MockKeys = Mutex<HashMap<String, (String, Vec<u8>)>>;
+
+    /// Minimal KMS speaking the same JSON operations
+    struct MockKms {
+        url: String,
+    }
+
+    impl MockKms {
+        fn start(token: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/v1", listener.local_addr().unwrap());
+            let keys: Arc<MockKeys> = Arc::default();
+
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else { continue };
+                    let (authorized, body) = read_request(&mut stream);
+                    let (status, reply) = if !authorized.contains(token) {
+                        (401, serde_json::json!({ "result_message": "unauthorized" }))
+                    } else {
+                        handle(&keys, &body)
+                    };
+                    let reply = reply.to_string();
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        status,
+                        reply.len(),
+                        reply
+                    );
+                }
+            });
+
+            Self { url }
+        }
+    }
+
+    fn read_request(stream: &mut TcpStream) -> (String, serde_json::Value) {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            data.extend_from_slice(&buf[..n]);
+            if let Some(split) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&data[..split]).to_string();
+                let length: usize = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                while data.len() < split + 4 + length {
+                    let n = stream.read(&mut buf).unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                }
+                let authorization = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Authorization: "))
+                    .unwrap_or_default()
+                    .to_string();
+                return (authorization, serde_json::from_slice(&data[split + 4..]).unwrap());
+            }
+        }
+    }
+
+    fn handle(
+        keys: &MockKeys,
+        request: &serde_json::Value,
+    ) -> (u16, serde_json::Value) {
+        let mut keys = keys.lock().unwrap();
+        let field = |name: &str| request[name].as_str().unwrap_or_default().to_string();
+        let bytes = |name: &str| hex::decode(field(name)).unwrap();
+        let not_found = (404, serde_json::json!({ "result_message": "Item_Not_Found" }));
+
+        match request["operation"].as_str().unwrap() {
+            "Create" => {
+                let id = hex::encode(random_bytes(8).unwrap());
+                keys.insert(id.clone(), (field("name"), random_bytes(32).unwrap()));
+                (200, serde_json::json!({ "unique_identifier": id }))
+            }
+            "Locate" => {
+                let ids: Vec<&String> = keys.iter()
+                    .filter(|(_, (name, _))| *name == field("name"))
+                    .map(|(id, _)| id)
+                    .collect();
+                (200, serde_json::json!({ "unique_identifiers": ids }))
+            }
+            "Encrypt" => match keys.get(&field("unique_identifier")) {
+                Some((_, key)) => {
+                    let sealed = seal(key, &bytes("data"), &bytes("aad")).unwrap();
+                    let (iv, data) = sealed.split_at(12);
+                    (200, serde_json::json!({ "data": hex::encode(data), "iv": hex::encode(iv) }))
+                }
+                None => not_found,
+            },
+            "Decrypt" => match keys.get(&field("unique_identifier")) {
+                Some((_, key)) => {
+                    let sealed = [bytes("iv"), bytes("data")].concat();
+                    match open(key, &sealed, &bytes("aad")) {
+                        Ok(data) => (200, serde_json::json!({ "data": hex::encode(data) })),
+                        Err(_) => (422, serde_json::json!({ "result_message": "Cryptographic_Failure" })),
+                    }
+                }
+                None => not_found,
+            },
+            "Destroy" => {
+                keys.remove(&field("unique_identifier"));
+                (200, serde_json::json!({}))
+            }
+            _ => (400, serde_json::json!({ "result_message": "Operation_Not_Supported" })),
+        }
+    }
+
+    #[test]
+    fn test_kms_store() {
+        let kms = MockKms::start("secret-token");
+        let store = KmsKeyStore::new(&kms.url, Some("secret-token".into())).unwrap();
+        super::super::exercise(&store);
+    }
+
+    #[test]
+    fn test_kms_keys_found_by_label() {
+        let kms = MockKms::start("secret-token");
+        let store = KmsKeyStore::new(&kms.url, Some("secret-token".into())).unwrap();
+        store.ensure_key("kek").unwrap();
+        let ciphertext = store.encrypt("kek", b"data", b"").unwrap();
+
+        // A fresh client locates the existing key instead of creating one
+        let store = KmsKeyStore::new(&kms.url, Some("secret-token".into())).unwrap();
+        assert!(store.has_key("kek").unwrap());
+        assert_eq!(store.decrypt("kek", &ciphertext, b"").unwrap(), b"data".to_vec());
+    }
+
+    #[test]
+    fn test_kms_rejects_bad_token_and_https() {
+        let kms = MockKms::start("secret-token");
+        let store = KmsKeyStore::new(&kms.url, Some("wrong".into())).unwrap();
+        assert!(store.ensure_key("kek").is_err());
+
+        assert!(KmsKeyStore::new("https://kms.example:443", None).is_err());
+    }
+
+    #[test]
+    fn test_kms_requires_loopback() {
+        assert!(matches!(
+            KmsKeyStore::new("http://192.0.2.1:5696", None),
+            Err(NodeError::Config(_))
+        ));
+        assert!(matches!(
+            KmsKeyStore::new("http://[2001:db8::1]:5696/v1", None),
+            Err(NodeError::Config(_))
+        ));
+        assert!(KmsKeyStore::new("http://127.0.0.1:5696", None).is_ok());
+        assert!(KmsKeyStore::new("http://[::1]:5696/v1", None).is_ok());
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n";
+        let (status, body) = parse_response(response).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"{\"a\":1}".to_vec());
+    }
+}
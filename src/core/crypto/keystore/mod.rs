@@ -0,0 +1,233 @@
+//! Pluggable storage for long-lived keys
+//!
+//! A `KeyStore` holds named AES-256 keys and exposes only authenticated
+//! encryption with them, so a backend can keep the key material somewhere
+//! the node never reads it (an HSM, a KMS). `KeyManager` uses a store to
+//! wrap its data keys; the recovery and permission managers encrypt through
+//! one directly.
+//!
+//! Backends:
+//! - `MemoryKeyStore`: process memory, for tests and volatile setups
+//! - `FileKeyStore`: keys in a local file, encrypted under a passphrase
+//! - `Pkcs11KeyStore`: non-extractable keys on a PKCS#11 token (feature
+//!   `pkcs11`, tested against SoftHSM)
+//! - `KmsKeyStore`: a KMIP-style KMS reached over HTTP
+
+pub mod file;
+pub mod kms;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+
+use crate::utils::{
+    config::StorageConfig,
+    error::{Result, NodeError},
+};
+use super::rng::system_rng;
+
+pub use file::FileKeyStore;
+pub use kms::KmsKeyStore;
+#[cfg(feature = "pkcs11")]
+pub use pkcs11::Pkcs11KeyStore;
+
+pub const KEY_LEN: usize = 32;
+
+/// Key file of the "file" store when `key_store_path` is not set, kept in
+/// the storage directory
+pub(crate) const DEFAULT_KEY_FILE: &str = "keys.json";
+const NONCE_LEN: usize = 12;
+
+/// Backend holding named AES-256 keys. Ciphertexts are opaque to callers
+/// and only decrypt with the store and label that produced them.
+pub trait KeyStore: Send + Sync {
+    /// Short backend name, recorded alongside data it protects
+    fn backend(&self) -> &'static str;
+
+    fn has_key(&self, label: &str) -> Result<bool>;
+
+    /// Create a key under `label` unless one exists
+    fn ensure_key(&self, label: &str) -> Result<()>;
+
+    fn encrypt(&self, label: &str, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>>;
+
+    fn decrypt(&self, label: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>>;
+
+    fn delete_key(&self, label: &str) -> Result<()>;
+}
+
+/// Keys held in process memory
+#[derive(Default)]
+pub struct MemoryKeyStore {
+    keys: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store holding a single caller-supplied key
+    pub fn with_key(label: &str, key: &[u8; KEY_LEN]) -> Self {
+        let store = Self::new();
+        store.keys.write().unwrap().insert(label.to_string(), key.to_vec());
+        store
+    }
+}
+
+impl KeyStore for MemoryKeyStore {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    fn has_key(&self, label: &str) -> Result<bool> {
+        Ok(self.keys.read().unwrap().contains_key(label))
+    }
+
+    fn ensure_key(&self, label: &str) -> Result<()> {
+        let mut keys = self.keys.write().unwrap();
+        if !keys.contains_key(label) {
+            keys.insert(label.to_string(), random_bytes(KEY_LEN)?);
+        }
+        Ok(())
+    }
+
+    fn encrypt(&self, label: &str, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let keys = self.keys.read().unwrap();
+        seal(key(&keys, label)?, plaintext, aad)
+    }
+
+    fn decrypt(&self, label: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let keys = self.keys.read().unwrap();
+        open(key(&keys, label)?, ciphertext, aad)
+    }
+
+    fn delete_key(&self, label: &str) -> Result<()> {
+        self.keys.write().unwrap().remove(label);
+        Ok(())
+    }
+}
+
+/// Build the key store selected by `storage.key_store`. Returns `None` for
+/// "passphrase", where the keyring derives its own KEK.
+pub fn from_config(config: &StorageConfig) -> Result<Option<Arc<dyn KeyStore>>> {
+    let required = |value: &Option<String>, name: &str| {
+        value.clone().ok_or_else(|| NodeError::Config(format!("{} must be set for key_store {}", name, config.key_store)))
+    };
+
+    let store: Arc<dyn KeyStore> = match config.key_store.as_str() {
+        "passphrase" => return Ok(None),
+        "file" => {
+            let path = config.key_store_path.clone()
+                .unwrap_or_else(|| Path::new(&config.path).join(DEFAULT_KEY_FILE).to_string_lossy().into_owned());
+            Arc::new(FileKeyStore::open(
+                Path::new(&path),
+                &config.encryption_key,
+                &crate::core::crypto::key_manager::KdfParams::from(config),
+            )?)
+        }
+        "kms" => Arc::new(KmsKeyStore::new(
+            &required(&config.kms_url, "kms_url")?,
+            config.kms_token.clone(),
+        )?),
+        #[cfg(feature = "pkcs11")]
+        "pkcs11" => Arc::new(Pkcs11KeyStore::open(
+            Path::new(&required(&config.pkcs11_module, "pkcs11_module")?),
+            &required(&config.pkcs11_token, "pkcs11_token")?,
+            &required(&config.pkcs11_pin, "pkcs11_pin")?,
+        )?),
+        #[cfg(not(feature = "pkcs11"))]
+        "pkcs11" => {
+            return Err(NodeError::Config("key_store pkcs11 needs the `pkcs11` feature".into()));
+        }
+        other => return Err(NodeError::Config(format!("Unknown key_store {}", other))),
+    };
+
+    Ok(Some(store))
+}
+
+fn key<'a>(keys: &'a HashMap<String, Vec<u8>>, label: &str) -> Result<&'a [u8]> {
+    keys.get(label)
+        .map(Vec::as_slice)
+        .ok_or_else(|| NodeError::Crypto(format!("No key with label {}", label)))
+}
+
+/// AES-256-GCM with a random nonce: `nonce || ciphertext || tag`
+pub(crate) fn seal(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|e| NodeError::Crypto(format!("Failed to initialize cipher: {}", e)))?;
+    let nonce = random_bytes(NONCE_LEN)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|e| NodeError::Crypto(format!("Encryption failed: {}", e)))?;
+    Ok([nonce, ciphertext].concat())
+}
+
+pub(crate) fn open(key: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if ciphertext.len() < NONCE_LEN {
+        return Err(NodeError::Crypto("Invalid ciphertext".into()));
+    }
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|e| NodeError::Crypto(format!("Failed to initialize cipher: {}", e)))?;
+    let (nonce, ciphertext) = ciphertext.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| NodeError::Crypto("Decryption failed".into()))
+}
+
+pub(crate) fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    system_rng()
+        .fill(&mut bytes)
+        .map_err(|_| NodeError::Crypto("Failed to generate random bytes".into()))?;
+    Ok(bytes)
+}
+
+/// Behaviour every backend has to share; run by each backend's tests
+#[cfg(test)]
+pub(crate) fn exercise(store: &dyn KeyStore) {
+    assert!(!store.has_key("exercise").unwrap());
+    assert!(store.encrypt("exercise", b"data", b"aad").is_err());
+
+    store.ensure_key("exercise").unwrap();
+    assert!(store.has_key("exercise").unwrap());
+
+    let ciphertext = store.encrypt("exercise", b"data", b"aad").unwrap();
+    assert_eq!(store.decrypt("exercise", &ciphertext, b"aad").unwrap(), b"data".to_vec());
+    assert!(store.decrypt("exercise", &ciphertext, b"other").is_err());
+
+    // Creating again keeps the existing key
+    store.ensure_key("exercise").unwrap();
+    assert_eq!(store.decrypt("exercise", &ciphertext, b"aad").unwrap(), b"data".to_vec());
+
+    store.ensure_key("exercise-other").unwrap();
+    assert!(store.decrypt("exercise-other", &ciphertext, b"aad").is_err());
+
+    store.delete_key("exercise").unwrap();
+    store.delete_key("exercise-other").unwrap();
+    assert!(!store.has_key("exercise").unwrap());
+    assert!(store.decrypt("exercise", &ciphertext, b"aad").is_err());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_store() {
+        exercise(&MemoryKeyStore::new());
+    }
+
+    #[test]
+    fn test_memory_store_with_key() {
+        let store = MemoryKeyStore::with_key("fixed", &[7u8; KEY_LEN]);
+        let ciphertext = store.encrypt("fixed", b"data", b"").unwrap();
+        assert_eq!(open(&[7u8; KEY_LEN], &ciphertext, b"").unwrap(), b"data".to_vec());
+    }
+}
@@ -0,0 +1,179 @@
+//! Key store on a PKCS#11 token
+//!
+//! Keys are generated on the token as sensitive, non-extractable AES-256
+//! secret keys and found again by label; encryption runs on the token with
+//! CKM_AES_GCM. The node only ever holds object handles. Tested against
+//! SoftHSM v2; set `SOFTHSM2_MODULE`, `PKCS11_TOKEN` and `PKCS11_PIN` to
+//! run the tests against an initialised token.
+
+use std::path::Path;
+use std::sync::Mutex;
+use cryptoki::{
+    context::{CInitializeArgs, Pkcs11},
+    error::{Error, RvError},
+    mechanism::{aead::GcmParams, Mechanism},
+    object::{Attribute, KeyType, ObjectClass, ObjectHandle},
+    session::{Session, UserType},
+    types::AuthPin,
+};
+
+use super::{KeyStore, KEY_LEN, random_bytes};
+use crate::utils::error::{Result, NodeError};
+
+const IV_LEN: usize = 12;
+const TAG_BITS: u64 = 128;
+
+pub struct Pkcs11KeyStore {
+    // Sessions may not be used from two threads at once
+    session: Mutex<Session>,
+    _context: Pkcs11,
+}
+
+impl Pkcs11KeyStore {
+    /// Load `module`, find the token labelled `token` and log in as user
+    pub fn open(module: &Path, token: &str, pin: &str) -> Result<Self> {
+        let context = Pkcs11::new(module).map_err(pkcs11_error)?;
+        match context.initialize(CInitializeArgs::OsThreads) {
+            // Another store in this process already initialised the module
+            Ok(()) | Err(Error::Pkcs11(RvError::CryptokiAlreadyInitialized)) => {}
+            Err(e) => return Err(pkcs11_error(e)),
+        }
+
+        let slot = context
+            .get_slots_with_token()
+            .map_err(pkcs11_error)?
+            .into_iter()
+            .find(|slot| {
+                context
+                    .get_token_info(*slot)
+                    .map(|info| info.label().trim() == token)
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| NodeError::Crypto(format!("PKCS#11 token {} not found", token)))?;
+
+        let session = context.open_rw_session(slot).map_err(pkcs11_error)?;
+        session
+            .login(UserType::User, Some(&AuthPin::new(pin.into())))
+            .map_err(pkcs11_error)?;
+
+        Ok(Self {
+            session: Mutex::new(session),
+            _context: context,
+        })
+    }
+
+    fn find(&self, session: &Session, label: &str) -> Result<Option<ObjectHandle>> {
+        let template = [
+            Attribute::Class(ObjectClass::SECRET_KEY),
+            Attribute::Label(label.as_bytes().to_vec()),
+        ];
+        Ok(session
+            .find_objects(&template)
+            .map_err(pkcs11_error)?
+            .into_iter()
+            .next())
+    }
+
+    fn handle(&self, session: &Session, label: &str) -> Result<ObjectHandle> {
+        self.find(session, label)?
+            .ok_or_else(|| NodeError::Crypto(format!("No key with label {}", label)))
+    }
+}
+
+impl KeyStore for Pkcs11KeyStore {
+    fn backend(&self) -> &'static str {
+        "pkcs11"
+    }
+
+    fn has_key(&self, label: &str) -> Result<bool> {
+        let session = self.session.lock().unwrap();
+        Ok(self.find(&session, label)?.is_some())
+    }
+
+    fn ensure_key(&self, label: &str) -> Result<()> {
+        let session = self.session.lock().unwrap();
+        if self.find(&session, label)?.is_some() {
+            return Ok(());
+        }
+
+        let template = [
+            Attribute::Class(ObjectClass::SECRET_KEY),
+            Attribute::KeyType(KeyType::AES),
+            Attribute::ValueLen((KEY_LEN as u64).into()),
+            Attribute::Label(label.as_bytes().to_vec()),
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Sensitive(true),
+            Attribute::Extractable(false),
+            Attribute::Encrypt(true),
+            Attribute::Decrypt(true),
+        ];
+        session
+            .generate_key(&Mechanism::AesKeyGen, &template)
+            .map_err(pkcs11_error)?;
+        Ok(())
+    }
+
+    /// Ciphertext layout: `iv || ciphertext || tag`
+    fn encrypt(&self, label: &str, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let session = self.session.lock().unwrap();
+        let key = self.handle(&session, label)?;
+
+        let iv = random_bytes(IV_LEN)?;
+        let mechanism = Mechanism::AesGcm(GcmParams::new(&iv, aad, TAG_BITS.into()));
+        let ciphertext = session
+            .encrypt(&mechanism, key, plaintext)
+            .map_err(pkcs11_error)?;
+
+        Ok([iv, ciphertext].concat())
+    }
+
+    fn decrypt(&self, label: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < IV_LEN {
+            return Err(NodeError::Crypto("Invalid ciphertext".into()));
+        }
+        let session = self.session.lock().unwrap();
+        let key = self.handle(&session, label)?;
+
+        let (iv, ciphertext) = ciphertext.split_at(IV_LEN);
+        let mechanism = Mechanism::AesGcm(GcmParams::new(iv, aad, TAG_BITS.into()));
+        session
+            .decrypt(&mechanism, key, ciphertext)
+            .map_err(|_| NodeError::Crypto("Decryption failed".into()))
+    }
+
+    fn delete_key(&self, label: &str) -> Result<()> {
+        let session = self.session.lock().unwrap();
+        if let Some(key) = self.find(&session, label)? {
+            session.destroy_object(key).map_err(pkcs11_error)?;
+        }
+        Ok(())
+    }
+}
+
+fn pkcs11_error(e: Error) -> NodeError {
+    NodeError::Crypto(format!("PKCS#11 error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against the token configured through the environment and is a
+    /// no-op without one. Kept as a single test so only one session is open.
+    #[test]
+    fn test_pkcs11_store() {
+        let Ok(module) = std::env::var("SOFTHSM2_MODULE") else {
+            eprintln!("SOFTHSM2_MODULE not set; skipping");
+            return;
+        };
+        let token = std::env::var("PKCS11_TOKEN").unwrap_or_else(|_| "freeghost-test".into());
+        let pin = std::env::var("PKCS11_PIN").unwrap_or_else(|_| "1234".into());
+
+        let store = Pkcs11KeyStore::open(Path::new(&module), &token, &pin).unwrap();
+        super::super::exercise(&store);
+        drop(store);
+
+        assert!(Pkcs11KeyStore::open(Path::new(&module), &token, "wrong-pin").is_err());
+    }
+}
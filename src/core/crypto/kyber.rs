@@ -4,12 +4,12 @@
 use crate::{
     utils::error::{Result, NodeError},
     core::crypto::{
-        ntt::NTTContext,
+        ntt::{NTTContext, barrett_reduce, cond_add_q},
         sampling::{random_poly, sample_cbd, expand_a},
+        rng::{CryptoRng, system_rng},
     },
 };
-use ring::rand::SystemRandom;
-use sha3::{Sha3_256, Shake256, digest::{Update, ExtendableOutput, XofReader}};
+use sha3::{Sha3_256, Digest};
 use std::convert::TryInto;
 
 // Kyber parameters for different security levels
@@ -24,40 +24,40 @@ const KYBER_DV: u8 = 4;
 /// Represents a polynomial in R_q = Z_q[X]/(X^n + 1)
 #[derive(Clone, Debug, PartialEq)]
 pub struct Polynomial {
-    coeffs: [i16; KYBER_N],
-    is_ntt: bool,  // Track if polynomial is in NTT form
+    pub(crate) coeffs: [i16; KYBER_N],
+    pub(crate) is_ntt: bool,  // Track if polynomial is in NTT form
 }
 
 /// Public key for Kyber KEM
 #[derive(Clone, Debug)]
 pub struct PublicKey {
     // Matrix A (k x k)
-    a: Vec<Vec<Polynomial>>,
+    pub(crate) a: Vec<Vec<Polynomial>>,
     // Vector t
-    t: Vec<Polynomial>,
+    pub(crate) t: Vec<Polynomial>,
 }
 
 /// Secret key for Kyber KEM
 #[derive(Clone, Debug)]
 pub struct SecretKey {
     // Vector s
-    s: Vec<Polynomial>,
+    pub(crate) s: Vec<Polynomial>,
     // Cached public key for re-encryption
-    public_key: PublicKey,
+    pub(crate) public_key: PublicKey,
 }
 
 /// Ciphertext for Kyber KEM
 #[derive(Clone, Debug)]
 pub struct Ciphertext {
     // Vector u
-    u: Vec<Polynomial>,
+    pub(crate) u: Vec<Polynomial>,
     // Scalar v
-    v: Polynomial,
+    pub(crate) v: Polynomial,
 }
 
 impl Polynomial {
     /// Create a new polynomial with zero coefficients
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             coeffs: [0; KYBER_N],
             is_ntt: false,
@@ -65,19 +65,14 @@ impl Polynomial {
     }
 
     /// Create a polynomial from coefficients
-    fn from_coeffs(coeffs: [i16; KYBER_N], is_ntt: bool) -> Self {
+    pub(crate) fn from_coeffs(coeffs: [i16; KYBER_N], is_ntt: bool) -> Self {
         Self { coeffs, is_ntt }
     }
 
     /// Add two polynomials in R_q
     fn add(&self, other: &Self) -> Self {
         assert_eq!(self.is_ntt, other.is_ntt, "Polynomials must be in same form");
-        let mut result = Self::new();
-        for i in 0..KYBER_N {
-            result.coeffs[i] = (self.coeffs[i] + other.coeffs[i]) % KYBER_Q;
-        }
-        result.is_ntt = self.is_ntt;
-        result
+        Self::from_coeffs(add_coeffs(&self.coeffs, &other.coeffs), self.is_ntt)
     }
 
     /// Multiply two polynomials using NTT
@@ -87,24 +82,20 @@ impl Polynomial {
         let mut b = other.clone();
         
         if !a.is_ntt {
-            a.to_ntt(ctx);
+            a.forward_ntt(ctx);
         }
         if !b.is_ntt {
-            b.to_ntt(ctx);
+            b.forward_ntt(ctx);
         }
 
-        // Pointwise multiplication in NTT domain
-        let mut result = Self::new();
-        for i in 0..KYBER_N {
-            result.coeffs[i] = ((a.coeffs[i] as i32 * b.coeffs[i] as i32) % KYBER_Q as i32) as i16;
-        }
-        result.is_ntt = true;
+        // Base multiplication in NTT domain
+        let result = Self::from_coeffs(ctx.basemul(&a.coeffs, &b.coeffs), true);
         
         Ok(result)
     }
 
     /// Convert polynomial to NTT form
-    fn to_ntt(&mut self, ctx: &NTTContext) {
+    fn forward_ntt(&mut self, ctx: &NTTContext) {
         if !self.is_ntt {
             ctx.forward(&mut self.coeffs);
             self.is_ntt = true;
@@ -112,7 +103,7 @@ impl Polynomial {
     }
 
     /// Convert polynomial from NTT form
-    fn from_ntt(&mut self, ctx: &NTTContext) {
+    fn inverse_ntt(&mut self, ctx: &NTTContext) {
         if self.is_ntt {
             ctx.inverse(&mut self.coeffs);
             self.is_ntt = false;
@@ -120,18 +111,18 @@ impl Polynomial {
     }
 
     /// Sample a polynomial with small coefficients
-    fn sample_noise(eta: u8) -> Result<Self> {
+    fn sample_noise(eta: u8, rng: &dyn CryptoRng) -> Result<Self> {
         Ok(Self {
-            coeffs: sample_cbd(eta)?.try_into().map_err(|_| 
+            coeffs: sample_cbd(eta, rng)?.try_into().map_err(|_| 
                 NodeError::Crypto("Invalid coefficient count".into()))?,
             is_ntt: false,
         })
     }
 
     /// Generate a random polynomial
-    fn random() -> Result<Self> {
+    fn random(rng: &dyn CryptoRng) -> Result<Self> {
         Ok(Self {
-            coeffs: random_poly(KYBER_Q)?.try_into().map_err(|_| 
+            coeffs: random_poly(KYBER_Q, rng)?.try_into().map_err(|_| 
                 NodeError::Crypto("Invalid coefficient count".into()))?,
             is_ntt: false,
         })
@@ -143,13 +134,16 @@ pub struct KyberKEM;
 impl KyberKEM {
     /// Generate a new key pair
     pub fn keygen() -> Result<(PublicKey, SecretKey)> {
+        Self::keygen_with_rng(&*system_rng())
+    }
+
+    /// Generate a key pair drawing all randomness from `rng`
+    pub fn keygen_with_rng(rng: &dyn CryptoRng) -> Result<(PublicKey, SecretKey)> {
         let ctx = NTTContext::new();
-        let rng = SystemRandom::new();
         
         // Generate random seed for matrix A
         let mut seed = [0u8; 32];
-        rng.fill(&mut seed)
-            .map_err(|_| NodeError::Crypto("Failed to generate random seed".into()))?;
+        rng.fill(&mut seed)?;
         
         // Generate matrix A using SHAKE-256
         let a_matrix = expand_a(&seed, KYBER_K)?;
@@ -157,38 +151,38 @@ impl KyberKEM {
         for i in 0..KYBER_K {
             for j in 0..KYBER_K {
                 a[i][j] = Polynomial::from_coeffs(
-                    a_matrix[i][j].try_into()
+                    a_matrix[i][j].clone().try_into()
                         .map_err(|_| NodeError::Crypto("Invalid matrix dimensions".into()))?,
                     false
                 );
-                a[i][j].to_ntt(&ctx);
+                a[i][j].forward_ntt(&ctx);
             }
         }
         
         // Sample secret vector s
         let mut s = Vec::with_capacity(KYBER_K);
         for _ in 0..KYBER_K {
-            let mut si = Polynomial::sample_noise(KYBER_ETA1)?;
-            si.to_ntt(&ctx);
+            let mut si = Polynomial::sample_noise(KYBER_ETA1, rng)?;
+            si.forward_ntt(&ctx);
             s.push(si);
         }
         
         // Sample error vector e
         let mut e = Vec::with_capacity(KYBER_K);
         for _ in 0..KYBER_K {
-            e.push(Polynomial::sample_noise(KYBER_ETA1)?);
+            e.push(Polynomial::sample_noise(KYBER_ETA1, rng)?);
         }
         
         // Compute t = As + e
         let mut t = Vec::with_capacity(KYBER_K);
         for i in 0..KYBER_K {
-            let mut ti = Polynomial::new();
+            let mut ti = Polynomial::from_coeffs([0; KYBER_N], true);
             for j in 0..KYBER_K {
                 let prod = a[i][j].multiply(&s[j], &ctx)?;
                 ti = ti.add(&prod);
             }
             let mut ei = e[i].clone();
-            ei.to_ntt(&ctx);
+            ei.forward_ntt(&ctx);
             ti = ti.add(&ei);
             t.push(ti);
         }
@@ -204,62 +198,65 @@ impl KyberKEM {
 
     /// Encapsulate a shared secret
     pub fn encapsulate(pk: &PublicKey) -> Result<(Vec<u8>, Ciphertext)> {
+        Self::encapsulate_with_rng(pk, &*system_rng())
+    }
+
+    /// Encapsulate drawing all randomness from `rng`
+    pub fn encapsulate_with_rng(pk: &PublicKey, rng: &dyn CryptoRng) -> Result<(Vec<u8>, Ciphertext)> {
         let ctx = NTTContext::new();
         
         // Sample random message
         let mut m = [0u8; 32];
-        SystemRandom::new()
-            .fill(&mut m)
-            .map_err(|_| NodeError::Crypto("Failed to generate random message".into()))?;
+        rng.fill(&mut m)?;
         
         // Sample noise vector r
         let mut r = Vec::with_capacity(KYBER_K);
         for _ in 0..KYBER_K {
-            let mut ri = Polynomial::sample_noise(KYBER_ETA1)?;
-            ri.to_ntt(&ctx);
+            let mut ri = Polynomial::sample_noise(KYBER_ETA1, rng)?;
+            ri.forward_ntt(&ctx);
             r.push(ri);
         }
         
         // Sample error vectors e1, e2
         let mut e1 = Vec::with_capacity(KYBER_K);
         for _ in 0..KYBER_K {
-            e1.push(Polynomial::sample_noise(KYBER_ETA2)?);
+            e1.push(Polynomial::sample_noise(KYBER_ETA2, rng)?);
         }
-        let e2 = Polynomial::sample_noise(KYBER_ETA2)?;
+        let e2 = Polynomial::sample_noise(KYBER_ETA2, rng)?;
         
         // Compute u = A^T r + e1
         let mut u = Vec::with_capacity(KYBER_K);
-        for i in 0..KYBER_K {
-            let mut ui = Polynomial::new();
-            for j in 0..KYBER_K {
-                let prod = pk.a[j][i].multiply(&r[j], &ctx)?;
+        for (i, e1i) in e1.iter().enumerate() {
+            let mut ui = Polynomial::from_coeffs([0; KYBER_N], true);
+            for (j, rj) in r.iter().enumerate() {
+                let prod = pk.a[j][i].multiply(rj, &ctx)?;
                 ui = ui.add(&prod);
             }
-            let mut e1i = e1[i].clone();
-            e1i.to_ntt(&ctx);
+            let mut e1i = e1i.clone();
+            e1i.forward_ntt(&ctx);
             ui = ui.add(&e1i);
-            ui.from_ntt(&ctx);
+            ui.inverse_ntt(&ctx);
             u.push(ui);
         }
         
         // Compute v = t^T r + e2 + ⌈q/2⌋ m
-        let mut v = Polynomial::new();
-        for i in 0..KYBER_K {
-            let prod = pk.t[i].multiply(&r[i], &ctx)?;
+        let mut v = Polynomial::from_coeffs([0; KYBER_N], true);
+        for (ti, ri) in pk.t.iter().zip(&r) {
+            let prod = ti.multiply(ri, &ctx)?;
             v = v.add(&prod);
         }
-        v.from_ntt(&ctx);
+        v.inverse_ntt(&ctx);
         v = v.add(&e2);
         
-        // Encode message in v
+        // Encode message in v; the bit selects q/2 through a mask, not a branch
         for i in 0..KYBER_N {
-            let m_bit = (m[i/8] >> (i%8)) & 1;
-            v.coeffs[i] = (v.coeffs[i] + ((KYBER_Q as i32 * m_bit as i32) / 2) as i16) % KYBER_Q;
+            let mask = -(((m[i/8] >> (i%8)) & 1) as i16);
+            v.coeffs[i] = barrett_reduce(v.coeffs[i] + (mask & (KYBER_Q / 2)));
         }
         
         // Derive shared secret
         let mut hasher = Sha3_256::new();
-        hasher.update(&m);
+        hasher.update(m);
         let mut shared_secret = [0u8; 32];
         shared_secret.copy_from_slice(&hasher.finalize());
         
@@ -277,29 +274,20 @@ impl KyberKEM {
         let mut v_prime = ct.v.clone();
         for i in 0..KYBER_K {
             let mut ui = ct.u[i].clone();
-            ui.to_ntt(&ctx);
+            ui.forward_ntt(&ctx);
             let prod = sk.s[i].multiply(&ui, &ctx)?;
             let mut sub = prod;
-            sub.from_ntt(&ctx);
+            sub.inverse_ntt(&ctx);
             for j in 0..KYBER_N {
-                v_prime.coeffs[j] = (v_prime.coeffs[j] - sub.coeffs[j]) % KYBER_Q;
+                v_prime.coeffs[j] = barrett_reduce(v_prime.coeffs[j] - sub.coeffs[j]);
             }
         }
         
-        // Decode message
-        let mut m = [0u8; 32];
-        for i in 0..KYBER_N {
-            let mut diff = v_prime.coeffs[i];
-            if diff < 0 {
-                diff += KYBER_Q;
-            }
-            let threshold = KYBER_Q / 4;
-            m[i/8] |= ((diff > threshold && diff <= (KYBER_Q - threshold)) as u8) << (i%8);
-        }
+        let m = decode_message(&v_prime.coeffs);
         
         // Derive shared secret
         let mut hasher = Sha3_256::new();
-        hasher.update(&m);
+        hasher.update(m);
         let mut shared_secret = [0u8; 32];
         shared_secret.copy_from_slice(&hasher.finalize());
         
@@ -307,9 +295,35 @@ impl KyberKEM {
     }
 }
 
+/// Coefficient-wise sum reduced into [0, q). Used on secret-dependent
+/// polynomials, so it reduces with `barrett_reduce` rather than `%`.
+pub fn add_coeffs(a: &[i16; KYBER_N], b: &[i16; KYBER_N]) -> [i16; KYBER_N] {
+    let mut sum = [0i16; KYBER_N];
+    for (s, (&x, &y)) in sum.iter_mut().zip(a.iter().zip(b)) {
+        *s = barrett_reduce(x + y);
+    }
+    sum
+}
+
+/// Decode the message bits from coefficients in (-q, q), rounding 2·coeff/q
+/// to the nearest bit. The coefficients derive from the secret key, so the
+/// division by q is done as a multiply by round(2^28 / q) and a shift, as in
+/// the reference implementation: integer division takes data-dependent time
+/// on many CPUs (KyberSlash).
+pub fn decode_message(coeffs: &[i16; KYBER_N]) -> [u8; 32] {
+    let mut m = [0u8; 32];
+    for (i, &coeff) in coeffs.iter().enumerate() {
+        let coeff = cond_add_q(coeff) as u32;
+        let bit = ((((coeff << 1) + 1665) * 80_635) >> 28) & 1;
+        m[i / 8] |= (bit as u8) << (i % 8);
+    }
+    m
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::crypto::rng::DeterministicRng;
 
     #[test]
     fn test_kyber_correctness() {
@@ -336,17 +350,49 @@ mod tests {
         assert_eq!(p3.coeffs, [0; KYBER_N]);
         
         // Test multiplication
-        let p4 = p1.multiply(&p2);
+        let p4 = p1.multiply(&p2, &NTTContext::new()).unwrap();
         assert_eq!(p4.coeffs, [0; KYBER_N]);
     }
 
     #[test]
     fn test_noise_sampling() {
-        let p = Polynomial::sample_noise(KYBER_ETA1).unwrap();
+        let p = Polynomial::sample_noise(KYBER_ETA1, &*system_rng()).unwrap();
         
         // Verify coefficients are within bounds
         for coeff in &p.coeffs {
             assert!(coeff.abs() <= KYBER_ETA1 as i16);
         }
     }
+
+    #[test]
+    fn test_seeded_kem_is_reproducible() {
+        let run = |seed: &[u8]| {
+            let rng = DeterministicRng::from_seed(seed);
+            let (pk, sk) = KyberKEM::keygen_with_rng(&rng).unwrap();
+            let (secret, ct) = KyberKEM::encapsulate_with_rng(&pk, &rng).unwrap();
+            (pk, sk, secret, ct)
+        };
+
+        let (pk1, sk1, secret1, ct1) = run(b"kyber-kat");
+        let (pk2, sk2, secret2, ct2) = run(b"kyber-kat");
+        assert_eq!(pk1.a, pk2.a);
+        assert_eq!(pk1.t, pk2.t);
+        assert_eq!(sk1.s, sk2.s);
+        assert_eq!(secret1, secret2);
+        assert_eq!((ct1.u, ct1.v), (ct2.u, ct2.v));
+
+        let (pk3, ..) = run(b"another seed");
+        assert_ne!(pk1.t, pk3.t);
+    }
+
+    #[test]
+    fn test_decode_message_rounds_like_division() {
+        for coeff in -(KYBER_Q - 1)..KYBER_Q {
+            let mut coeffs = [0; KYBER_N];
+            coeffs[9] = coeff;
+            let canonical = coeff.rem_euclid(KYBER_Q) as u32;
+            let expected = ((2 * canonical + KYBER_Q as u32 / 2) / KYBER_Q as u32) & 1;
+            assert_eq!(decode_message(&coeffs)[1] >> 1 & 1, expected as u8, "coefficient {}", coeff);
+        }
+    }
 }
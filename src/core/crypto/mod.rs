@@ -1,15 +1,32 @@
 //! Cryptographic primitives and implementations
 
+pub mod audit;
+pub mod audit_export;
+pub mod credentials;
 pub mod key_manager;
+pub mod keystore;
 pub mod quantum;
 pub mod kyber;
+pub mod dilithium;
 pub mod ntt;
+pub mod poisoning;
+#[cfg(target_arch = "x86_64")]
+mod ntt_avx2;
+pub mod rng;
 pub mod sampling;
+pub mod secure_memory;
 pub mod serialization;
+pub mod shamir;
+pub mod sigma;
+pub mod tokens;
+pub mod types;
+pub mod unseal;
+pub mod zkp;
 
 // Re-export commonly used types
 pub use kyber::{KyberKEM, PublicKey, SecretKey, Ciphertext};
-pub use ntt::NTTContext;
+pub use ntt::{NTTContext, NttBackend};
+pub use rng::{CryptoRng, SharedRng, system_rng};
 pub use serialization::{
     serialize_public_key, deserialize_public_key,
     serialize_secret_key, deserialize_secret_key,
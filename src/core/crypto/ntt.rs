@@ -1,207 +1,420 @@
 //! Number Theoretic Transform implementation for Kyber
 //! This module provides efficient polynomial multiplication in R_q
+//!
+//! All arithmetic that touches coefficients is branch-free: reductions use
+//! arithmetic shifts and masks instead of comparisons, and the only loops are
+//! over public bounds (N and the layer count). Twiddle factors are kept in
+//! Montgomery form so every multiplication is a single `montgomery_reduce`.
 
-use crate::utils::error::{Result, NodeError};
+#[cfg(target_arch = "x86_64")]
+use super::ntt_avx2;
 
 // NTT parameters for Kyber
-const N: usize = 256;
-const Q: i16 = 3329;
+pub(super) const N: usize = 256;
+pub(super) const Q: i16 = 3329;
 
-// Primitive root of unity modulo q
+// Primitive 256-th root of unity modulo q
 const ZETA: i16 = 17;
 
 /// Montgomery reduction constants
-const QINV: i32 = 62209;  // q^(-1) mod 2^16
+pub(super) const QINV: i16 = -3327;  // q^(-1) mod 2^16 (signed representative)
 const R: i32 = 1 << 16;   // R = 2^16
+pub(super) const R2_MOD_Q: i16 = 1353; // R^2 mod q, used to move values into Montgomery form
+
+/// Barrett constant: round(2^26 / q)
+pub(super) const BARRETT_V: i32 = ((1 << 26) + Q as i32 / 2) / Q as i32;
+
+/// Number of butterfly layers; Kyber's NTT stops at degree-one factors
+const LAYERS: usize = 7;
+
+/// Arithmetic backend selected for an `NTTContext`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NttBackend {
+    /// Portable scalar code, available everywhere
+    Scalar,
+    /// AVX2 vectorised code, 16 coefficients per instruction
+    Avx2,
+}
 
 /// Stores pre-computed twiddle factors for NTT
 pub struct NTTContext {
-    // Powers of zeta
-    zetas: [i16; N],
-    // Inverse powers of zeta
-    zetas_inv: [i16; N],
+    // Powers of zeta in bit-reversed order, Montgomery form
+    zetas: [i16; N / 2],
+    // Inverse powers of zeta in bit-reversed order, Montgomery form
+    zetas_inv: [i16; N / 2],
+    // (2^LAYERS)^(-1) in Montgomery form, applied at the end of `inverse`
+    n_inv: i16,
+    backend: NttBackend,
 }
 
 impl NTTContext {
-    /// Initialize NTT context with pre-computed twiddle factors
+    /// Initialize NTT context with pre-computed twiddle factors, using the
+    /// fastest backend the running CPU supports
     pub fn new() -> Self {
+        Self::with_backend(detect_backend())
+    }
+
+    /// Initialize NTT context that always uses the portable scalar code
+    pub fn scalar() -> Self {
+        Self::with_backend(NttBackend::Scalar)
+    }
+
+    fn with_backend(backend: NttBackend) -> Self {
         let mut ctx = Self {
-            zetas: [0; N],
-            zetas_inv: [0; N],
+            zetas: [0; N / 2],
+            zetas_inv: [0; N / 2],
+            n_inv: 0,
+            backend,
         };
-        
+
         // Pre-compute twiddle factors
         ctx.precompute_twiddle_factors();
         ctx
     }
 
+    /// Backend this context dispatches to
+    pub fn backend(&self) -> NttBackend {
+        self.backend
+    }
+
     /// Pre-compute twiddle factors (powers of zeta)
     fn precompute_twiddle_factors(&mut self) {
-        let mut tmp = 1i16;
-        for i in 0..N {
-            self.zetas[i] = tmp;
-            tmp = montgomery_reduce((tmp as i32 * ZETA as i32) % Q as i32) as i16;
+        for k in 0..N / 2 {
+            // Bit-reverse k over LAYERS bits
+            let exp = ((k as u8).reverse_bits() >> 1) as u32;
+            self.zetas[k] = to_mont(pow_mod(ZETA, exp));
+            // zeta^256 = 1, so zeta^(-e) = zeta^(256 - e)
+            self.zetas_inv[k] = to_mont(pow_mod(ZETA, N as u32 - exp));
         }
 
-        // Compute inverse twiddle factors
-        let zeta_inv = mod_inverse(ZETA, Q);
-        tmp = 1i16;
-        for i in 0..N {
-            self.zetas_inv[i] = tmp;
-            tmp = montgomery_reduce((tmp as i32 * zeta_inv as i32) % Q as i32) as i16;
-        }
+        self.n_inv = to_mont(mod_inverse(1 << LAYERS));
     }
 
     /// Forward Number Theoretic Transform
+    ///
+    /// Expects coefficients in `(-q, q)` and leaves them in `[0, q)`.
     pub fn forward(&self, a: &mut [i16; N]) {
-        let mut k = 1;
-        let mut len = N / 2;
+        match self.backend {
+            #[cfg(target_arch = "x86_64")]
+            NttBackend::Avx2 => unsafe { ntt_avx2::forward(&self.zetas, a) },
+            _ => forward_scalar(&self.zetas, a),
+        }
+    }
 
-        while len >= 1 {
-            let mut start = 0;
-            let mut j = 0;
+    /// Inverse Number Theoretic Transform
+    ///
+    /// Undoes `forward` exactly, including the 2^(-LAYERS) scaling.
+    pub fn inverse(&self, a: &mut [i16; N]) {
+        match self.backend {
+            #[cfg(target_arch = "x86_64")]
+            NttBackend::Avx2 => unsafe { ntt_avx2::inverse(&self.zetas_inv, self.n_inv, a) },
+            _ => inverse_scalar(&self.zetas_inv, self.n_inv, a),
+        }
+    }
 
-            while start < N {
-                let zeta = self.zetas[k];
-                k += 1;
+    /// Product mod q of two polynomials in NTT form
+    ///
+    /// The transform stops at degree-one factors, so coefficients `2i` and
+    /// `2i + 1` form a residue mod `X^2 - zeta^(2·br(i) + 1)` and are
+    /// multiplied as such (Kyber's base multiplication), not lane by lane.
+    /// Inputs must be in `[0, q)`; the output is in `[0, q)`.
+    pub fn basemul(&self, a: &[i16; N], b: &[i16; N]) -> [i16; N] {
+        let mut out = [0i16; N];
+        match self.backend {
+            #[cfg(target_arch = "x86_64")]
+            NttBackend::Avx2 => unsafe { ntt_avx2::basemul(&self.zetas, a, b, &mut out) },
+            _ => basemul_scalar(&self.zetas, a, b, &mut out),
+        }
+        out
+    }
+}
 
-                for i in start..(start + len) {
-                    let t = montgomery_reduce((zeta as i32 * a[i + len] as i32) % Q as i32) as i16;
-                    a[i + len] = barrett_reduce(a[i] - t);
-                    a[i] = barrett_reduce(a[i] + t);
-                }
+impl Default for NTTContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-                start = j + 2 * len;
-                j = start;
-            }
+/// Pick the fastest backend supported by the running CPU
+fn detect_backend() -> NttBackend {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return NttBackend::Avx2;
+        }
+    }
+    NttBackend::Scalar
+}
+
+pub(super) fn forward_scalar(zetas: &[i16; N / 2], a: &mut [i16; N]) {
+    let mut len = N / 2;
+    while len >= 2 {
+        forward_layer(zetas, a, len);
+        len >>= 1;
+    }
+}
+
+pub(super) fn inverse_scalar(zetas_inv: &[i16; N / 2], n_inv: i16, a: &mut [i16; N]) {
+    let mut len = 2;
+    while len <= N / 2 {
+        inverse_layer(zetas_inv, a, len);
+        len <<= 1;
+    }
+
+    // Multiply by 2^(-LAYERS) mod q
+    for coeff in a.iter_mut() {
+        *coeff = barrett_reduce(fqmul(n_inv, *coeff));
+    }
+}
 
-            len >>= 1;
+pub(super) fn basemul_scalar(zetas: &[i16; N / 2], a: &[i16; N], b: &[i16; N], out: &mut [i16; N]) {
+    // Each block of four holds two residues, mod X^2 - zeta and X^2 + zeta
+    for i in 0..N / 4 {
+        let zeta = zetas[N / 4 + i];
+        for (j, zeta) in [(4 * i, zeta), (4 * i + 2, -zeta)] {
+            let (r0, r1) = basemul_pair(a[j], a[j + 1], b[j], b[j + 1], zeta);
+            out[j] = r0;
+            out[j + 1] = r1;
         }
     }
+}
 
-    /// Inverse Number Theoretic Transform
-    pub fn inverse(&self, a: &mut [i16; N]) {
-        let mut k = N - 1;
-        let mut len = 1;
-
-        while len < N {
-            let mut start = 0;
-            let mut j = 0;
-
-            while start < N {
-                let zeta_inv = self.zetas_inv[k];
-                k -= 1;
-
-                for i in start..(start + len) {
-                    let t = a[i];
-                    a[i] = barrett_reduce(t + a[i + len]);
-                    a[i + len] = montgomery_reduce((zeta_inv as i32 * 
-                        barrett_reduce(t - a[i + len]) as i32) % Q as i32) as i16;
-                }
+/// `(a0 + a1 X)(b0 + b1 X) mod (X^2 - zeta)` for `zeta` in Montgomery form
+pub(super) fn basemul_pair(a0: i16, a1: i16, b0: i16, b1: i16, zeta: i16) -> (i16, i16) {
+    let r0 = fqmul(fqmul(a1, b1), zeta) + fqmul(a0, b0);
+    let r1 = fqmul(a0, b1) + fqmul(a1, b0);
+    // fqmul leaves a factor R^(-1); the final fqmul by R^2 cancels it
+    (barrett_reduce(fqmul(r0, R2_MOD_Q)), barrett_reduce(fqmul(r1, R2_MOD_Q)))
+}
 
-                start = j + 2 * len;
-                j = start;
-            }
+/// One Cooley-Tukey layer with butterflies of half-width `len`
+pub(super) fn forward_layer(zetas: &[i16; N / 2], a: &mut [i16; N], len: usize) {
+    let mut start = 0;
 
-            len <<= 1;
+    while start < N {
+        let zeta = zetas[twiddle_index(len, start)];
+
+        for i in start..(start + len) {
+            let t = fqmul(zeta, a[i + len]);
+            a[i + len] = barrett_reduce(a[i] - t);
+            a[i] = barrett_reduce(a[i] + t);
         }
 
-        // Multiply by N^(-1) mod q
-        let n_inv = mod_inverse(N as i16, Q);
-        for i in 0..N {
-            a[i] = montgomery_reduce((a[i] as i32 * n_inv as i32) % Q as i32) as i16;
+        start += 2 * len;
+    }
+}
+
+/// One Gentleman-Sande layer with butterflies of half-width `len`
+pub(super) fn inverse_layer(zetas_inv: &[i16; N / 2], a: &mut [i16; N], len: usize) {
+    let mut start = 0;
+
+    while start < N {
+        let zeta_inv = zetas_inv[twiddle_index(len, start)];
+
+        for i in start..(start + len) {
+            let t = a[i];
+            a[i] = barrett_reduce(t + a[i + len]);
+            a[i + len] = barrett_reduce(fqmul(zeta_inv, t - a[i + len]));
         }
+
+        start += 2 * len;
     }
 }
 
+/// Index of the twiddle factor used by the block starting at `start` in the
+/// layer with half-width `len`. Forward and inverse share this so that each
+/// inverse butterfly uses exactly the inverse of its forward twiddle.
+pub(super) fn twiddle_index(len: usize, start: usize) -> usize {
+    (N / 2) / len + start / (2 * len)
+}
+
 /// Montgomery reduction
-/// Computes aR^(-1) mod q where R = 2^16
-fn montgomery_reduce(a: i32) -> i32 {
-    let u = (a * QINV) & ((1 << 16) - 1);
-    let t = (a - u * Q as i32) >> 16;
-    if t >= Q as i32 {
-        t - Q as i32
-    } else {
-        t
-    }
+/// Computes aR^(-1) mod q where R = 2^16, for |a| < q * 2^15.
+/// The result lies in (-q, q).
+pub(crate) fn montgomery_reduce(a: i32) -> i16 {
+    let t = (a as i16).wrapping_mul(QINV);
+    ((a - t as i32 * Q as i32) >> 16) as i16
+}
+
+/// Multiplication followed by Montgomery reduction
+pub(crate) fn fqmul(a: i16, b: i16) -> i16 {
+    montgomery_reduce(a as i32 * b as i32)
 }
 
 /// Barrett reduction
-/// Reduces input mod q
-fn barrett_reduce(a: i16) -> i16 {
-    let v = ((a as i32 * 20159) + (1 << 25)) >> 26;
-    let t = a as i32 - v * Q as i32;
-    t as i16
+/// Reduces input mod q into the canonical range [0, q)
+pub(crate) fn barrett_reduce(a: i16) -> i16 {
+    let t = ((BARRETT_V * a as i32 + (1 << 25)) >> 26) as i16;
+    cond_add_q(a.wrapping_sub(t.wrapping_mul(Q)))
 }
 
-/// Compute modular multiplicative inverse using extended Euclidean algorithm
-fn mod_inverse(a: i16, m: i16) -> i16 {
-    let mut t = 0i16;
-    let mut newt = 1i16;
-    let mut r = m;
-    let mut newr = a;
+/// Adds q if `a` is negative, without branching
+pub(crate) fn cond_add_q(a: i16) -> i16 {
+    a + ((a >> 15) & Q)
+}
 
-    while newr != 0 {
-        let quotient = r / newr;
-        (t, newt) = (newt, t - quotient * newt);
-        (r, newr) = (newr, r - quotient * newr);
-    }
+/// Subtracts q if `a >= q`, without branching
+pub(crate) fn cond_sub_q(a: i16) -> i16 {
+    cond_add_q(a - Q)
+}
+
+/// Convert a canonical value into Montgomery form (aR mod q)
+fn to_mont(a: i16) -> i16 {
+    barrett_reduce(fqmul(a, R2_MOD_Q))
+}
+
+/// Modular exponentiation in Montgomery form.
+/// Only the exponent steers control flow, and every caller passes a public
+/// exponent; the base is never branched on.
+fn pow_mod(base: i16, exp: u32) -> i16 {
+    let base = to_mont(base);
+    let mut acc = to_mont(1);
 
-    if t < 0 {
-        t += m;
+    for bit in (0..u32::BITS - exp.leading_zeros()).rev() {
+        acc = fqmul(acc, acc);
+        if (exp >> bit) & 1 == 1 {
+            acc = fqmul(acc, base);
+        }
     }
-    t
+
+    barrett_reduce(montgomery_reduce(acc as i32))
+}
+
+/// Compute the modular multiplicative inverse via Fermat's little theorem.
+/// The exponent q - 2 is fixed, so the running time is independent of `a`.
+fn mod_inverse(a: i16) -> i16 {
+    pow_mod(a, (Q - 2) as u32)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rand::{Rng, thread_rng};
+    use crate::core::crypto::rng::{CryptoRng, DeterministicRng};
+
+    /// Polynomial with coefficients uniform in `range`
+    fn random_poly(rng: &DeterministicRng, range: std::ops::Range<i16>) -> [i16; N] {
+        let mut bytes = [0u8; 2 * N];
+        rng.fill(&mut bytes).unwrap();
+        let width = (range.end - range.start) as u16;
+        let mut a = [0i16; N];
+        for (coeff, pair) in a.iter_mut().zip(bytes.chunks_exact(2)) {
+            *coeff = range.start + (u16::from_le_bytes([pair[0], pair[1]]) % width) as i16;
+        }
+        a
+    }
+
+    /// Negacyclic schoolbook product mod q: X^N = -1
+    fn schoolbook(a: &[i16; N], b: &[i16; N]) -> [i16; N] {
+        let mut acc = [0i64; N];
+        for i in 0..N {
+            for j in 0..N {
+                let product = a[i] as i64 * b[j] as i64;
+                if i + j < N {
+                    acc[i + j] += product;
+                } else {
+                    acc[i + j - N] -= product;
+                }
+            }
+        }
+        acc.map(|c| c.rem_euclid(Q as i64) as i16)
+    }
 
     #[test]
     fn test_ntt_roundtrip() {
         let ctx = NTTContext::new();
-        let mut rng = thread_rng();
-        
+        let rng = DeterministicRng::from_seed(b"ntt-roundtrip");
+
         // Generate random polynomial
-        let mut a = [0i16; N];
-        for coeff in &mut a {
-            *coeff = rng.gen_range(0..Q);
-        }
-        
+        let mut a = random_poly(&rng, 0..Q);
+
         // Save original
         let original = a;
-        
+
         // Forward NTT
         ctx.forward(&mut a);
-        
+
         // Inverse NTT
         ctx.inverse(&mut a);
-        
+
         // Check if we got back the original polynomial
         for i in 0..N {
             assert_eq!(barrett_reduce(a[i]), barrett_reduce(original[i]));
         }
     }
 
+    #[test]
+    fn test_basemul_is_negacyclic_product() {
+        let rng = DeterministicRng::from_seed(b"ntt-basemul");
+        for ctx in [NTTContext::scalar(), NTTContext::new()] {
+            for _ in 0..20 {
+                let a = random_poly(&rng, 0..Q);
+                let b = random_poly(&rng, 0..Q);
+                let (mut a_hat, mut b_hat) = (a, b);
+                ctx.forward(&mut a_hat);
+                ctx.forward(&mut b_hat);
+                let mut product = ctx.basemul(&a_hat, &b_hat);
+                ctx.inverse(&mut product);
+                assert_eq!(product, schoolbook(&a, &b));
+            }
+
+            // X · X^255 = X^256 = -1
+            let mut x = [0i16; N];
+            x[1] = 1;
+            let mut x255 = [0i16; N];
+            x255[255] = 1;
+            let mut expected = [0i16; N];
+            expected[0] = Q - 1;
+            ctx.forward(&mut x);
+            ctx.forward(&mut x255);
+            let mut product = ctx.basemul(&x, &x255);
+            ctx.inverse(&mut product);
+            assert_eq!(product, expected);
+        }
+    }
+
     #[test]
     fn test_montgomery_reduction() {
-        let a = 12345i32;
+        let a = 12345i32 % Q as i32;
         let reduced = montgomery_reduce(a * R % (Q as i32));
-        assert!(reduced >= 0 && reduced < Q as i32);
+        assert!(reduced > -Q && reduced < Q);
+        assert_eq!(cond_add_q(reduced) as i32, a);
     }
 
     #[test]
     fn test_barrett_reduction() {
         let a = 12345i16;
         let reduced = barrett_reduce(a);
-        assert!(reduced >= 0 && reduced < Q);
+        assert!((0..Q).contains(&reduced));
+        assert_eq!(reduced, a % Q);
+
+        // Extremes of the i16 range must not overflow
+        assert_eq!(barrett_reduce(i16::MAX), (i16::MAX % Q));
+        assert_eq!(barrett_reduce(i16::MIN), (i16::MIN % Q + Q) % Q);
+    }
+
+    #[test]
+    fn test_conditional_reductions() {
+        for a in -Q..(2 * Q) {
+            let expected = ((a % Q) + Q) % Q;
+            if a < Q {
+                assert_eq!(cond_add_q(a), if a < 0 { a + Q } else { a });
+            }
+            if a >= 0 {
+                assert_eq!(cond_sub_q(a), expected);
+            }
+        }
     }
 
     #[test]
     fn test_mod_inverse() {
         let a = 17i16;
-        let inv = mod_inverse(a, Q);
+        let inv = mod_inverse(a);
         assert_eq!((a as i32 * inv as i32) % Q as i32, 1);
     }
+
+    #[test]
+    fn test_zeta_is_primitive_root() {
+        assert_eq!(pow_mod(ZETA, 256), 1);
+        assert_eq!(pow_mod(ZETA, 128), Q - 1);
+    }
 }
@@ -0,0 +1,280 @@
+//! AVX2 backend for the Kyber NTT
+//! Processes 16 coefficients per instruction and produces bit-identical
+//! results to the scalar code in `ntt.rs`.
+//!
+//! Every function here requires AVX2; callers must check for it at runtime
+//! (see `NTTContext::new`) before dispatching.
+
+use std::arch::x86_64::*;
+
+use super::ntt::{
+    N, Q, QINV, R2_MOD_Q, BARRETT_V,
+    forward_layer, inverse_layer, twiddle_index,
+};
+
+/// Coefficients per 256-bit register
+const LANES: usize = 16;
+
+/// Montgomery multiplication of 16 lanes, identical to scalar `fqmul`.
+///
+/// The low halves of `a*b` and `t*q` are equal by construction, so the
+/// difference of the high halves is exactly `(a*b - t*q) >> 16`.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn fqmul(a: __m256i, b: __m256i) -> __m256i {
+    let lo = _mm256_mullo_epi16(a, b);
+    let hi = _mm256_mulhi_epi16(a, b);
+    let t = _mm256_mullo_epi16(lo, _mm256_set1_epi16(QINV));
+    let tq = _mm256_mulhi_epi16(t, _mm256_set1_epi16(Q));
+    _mm256_sub_epi16(hi, tq)
+}
+
+/// Adds q to negative lanes
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn cond_add_q(a: __m256i) -> __m256i {
+    let mask = _mm256_srai_epi16(a, 15);
+    _mm256_add_epi16(a, _mm256_and_si256(mask, _mm256_set1_epi16(Q)))
+}
+
+/// Subtracts q from lanes that are `>= q`
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn cond_sub_q(a: __m256i) -> __m256i {
+    cond_add_q(_mm256_sub_epi16(a, _mm256_set1_epi16(Q)))
+}
+
+/// Barrett reduction of 16 lanes into `[0, q)`.
+///
+/// Uses a floored quotient (the scalar code rounds), which leaves the
+/// remainder in `[-q, 2q)`; the two conditional corrections bring it to the
+/// same canonical value the scalar code returns.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn barrett_reduce(a: __m256i) -> __m256i {
+    let t = _mm256_mulhi_epi16(a, _mm256_set1_epi16(BARRETT_V as i16));
+    let t = _mm256_srai_epi16(t, 10);
+    let r = _mm256_sub_epi16(a, _mm256_mullo_epi16(t, _mm256_set1_epi16(Q)));
+    cond_sub_q(cond_add_q(r))
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn load(a: &[i16; N], i: usize) -> __m256i {
+    _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i)
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn store(a: &mut [i16; N], i: usize, v: __m256i) {
+    _mm256_storeu_si256(a.as_mut_ptr().add(i) as *mut __m256i, v)
+}
+
+/// Forward NTT. Layers whose butterflies span at least one register are
+/// vectorised; the last three layers (half-width 8, 4, 2) would need lane
+/// shuffles and stay scalar.
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn forward(zetas: &[i16; N / 2], a: &mut [i16; N]) {
+    let mut len = N / 2;
+
+    while len >= LANES {
+        let mut start = 0;
+
+        while start < N {
+            let zeta = _mm256_set1_epi16(zetas[twiddle_index(len, start)]);
+
+            for i in (start..start + len).step_by(LANES) {
+                let lo = load(a, i);
+                let t = fqmul(zeta, load(a, i + len));
+                store(a, i + len, barrett_reduce(_mm256_sub_epi16(lo, t)));
+                store(a, i, barrett_reduce(_mm256_add_epi16(lo, t)));
+            }
+
+            start += 2 * len;
+        }
+
+        len >>= 1;
+    }
+
+    while len >= 2 {
+        forward_layer(zetas, a, len);
+        len >>= 1;
+    }
+}
+
+/// Inverse NTT, including the final 2^(-7) scaling
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn inverse(zetas_inv: &[i16; N / 2], n_inv: i16, a: &mut [i16; N]) {
+    let mut len = 2;
+
+    while len < LANES {
+        inverse_layer(zetas_inv, a, len);
+        len <<= 1;
+    }
+
+    while len <= N / 2 {
+        let mut start = 0;
+
+        while start < N {
+            let zeta_inv = _mm256_set1_epi16(zetas_inv[twiddle_index(len, start)]);
+
+            for i in (start..start + len).step_by(LANES) {
+                let t = load(a, i);
+                let hi = load(a, i + len);
+                store(a, i, barrett_reduce(_mm256_add_epi16(t, hi)));
+                store(a, i + len, barrett_reduce(fqmul(zeta_inv, _mm256_sub_epi16(t, hi))));
+            }
+
+            start += 2 * len;
+        }
+
+        len <<= 1;
+    }
+
+    let n_inv = _mm256_set1_epi16(n_inv);
+    for i in (0..N).step_by(LANES) {
+        store(a, i, barrett_reduce(fqmul(n_inv, load(a, i))));
+    }
+}
+
+/// Swaps each even lane with the odd lane after it
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn swap_pairs(a: __m256i) -> __m256i {
+    const SWAP: i32 = 0b10_11_00_01;
+    _mm256_shufflehi_epi16(_mm256_shufflelo_epi16(a, SWAP), SWAP)
+}
+
+/// Base multiplication of the degree-one residues, identical to scalar
+/// `basemul_scalar`. A register holds four blocks of two residues each,
+/// so even lanes get `a0·b0 + zeta·a1·b1` and odd lanes `a0·b1 + a1·b0`.
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn basemul(zetas: &[i16; N / 2], a: &[i16; N], b: &[i16; N], out: &mut [i16; N]) {
+    let r2 = _mm256_set1_epi16(R2_MOD_Q);
+    for i in (0..N).step_by(LANES) {
+        let mut lane_zetas = [0i16; LANES];
+        for (block, lanes) in lane_zetas.chunks_exact_mut(4).enumerate() {
+            let zeta = zetas[N / 4 + i / 4 + block];
+            lanes.copy_from_slice(&[zeta, zeta, -zeta, -zeta]);
+        }
+        let zeta = _mm256_loadu_si256(lane_zetas.as_ptr() as *const __m256i);
+
+        let a = load(a, i);
+        let b = load(b, i);
+        // [a0·b0, a1·b1, ...] and [a0·b1, a1·b0, ...]
+        let products = fqmul(a, b);
+        let cross = fqmul(a, swap_pairs(b));
+
+        let even = _mm256_add_epi16(fqmul(swap_pairs(products), zeta), products);
+        let odd = _mm256_add_epi16(swap_pairs(cross), cross);
+        let r = _mm256_blend_epi16(even, odd, 0b1010_1010);
+        store(out, i, barrett_reduce(fqmul(r, r2)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::crypto::{
+        ntt::{self, NTTContext, NttBackend},
+        rng::{CryptoRng, DeterministicRng},
+    };
+
+    fn avx2_available() -> bool {
+        is_x86_feature_detected!("avx2")
+    }
+
+    fn random_poly(rng: &DeterministicRng, range: std::ops::Range<i16>) -> [i16; N] {
+        let mut bytes = [0u8; 2 * N];
+        rng.fill(&mut bytes).unwrap();
+        let width = (range.end - range.start) as u16;
+        let mut a = [0i16; N];
+        for (coeff, pair) in a.iter_mut().zip(bytes.chunks_exact(2)) {
+            *coeff = range.start + (u16::from_le_bytes([pair[0], pair[1]]) % width) as i16;
+        }
+        a
+    }
+
+    #[test]
+    fn test_backend_detection() {
+        let ctx = NTTContext::new();
+        if avx2_available() {
+            assert_eq!(ctx.backend(), NttBackend::Avx2);
+        } else {
+            assert_eq!(ctx.backend(), NttBackend::Scalar);
+        }
+        assert_eq!(NTTContext::scalar().backend(), NttBackend::Scalar);
+    }
+
+    #[test]
+    fn test_reductions_match_scalar() {
+        if !avx2_available() {
+            return;
+        }
+
+        let mut input = [0i16; LANES];
+        let mut output = [0i16; LANES];
+        for base in (i16::MIN as i32..=i16::MAX as i32).step_by(LANES) {
+            for (j, lane) in input.iter_mut().enumerate() {
+                *lane = (base + j as i32) as i16;
+            }
+            unsafe {
+                let v = _mm256_loadu_si256(input.as_ptr() as *const __m256i);
+                _mm256_storeu_si256(output.as_mut_ptr() as *mut __m256i, barrett_reduce(v));
+            }
+            for j in 0..LANES {
+                assert_eq!(output[j], ntt::barrett_reduce(input[j]), "barrett({})", input[j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fqmul_matches_scalar() {
+        if !avx2_available() {
+            return;
+        }
+
+        let rng = DeterministicRng::from_seed(b"avx2-fqmul");
+        for _ in 0..1000 {
+            let a = random_poly(&rng, -Q..Q);
+            let b = random_poly(&rng, 0..Q);
+            let mut out = [0i16; N];
+            unsafe {
+                for i in (0..N).step_by(LANES) {
+                    store(&mut out, i, fqmul(load(&a, i), load(&b, i)));
+                }
+            }
+            for i in 0..N {
+                assert_eq!(out[i], ntt::fqmul(a[i], b[i]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_transforms_match_scalar() {
+        if !avx2_available() {
+            return;
+        }
+
+        let scalar = NTTContext::scalar();
+        let simd = NTTContext::new();
+
+        let rng = DeterministicRng::from_seed(b"avx2-transforms");
+        for _ in 0..100 {
+            let original = random_poly(&rng, -Q + 1..Q);
+
+            let mut a = original;
+            let mut b = original;
+            scalar.forward(&mut a);
+            simd.forward(&mut b);
+            assert_eq!(a, b);
+
+            let c = random_poly(&rng, 0..Q);
+            assert_eq!(scalar.basemul(&a, &c), simd.basemul(&b, &c));
+
+            scalar.inverse(&mut a);
+            simd.inverse(&mut b);
+            assert_eq!(a, b);
+        }
+    }
+}
@@ -1,11 +1,44 @@
 // src/core/crypto/poisoning/detector.rs
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
-use sha3::{Sha3_256, Digest};
-use tokio::sync::RwLock;
+
+//! Background integrity scanning of guarded memory
+//!
+//! Regions are `SecureMemory` (or `Secret`) values behind a lock that the
+//! owner keeps in an `Arc`; the detector only holds a `Weak` and forgets a
+//! region once its owner drops it. Every `check_interval` it checks each
+//! region's canary and contents digest, scans raw regions for spray
+//! patterns, and flags key material whose last check is overdue. Alerts go
+//! to the `AlertHandler` (normally the audit log) and, at High or
+//! Critical, trigger the configured `PoisonResponse`.
+//!
+//! The keyring registers its root secret and data keys as it allocates
+//! them, under the audit session of the running node; the API refuses
+//! requests while that session is suspended.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+use async_trait::async_trait;
+use parking_lot::RwLock as SyncRwLock;
+use serde_json::json;
+use tokio::sync::{Notify, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 use tracing::{info, warn, error};
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+use crate::core::crypto::{
+    audit::{AnomalySeverity, AuditEventType, AuditSystem},
+    secure_memory::{Secret, SecureMemory, SecureMemoryError},
+};
+
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// A key region not checked for this many intervals means scanning stalled
+const STALL_INTERVALS: u32 = 10;
+const MIN_STALL: Duration = Duration::from_secs(1);
+// Runs this long of one filler byte do not occur in key material
+const SPRAY_RUN: usize = 16;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MemoryRegionType {
@@ -19,23 +52,23 @@ pub enum MemoryRegionType {
 pub struct MemoryRegion {
     id: String,
     region_type: MemoryRegionType,
-    size: usize,
-    canary_positions: Vec<usize>,
+    session_id: Option<Uuid>,
+    memory: Weak<dyn WatchedRegion>,
     last_check: SystemTime,
-    pattern_hash: [u8; 32],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PoisoningAlert {
     pub timestamp: SystemTime,
     pub memory_region: String,
     pub region_type: MemoryRegionType,
+    pub session_id: Option<Uuid>,
     pub detection_type: DetectionType,
     pub severity: AlertSeverity,
     pub pattern_mismatch: Option<Vec<usize>>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DetectionType {
     CanaryModification,
     PatternMismatch,
@@ -43,7 +76,8 @@ pub enum DetectionType {
     TimingAnomaly,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Ordered most severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum AlertSeverity {
     Critical,
     High,
@@ -51,209 +85,344 @@ pub enum AlertSeverity {
     Low,
 }
 
+impl From<AlertSeverity> for AnomalySeverity {
+    fn from(severity: AlertSeverity) -> Self {
+        match severity {
+            AlertSeverity::Critical => AnomalySeverity::Critical,
+            AlertSeverity::High => AnomalySeverity::High,
+            AlertSeverity::Medium => AnomalySeverity::Medium,
+            AlertSeverity::Low => AnomalySeverity::Low,
+        }
+    }
+}
+
+/// What the node does about a High or Critical alert, beyond reporting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoisonResponse {
+    /// Report only
+    Alert,
+    /// Zeroize the affected region and every key material region
+    ZeroizeKeys,
+    /// Suspend the session the affected region belongs to
+    SuspendSessions,
+    /// Ask the node to shut down
+    Shutdown,
+}
+
+impl FromStr for PoisonResponse {
+    type Err = MemoryProtectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alert" => Ok(PoisonResponse::Alert),
+            "zeroize" => Ok(PoisonResponse::ZeroizeKeys),
+            "suspend" => Ok(PoisonResponse::SuspendSessions),
+            "shutdown" => Ok(PoisonResponse::Shutdown),
+            other => Err(MemoryProtectionError::UnknownResponse(other.to_string())),
+        }
+    }
+}
+
+/// Memory the detector can watch
+pub trait WatchedRegion: Send + Sync {
+    fn check_integrity(&self) -> Result<(), SecureMemoryError>;
+
+    /// Offsets where `patterns` match, with the matching pattern's severity.
+    /// Typed values have no meaningful byte layout and are not scanned.
+    fn find_patterns(&self, _patterns: &[Pattern]) -> Result<Vec<(usize, AlertSeverity)>, SecureMemoryError> {
+        Ok(Vec::new())
+    }
+
+    fn zeroize(&self);
+}
+
+impl WatchedRegion for SyncRwLock<SecureMemory> {
+    fn check_integrity(&self) -> Result<(), SecureMemoryError> {
+        self.read().check_integrity()
+    }
+
+    fn find_patterns(&self, patterns: &[Pattern]) -> Result<Vec<(usize, AlertSeverity)>, SecureMemoryError> {
+        let memory = self.read();
+        let bytes = memory.access()?;
+        Ok(patterns
+            .iter()
+            .flat_map(|pattern| pattern.find(&bytes).into_iter().map(|pos| (pos, pattern.severity)))
+            .collect())
+    }
+
+    fn zeroize(&self) {
+        self.write().clear();
+    }
+}
+
+impl<T: Zeroize + Send + Sync> WatchedRegion for SyncRwLock<Secret<T>> {
+    fn check_integrity(&self) -> Result<(), SecureMemoryError> {
+        self.read().check_integrity()
+    }
+
+    fn zeroize(&self) {
+        if let Ok(mut value) = self.write().expose_mut() {
+            value.zeroize();
+        }
+    }
+}
+
 pub struct MemoryPoisonDetector {
-    regions: Arc<RwLock<HashMap<String, MemoryRegion>>>,
-    canary_values: Arc<RwLock<HashMap<String, Vec<AtomicU64>>>>,
+    regions: Arc<SyncRwLock<HashMap<String, MemoryRegion>>>,
     alert_handler: Box<dyn AlertHandler + Send + Sync>,
     check_interval: Duration,
     last_full_scan: Arc<RwLock<SystemTime>>,
     scan_patterns: Arc<RwLock<Vec<Pattern>>>,
+    response: PoisonResponse,
+    suspended_sessions: Arc<SyncRwLock<HashSet<Uuid>>>,
+    shutdown: Arc<Notify>,
 }
 
-#[derive(Clone)]
-struct Pattern {
+/// Byte sequence to look for; positions where `mask` is false match any
+/// byte
+#[derive(Debug, Clone)]
+pub struct Pattern {
     sequence: Vec<u8>,
     mask: Vec<bool>,
     severity: AlertSeverity,
 }
 
+impl Pattern {
+    pub fn new(sequence: Vec<u8>, severity: AlertSeverity) -> Self {
+        let mask = vec![true; sequence.len()];
+        Self { sequence, mask, severity }
+    }
+
+    pub fn masked(sequence: Vec<u8>, mask: Vec<bool>, severity: AlertSeverity) -> Self {
+        assert_eq!(sequence.len(), mask.len(), "pattern and mask differ in length");
+        Self { sequence, mask, severity }
+    }
+
+    fn find(&self, bytes: &[u8]) -> Vec<usize> {
+        if self.sequence.is_empty() || bytes.len() < self.sequence.len() {
+            return Vec::new();
+        }
+        (0..=bytes.len() - self.sequence.len())
+            .filter(|&start| {
+                self.sequence
+                    .iter()
+                    .zip(&self.mask)
+                    .enumerate()
+                    .all(|(i, (&expected, &care))| !care || bytes[start + i] == expected)
+            })
+            .collect()
+    }
+}
+
+/// Heap spray fillers: 'A', x86 NOP sleds and int3 padding
+fn default_patterns() -> Vec<Pattern> {
+    [0x41u8, 0x90, 0xCC]
+        .into_iter()
+        .map(|byte| Pattern::new(vec![byte; SPRAY_RUN], AlertSeverity::High))
+        .collect()
+}
+
+#[async_trait]
 pub trait AlertHandler: Send + Sync {
-    fn handle_alert(&self, alert: PoisoningAlert);
+    async fn handle_alert(&self, alert: &PoisoningAlert);
     fn log_event(&self, event: &str, severity: AlertSeverity);
 }
 
 impl MemoryPoisonDetector {
     pub fn new(alert_handler: Box<dyn AlertHandler + Send + Sync>) -> Self {
         Self {
-            regions: Arc::new(RwLock::new(HashMap::new())),
-            canary_values: Arc::new(RwLock::new(HashMap::new())),
+            regions: Arc::new(SyncRwLock::new(HashMap::new())),
             alert_handler,
-            check_interval: Duration::from_millis(100),
+            check_interval: DEFAULT_CHECK_INTERVAL,
             last_full_scan: Arc::new(RwLock::new(SystemTime::now())),
-            scan_patterns: Arc::new(RwLock::new(Vec::new())),
+            scan_patterns: Arc::new(RwLock::new(default_patterns())),
+            response: PoisonResponse::Alert,
+            suspended_sessions: Arc::new(SyncRwLock::new(HashSet::new())),
+            shutdown: Arc::new(Notify::new()),
         }
     }
 
-    pub async fn protect_region(
+    pub fn with_check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    pub fn with_response(mut self, response: PoisonResponse) -> Self {
+        self.response = response;
+        self
+    }
+
+    /// Watch `region` until its owner drops it. `session_id` names the
+    /// session it belongs to, for `PoisonResponse::SuspendSessions`.
+    pub fn protect_region<R: WatchedRegion + 'static>(
         &self,
-        region: &mut [u8],
+        region: &Arc<R>,
         region_id: String,
         region_type: MemoryRegionType,
+        session_id: Option<Uuid>,
     ) -> Result<(), MemoryProtectionError> {
-        let canary = self.generate_canary();
-        let canary_positions = self.calculate_canary_positions(region.len());
-        
-        // Place canaries
-        for &pos in &canary_positions {
-            if pos + 8 <= region.len() {
-                region[pos..pos + 8].copy_from_slice(&canary.to_le_bytes());
-            }
+        let mut regions = self.regions.write();
+        if regions.get(&region_id).is_some_and(|r| r.memory.strong_count() > 0) {
+            return Err(MemoryProtectionError::AlreadyProtected);
         }
+        region.check_integrity().map_err(|_| MemoryProtectionError::ProtectionFailed)?;
 
-        // Calculate pattern hash
-        let pattern_hash = self.calculate_pattern_hash(region);
-
-        // Register region
-        let memory_region = MemoryRegion {
-            id: region_id.clone(),
+        let memory: Weak<R> = Arc::downgrade(region);
+        regions.insert(region_id.clone(), MemoryRegion {
+            id: region_id,
             region_type,
-            size: region.len(),
-            canary_positions,
+            session_id,
+            memory,
             last_check: SystemTime::now(),
-            pattern_hash,
-        };
+        });
 
-        let mut regions = self.regions.write().await;
-        regions.insert(region_id.clone(), memory_region);
+        Ok(())
+    }
 
-        // Store canary values
-        let mut canary_values = self.canary_values.write().await;
-        canary_values.insert(
-            region_id,
-            vec![AtomicU64::new(canary); canary_positions.len()],
-        );
+    pub fn unprotect_region(&self, region_id: &str) {
+        self.regions.write().remove(region_id);
+    }
 
-        Ok(())
+    pub async fn add_pattern(&self, pattern: Pattern) {
+        self.scan_patterns.write().await.push(pattern);
     }
 
+    /// Scan every region once; false if anything was reported
     pub async fn check_memory(&self) -> bool {
         let now = SystemTime::now();
-        let mut is_safe = true;
-        let regions = self.regions.read().await;
-        let canary_values = self.canary_values.read().await;
-
-        for (region_id, region) in regions.iter() {
-            if let Some(canaries) = canary_values.get(region_id) {
-                // Check canaries
-                for (idx, &pos) in region.canary_positions.iter().enumerate() {
-                    let expected = canaries[idx].load(Ordering::Relaxed);
-                    if !self.verify_canary(region_id, pos, expected).await {
-                        is_safe = false;
-                        self.alert_handler.handle_alert(PoisoningAlert {
-                            timestamp: now,
-                            memory_region: region_id.clone(),
-                            region_type: region.region_type,
-                            detection_type: DetectionType::CanaryModification,
-                            severity: AlertSeverity::Critical,
-                            pattern_mismatch: None,
-                        });
+        let stall = (self.check_interval * STALL_INTERVALS).max(MIN_STALL);
+        let patterns = self.scan_patterns.read().await.clone();
+        let mut alerts = Vec::new();
+
+        {
+            let mut regions = self.regions.write();
+            regions.retain(|_, region| region.memory.strong_count() > 0);
+
+            for region in regions.values_mut() {
+                let Some(memory) = region.memory.upgrade() else {
+                    continue;
+                };
+                let alert = |detection_type, severity, pattern_mismatch| PoisoningAlert {
+                    timestamp: now,
+                    memory_region: region.id.clone(),
+                    region_type: region.region_type,
+                    session_id: region.session_id,
+                    detection_type,
+                    severity,
+                    pattern_mismatch,
+                };
+
+                match memory.check_integrity() {
+                    Ok(()) => {}
+                    Err(SecureMemoryError::CanaryModified) => {
+                        alerts.push(alert(DetectionType::CanaryModification, AlertSeverity::Critical, None));
                     }
+                    Err(SecureMemoryError::ContentsModified) => {
+                        alerts.push(alert(DetectionType::UnexpectedModification, AlertSeverity::Critical, None));
+                    }
+                    Err(e) => warn!("Could not check memory region {}: {}", region.id, e),
                 }
 
-                // Check patterns
-                if let Some(mismatches) = self.check_patterns(region_id).await {
-                    is_safe = false;
-                    self.alert_handler.handle_alert(PoisoningAlert {
-                        timestamp: now,
-                        memory_region: region_id.clone(),
-                        region_type: region.region_type,
-                        detection_type: DetectionType::PatternMismatch,
-                        severity: AlertSeverity::High,
-                        pattern_mismatch: Some(mismatches),
-                    });
+                match memory.find_patterns(&patterns) {
+                    Ok(matches) if !matches.is_empty() => {
+                        let severity = matches.iter().map(|&(_, severity)| severity).min().unwrap();
+                        let positions = matches.into_iter().map(|(pos, _)| pos).collect();
+                        alerts.push(alert(DetectionType::PatternMismatch, severity, Some(positions)));
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Could not scan memory region {}: {}", region.id, e),
                 }
 
-                // Check timing anomalies
-                if self.check_timing_anomalies(region).await {
-                    is_safe = false;
-                    self.alert_handler.handle_alert(PoisoningAlert {
-                        timestamp: now,
-                        memory_region: region_id.clone(),
-                        region_type: region.region_type,
-                        detection_type: DetectionType::TimingAnomaly,
-                        severity: AlertSeverity::Medium,
-                        pattern_mismatch: None,
-                    });
+                let overdue = now.duration_since(region.last_check).unwrap_or_default() > stall;
+                if overdue && region.region_type == MemoryRegionType::KeyMaterial {
+                    alerts.push(alert(DetectionType::TimingAnomaly, AlertSeverity::Medium, None));
                 }
+
+                region.last_check = now;
+            }
+        }
+
+        for alert in &alerts {
+            self.alert_handler.handle_alert(alert).await;
+            if alert.severity <= AlertSeverity::High {
+                self.respond(alert).await;
             }
         }
 
         // Update last full scan time
-        if is_safe {
+        if alerts.is_empty() {
             *self.last_full_scan.write().await = now;
         }
 
-        is_safe
+        alerts.is_empty()
     }
 
-    async fn verify_canary(&self, region_id: &str, position: usize, expected: u64) -> bool {
-        // Implement actual memory verification using low-level memory access
-        // This is a critical section that needs to be implemented carefully
-        unsafe {
-            // Get pointer to the memory region
-            let ptr = position as *const u8;
-            let value = std::ptr::read_volatile(ptr as *const u64);
-            value == expected
-        }
+    /// Scan every `check_interval` until the detector is dropped
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let detector = Arc::downgrade(self);
+        let check_interval = self.check_interval;
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(check_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(detector) = detector.upgrade() else {
+                    break;
+                };
+                detector.check_memory().await;
+            }
+        })
     }
 
-    fn generate_canary(&self) -> u64 {
-        let mut hasher = Sha3_256::new();
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos()
-            .to_le_bytes();
-        
-        hasher.update(&now);
-        let result = hasher.finalize();
-        
-        u64::from_le_bytes(result[..8].try_into().unwrap())
-    }
-
-    fn calculate_canary_positions(&self, region_size: usize) -> Vec<usize> {
-        let mut positions = Vec::new();
-        let interval = region_size / 4;
-        
-        for i in 0..4 {
-            positions.push(i * interval);
-        }
-        
-        if region_size > 32 {
-            positions.push(region_size - 8);
-        }
-        
-        positions
+    /// Time of the last scan that found nothing
+    pub async fn last_full_scan(&self) -> SystemTime {
+        *self.last_full_scan.read().await
     }
 
-    fn calculate_pattern_hash(&self, region: &[u8]) -> [u8; 32] {
-        let mut hasher = Sha3_256::new();
-        hasher.update(region);
-        hasher.finalize().into()
+    pub fn is_session_suspended(&self, session_id: &Uuid) -> bool {
+        self.suspended_sessions.read().contains(session_id)
     }
 
-    async fn check_patterns(&self, region_id: &str) -> Option<Vec<usize>> {
-        let patterns = self.scan_patterns.read().await;
-        let mut mismatches = Vec::new();
-
-        for pattern in patterns.iter() {
-            // Implement pattern matching
-            // Return positions where patterns were found
-        }
+    pub fn resume_session(&self, session_id: &Uuid) {
+        self.suspended_sessions.write().remove(session_id);
+    }
 
-        if mismatches.is_empty() {
-            None
-        } else {
-            Some(mismatches)
-        }
+    /// Resolves once a `PoisonResponse::Shutdown` has been triggered
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
     }
 
-    async fn check_timing_anomalies(&self, region: &MemoryRegion) -> bool {
-        let now = SystemTime::now();
-        let elapsed = now.duration_since(region.last_check).unwrap();
-        
-        // Check for suspicious timing patterns
-        elapsed > Duration::from_secs(1) && region.region_type == MemoryRegionType::KeyMaterial
+    async fn respond(&self, alert: &PoisoningAlert) {
+        match self.response {
+            PoisonResponse::Alert => {}
+            PoisonResponse::ZeroizeKeys => {
+                let regions = self.regions.read();
+                for region in regions.values() {
+                    let affected = region.id == alert.memory_region
+                        || region.region_type == MemoryRegionType::KeyMaterial;
+                    if let (true, Some(memory)) = (affected, region.memory.upgrade()) {
+                        memory.zeroize();
+                        self.alert_handler.log_event(&format!("Zeroized memory region {}", region.id), AlertSeverity::High);
+                    }
+                }
+            }
+            PoisonResponse::SuspendSessions => match alert.session_id {
+                Some(session_id) => {
+                    self.suspended_sessions.write().insert(session_id);
+                    self.alert_handler.log_event(&format!("Suspended session {}", session_id), AlertSeverity::High);
+                }
+                None => self.alert_handler.log_event(
+                    &format!("Memory region {} has no session to suspend", alert.memory_region),
+                    AlertSeverity::Medium,
+                ),
+            },
+            PoisonResponse::Shutdown => {
+                self.alert_handler.log_event("Requesting shutdown after memory poisoning", AlertSeverity::Critical);
+                // Keeps a permit if nobody is waiting yet
+                self.shutdown.notify_one();
+            }
+        }
     }
 }
 
@@ -265,6 +434,8 @@ pub enum MemoryProtectionError {
     InvalidSize,
     #[error("Region already protected")]
     AlreadyProtected,
+    #[error("Unknown poisoning response {0}")]
+    UnknownResponse(String),
 }
 
 // Implementation of a logging alert handler
@@ -272,13 +443,14 @@ pub struct LoggingAlertHandler {
     log_path: std::path::PathBuf,
 }
 
+#[async_trait]
 impl AlertHandler for LoggingAlertHandler {
-    fn handle_alert(&self, alert: PoisoningAlert) {
+    async fn handle_alert(&self, alert: &PoisoningAlert) {
         error!(
             "Memory poisoning detected: {:?} in region {} ({:?})",
             alert.detection_type, alert.memory_region, alert.severity
         );
-        
+
         // Log to file
         if let Ok(mut file) = std::fs::OpenOptions::new()
             .append(true)
@@ -298,53 +470,210 @@ impl AlertHandler for LoggingAlertHandler {
     }
 
     fn log_event(&self, event: &str, severity: AlertSeverity) {
-        match severity {
-            AlertSeverity::Critical => error!("{}", event),
-            AlertSeverity::High => error!("{}", event),
-            AlertSeverity::Medium => warn!("{}", event),
-            AlertSeverity::Low => info!("{}", event),
+        log_at(event, severity);
+    }
+}
+
+/// Records alerts as `AnomalyDetected` audit events
+pub struct AuditAlertHandler {
+    audit: Arc<AuditSystem>,
+}
+
+impl AuditAlertHandler {
+    pub fn new(audit: Arc<AuditSystem>) -> Self {
+        Self { audit }
+    }
+}
+
+#[async_trait]
+impl AlertHandler for AuditAlertHandler {
+    async fn handle_alert(&self, alert: &PoisoningAlert) {
+        self.log_event(
+            &format!("Memory poisoning detected: {:?} in region {}", alert.detection_type, alert.memory_region),
+            alert.severity,
+        );
+
+        let metadata = json!({
+            "source": "memory_poison_detector",
+            "region": alert.memory_region,
+            "region_type": format!("{:?}", alert.region_type),
+            "detection": format!("{:?}", alert.detection_type),
+            "session_id": alert.session_id,
+            "positions": alert.pattern_mismatch,
+        });
+        if let Err(e) = self.audit
+            .record_event(AuditEventType::AnomalyDetected { severity: alert.severity.into() }, None, Some(metadata))
+            .await
+        {
+            error!("Failed to audit memory poisoning alert: {}", e);
         }
     }
+
+    fn log_event(&self, event: &str, severity: AlertSeverity) {
+        log_at(event, severity);
+    }
+}
+
+fn log_at(event: &str, severity: AlertSeverity) {
+    match severity {
+        AlertSeverity::Critical => error!("{}", event),
+        AlertSeverity::High => error!("{}", event),
+        AlertSeverity::Medium => warn!("{}", event),
+        AlertSeverity::Low => info!("{}", event),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use crate::core::crypto::quantum::SecurityLevel;
+    use parking_lot::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<PoisoningAlert>>>);
+
+    #[async_trait]
+    impl AlertHandler for Collect {
+        async fn handle_alert(&self, alert: &PoisoningAlert) {
+            self.0.lock().push(alert.clone());
+        }
+
+        fn log_event(&self, _event: &str, _severity: AlertSeverity) {}
+    }
+
+    fn region(bytes: &[u8]) -> Arc<SyncRwLock<SecureMemory>> {
+        let mut memory = SecureMemory::new(bytes.len()).unwrap();
+        memory.write(bytes).unwrap();
+        Arc::new(SyncRwLock::new(memory))
+    }
 
     #[tokio::test]
     async fn test_memory_protection() {
-        let handler = Box::new(LoggingAlertHandler {
-            log_path: PathBuf::from("test_security.log"),
-        });
-        
-        let detector = MemoryPoisonDetector::new(handler);
-        let mut test_region = vec![0u8; 1024];
-        
-        let result = detector.protect_region(
-            &mut test_region,
-            "test_region".to_string(),
-            MemoryRegionType::General
-        ).await;
-        
-        assert!(result.is_ok());
+        let detector = MemoryPoisonDetector::new(Box::new(Collect::default()));
+        let key = region(&[7u8; 32]);
+
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, None).unwrap();
+        assert!(matches!(
+            detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, None),
+            Err(MemoryProtectionError::AlreadyProtected)
+        ));
+        assert!(detector.check_memory().await);
+
+        // Legitimate writes go through a scope and are not reported
+        key.write().write(&[8u8; 32]).unwrap();
+        assert!(detector.check_memory().await);
+
+        // Regions are forgotten once their owner drops them
+        drop(key);
         assert!(detector.check_memory().await);
+        assert!(detector.regions.read().is_empty());
     }
 
     #[tokio::test]
     async fn test_canary_verification() {
-        let handler = Box::new(LoggingAlertHandler {
-            log_path: PathBuf::from("test_security.log"),
-        });
-        
-        let detector = MemoryPoisonDetector::new(handler);
-        let canary = detector.generate_canary();
-        
-        let positions = detector.calculate_canary_positions(1024);
-        assert!(!positions.is_empty());
-        
-        // Test canary generation consistency
-        let canary2 = detector.generate_canary();
-        assert_ne!(canary, canary2);
-    }
-}
\ No newline at end of file
+        let alerts = Collect::default();
+        let detector = MemoryPoisonDetector::new(Box::new(alerts.clone()));
+        let key = region(&[7u8; 32]);
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, None).unwrap();
+
+        // Zero the whole random canary
+        for offset in 1..=8 {
+            key.read().tamper(-offset, 0);
+        }
+        assert!(!detector.check_memory().await);
+        assert_eq!(alerts.0.lock()[0].detection_type, DetectionType::CanaryModification);
+        assert_eq!(alerts.0.lock()[0].severity, AlertSeverity::Critical);
+    }
+
+    #[tokio::test]
+    async fn test_modification_zeroizes_keys() {
+        let alerts = Collect::default();
+        let detector = MemoryPoisonDetector::new(Box::new(alerts.clone()))
+            .with_response(PoisonResponse::ZeroizeKeys);
+        let template = region(&[1u8; 16]);
+        let key = Arc::new(SyncRwLock::new(Secret::new([9u8; 32]).unwrap()));
+        detector.protect_region(&template, "template".into(), MemoryRegionType::Template, None).unwrap();
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, None).unwrap();
+
+        template.read().tamper(0, 2);
+        assert!(!detector.check_memory().await);
+        assert_eq!(alerts.0.lock()[0].detection_type, DetectionType::UnexpectedModification);
+
+        assert_eq!(*key.read().expose().unwrap(), [0u8; 32]);
+        let mut buf = [0xFFu8; 16];
+        template.read().read(&mut buf).unwrap();
+        assert_eq!(buf, [0u8; 16]);
+    }
+
+    #[tokio::test]
+    async fn test_spray_pattern_detected() {
+        let alerts = Collect::default();
+        let detector = MemoryPoisonDetector::new(Box::new(alerts.clone()));
+        let mut bytes = [3u8; 64];
+        bytes[10..10 + SPRAY_RUN].fill(0x90);
+        let buffer = region(&bytes);
+        detector.protect_region(&buffer, "buffer".into(), MemoryRegionType::BiometricData, None).unwrap();
+
+        assert!(!detector.check_memory().await);
+        let alert = alerts.0.lock()[0].clone();
+        assert_eq!(alert.detection_type, DetectionType::PatternMismatch);
+        assert_eq!(alert.pattern_mismatch, Some(vec![10]));
+
+        let masked = Pattern::masked(vec![0xDE, 0x00, 0xEF], vec![true, false, true], AlertSeverity::Low);
+        assert_eq!(masked.find(&[0, 0xDE, 0x12, 0xEF]), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_suspend_and_shutdown_responses() {
+        let session = Uuid::new_v4();
+        let detector = MemoryPoisonDetector::new(Box::new(Collect::default()))
+            .with_response(PoisonResponse::SuspendSessions);
+        let key = region(&[7u8; 32]);
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, Some(session)).unwrap();
+        key.read().tamper(0, 0);
+        detector.check_memory().await;
+        assert!(detector.is_session_suspended(&session));
+        detector.resume_session(&session);
+        assert!(!detector.is_session_suspended(&session));
+
+        let detector = MemoryPoisonDetector::new(Box::new(Collect::default()))
+            .with_response(PoisonResponse::Shutdown);
+        let key = region(&[7u8; 32]);
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, None).unwrap();
+        key.read().tamper(1, 0);
+        detector.check_memory().await;
+        tokio::time::timeout(Duration::from_secs(1), detector.shutdown_requested()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_background_scan_audits_alerts() {
+        let audit = Arc::new(AuditSystem::new(30, SecurityLevel::Standard));
+        let detector = Arc::new(
+            MemoryPoisonDetector::new(Box::new(AuditAlertHandler::new(audit.clone())))
+                .with_check_interval(Duration::from_millis(10)),
+        );
+        let key = region(&[7u8; 32]);
+        detector.protect_region(&key, "key".into(), MemoryRegionType::KeyMaterial, None).unwrap();
+        let scanner = detector.start();
+
+        let mut feed = audit.subscribe();
+        key.read().tamper(5, 0);
+        let event = tokio::time::timeout(Duration::from_secs(5), feed.recv()).await.unwrap().unwrap();
+        assert!(matches!(
+            event.event_type,
+            AuditEventType::AnomalyDetected { severity: AnomalySeverity::Critical }
+        ));
+        assert_eq!(event.metadata.unwrap()["region"], "key");
+
+        // The scanner stops with the detector
+        drop(detector);
+        tokio::time::timeout(Duration::from_secs(1), scanner).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!("zeroize".parse::<PoisonResponse>().unwrap(), PoisonResponse::ZeroizeKeys);
+        assert_eq!("shutdown".parse::<PoisonResponse>().unwrap(), PoisonResponse::Shutdown);
+        assert!("reboot".parse::<PoisonResponse>().is_err());
+    }
+}
@@ -0,0 +1,8 @@
+//! Detection of tampering with key material in memory
+
+pub mod detector;
+
+pub use detector::{
+    AlertHandler, AlertSeverity, AuditAlertHandler, DetectionType, MemoryPoisonDetector,
+    MemoryRegionType, Pattern, PoisonResponse, PoisoningAlert,
+};
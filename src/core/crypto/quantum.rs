@@ -1,5 +1,4 @@
 use std::sync::RwLock;
-use ring::rand::SystemRandom;
 use sha3::{Sha3_256, Digest};
 
 use crate::{
@@ -7,6 +6,7 @@ use crate::{
     core::crypto::{
         kyber::{KyberKEM, PublicKey as KyberPublicKey, SecretKey as KyberSecretKey},
         dilithium::{Dilithium, PublicKey as DilithiumPublicKey, SecretKey as DilithiumSecretKey, Signature},
+        rng::{CryptoRng, SharedRng, system_rng},
         serialization::{
             serialize_public_key, deserialize_public_key,
             serialize_secret_key, deserialize_secret_key,
@@ -16,7 +16,7 @@ use crate::{
 };
 
 pub struct QuantumResistantProcessor {
-    rng: SystemRandom,
+    rng: SharedRng,
     state: RwLock<ProcessorState>,
     kyber_cache: RwLock<KyberCache>,
 }
@@ -48,8 +48,13 @@ pub enum QuantumAlgorithm {
 
 impl QuantumResistantProcessor {
     pub fn new() -> Result<Self> {
+        Self::with_rng(system_rng())
+    }
+
+    /// Build a processor that draws all of its randomness from `rng`
+    pub fn with_rng(rng: SharedRng) -> Result<Self> {
         Ok(Self {
-            rng: SystemRandom::new(),
+            rng,
             state: RwLock::new(ProcessorState {
                 current_round: 0,
                 entropy_pool: Vec::with_capacity(1024),
@@ -58,9 +63,14 @@ impl QuantumResistantProcessor {
         })
     }
 
+    /// The randomness provider shared with components built on this processor
+    pub fn rng(&self) -> &SharedRng {
+        &self.rng
+    }
+
     pub fn generate_keypair(&self) -> Result<((Vec<u8>, Vec<u8>), (Vec<u8>, Vec<u8>))> {
         // Generate Kyber keypair
-        let (pk, sk) = KyberKEM::keygen()?;
+        let (pk, sk) = KyberKEM::keygen_with_rng(&*self.rng)?;
         
         // Cache the keypair for later use
         let mut cache = self.kyber_cache.write().unwrap();
@@ -74,6 +84,9 @@ impl QuantumResistantProcessor {
         Ok((pk_bytes, sk_bytes))
     }
 
+    /// Binds features to a Kyber encapsulation. This is not zero-knowledge
+    /// and only the holder of the secret key can check it; use
+    /// `zkp::ZKProofGenerator` for proofs other nodes can verify.
     pub fn create_zkp(
         &self,
         features: &[f32],
@@ -96,8 +109,8 @@ impl QuantumResistantProcessor {
         }
         
         // Generate commitment using Kyber encapsulation
-        let (pk, _) = KyberKEM::keygen()?; // Temporary key for proof
-        let (shared_secret, ct) = KyberKEM::encapsulate(&pk)?;
+        let (pk, _) = KyberKEM::keygen_with_rng(&*self.rng)?; // Temporary key for proof
+        let (shared_secret, ct) = KyberKEM::encapsulate_with_rng(&pk, &*self.rng)?;
         
         // Create challenge using features and commitment
         let mut hasher = Sha3_256::new();
@@ -115,6 +128,7 @@ impl QuantumResistantProcessor {
         })
     }
 
+    /// Checks a proof from `create_zkp`; requires the local secret key
     pub fn verify_zkp(
         &self,
         proof: &ZeroKnowledgeProof,
@@ -168,15 +182,13 @@ impl QuantumResistantProcessor {
         
         // Generate new entropy
         let mut new_entropy = vec![0u8; 1024];
-        self.rng
-            .fill(&mut new_entropy)
-            .map_err(|_| NodeError::Crypto("Failed to generate entropy".into()))?;
+        self.rng.fill(&mut new_entropy)?;
 
         // Update entropy pool
         state.entropy_pool = new_entropy;
         
         // Regenerate Kyber keypair
-        let (pk, sk) = KyberKEM::keygen()?;
+        let (pk, sk) = KyberKEM::keygen_with_rng(&*self.rng)?;
         let mut cache = self.kyber_cache.write().unwrap();
         cache.public_key = Some(pk);
         cache.secret_key = Some(sk);
@@ -222,6 +234,15 @@ mod tests {
         assert!(valid);
     }
 
+    #[test]
+    fn test_injected_rng_is_used() {
+        use crate::core::crypto::rng::DeterministicRng;
+
+        let first = QuantumResistantProcessor::with_rng(DeterministicRng::shared(b"processor")).unwrap();
+        let second = QuantumResistantProcessor::with_rng(DeterministicRng::shared(b"processor")).unwrap();
+        assert_eq!(first.generate_keypair().unwrap(), second.generate_keypair().unwrap());
+    }
+
     #[test]
     fn test_entropy_refresh() {
         let processor = QuantumResistantProcessor::new().unwrap();
@@ -0,0 +1,234 @@
+//! Randomness provider for the crypto layer
+//!
+//! Code that needs random bytes takes a `CryptoRng` instead of reaching for
+//! the OS directly. Production paths use `system_rng()`, which health-tests
+//! the operating system output as it is drawn; tests can substitute a
+//! seeded `DeterministicRng` to replay a failure or run known-answer tests.
+
+use std::sync::{Arc, OnceLock};
+use parking_lot::Mutex;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::utils::error::{Result, NodeError};
+
+/// Source of cryptographically secure random bytes
+pub trait CryptoRng: Send + Sync {
+    fn fill(&self, dest: &mut [u8]) -> Result<()>;
+}
+
+pub type SharedRng = Arc<dyn CryptoRng>;
+
+/// The process-wide health-tested OS generator
+pub fn system_rng() -> SharedRng {
+    static RNG: OnceLock<SharedRng> = OnceLock::new();
+    RNG.get_or_init(|| Arc::new(OsRng::new())).clone()
+}
+
+/// Min-entropy claimed per byte of OS output
+const CLAIMED_ENTROPY_BITS: u32 = 8;
+/// False positive probability of the repetition count test, as -log2(alpha)
+const FALSE_POSITIVE_EXPONENT: u32 = 40;
+/// Bytes run through the health test before the first output is released
+const STARTUP_SAMPLES: usize = 1024;
+
+/// Continuous repetition count test (SP 800-90B, section 4.4.1).
+///
+/// Fails once the same sample repeats `cutoff` times in a row. A failure is
+/// latched: the source must not be used again until it is recreated.
+#[derive(Debug)]
+pub struct RepetitionCountTest {
+    cutoff: u32,
+    last: Option<u8>,
+    count: u32,
+    failed: bool,
+}
+
+impl RepetitionCountTest {
+    /// C = 1 + ceil(-log2(alpha) / H)
+    pub fn new(entropy_bits: u32, false_positive_exponent: u32) -> Self {
+        Self {
+            cutoff: 1 + false_positive_exponent.div_ceil(entropy_bits),
+            last: None,
+            count: 0,
+            failed: false,
+        }
+    }
+
+    pub fn cutoff(&self) -> u32 {
+        self.cutoff
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Feed samples through the test, returning false on (or after) a failure
+    pub fn process(&mut self, samples: &[u8]) -> bool {
+        for &sample in samples {
+            if self.failed {
+                break;
+            }
+            if self.last == Some(sample) {
+                self.count += 1;
+                if self.count >= self.cutoff {
+                    self.failed = true;
+                }
+            } else {
+                self.last = Some(sample);
+                self.count = 1;
+            }
+        }
+        !self.failed
+    }
+}
+
+/// OS randomness with continuous health testing
+pub struct OsRng {
+    source: SystemRandom,
+    health: Mutex<OsRngHealth>,
+}
+
+struct OsRngHealth {
+    rct: RepetitionCountTest,
+    started: bool,
+}
+
+impl OsRng {
+    pub fn new() -> Self {
+        Self {
+            source: SystemRandom::new(),
+            health: Mutex::new(OsRngHealth {
+                rct: RepetitionCountTest::new(CLAIMED_ENTROPY_BITS, FALSE_POSITIVE_EXPONENT),
+                started: false,
+            }),
+        }
+    }
+
+    fn draw(&self, health: &mut OsRngHealth, dest: &mut [u8]) -> Result<()> {
+        if health.rct.failed() {
+            return Err(NodeError::Crypto("Entropy source failed its health test".into()));
+        }
+        self.source
+            .fill(dest)
+            .map_err(|_| NodeError::Crypto("Failed to generate random bytes".into()))?;
+        if !health.rct.process(dest) {
+            dest.fill(0);
+            return Err(NodeError::Crypto("Entropy source failed repetition count test".into()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for OsRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CryptoRng for OsRng {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        let mut health = self.health.lock();
+        if !health.started {
+            let mut startup = [0u8; STARTUP_SAMPLES];
+            self.draw(&mut health, &mut startup)?;
+            health.started = true;
+        }
+        self.draw(&mut health, dest)
+    }
+}
+
+/// Seeded SHAKE256 stream. Never use outside tests: the output is fully
+/// determined by the seed.
+#[cfg(any(test, feature = "test-rng"))]
+pub struct DeterministicRng {
+    reader: Mutex<sha3::Shake256Reader>,
+}
+
+#[cfg(any(test, feature = "test-rng"))]
+impl DeterministicRng {
+    pub fn from_seed(seed: &[u8]) -> Self {
+        use sha3::{Shake256, digest::{Update, ExtendableOutput}};
+
+        let mut shake = Shake256::default();
+        shake.update(b"freeghost-deterministic-rng");
+        shake.update(seed);
+        Self {
+            reader: Mutex::new(shake.finalize_xof()),
+        }
+    }
+
+    pub fn shared(seed: &[u8]) -> SharedRng {
+        Arc::new(Self::from_seed(seed))
+    }
+}
+
+#[cfg(any(test, feature = "test-rng"))]
+impl CryptoRng for DeterministicRng {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        use sha3::digest::XofReader;
+
+        self.reader.lock().read(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic_rng_replays() {
+        let a = DeterministicRng::from_seed(b"seed");
+        let b = DeterministicRng::from_seed(b"seed");
+        let mut x = [0u8; 64];
+        let mut y = [0u8; 64];
+        a.fill(&mut x).unwrap();
+        b.fill(&mut y[..10]).unwrap();
+        b.fill(&mut y[10..]).unwrap();
+        // The stream does not depend on how reads are split
+        assert_eq!(x, y);
+
+        let c = DeterministicRng::from_seed(b"other seed");
+        let mut z = [0u8; 64];
+        c.fill(&mut z).unwrap();
+        assert_ne!(x, z);
+    }
+
+    #[test]
+    fn test_rct_cutoff() {
+        let rct = RepetitionCountTest::new(CLAIMED_ENTROPY_BITS, FALSE_POSITIVE_EXPONENT);
+        assert_eq!(rct.cutoff(), 6);
+        assert_eq!(RepetitionCountTest::new(1, 20).cutoff(), 21);
+    }
+
+    #[test]
+    fn test_rct_detects_stuck_source() {
+        let mut rct = RepetitionCountTest::new(CLAIMED_ENTROPY_BITS, FALSE_POSITIVE_EXPONENT);
+        assert!(rct.process(&[1, 2, 2, 2, 2, 2, 3]));
+        // Runs continue across calls
+        assert!(rct.process(&[3, 3, 3, 3]));
+        assert!(!rct.process(&[3]));
+
+        // The failure is latched
+        assert!(!rct.process(&[4, 5, 6]));
+        assert!(rct.failed());
+    }
+
+    #[test]
+    fn test_os_rng() {
+        let rng = OsRng::new();
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        rng.fill(&mut a).unwrap();
+        rng.fill(&mut b).unwrap();
+        assert_ne!(a, b);
+        assert!(!rng.health.lock().rct.failed());
+    }
+
+    #[test]
+    fn test_os_rng_refuses_after_failure() {
+        let rng = OsRng::new();
+        rng.health.lock().rct.failed = true;
+        assert!(rng.fill(&mut [0u8; 16]).is_err());
+    }
+}
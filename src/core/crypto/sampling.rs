@@ -1,22 +1,22 @@
 //! Sampling functions for Kyber
 //! Implements noise sampling using binomial distribution and uniform sampling
 
-use crate::utils::error::{Result, NodeError};
-use ring::rand::{SecureRandom, SystemRandom};
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::rng::CryptoRng,
+};
 use sha3::{Shake256, digest::{Update, ExtendableOutput, XofReader}};
 
 /// Generate a random polynomial in R_q with coefficients uniformly random mod q
-pub fn random_poly(q: i16) -> Result<Vec<i16>> {
+pub fn random_poly(q: i16, rng: &dyn CryptoRng) -> Result<Vec<i16>> {
     let mut coeffs = vec![0i16; 256];
-    let rng = SystemRandom::new();
     
     // We need ceil(log2(q)) bits per coefficient
     let bits_needed = 32 - (q - 1).leading_zeros() as usize;
-    let bytes_per_coeff = (bits_needed + 7) / 8;
+    let bytes_per_coeff = bits_needed.div_ceil(8);
     
     let mut bytes = vec![0u8; 256 * bytes_per_coeff];
-    rng.fill(&mut bytes)
-        .map_err(|_| NodeError::Crypto("Failed to generate random bytes".into()))?;
+    rng.fill(&mut bytes)?;
 
     for (i, chunk) in bytes.chunks(bytes_per_coeff).enumerate() {
         let mut val = 0u32;
@@ -32,8 +32,7 @@ pub fn random_poly(q: i16) -> Result<Vec<i16>> {
             // Try again for this coefficient
             let mut retry_bytes = vec![0u8; bytes_per_coeff];
             while val >= q as u32 {
-                rng.fill(&mut retry_bytes)
-                    .map_err(|_| NodeError::Crypto("Failed to generate random bytes".into()))?;
+                rng.fill(&mut retry_bytes)?;
                 val = 0;
                 for &byte in &retry_bytes {
                     val = (val << 8) | byte as u32;
@@ -48,59 +47,113 @@ pub fn random_poly(q: i16) -> Result<Vec<i16>> {
 }
 
 /// Sample from centered binomial distribution with parameter eta
-pub fn sample_cbd(eta: u8) -> Result<Vec<i16>> {
-    let mut coeffs = vec![0i16; 256];
-    let rng = SystemRandom::new();
-    
+pub fn sample_cbd(eta: u8, rng: &dyn CryptoRng) -> Result<Vec<i16>> {
     // We need 2*eta bits per coefficient
-    let bytes_needed = (256 * 2 * eta as usize + 7) / 8;
+    let bytes_needed = (256 * 2 * eta as usize).div_ceil(8);
     let mut bytes = vec![0u8; bytes_needed];
-    
-    rng.fill(&mut bytes)
-        .map_err(|_| NodeError::Crypto("Failed to generate random bytes".into()))?;
+    rng.fill(&mut bytes)?;
+
+    cbd_from_bytes(&bytes, eta)
+}
+
+/// Deterministically map uniform bytes to CBD_eta coefficients.
+///
+/// The bytes are secret, so every path here is branch-free and indexes
+/// memory only by loop counters: bits are counted with masks and shifts,
+/// never compared.
+pub fn cbd_from_bytes(bytes: &[u8], eta: u8) -> Result<Vec<i16>> {
+    if eta == 0 || bytes.len() < (256 * 2 * eta as usize).div_ceil(8) {
+        return Err(NodeError::Crypto("Not enough randomness for CBD sampling".into()));
+    }
+
+    Ok(match eta {
+        2 => cbd2(bytes),
+        3 => cbd3(bytes),
+        _ => cbd_generic(bytes, eta),
+    })
+}
+
+/// CBD with eta = 2: 4 bits per coefficient, 8 coefficients per 32-bit word
+fn cbd2(bytes: &[u8]) -> Vec<i16> {
+    let mut coeffs = vec![0i16; 256];
+
+    for (i, chunk) in bytes.chunks_exact(4).take(256 / 8).enumerate() {
+        let t = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        // Pairwise bit sums: each 2-bit field holds the popcount of a bit pair
+        let d = (t & 0x5555_5555) + ((t >> 1) & 0x5555_5555);
+
+        for j in 0..8 {
+            let a = ((d >> (4 * j)) & 0x3) as i16;
+            let b = ((d >> (4 * j + 2)) & 0x3) as i16;
+            coeffs[8 * i + j] = a - b;
+        }
+    }
+
+    coeffs
+}
+
+/// CBD with eta = 3: 6 bits per coefficient, 4 coefficients per 24-bit word
+fn cbd3(bytes: &[u8]) -> Vec<i16> {
+    let mut coeffs = vec![0i16; 256];
+
+    for (i, chunk) in bytes.chunks_exact(3).take(256 / 4).enumerate() {
+        let t = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], 0]);
+        // Each 3-bit field holds the popcount of a bit triple
+        let d = (t & 0x0024_9249) + ((t >> 1) & 0x0024_9249) + ((t >> 2) & 0x0024_9249);
+
+        for j in 0..4 {
+            let a = ((d >> (6 * j)) & 0x7) as i16;
+            let b = ((d >> (6 * j + 3)) & 0x7) as i16;
+            coeffs[4 * i + j] = a - b;
+        }
+    }
+
+    coeffs
+}
 
+/// CBD for any other eta, one bit at a time. Loop bounds depend only on the
+/// public parameter eta.
+fn cbd_generic(bytes: &[u8], eta: u8) -> Vec<i16> {
+    let mut coeffs = vec![0i16; 256];
     let mut bit_pos = 0;
+
     for coeff in &mut coeffs {
         let mut a = 0u32;
         let mut b = 0u32;
         
         // Count 1s in first eta bits
         for _ in 0..eta {
-            let byte_idx = bit_pos / 8;
-            let bit_idx = bit_pos % 8;
-            a += ((bytes[byte_idx] >> bit_idx) & 1) as u32;
+            a += ((bytes[bit_pos / 8] >> (bit_pos % 8)) & 1) as u32;
             bit_pos += 1;
         }
         
         // Count 1s in second eta bits
         for _ in 0..eta {
-            let byte_idx = bit_pos / 8;
-            let bit_idx = bit_pos % 8;
-            b += ((bytes[byte_idx] >> bit_idx) & 1) as u32;
+            b += ((bytes[bit_pos / 8] >> (bit_pos % 8)) & 1) as u32;
             bit_pos += 1;
         }
         
         *coeff = (a as i16) - (b as i16);
     }
 
-    Ok(coeffs)
+    coeffs
 }
 
 /// Expand seed into matrix A using SHAKE-128
 pub fn expand_a(seed: &[u8], k: usize) -> Result<Vec<Vec<Vec<i16>>>> {
     let mut a = vec![vec![vec![0i16; 256]; k]; k];
-    let mut shake = Shake256::default();
     
-    for i in 0..k {
-        for j in 0..k {
+    for (i, row) in a.iter_mut().enumerate() {
+        for (j, poly) in row.iter_mut().enumerate() {
             // Create unique input for each matrix element
+            let mut shake = Shake256::default();
             shake.update(seed);
             shake.update(&[i as u8, j as u8]);
             
             let mut reader = shake.finalize_xof();
             let mut buf = [0u8; 3];  // 3 bytes gives us enough bits for q=3329
             
-            for coeff in &mut a[i][j] {
+            for coeff in poly.iter_mut() {
                 loop {
                     reader.read(&mut buf);
                     let val = u32::from_le_bytes([buf[0], buf[1], buf[2], 0]) & 0x0FFF;
@@ -119,12 +172,13 @@ pub fn expand_a(seed: &[u8], k: usize) -> Result<Vec<Vec<Vec<i16>>>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::crypto::rng::{system_rng, DeterministicRng};
     use std::collections::HashMap;
 
     #[test]
     fn test_random_poly() {
         let q = 3329;
-        let poly = random_poly(q).unwrap();
+        let poly = random_poly(q, &*system_rng()).unwrap();
         
         // Check length
         assert_eq!(poly.len(), 256);
@@ -138,12 +192,12 @@ mod tests {
     #[test]
     fn test_cbd_distribution() {
         let eta = 2;
-        let samples = sample_cbd(eta).unwrap();
+        let samples = sample_cbd(eta, &*system_rng()).unwrap();
         let mut histogram = HashMap::new();
         
         // Count occurrences of each value
         for &x in &samples {
-            *histogram.entry(x).or_insert(0) += 1;
+            *histogram.entry(x).or_insert(0i32) += 1;
         }
         
         // Check range
@@ -160,6 +214,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cbd_specialised_matches_generic() {
+        let mut bytes = vec![0u8; 256 * 2 * 3 / 8];
+        system_rng().fill(&mut bytes).unwrap();
+
+        for eta in [2u8, 3] {
+            let len = 256 * 2 * eta as usize / 8;
+            let fast = cbd_from_bytes(&bytes[..len], eta).unwrap();
+            let reference = cbd_generic(&bytes[..len], eta);
+            assert_eq!(fast, reference);
+        }
+    }
+
+    #[test]
+    fn test_seeded_sampling_is_reproducible() {
+        let first = DeterministicRng::from_seed(b"sampling");
+        let second = DeterministicRng::from_seed(b"sampling");
+
+        assert_eq!(random_poly(3329, &first).unwrap(), random_poly(3329, &second).unwrap());
+        assert_eq!(sample_cbd(2, &first).unwrap(), sample_cbd(2, &second).unwrap());
+        assert_ne!(sample_cbd(2, &first).unwrap(), sample_cbd(2, &DeterministicRng::from_seed(b"other")).unwrap());
+    }
+
+    #[test]
+    fn test_cbd_rejects_short_input() {
+        assert!(cbd_from_bytes(&[0u8; 16], 2).is_err());
+    }
+
     #[test]
     fn test_expand_a() {
         let seed = [0u8; 32];
@@ -176,12 +258,8 @@ mod tests {
         }
         
         // Check range
-        for i in 0..k {
-            for j in 0..k {
-                for coeff in &a[i][j] {
-                    assert!(*coeff >= 0 && *coeff < 3329);
-                }
-            }
+        for coeff in a.iter().flatten().flatten() {
+            assert!((0..3329).contains(coeff));
         }
         
         // Verify deterministic expansion
@@ -1,9 +1,35 @@
 // src/core/crypto/secure_memory.rs
 
-use std::alloc::{alloc, dealloc, Layout};
-use std::ptr;
-use std::sync::atomic::{AtomicBool, Ordering};
+//! Guarded memory for key material
+//!
+//! Each `SecureMemory` region gets pages of its own, mapped between two
+//! `PROT_NONE` guard pages, locked against swapping and excluded from core
+//! dumps. The data pages stay inaccessible too, except while an access
+//! scope (`access`, `access_mut`) is alive, so a stray read or an overflow
+//! from a neighbouring buffer faults instead of leaking key bytes. Data
+//! sits at the end of its pages (up to alignment) so overruns hit the
+//! trailing guard at once.
+//!
+//! Writes that get around the page protection (a debugger, `/proc/pid/mem`)
+//! are caught by `check_integrity`: a random canary sits right before the
+//! data, and the data pages are hashed whenever a write scope closes.
+//!
+//! `Secret<T>` keeps a single value in such a region and zeroizes it on drop.
+
+use std::alloc::Layout;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{compiler_fence, Ordering};
+use parking_lot::Mutex;
+use sha3::{Digest, Sha3_256};
 use thiserror::Error;
+use zeroize::Zeroize;
+
+use super::rng::system_rng;
+
+const CANARY_LEN: usize = 8;
 
 #[derive(Debug, Error)]
 pub enum SecureMemoryError {
@@ -13,116 +39,491 @@ pub enum SecureMemoryError {
     LockFailed,
     #[error("Invalid memory alignment")]
     InvalidAlignment,
+    #[error("Failed to change memory protection")]
+    ProtectFailed,
+    #[error("Canary before secure memory was overwritten")]
+    CanaryModified,
+    #[error("Secure memory changed outside an access scope")]
+    ContentsModified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protection {
+    NoAccess,
+    ReadOnly,
+    ReadWrite,
 }
 
 pub struct SecureMemory {
-    ptr: *mut u8,
-    layout: Layout,
-    locked: AtomicBool,
+    // Start of the mapping, i.e. of the leading guard page
+    base: NonNull<u8>,
+    // Start of the caller's bytes
+    data: NonNull<u8>,
+    size: usize,
+    page_size: usize,
+    // Data pages, excluding both guards
+    data_pages: usize,
+    // Read scopes currently open; only `&self` can open them
+    readers: Mutex<usize>,
+    canary: u64,
+    // SHA3-256 of the data pages as the last write scope left them
+    digest: [u8; 32],
 }
 
+// The region is only reached through scopes, which serialise protection
+// changes on `readers`
+unsafe impl Send for SecureMemory {}
+unsafe impl Sync for SecureMemory {}
+
 impl SecureMemory {
     pub fn new(size: usize) -> Result<Self, SecureMemoryError> {
-        // Ensure proper alignment for the platform
-        let align = std::mem::align_of::<usize>();
-        let layout = Layout::from_size_align(size, align)
-            .map_err(|_| SecureMemoryError::InvalidAlignment)?;
-
-        // Allocate memory
-        let ptr = unsafe { alloc(layout) };
-        if ptr.is_null() {
-            return Err(SecureMemoryError::AllocationFailed);
+        Self::with_layout(
+            Layout::from_size_align(size, std::mem::align_of::<usize>())
+                .map_err(|_| SecureMemoryError::InvalidAlignment)?,
+        )
+    }
+
+    pub fn with_layout(layout: Layout) -> Result<Self, SecureMemoryError> {
+        let page_size = sys::page_size();
+        if layout.align() > page_size {
+            return Err(SecureMemoryError::InvalidAlignment);
         }
+        // Room for the canary in front of the data after aligning it
+        let data_pages = (layout.size() + CANARY_LEN + layout.align() - 1).div_ceil(page_size) * page_size;
+        let total = data_pages
+            .checked_add(2 * page_size)
+            .ok_or(SecureMemoryError::AllocationFailed)?;
 
-        // Lock memory pages to prevent swapping
-        #[cfg(target_family = "unix")]
-        unsafe {
-            use libc::{mlock, ENOMEM};
-            if mlock(ptr as *const _, size) == -1 {
-                dealloc(ptr, layout);
-                return Err(SecureMemoryError::LockFailed);
-            }
+        let base = sys::map(total)?;
+        // From here on Drop releases the mapping
+        let offset = (data_pages - layout.size()) & !(layout.align() - 1);
+        let mut canary = [0u8; CANARY_LEN];
+        system_rng()
+            .fill(&mut canary)
+            .map_err(|_| SecureMemoryError::AllocationFailed)?;
+        let mut memory = Self {
+            base,
+            data: unsafe { NonNull::new_unchecked(base.as_ptr().add(page_size + offset)) },
+            size: layout.size(),
+            page_size,
+            data_pages,
+            readers: Mutex::new(0),
+            canary: u64::from_le_bytes(canary),
+            digest: [0u8; 32],
+        };
+
+        let scope = memory.access_mut()?;
+        sys::lock(scope.memory.data_start(), data_pages)?;
+        sys::exclude_from_dumps(scope.memory.data_start(), data_pages);
+        unsafe { ptr::write_unaligned(scope.memory.canary_ptr(), scope.memory.canary) };
+        // Closing the scope records the digest
+        drop(scope);
+
+        Ok(memory)
+    }
+
+    /// Check the canary and that the data is as the last write scope left
+    /// it
+    pub fn check_integrity(&self) -> Result<(), SecureMemoryError> {
+        let _scope = self.access()?;
+        if unsafe { ptr::read_unaligned(self.canary_ptr()) } != self.canary {
+            return Err(SecureMemoryError::CanaryModified);
+        }
+        if self.content_digest() != self.digest {
+            return Err(SecureMemoryError::ContentsModified);
         }
+        Ok(())
+    }
 
-        #[cfg(target_family = "windows")]
-        unsafe {
-            use winapi::um::memoryapi::{VirtualLock};
-            if VirtualLock(ptr as *mut _, size) == 0 {
-                dealloc(ptr, layout);
-                return Err(SecureMemoryError::LockFailed);
-            }
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Make the region readable until the returned scope is dropped
+    pub fn access(&self) -> Result<MemoryRef<'_>, SecureMemoryError> {
+        let mut readers = self.readers.lock();
+        if *readers == 0 {
+            self.protect(Protection::ReadOnly)?;
         }
+        *readers += 1;
+        Ok(MemoryRef { memory: self })
+    }
 
-        Ok(Self {
-            ptr,
-            layout,
-            locked: AtomicBool::new(true),
-        })
+    /// Make the region writable until the returned scope is dropped
+    pub fn access_mut(&mut self) -> Result<MemoryMut<'_>, SecureMemoryError> {
+        self.protect(Protection::ReadWrite)?;
+        Ok(MemoryMut { memory: self })
     }
 
     pub fn write(&mut self, data: &[u8]) -> Result<(), SecureMemoryError> {
-        if !self.locked.load(Ordering::SeqCst) {
-            return Err(SecureMemoryError::LockFailed);
+        if data.len() > self.size {
+            return Err(SecureMemoryError::InvalidAlignment);
         }
+        self.access_mut()?[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
 
-        if data.len() > self.layout.size() {
+    pub fn read(&self, buf: &mut [u8]) -> Result<(), SecureMemoryError> {
+        if buf.len() > self.size {
             return Err(SecureMemoryError::InvalidAlignment);
         }
+        buf.copy_from_slice(&self.access()?[..buf.len()]);
+        Ok(())
+    }
 
-        unsafe {
-            ptr::copy_nonoverlapping(data.as_ptr(), self.ptr, data.len());
+    pub fn clear(&mut self) {
+        if let Ok(mut scope) = self.access_mut() {
+            wipe(&mut scope);
         }
+    }
 
-        Ok(())
+    fn data_start(&self) -> *mut u8 {
+        unsafe { self.base.as_ptr().add(self.page_size) }
     }
 
-    pub fn read(&self, buf: &mut [u8]) -> Result<(), SecureMemoryError> {
-        if !self.locked.load(Ordering::SeqCst) {
-            return Err(SecureMemoryError::LockFailed);
-        }
+    fn canary_ptr(&self) -> *mut u64 {
+        unsafe { self.data.as_ptr().sub(CANARY_LEN) as *mut u64 }
+    }
 
-        if buf.len() > self.layout.size() {
-            return Err(SecureMemoryError::InvalidAlignment);
+    // Caller must have the region readable
+    fn content_digest(&self) -> [u8; 32] {
+        let pages = unsafe { std::slice::from_raw_parts(self.data_start(), self.data_pages) };
+        Sha3_256::digest(pages).into()
+    }
+
+    /// Overwrite a byte relative to the data, bypassing access scopes the
+    /// way an attacker writing through the debugger interface would
+    #[cfg(test)]
+    pub(crate) fn tamper(&self, offset: isize, value: u8) {
+        let readers = self.readers.lock();
+        self.protect(Protection::ReadWrite).unwrap();
+        unsafe { ptr::write_volatile(self.data.as_ptr().offset(offset), value) };
+        let restore = if *readers > 0 { Protection::ReadOnly } else { Protection::NoAccess };
+        self.protect(restore).unwrap();
+    }
+
+    fn protect(&self, protection: Protection) -> Result<(), SecureMemoryError> {
+        sys::protect(self.data_start(), self.data_pages, protection)
+    }
+
+    fn release_reader(&self) {
+        let mut readers = self.readers.lock();
+        *readers -= 1;
+        if *readers == 0 {
+            // Only fails for a bad range, which the constructor rules out
+            let _ = self.protect(Protection::NoAccess);
         }
+    }
+}
 
-        unsafe {
-            ptr::copy_nonoverlapping(self.ptr, buf.as_mut_ptr(), buf.len());
+impl Drop for SecureMemory {
+    fn drop(&mut self) {
+        // Wipe the whole data area, not just the caller's bytes
+        if self.protect(Protection::ReadWrite).is_ok() {
+            unsafe {
+                wipe(std::slice::from_raw_parts_mut(self.data_start(), self.data_pages));
+            }
         }
+        sys::unlock(self.data_start(), self.data_pages);
+        sys::unmap(self.base, self.data_pages + 2 * self.page_size);
+    }
+}
 
-        Ok(())
+impl fmt::Debug for SecureMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureMemory").field("size", &self.size).finish_non_exhaustive()
     }
+}
 
-    pub fn clear(&mut self) {
-        if self.locked.load(Ordering::SeqCst) {
+/// Read access scope; the region goes back to `PROT_NONE` when the last
+/// one is dropped
+pub struct MemoryRef<'a> {
+    memory: &'a SecureMemory,
+}
+
+impl Deref for MemoryRef<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.memory.data.as_ptr(), self.memory.size) }
+    }
+}
+
+impl Drop for MemoryRef<'_> {
+    fn drop(&mut self) {
+        self.memory.release_reader();
+    }
+}
+
+/// Write access scope
+pub struct MemoryMut<'a> {
+    memory: &'a mut SecureMemory,
+}
+
+impl Deref for MemoryMut<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.memory.data.as_ptr(), self.memory.size) }
+    }
+}
+
+impl DerefMut for MemoryMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.memory.data.as_ptr(), self.memory.size) }
+    }
+}
+
+impl Drop for MemoryMut<'_> {
+    fn drop(&mut self) {
+        self.memory.digest = self.memory.content_digest();
+        let _ = self.memory.protect(Protection::NoAccess);
+    }
+}
+
+/// A value kept in its own guarded region, zeroized when dropped. Memory
+/// the value owns elsewhere (a `Vec`'s buffer) is not guarded, but is
+/// zeroized through `T`'s `Zeroize` impl.
+pub struct Secret<T: Zeroize> {
+    memory: SecureMemory,
+    _value: PhantomData<T>,
+}
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(mut value: T) -> Result<Self, SecureMemoryError> {
+        let mut memory = match SecureMemory::with_layout(Layout::new::<T>()) {
+            Ok(memory) => memory,
+            Err(e) => {
+                value.zeroize();
+                return Err(e);
+            }
+        };
+
+        let mut scope = match memory.access_mut() {
+            Ok(scope) => scope,
+            Err(e) => {
+                value.zeroize();
+                return Err(e);
+            }
+        };
+        // The region is laid out for `T`, so the pointer is aligned
+        unsafe { ptr::write(scope.as_mut_ptr() as *mut T, value) };
+        drop(scope);
+
+        Ok(Self {
+            memory,
+            _value: PhantomData,
+        })
+    }
+
+    /// Read access to the value for the lifetime of the returned scope
+    pub fn expose(&self) -> Result<SecretRef<'_, T>, SecureMemoryError> {
+        Ok(SecretRef {
+            scope: self.memory.access()?,
+            _value: PhantomData,
+        })
+    }
+
+    /// Write access to the value for the lifetime of the returned scope
+    pub fn expose_mut(&mut self) -> Result<SecretMut<'_, T>, SecureMemoryError> {
+        Ok(SecretMut {
+            scope: self.memory.access_mut()?,
+            _value: PhantomData,
+        })
+    }
+
+    /// See `SecureMemory::check_integrity`. Only covers the value itself,
+    /// not memory it owns elsewhere.
+    pub fn check_integrity(&self) -> Result<(), SecureMemoryError> {
+        self.memory.check_integrity()
+    }
+
+    /// See `SecureMemory::tamper`
+    #[cfg(test)]
+    pub(crate) fn tamper(&self, offset: isize, value: u8) {
+        self.memory.tamper(offset, value);
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        // Without write access the value cannot be dropped in place; leaking
+        // it is better than faulting, and unmapping still discards it
+        if let Ok(mut scope) = self.memory.access_mut() {
+            let value = scope.as_mut_ptr() as *mut T;
             unsafe {
-                ptr::write_bytes(self.ptr, 0, self.layout.size());
+                (*value).zeroize();
+                ptr::drop_in_place(value);
             }
         }
     }
 }
 
-impl Drop for SecureMemory {
-    fn drop(&mut self) {
-        self.clear();
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
 
-        #[cfg(target_family = "unix")]
-        unsafe {
-            use libc::munlock;
-            munlock(self.ptr as *const _, self.layout.size());
+pub struct SecretRef<'a, T> {
+    scope: MemoryRef<'a>,
+    _value: PhantomData<&'a T>,
+}
+
+impl<T> Deref for SecretRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*(self.scope.as_ptr() as *const T) }
+    }
+}
+
+pub struct SecretMut<'a, T> {
+    scope: MemoryMut<'a>,
+    _value: PhantomData<&'a mut T>,
+}
+
+impl<T> Deref for SecretMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*(self.scope.as_ptr() as *const T) }
+    }
+}
+
+impl<T> DerefMut for SecretMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *(self.scope.as_mut_ptr() as *mut T) }
+    }
+}
+
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(target_family = "unix")]
+mod sys {
+    use super::{Protection, SecureMemoryError};
+    use std::ptr::{self, NonNull};
+
+    pub fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    pub fn map(len: usize) -> Result<NonNull<u8>, SecureMemoryError> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(SecureMemoryError::AllocationFailed);
         }
+        NonNull::new(ptr as *mut u8).ok_or(SecureMemoryError::AllocationFailed)
+    }
 
-        #[cfg(target_family = "windows")]
-        unsafe {
-            use winapi::um::memoryapi::VirtualUnlock;
-            VirtualUnlock(self.ptr as *mut _, self.layout.size());
+    pub fn unmap(ptr: NonNull<u8>, len: usize) {
+        unsafe { libc::munmap(ptr.as_ptr() as *mut _, len) };
+    }
+
+    pub fn protect(ptr: *mut u8, len: usize, protection: Protection) -> Result<(), SecureMemoryError> {
+        let prot = match protection {
+            Protection::NoAccess => libc::PROT_NONE,
+            Protection::ReadOnly => libc::PROT_READ,
+            Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+        };
+        if unsafe { libc::mprotect(ptr as *mut _, len, prot) } == -1 {
+            return Err(SecureMemoryError::ProtectFailed);
         }
+        Ok(())
+    }
+
+    pub fn lock(ptr: *mut u8, len: usize) -> Result<(), SecureMemoryError> {
+        if unsafe { libc::mlock(ptr as *const _, len) } == -1 {
+            return Err(SecureMemoryError::LockFailed);
+        }
+        Ok(())
+    }
+
+    pub fn unlock(ptr: *mut u8, len: usize) {
+        unsafe { libc::munlock(ptr as *const _, len) };
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn exclude_from_dumps(ptr: *mut u8, len: usize) {
+        // Best effort: the pages are still protected if this is unsupported
+        unsafe { libc::madvise(ptr as *mut _, len, libc::MADV_DONTDUMP) };
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn exclude_from_dumps(_ptr: *mut u8, _len: usize) {}
+}
+
+#[cfg(target_family = "windows")]
+mod sys {
+    use super::{Protection, SecureMemoryError};
+    use std::ptr::{self, NonNull};
+    use winapi::um::{
+        memoryapi::{VirtualAlloc, VirtualFree, VirtualLock, VirtualProtect, VirtualUnlock},
+        sysinfoapi::GetSystemInfo,
+        winnt::{MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PAGE_NOACCESS, PAGE_READONLY, PAGE_READWRITE},
+    };
 
+    pub fn page_size() -> usize {
         unsafe {
-            dealloc(self.ptr, self.layout);
+            let mut info = std::mem::zeroed();
+            GetSystemInfo(&mut info);
+            info.dwPageSize as usize
         }
+    }
+
+    pub fn map(len: usize) -> Result<NonNull<u8>, SecureMemoryError> {
+        let ptr = unsafe { VirtualAlloc(ptr::null_mut(), len, MEM_RESERVE | MEM_COMMIT, PAGE_NOACCESS) };
+        NonNull::new(ptr as *mut u8).ok_or(SecureMemoryError::AllocationFailed)
+    }
 
-        self.locked.store(false, Ordering::SeqCst);
+    pub fn unmap(ptr: NonNull<u8>, _len: usize) {
+        unsafe { VirtualFree(ptr.as_ptr() as *mut _, 0, MEM_RELEASE) };
+    }
+
+    pub fn protect(ptr: *mut u8, len: usize, protection: Protection) -> Result<(), SecureMemoryError> {
+        let prot = match protection {
+            Protection::NoAccess => PAGE_NOACCESS,
+            Protection::ReadOnly => PAGE_READONLY,
+            Protection::ReadWrite => PAGE_READWRITE,
+        };
+        let mut old = 0;
+        if unsafe { VirtualProtect(ptr as *mut _, len, prot, &mut old) } == 0 {
+            return Err(SecureMemoryError::ProtectFailed);
+        }
+        Ok(())
     }
+
+    pub fn lock(ptr: *mut u8, len: usize) -> Result<(), SecureMemoryError> {
+        if unsafe { VirtualLock(ptr as *mut _, len) } == 0 {
+            return Err(SecureMemoryError::LockFailed);
+        }
+        Ok(())
+    }
+
+    pub fn unlock(ptr: *mut u8, len: usize) {
+        unsafe { VirtualUnlock(ptr as *mut _, len) };
+    }
+
+    // Windows has no per-region opt-out from crash dumps
+    pub fn exclude_from_dumps(_ptr: *mut u8, _len: usize) {}
 }
 
 #[cfg(test)]
@@ -139,9 +540,9 @@ mod tests {
     fn test_secure_memory_write_read() {
         let mut mem = SecureMemory::new(1024).unwrap();
         let data = b"test data";
-        
+
         assert!(mem.write(data).is_ok());
-        
+
         let mut buf = vec![0u8; data.len()];
         assert!(mem.read(&mut buf).is_ok());
         assert_eq!(&buf, data);
@@ -151,12 +552,109 @@ mod tests {
     fn test_secure_memory_clear() {
         let mut mem = SecureMemory::new(1024).unwrap();
         let data = b"test data";
-        
+
         mem.write(data).unwrap();
         mem.clear();
-        
+
         let mut buf = vec![0u8; data.len()];
         mem.read(&mut buf).unwrap();
         assert_eq!(&buf, &vec![0u8; data.len()]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_data_ends_at_guard_page() {
+        let guard = |mem: &SecureMemory| mem.data_start() as usize + mem.data_pages;
+
+        let mem = SecureMemory::new(64).unwrap();
+        assert_eq!(mem.data.as_ptr() as usize + mem.len(), guard(&mem));
+
+        // Alignment can leave a gap shorter than the alignment
+        let mem = SecureMemory::new(100).unwrap();
+        let end = mem.data.as_ptr() as usize + mem.len();
+        assert!(end <= guard(&mem) && guard(&mem) - end < std::mem::align_of::<usize>());
+    }
+
+    #[test]
+    fn test_nested_read_scopes() {
+        let mut mem = SecureMemory::new(16).unwrap();
+        mem.write(&[7u8; 16]).unwrap();
+
+        let outer = mem.access().unwrap();
+        {
+            let inner = mem.access().unwrap();
+            assert_eq!(inner[0], 7);
+        }
+        // Still readable while `outer` is open
+        assert_eq!(outer[15], 7);
+        drop(outer);
+        assert_eq!(*mem.readers.lock(), 0);
+    }
+
+    #[test]
+    fn test_integrity_check() {
+        let mut mem = SecureMemory::new(32).unwrap();
+        mem.write(&[5u8; 32]).unwrap();
+        assert!(mem.check_integrity().is_ok());
+
+        mem.tamper(3, 0xFF);
+        assert!(matches!(mem.check_integrity(), Err(SecureMemoryError::ContentsModified)));
+
+        // A write through a scope is legitimate and re-baselines the digest
+        mem.write(&[6u8; 32]).unwrap();
+        assert!(mem.check_integrity().is_ok());
+
+        // Last byte of the little-endian canary
+        mem.tamper(-1, !(mem.canary >> 56) as u8);
+        assert!(matches!(mem.check_integrity(), Err(SecureMemoryError::CanaryModified)));
+    }
+
+    #[test]
+    fn test_secret_roundtrip() {
+        let mut secret = Secret::new(vec![1u8, 2, 3]).unwrap();
+        assert_eq!(*secret.expose().unwrap(), vec![1, 2, 3]);
+
+        secret.expose_mut().unwrap().push(4);
+        assert_eq!(secret.expose().unwrap().len(), 4);
+        assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+    }
+
+    #[test]
+    fn test_secret_fixed_size() {
+        let secret = Secret::new([0xABu8; 32]).unwrap();
+        assert_eq!(secret.expose().unwrap()[31], 0xAB);
+    }
+
+    /// Touching the region outside a scope or past its end must fault.
+    /// Runs in a child process so the crash does not take the test binary
+    /// down; the child is this same test, selected by an env variable.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_access_outside_scope_faults() {
+        for case in ["outside-scope", "overflow"] {
+            if std::env::var("SECURE_MEMORY_FAULT").as_deref() == Ok(case) {
+                let mut mem = SecureMemory::new(64).unwrap();
+                mem.write(&[1u8; 64]).unwrap();
+                let ptr = mem.data.as_ptr();
+                unsafe {
+                    match case {
+                        "outside-scope" => { ptr::read_volatile(ptr); }
+                        _ => {
+                            let _scope = mem.access().unwrap();
+                            ptr::read_volatile(ptr.add(64));
+                        }
+                    }
+                }
+                std::process::exit(0);
+            }
+        }
+
+        for case in ["outside-scope", "overflow"] {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "core::crypto::secure_memory::tests::test_access_outside_scope_faults", "--nocapture"])
+                .env("SECURE_MEMORY_FAULT", case)
+                .status()
+                .unwrap();
+            assert!(!status.success(), "{} did not fault", case);
+        }
+    }
+}
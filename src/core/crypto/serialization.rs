@@ -6,12 +6,16 @@ use crate::{
     core::crypto::kyber::{PublicKey, SecretKey, Ciphertext, Polynomial},
 };
 
-/// Maximum size for serialized public key
-const MAX_PK_SIZE: usize = 1024;
-/// Maximum size for serialized secret key
-const MAX_SK_SIZE: usize = 1024;
-/// Maximum size for serialized ciphertext
-const MAX_CT_SIZE: usize = 1024;
+/// Vector length of Kyber768; every serialized dimension must equal it
+const KYBER_K: usize = 3;
+/// Serialized polynomial: NTT flag and 256 little-endian coefficients
+const POLY_SIZE: usize = 513;
+/// Maximum size for serialized public key: dimensions, matrix A, vector t
+const MAX_PK_SIZE: usize = 3 + (KYBER_K + 1) * KYBER_K * POLY_SIZE;
+/// Maximum size for serialized secret key: vector s and the public key
+const MAX_SK_SIZE: usize = 1 + KYBER_K * POLY_SIZE + MAX_PK_SIZE;
+/// Maximum size for serialized ciphertext: vector u and polynomial v
+const MAX_CT_SIZE: usize = 1 + (KYBER_K + 1) * POLY_SIZE;
 
 /// Serialize a polynomial to bytes
 pub fn serialize_polynomial(poly: &Polynomial) -> Vec<u8> {
@@ -90,15 +94,18 @@ pub fn deserialize_public_key(bytes: &[u8]) -> Result<PublicKey> {
     pos += 1;
     let cols = bytes[pos] as usize;
     pos += 1;
+    if rows != KYBER_K || cols != KYBER_K {
+        return Err(NodeError::Crypto("Invalid public key dimensions".into()));
+    }
     
     // Read matrix A
     let mut a = vec![vec![Polynomial::new(); cols]; rows];
-    for i in 0..rows {
-        for j in 0..cols {
+    for row in a.iter_mut() {
+        for poly in row.iter_mut() {
             if pos + 513 > bytes.len() {
                 return Err(NodeError::Crypto("Invalid public key bytes".into()));
             }
-            a[i][j] = deserialize_polynomial(&bytes[pos..pos+513])?;
+            *poly = deserialize_polynomial(&bytes[pos..pos+513])?;
             pos += 513;
         }
     }
@@ -109,6 +116,9 @@ pub fn deserialize_public_key(bytes: &[u8]) -> Result<PublicKey> {
     }
     let t_len = bytes[pos] as usize;
     pos += 1;
+    if t_len != KYBER_K {
+        return Err(NodeError::Crypto("Invalid public key dimensions".into()));
+    }
     
     let mut t = Vec::with_capacity(t_len);
     for _ in 0..t_len {
@@ -153,6 +163,9 @@ pub fn deserialize_secret_key(bytes: &[u8]) -> Result<SecretKey> {
     // Read vector s
     let s_len = bytes[pos] as usize;
     pos += 1;
+    if s_len != KYBER_K {
+        return Err(NodeError::Crypto("Invalid secret key dimensions".into()));
+    }
     
     let mut s = Vec::with_capacity(s_len);
     for _ in 0..s_len {
@@ -200,6 +213,9 @@ pub fn deserialize_ciphertext(bytes: &[u8]) -> Result<Ciphertext> {
     // Read vector u
     let u_len = bytes[pos] as usize;
     pos += 1;
+    if u_len != KYBER_K {
+        return Err(NodeError::Crypto("Invalid ciphertext dimensions".into()));
+    }
     
     let mut u = Vec::with_capacity(u_len);
     for _ in 0..u_len {
@@ -0,0 +1,222 @@
+//! Shamir secret sharing over GF(2^8)
+//!
+//! Each byte of the secret is the constant term of its own random polynomial
+//! of degree `threshold - 1`; share `i` holds the evaluations at `x = i`. Any
+//! `threshold` shares recover the secret by Lagrange interpolation at zero,
+//! fewer reveal nothing about it.
+//!
+//! Field arithmetic uses the AES polynomial and is written without tables
+//! or secret-dependent branches.
+
+use super::rng::system_rng;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ShamirError {
+    #[error("Threshold must be between 1 and the number of shares")]
+    InvalidThreshold,
+    #[error("Secret must not be empty")]
+    EmptySecret,
+    #[error("Need at least {0} shares")]
+    NotEnoughShares(usize),
+    #[error("Duplicate share index {0}")]
+    DuplicateIndex(u8),
+    #[error("Invalid share: {0}")]
+    InvalidShare(String),
+    #[error("Randomness failure")]
+    Randomness,
+}
+
+/// One share: the evaluation point and the evaluations for every byte
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    pub index: u8,
+    pub value: Vec<u8>,
+}
+
+impl std::fmt::Debug for Share {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Share")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Split `secret` into `count` shares, any `threshold` of which recover it.
+/// Shares are numbered from 1.
+pub fn split(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>, ShamirError> {
+    if threshold == 0 || threshold > count {
+        return Err(ShamirError::InvalidThreshold);
+    }
+    if secret.is_empty() {
+        return Err(ShamirError::EmptySecret);
+    }
+
+    // coefficients[k][b] is the x^(k+1) coefficient for secret byte b
+    let rng = system_rng();
+    let mut coefficients = vec![vec![0u8; secret.len()]; threshold as usize - 1];
+    for row in coefficients.iter_mut() {
+        rng.fill(row).map_err(|_| ShamirError::Randomness)?;
+    }
+
+    let shares = (1..=count)
+        .map(|x| {
+            let value = secret
+                .iter()
+                .enumerate()
+                .map(|(b, &s)| {
+                    // Horner's rule from the highest coefficient down
+                    let high = coefficients
+                        .iter()
+                        .rev()
+                        .fold(0u8, |acc, row| gf_mul(acc, x) ^ row[b]);
+                    gf_mul(high, x) ^ s
+                })
+                .collect();
+            Share { index: x, value }
+        })
+        .collect();
+
+    for row in coefficients.iter_mut() {
+        row.fill(0);
+    }
+
+    Ok(shares)
+}
+
+/// Recover the secret from at least `threshold` distinct shares. With fewer
+/// shares than the threshold the result is garbage, not an error; callers
+/// must authenticate what they recover.
+pub fn combine(shares: &[Share], threshold: u8) -> Result<Vec<u8>, ShamirError> {
+    if threshold == 0 {
+        return Err(ShamirError::InvalidThreshold);
+    }
+    if shares.len() < threshold as usize {
+        return Err(ShamirError::NotEnoughShares(threshold as usize));
+    }
+
+    let shares = &shares[..threshold as usize];
+    let len = shares[0].value.len();
+    for (i, share) in shares.iter().enumerate() {
+        if share.index == 0 {
+            return Err(ShamirError::InvalidShare("index 0 is the secret".into()));
+        }
+        if share.value.len() != len || len == 0 {
+            return Err(ShamirError::InvalidShare("length mismatch".into()));
+        }
+        if shares[..i].iter().any(|other| other.index == share.index) {
+            return Err(ShamirError::DuplicateIndex(share.index));
+        }
+    }
+
+    // Lagrange basis at x = 0: l_i = prod_{j != i} x_j / (x_j - x_i)
+    let basis: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1u8, |acc, other| {
+                    gf_mul(acc, gf_mul(other.index, gf_inv(other.index ^ share.index)))
+                })
+        })
+        .collect();
+
+    Ok((0..len)
+        .map(|b| {
+            shares
+                .iter()
+                .zip(&basis)
+                .fold(0u8, |acc, (share, &l)| acc ^ gf_mul(share.value[b], l))
+        })
+        .collect())
+}
+
+/// Multiplication modulo x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverse as a^254; maps 0 to 0
+fn gf_inv(a: u8) -> u8 {
+    let a2 = gf_mul(a, a);
+    let a4 = gf_mul(a2, a2);
+    let a8 = gf_mul(a4, a4);
+    let a16 = gf_mul(a8, a8);
+    let a32 = gf_mul(a16, a16);
+    let a64 = gf_mul(a32, a32);
+    let a128 = gf_mul(a64, a64);
+    // 254 = 128 + 64 + 32 + 16 + 8 + 4 + 2
+    [a64, a32, a16, a8, a4, a2].iter().fold(a128, |acc, &p| gf_mul(acc, p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_inverse() {
+        assert_eq!(gf_mul(0x53, 0xca), 0x01);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_any_threshold_subset_recovers() {
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = split(&secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                    assert_eq!(combine(&subset, 3).unwrap(), secret);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_below_threshold() {
+        let secret = [0x42u8; 32];
+        let shares = split(&secret, 3, 5).unwrap();
+
+        assert_eq!(
+            combine(&shares[..2], 3),
+            Err(ShamirError::NotEnoughShares(3))
+        );
+        // Interpolating too few points gives an unrelated value
+        assert_ne!(combine(&shares[..2], 2).unwrap(), secret.to_vec());
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(split(&[1], 0, 3), Err(ShamirError::InvalidThreshold));
+        assert_eq!(split(&[1], 4, 3), Err(ShamirError::InvalidThreshold));
+        assert_eq!(split(&[], 2, 3), Err(ShamirError::EmptySecret));
+
+        let shares = split(&[1, 2, 3], 2, 3).unwrap();
+        let duplicate = [shares[0].clone(), shares[0].clone()];
+        assert_eq!(combine(&duplicate, 2), Err(ShamirError::DuplicateIndex(1)));
+    }
+
+    #[test]
+    fn test_resplit_keeps_secret() {
+        let secret = [7u8; 32];
+        let first = split(&secret, 2, 3).unwrap();
+        let second = split(&secret, 3, 4).unwrap();
+
+        assert_ne!(first[0].value, second[0].value);
+        assert_eq!(combine(&first[1..], 2).unwrap(), secret.to_vec());
+        assert_eq!(combine(&second[1..], 3).unwrap(), secret.to_vec());
+    }
+}
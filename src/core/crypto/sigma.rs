@@ -0,0 +1,491 @@
+//! Lattice-based sigma protocol (Lyubashevsky identification scheme)
+//! made non-interactive with Fiat-Shamir with aborts.
+//!
+//! Proves knowledge of a short vector `s = (s1, s2)` such that
+//! `A·s1 + s2 = t` over R_q = Z_q[X]/(X^256 + 1), where `A` is expanded
+//! from a public seed. The proof reveals nothing about `s` beyond the
+//! statement: rejection sampling makes the response `z` independent of the
+//! secret. Soundness rests on Module-SIS, so it is believed to hold against
+//! quantum adversaries. Ring parameters follow Dilithium3.
+
+use sha3::{Shake256, digest::{Update, ExtendableOutput, XofReader}};
+
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::{
+        sampling::cbd_from_bytes,
+        rng::{CryptoRng, system_rng},
+    },
+};
+
+pub const N: usize = 256;
+pub const Q: i64 = 8_380_417;
+/// Rows of A (length of s2 and t)
+pub const K: usize = 4;
+/// Columns of A (length of s1)
+pub const L: usize = 4;
+/// Secret coefficients lie in [-ETA, ETA]
+pub const ETA: u8 = 2;
+/// Number of ±1 coefficients in a challenge polynomial
+const TAU: usize = 39;
+/// Masking range: y has coefficients in (-GAMMA1, GAMMA1]
+const GAMMA1: i64 = 1 << 17;
+/// Upper bound on ||c·s||∞
+const BETA: i64 = TAU as i64 * ETA as i64;
+/// Give up after this many rejected attempts (expected is ~3.4)
+const MAX_ATTEMPTS: usize = 1000;
+
+pub const SEED_LEN: usize = 32;
+pub const CHALLENGE_LEN: usize = 32;
+/// Serialized proof: challenge seed followed by (L + K) polynomials of
+/// 4-byte coefficients
+pub const PROOF_LEN: usize = CHALLENGE_LEN + (L + K) * N * 4;
+
+/// Polynomial in R_q with coefficients stored as signed integers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RingElement {
+    coeffs: [i64; N],
+}
+
+impl RingElement {
+    pub fn zero() -> Self {
+        Self { coeffs: [0; N] }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let mut out = Self::zero();
+        for i in 0..N {
+            out.coeffs[i] = (self.coeffs[i] + other.coeffs[i]).rem_euclid(Q);
+        }
+        out
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        let mut out = Self::zero();
+        for i in 0..N {
+            out.coeffs[i] = (self.coeffs[i] - other.coeffs[i]).rem_euclid(Q);
+        }
+        out
+    }
+
+    /// Negacyclic schoolbook multiplication: X^N = -1
+    ///
+    /// Skips zero coefficients of `self`, so `self` must be public (the
+    /// matrix A or a challenge); secrets only ever appear as `other`.
+    fn mul(&self, other: &Self) -> Self {
+        let mut acc = [0i64; 2 * N];
+        for i in 0..N {
+            if self.coeffs[i] == 0 {
+                continue;
+            }
+            for j in 0..N {
+                acc[i + j] += self.coeffs[i] * other.coeffs[j];
+            }
+        }
+
+        let mut out = Self::zero();
+        for i in 0..N {
+            out.coeffs[i] = (acc[i] - acc[i + N]).rem_euclid(Q);
+        }
+        out
+    }
+
+    /// Infinity norm of the centered representative
+    fn norm_inf(&self) -> i64 {
+        self.coeffs
+            .iter()
+            .map(|&c| {
+                let c = c.rem_euclid(Q);
+                if c > Q / 2 { Q - c } else { c }
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        for &c in &self.coeffs {
+            out.extend_from_slice(&(c as i32).to_le_bytes());
+        }
+    }
+
+    /// Coefficients must be canonical, in [0, q), so every element has
+    /// exactly one encoding
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut out = Self::zero();
+        for (i, chunk) in bytes.chunks_exact(4).take(N).enumerate() {
+            out.coeffs[i] = i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as i64;
+        }
+        if !out.is_canonical() {
+            return Err(NodeError::Crypto("Ring element coefficient out of range".into()));
+        }
+        Ok(out)
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.coeffs.iter().all(|c| (0..Q).contains(c))
+    }
+}
+
+/// Public statement: the matrix seed and the image `t = A·s1 + s2`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    pub matrix_seed: [u8; SEED_LEN],
+    pub t: Vec<RingElement>,
+}
+
+/// Short witness `(s1, s2)`
+pub struct Witness {
+    s1: Vec<RingElement>,
+    s2: Vec<RingElement>,
+}
+
+/// Non-interactive proof: challenge seed and response z = y + c·s
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigmaProof {
+    challenge: [u8; CHALLENGE_LEN],
+    z1: Vec<RingElement>,
+    z2: Vec<RingElement>,
+}
+
+impl Witness {
+    /// Deterministically derive a short witness from secret key material
+    pub fn derive(key_material: &[u8]) -> Result<Self> {
+        let mut shake = Shake256::default();
+        shake.update(b"freeghost-sigma-witness");
+        shake.update(key_material);
+        let mut reader = shake.finalize_xof();
+
+        let mut sample = || -> Result<RingElement> {
+            let mut buf = [0u8; N * 2 * ETA as usize / 8];
+            reader.read(&mut buf);
+            let coeffs = cbd_from_bytes(&buf, ETA)?;
+            let mut poly = RingElement::zero();
+            for (dst, src) in poly.coeffs.iter_mut().zip(coeffs) {
+                *dst = src as i64;
+            }
+            Ok(poly)
+        };
+
+        let s1 = (0..L).map(|_| sample()).collect::<Result<Vec<_>>>()?;
+        let s2 = (0..K).map(|_| sample()).collect::<Result<Vec<_>>>()?;
+        Ok(Self { s1, s2 })
+    }
+
+    /// Compute the public statement for this witness under `matrix_seed`
+    pub fn statement(&self, matrix_seed: [u8; SEED_LEN]) -> Statement {
+        let a = expand_matrix(&matrix_seed);
+        let t = apply(&a, &self.s1, &self.s2);
+        Statement { matrix_seed, t }
+    }
+}
+
+impl Statement {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SEED_LEN + K * N * 4);
+        out.extend_from_slice(&self.matrix_seed);
+        for poly in &self.t {
+            poly.to_bytes(&mut out);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != SEED_LEN + K * N * 4 {
+            return Err(NodeError::Crypto("Invalid statement length".into()));
+        }
+        let mut matrix_seed = [0u8; SEED_LEN];
+        matrix_seed.copy_from_slice(&bytes[..SEED_LEN]);
+        let t = bytes[SEED_LEN..]
+            .chunks_exact(N * 4)
+            .map(RingElement::from_bytes)
+            .collect::<Result<_>>()?;
+        Ok(Self { matrix_seed, t })
+    }
+
+    /// Check that `witness` satisfies this statement
+    pub fn is_satisfied_by(&self, witness: &Witness) -> bool {
+        witness.statement(self.matrix_seed).t == self.t
+    }
+}
+
+impl SigmaProof {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(PROOF_LEN);
+        out.extend_from_slice(&self.challenge);
+        for poly in self.z1.iter().chain(self.z2.iter()) {
+            poly.to_bytes(&mut out);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != PROOF_LEN {
+            return Err(NodeError::Crypto("Invalid proof length".into()));
+        }
+        let mut challenge = [0u8; CHALLENGE_LEN];
+        challenge.copy_from_slice(&bytes[..CHALLENGE_LEN]);
+        let mut polys = bytes[CHALLENGE_LEN..]
+            .chunks_exact(N * 4)
+            .map(RingElement::from_bytes);
+        let z1: Vec<_> = polys.by_ref().take(L).collect::<Result<_>>()?;
+        let z2: Vec<_> = polys.collect::<Result<_>>()?;
+        if z1.iter().chain(z2.iter()).any(|z| z.norm_inf() >= GAMMA1 - BETA) {
+            return Err(NodeError::Crypto("Proof response out of range".into()));
+        }
+        Ok(Self { challenge, z1, z2 })
+    }
+}
+
+/// Prove knowledge of `witness` for `statement`, bound to `context`
+pub fn prove(statement: &Statement, witness: &Witness, context: &[u8]) -> Result<SigmaProof> {
+    prove_with_rng(statement, witness, context, &*system_rng())
+}
+
+/// Like `prove`, drawing the masking vectors from `rng`
+pub fn prove_with_rng(
+    statement: &Statement,
+    witness: &Witness,
+    context: &[u8],
+    rng: &dyn CryptoRng,
+) -> Result<SigmaProof> {
+    let a = expand_matrix(&statement.matrix_seed);
+
+    for _ in 0..MAX_ATTEMPTS {
+        // Commitment: w = A·y1 + y2 for a fresh masking vector y
+        let y1 = (0..L).map(|_| sample_mask(rng)).collect::<Result<Vec<_>>>()?;
+        let y2 = (0..K).map(|_| sample_mask(rng)).collect::<Result<Vec<_>>>()?;
+        let w = apply(&a, &y1, &y2);
+
+        // Challenge: c = H(context, statement, w)
+        let challenge = challenge_seed(context, statement, &w);
+        let c = sample_in_ball(&challenge);
+
+        // Response: z = y + c·s, rejected unless it hides s
+        let z1: Vec<_> = y1.iter().zip(&witness.s1).map(|(y, s)| y.add(&c.mul(s))).collect();
+        let z2: Vec<_> = y2.iter().zip(&witness.s2).map(|(y, s)| y.add(&c.mul(s))).collect();
+
+        if z1.iter().chain(z2.iter()).all(|z| z.norm_inf() < GAMMA1 - BETA) {
+            return Ok(SigmaProof { challenge, z1, z2 });
+        }
+    }
+
+    Err(NodeError::Crypto("Proof generation exceeded rejection sampling attempts".into()))
+}
+
+/// Verify `proof` against `statement` and `context` using public data only
+pub fn verify(statement: &Statement, proof: &SigmaProof, context: &[u8]) -> bool {
+    if statement.t.len() != K || proof.z1.len() != L || proof.z2.len() != K {
+        return false;
+    }
+    // Canonical coefficients only: z ± q would otherwise verify as well
+    if statement.t.iter().chain(proof.z1.iter()).chain(proof.z2.iter()).any(|p| !p.is_canonical()) {
+        return false;
+    }
+    if proof.z1.iter().chain(proof.z2.iter()).any(|z| z.norm_inf() >= GAMMA1 - BETA) {
+        return false;
+    }
+
+    // A·z1 + z2 - c·t = A·y1 + y2 = w for an honest proof
+    let a = expand_matrix(&statement.matrix_seed);
+    let c = sample_in_ball(&proof.challenge);
+    let w: Vec<_> = apply(&a, &proof.z1, &proof.z2)
+        .iter()
+        .zip(&statement.t)
+        .map(|(az, t)| az.sub(&c.mul(t)))
+        .collect();
+
+    challenge_seed(context, statement, &w) == proof.challenge
+}
+
+/// Expand the public K x L matrix A from its seed by rejection sampling
+fn expand_matrix(seed: &[u8; SEED_LEN]) -> Vec<Vec<RingElement>> {
+    let mut a = vec![vec![RingElement::zero(); L]; K];
+
+    for (i, row) in a.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            let mut shake = Shake256::default();
+            shake.update(b"freeghost-sigma-matrix");
+            shake.update(seed);
+            shake.update(&[i as u8, j as u8]);
+            let mut reader = shake.finalize_xof();
+
+            let mut buf = [0u8; 3];
+            for coeff in entry.coeffs.iter_mut() {
+                loop {
+                    reader.read(&mut buf);
+                    let val = u32::from_le_bytes([buf[0], buf[1], buf[2], 0]) & 0x7F_FFFF;
+                    if (val as i64) < Q {
+                        *coeff = val as i64;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    a
+}
+
+/// Compute A·v1 + v2
+fn apply(a: &[Vec<RingElement>], v1: &[RingElement], v2: &[RingElement]) -> Vec<RingElement> {
+    a.iter()
+        .zip(v2)
+        .map(|(row, v2i)| {
+            row.iter()
+                .zip(v1)
+                .fold(v2i.clone(), |acc, (aij, v1j)| acc.add(&aij.mul(v1j)))
+        })
+        .collect()
+}
+
+/// Uniform masking polynomial with coefficients in (-GAMMA1, GAMMA1]
+fn sample_mask(rng: &dyn CryptoRng) -> Result<RingElement> {
+    let mut buf = [0u8; N * 3];
+    rng.fill(&mut buf)?;
+
+    let mut poly = RingElement::zero();
+    for (coeff, chunk) in poly.coeffs.iter_mut().zip(buf.chunks_exact(3)) {
+        // 18 bits give exactly 2·GAMMA1 equally likely values
+        let v = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], 0]) & 0x3_FFFF;
+        *coeff = GAMMA1 - v as i64;
+    }
+    Ok(poly)
+}
+
+fn challenge_seed(context: &[u8], statement: &Statement, w: &[RingElement]) -> [u8; CHALLENGE_LEN] {
+    let mut shake = Shake256::default();
+    shake.update(b"freeghost-sigma-challenge");
+    shake.update(&(context.len() as u64).to_le_bytes());
+    shake.update(context);
+    shake.update(&statement.to_bytes());
+    let mut w_bytes = Vec::with_capacity(K * N * 4);
+    for poly in w {
+        poly.to_bytes(&mut w_bytes);
+    }
+    shake.update(&w_bytes);
+
+    let mut out = [0u8; CHALLENGE_LEN];
+    shake.finalize_xof().read(&mut out);
+    out
+}
+
+/// Map a challenge seed to a polynomial with exactly TAU coefficients in
+/// {-1, 1} and the rest zero (Dilithium's SampleInBall)
+fn sample_in_ball(seed: &[u8; CHALLENGE_LEN]) -> RingElement {
+    let mut shake = Shake256::default();
+    shake.update(seed);
+    let mut reader = shake.finalize_xof();
+
+    let mut signs_bytes = [0u8; 8];
+    reader.read(&mut signs_bytes);
+    let mut signs = u64::from_le_bytes(signs_bytes);
+
+    let mut c = RingElement::zero();
+    let mut byte = [0u8; 1];
+    for i in (N - TAU)..N {
+        let j = loop {
+            reader.read(&mut byte);
+            if (byte[0] as usize) <= i {
+                break byte[0] as usize;
+            }
+        };
+        c.coeffs[i] = c.coeffs[j];
+        c.coeffs[j] = 1 - 2 * (signs & 1) as i64;
+        signs >>= 1;
+    }
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_statement() -> (Statement, Witness) {
+        let witness = Witness::derive(b"test key material").unwrap();
+        let statement = witness.statement([7u8; SEED_LEN]);
+        (statement, witness)
+    }
+
+    #[test]
+    fn test_prove_and_verify() {
+        let (statement, witness) = test_statement();
+        let proof = prove(&statement, &witness, b"context").unwrap();
+        assert!(verify(&statement, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_wrong_context_rejected() {
+        let (statement, witness) = test_statement();
+        let proof = prove(&statement, &witness, b"context").unwrap();
+        assert!(!verify(&statement, &proof, b"other context"));
+    }
+
+    #[test]
+    fn test_wrong_statement_rejected() {
+        let (statement, witness) = test_statement();
+        let proof = prove(&statement, &witness, b"context").unwrap();
+
+        let other = Witness::derive(b"other key material").unwrap().statement(statement.matrix_seed);
+        assert!(!verify(&other, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_wrong_witness_cannot_prove() {
+        let (statement, _) = test_statement();
+        let wrong = Witness::derive(b"other key material").unwrap();
+        assert!(!statement.is_satisfied_by(&wrong));
+
+        // A proof built from the wrong witness does not verify
+        let proof = prove(&statement, &wrong, b"context").unwrap();
+        assert!(!verify(&statement, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        let (statement, witness) = test_statement();
+        let proof = prove(&statement, &witness, b"context").unwrap();
+
+        let proof_bytes = proof.to_bytes();
+        assert_eq!(proof_bytes.len(), PROOF_LEN);
+        let decoded = SigmaProof::from_bytes(&proof_bytes).unwrap();
+        assert_eq!(decoded, proof);
+
+        let statement2 = Statement::from_bytes(&statement.to_bytes()).unwrap();
+        assert!(verify(&statement2, &decoded, b"context"));
+    }
+
+    #[test]
+    fn test_seeded_proof_is_reproducible() {
+        use crate::core::crypto::rng::DeterministicRng;
+
+        let (statement, witness) = test_statement();
+        let first = prove_with_rng(&statement, &witness, b"context", &DeterministicRng::from_seed(b"sigma")).unwrap();
+        let second = prove_with_rng(&statement, &witness, b"context", &DeterministicRng::from_seed(b"sigma")).unwrap();
+        assert_eq!(first, second);
+        assert!(verify(&statement, &first, b"context"));
+    }
+
+    #[test]
+    fn test_tampered_response_rejected() {
+        let (statement, witness) = test_statement();
+        let mut proof = prove(&statement, &witness, b"context").unwrap();
+        proof.z1[0].coeffs[0] += 1;
+        assert!(!verify(&statement, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_noncanonical_response_rejected() {
+        let (statement, witness) = test_statement();
+        let proof = prove(&statement, &witness, b"context").unwrap();
+
+        // z + q is the same element mod q but must not verify or decode
+        let mut tweaked = proof.clone();
+        tweaked.z1[0].coeffs[0] += Q;
+        assert!(!verify(&statement, &tweaked, b"context"));
+        assert!(SigmaProof::from_bytes(&tweaked.to_bytes()).is_err());
+
+        let mut bytes = statement.to_bytes();
+        bytes[SEED_LEN..SEED_LEN + 4].copy_from_slice(&(Q as i32).to_le_bytes());
+        assert!(Statement::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,530 @@
+//! Unlinkable one-time access tokens (Privacy Pass style)
+//!
+//! Issuance is a verifiable oblivious PRF over ristretto255, modeled on the
+//! Privacy Pass privately verifiable token type (RFC 9497 / RFC 9578):
+//!
+//! 1. The client picks a random nonce per token, hashes
+//!    `(key id, scope, nonce)` to a group element `T` and sends the blinded
+//!    element `r·T`.
+//! 2. The node returns `k·(r·T)` for every element together with one batched
+//!    DLEQ proof that the same key `k` behind the published `K = k·G` was used.
+//! 3. The client removes the blind, getting `k·T`, and keeps
+//!    `H(key id, scope, nonce, k·T)` as the token authenticator.
+//!
+//! The node only ever sees `r·T`, which is uniformly random, so a redeemed
+//! token cannot be linked to the issuance request it came from. Redemption
+//! recomputes `k·T`; double spending is prevented by the caller recording
+//! `Token::id` in a spent set.
+//!
+//! Keys rotate every epoch and are derived from a master seed, so tokens are
+//! tied to the epoch they were issued in and expire with it. Hashes use SHA3
+//! rather than the RFC's SHA-512, so tokens do not interoperate with other
+//! Privacy Pass deployments.
+
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
+use serde::{Serialize, Deserialize};
+use sha3::{Sha3_256, Sha3_512, Digest};
+use thiserror::Error;
+
+use super::rng::system_rng;
+
+const ELEMENT_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+/// Largest number of tokens issued in one batch
+pub const MAX_BATCH: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("Invalid token request: {0}")]
+    InvalidRequest(String),
+    #[error("Key epoch {0} is not accepted")]
+    EpochNotAccepted(u64),
+    #[error("Issuance proof rejected")]
+    InvalidProof,
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+    #[error("Token is not valid for scope {0}")]
+    ScopeMismatch(String),
+    #[error("Randomness failure")]
+    Randomness,
+}
+
+/// Public key for one epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenPublicKey {
+    pub epoch: u64,
+    pub key: [u8; ELEMENT_LEN],
+}
+
+impl TokenPublicKey {
+    pub fn key_id(&self) -> [u8; 32] {
+        Sha3_256::new()
+            .chain_update(b"freeghost-token-key-id")
+            .chain_update(self.epoch.to_le_bytes())
+            .chain_update(self.key)
+            .finalize()
+            .into()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRequest {
+    pub key_id: [u8; 32],
+    pub blinded: Vec<[u8; ELEMENT_LEN]>,
+}
+
+/// Batched proof that `log_G(K) == log_M(Z)` for the combined elements
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DleqProof {
+    pub c: [u8; 32],
+    pub s: [u8; 32],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub evaluated: Vec<[u8; ELEMENT_LEN]>,
+    pub proof: DleqProof,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    pub epoch: u64,
+    pub key_id: [u8; 32],
+    pub scope: String,
+    pub nonce: [u8; NONCE_LEN],
+    pub authenticator: Vec<u8>,
+}
+
+impl Token {
+    /// Identifier recorded in the spent set
+    pub fn id(&self) -> [u8; 32] {
+        Sha3_256::new()
+            .chain_update(b"freeghost-token-id")
+            .chain_update(self.key_id)
+            .chain_update(self.nonce)
+            .finalize()
+            .into()
+    }
+}
+
+/// Node side: derives per-epoch keys, evaluates requests, checks tokens
+pub struct TokenIssuer {
+    master_seed: [u8; 32],
+    epoch_duration: u64,
+}
+
+impl TokenIssuer {
+    pub fn new(master_seed: [u8; 32], epoch_duration: u64) -> Self {
+        Self {
+            master_seed,
+            epoch_duration: epoch_duration.max(1),
+        }
+    }
+
+    pub fn epoch_at(&self, now: i64) -> u64 {
+        now.max(0) as u64 / self.epoch_duration
+    }
+
+    /// Tokens from the current and the previous epoch are redeemable
+    pub fn accepts_epoch(&self, epoch: u64, now: i64) -> bool {
+        let current = self.epoch_at(now);
+        epoch == current || epoch + 1 == current
+    }
+
+    pub fn public_key(&self, epoch: u64) -> TokenPublicKey {
+        TokenPublicKey {
+            epoch,
+            key: (self.secret(epoch) * RISTRETTO_BASEPOINT_POINT).compress().to_bytes(),
+        }
+    }
+
+    /// Evaluate a batch of blinded elements under the current epoch key
+    pub fn issue(&self, request: &TokenRequest, now: i64) -> Result<TokenResponse, TokenError> {
+        if request.blinded.is_empty() || request.blinded.len() > MAX_BATCH {
+            return Err(TokenError::InvalidRequest(format!(
+                "Batch size must be between 1 and {}",
+                MAX_BATCH
+            )));
+        }
+
+        let epoch = self.epoch_at(now);
+        let public_key = self.public_key(epoch);
+        if request.key_id != public_key.key_id() {
+            return Err(TokenError::EpochNotAccepted(epoch));
+        }
+
+        let secret = self.secret(epoch);
+        let blinded = request.blinded
+            .iter()
+            .map(decode_element)
+            .collect::<Result<Vec<_>, _>>()?;
+        let evaluated: Vec<RistrettoPoint> = blinded.iter().map(|m| secret * m).collect();
+
+        let proof = prove_dleq(&secret, &public_key, &blinded, &evaluated)?;
+
+        Ok(TokenResponse {
+            evaluated: evaluated.iter().map(|z| z.compress().to_bytes()).collect(),
+            proof,
+        })
+    }
+
+    /// Check that `token` was issued by this node for `scope` and is still
+    /// within an accepted epoch. Does not check for double spending.
+    pub fn verify(&self, token: &Token, scope: &str, now: i64) -> Result<(), TokenError> {
+        if token.scope != scope {
+            return Err(TokenError::ScopeMismatch(scope.to_string()));
+        }
+        if !self.accepts_epoch(token.epoch, now) {
+            return Err(TokenError::EpochNotAccepted(token.epoch));
+        }
+
+        let public_key = self.public_key(token.epoch);
+        if token.key_id != public_key.key_id() {
+            return Err(TokenError::InvalidToken("Unknown key".into()));
+        }
+
+        let input = token_input(&token.key_id, &token.scope, &token.nonce);
+        let expected = authenticator(&token.key_id, &token.scope, &token.nonce, &(self.secret(token.epoch) * input));
+
+        ring::constant_time::verify_slices_are_equal(&expected, &token.authenticator)
+            .map_err(|_| TokenError::InvalidToken("Authenticator mismatch".into()))
+    }
+
+    fn secret(&self, epoch: u64) -> Scalar {
+        hash_to_scalar(&[b"freeghost-token-epoch-key", &self.master_seed, &epoch.to_le_bytes()])
+    }
+}
+
+/// Client state between `blind` and `finalize`; holds the blinding factors
+pub struct BlindState {
+    public_key: TokenPublicKey,
+    scope: String,
+    entries: Vec<([u8; NONCE_LEN], Scalar, RistrettoPoint)>,
+}
+
+/// Prepare `count` blinded token requests for `scope` under `public_key`
+pub fn blind(
+    public_key: &TokenPublicKey,
+    scope: &str,
+    count: usize,
+) -> Result<(TokenRequest, BlindState), TokenError> {
+    let key_id = public_key.key_id();
+    let mut entries = Vec::with_capacity(count);
+    let mut blinded = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let nonce = random_bytes::<NONCE_LEN>()?;
+        let blind = random_scalar()?;
+        let element = blind * token_input(&key_id, scope, &nonce);
+
+        blinded.push(element.compress().to_bytes());
+        entries.push((nonce, blind, element));
+    }
+
+    Ok((
+        TokenRequest { key_id, blinded },
+        BlindState { public_key: public_key.clone(), scope: scope.to_string(), entries },
+    ))
+}
+
+/// Verify the issuance proof and unblind the evaluated elements into tokens
+pub fn finalize(state: BlindState, response: &TokenResponse) -> Result<Vec<Token>, TokenError> {
+    if response.evaluated.len() != state.entries.len() {
+        return Err(TokenError::InvalidProof);
+    }
+
+    let blinded: Vec<RistrettoPoint> = state.entries.iter().map(|(_, _, m)| *m).collect();
+    let evaluated = response.evaluated
+        .iter()
+        .map(decode_element)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !verify_dleq(&state.public_key, &blinded, &evaluated, &response.proof)? {
+        return Err(TokenError::InvalidProof);
+    }
+
+    let key_id = state.public_key.key_id();
+    Ok(state.entries
+        .iter()
+        .zip(&evaluated)
+        .map(|((nonce, blind, _), z)| {
+            let unblinded = blind.invert() * z;
+            Token {
+                epoch: state.public_key.epoch,
+                key_id,
+                scope: state.scope.clone(),
+                nonce: *nonce,
+                authenticator: authenticator(&key_id, &state.scope, nonce, &unblinded),
+            }
+        })
+        .collect())
+}
+
+/// Combine the batch into single elements `(M, Z)` using weights derived
+/// from the whole transcript, so one proof covers every element
+fn combine(
+    public_key: &TokenPublicKey,
+    blinded: &[RistrettoPoint],
+    evaluated: &[RistrettoPoint],
+) -> (RistrettoPoint, RistrettoPoint) {
+    let mut seed = Sha3_256::new()
+        .chain_update(b"freeghost-token-dleq-seed")
+        .chain_update(public_key.key);
+    for (m, z) in blinded.iter().zip(evaluated) {
+        seed.update(m.compress().as_bytes());
+        seed.update(z.compress().as_bytes());
+    }
+    let seed = seed.finalize();
+
+    let mut m_sum = RistrettoPoint::default();
+    let mut z_sum = RistrettoPoint::default();
+    for (i, (m, z)) in blinded.iter().zip(evaluated).enumerate() {
+        let d = hash_to_scalar(&[b"freeghost-token-dleq-weight", &seed, &(i as u64).to_le_bytes()]);
+        m_sum += d * m;
+        z_sum += d * z;
+    }
+    (m_sum, z_sum)
+}
+
+fn dleq_challenge(
+    public_key: &TokenPublicKey,
+    m: &RistrettoPoint,
+    z: &RistrettoPoint,
+    t2: &RistrettoPoint,
+    t3: &RistrettoPoint,
+) -> Scalar {
+    hash_to_scalar(&[
+        b"freeghost-token-dleq-challenge",
+        &public_key.key,
+        m.compress().as_bytes(),
+        z.compress().as_bytes(),
+        t2.compress().as_bytes(),
+        t3.compress().as_bytes(),
+    ])
+}
+
+fn prove_dleq(
+    secret: &Scalar,
+    public_key: &TokenPublicKey,
+    blinded: &[RistrettoPoint],
+    evaluated: &[RistrettoPoint],
+) -> Result<DleqProof, TokenError> {
+    let (m, z) = combine(public_key, blinded, evaluated);
+
+    let r = random_scalar()?;
+    let t2 = r * RISTRETTO_BASEPOINT_POINT;
+    let t3 = r * m;
+    let c = dleq_challenge(public_key, &m, &z, &t2, &t3);
+    let s = r - c * secret;
+
+    Ok(DleqProof { c: c.to_bytes(), s: s.to_bytes() })
+}
+
+fn verify_dleq(
+    public_key: &TokenPublicKey,
+    blinded: &[RistrettoPoint],
+    evaluated: &[RistrettoPoint],
+    proof: &DleqProof,
+) -> Result<bool, TokenError> {
+    let key = decode_element(&public_key.key)?;
+    let (c, s) = match (
+        Scalar::from_canonical_bytes(proof.c),
+        Scalar::from_canonical_bytes(proof.s),
+    ) {
+        (Some(c), Some(s)) => (c, s),
+        _ => return Ok(false),
+    };
+
+    let (m, z) = combine(public_key, blinded, evaluated);
+    let t2 = s * RISTRETTO_BASEPOINT_POINT + c * key;
+    let t3 = s * m + c * z;
+
+    Ok(dleq_challenge(public_key, &m, &z, &t2, &t3) == c)
+}
+
+fn token_input(key_id: &[u8; 32], scope: &str, nonce: &[u8; NONCE_LEN]) -> RistrettoPoint {
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&Sha3_512::new()
+        .chain_update(b"freeghost-token-input")
+        .chain_update(key_id)
+        .chain_update((scope.len() as u64).to_le_bytes())
+        .chain_update(scope.as_bytes())
+        .chain_update(nonce)
+        .finalize());
+    RistrettoPoint::from_uniform_bytes(&wide)
+}
+
+fn authenticator(
+    key_id: &[u8; 32],
+    scope: &str,
+    nonce: &[u8; NONCE_LEN],
+    unblinded: &RistrettoPoint,
+) -> Vec<u8> {
+    Sha3_512::new()
+        .chain_update(b"freeghost-token-authenticator")
+        .chain_update(key_id)
+        .chain_update((scope.len() as u64).to_le_bytes())
+        .chain_update(scope.as_bytes())
+        .chain_update(nonce)
+        .chain_update(unblinded.compress().as_bytes())
+        .finalize()
+        .to_vec()
+}
+
+fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha3_512::new();
+    for part in parts {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+fn decode_element(bytes: &[u8; ELEMENT_LEN]) -> Result<RistrettoPoint, TokenError> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .filter(|p| *p != RistrettoPoint::default())
+        .ok_or_else(|| TokenError::InvalidRequest("Invalid group element".into()))
+}
+
+fn random_scalar() -> Result<Scalar, TokenError> {
+    let wide = random_bytes::<64>()?;
+    Ok(Scalar::from_bytes_mod_order_wide(&wide))
+}
+
+fn random_bytes<const LEN: usize>() -> Result<[u8; LEN], TokenError> {
+    let mut out = [0u8; LEN];
+    system_rng().fill(&mut out).map_err(|_| TokenError::Randomness)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: u64 = 3600;
+    const NOW: i64 = 1_700_000_000;
+
+    fn issuer() -> TokenIssuer {
+        TokenIssuer::new([7u8; 32], EPOCH)
+    }
+
+    fn issue(issuer: &TokenIssuer, scope: &str, count: usize) -> Vec<Token> {
+        let public_key = issuer.public_key(issuer.epoch_at(NOW));
+        let (request, state) = blind(&public_key, scope, count).unwrap();
+        let response = issuer.issue(&request, NOW).unwrap();
+        finalize(state, &response).unwrap()
+    }
+
+    #[test]
+    fn test_batch_issuance_and_redemption() {
+        let issuer = issuer();
+        let tokens = issue(&issuer, "example.org", 16);
+
+        assert_eq!(tokens.len(), 16);
+        for token in &tokens {
+            issuer.verify(token, "example.org", NOW).unwrap();
+        }
+
+        // Every token is distinct
+        let mut ids: Vec<_> = tokens.iter().map(Token::id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 16);
+    }
+
+    #[test]
+    fn test_request_does_not_reveal_token() {
+        let issuer = issuer();
+        let public_key = issuer.public_key(issuer.epoch_at(NOW));
+        let (request, state) = blind(&public_key, "example.org", 4).unwrap();
+        let response = issuer.issue(&request, NOW).unwrap();
+        let tokens = finalize(state, &response).unwrap();
+
+        // Nothing the node saw at issuance appears in the redeemed tokens
+        for token in &tokens {
+            let input = token_input(&token.key_id, &token.scope, &token.nonce).compress().to_bytes();
+            assert!(!request.blinded.contains(&input));
+        }
+    }
+
+    #[test]
+    fn test_scope_enforced() {
+        let issuer = issuer();
+        let token = issue(&issuer, "example.org", 1).remove(0);
+
+        assert!(matches!(issuer.verify(&token, "other.org", NOW), Err(TokenError::ScopeMismatch(_))));
+
+        let mut moved = token.clone();
+        moved.scope = "other.org".into();
+        assert!(issuer.verify(&moved, "other.org", NOW).is_err());
+    }
+
+    #[test]
+    fn test_epochs() {
+        let issuer = issuer();
+        let token = issue(&issuer, "example.org", 1).remove(0);
+
+        // Still valid during the next epoch, rejected after that
+        issuer.verify(&token, "example.org", NOW + EPOCH as i64).unwrap();
+        assert!(matches!(
+            issuer.verify(&token, "example.org", NOW + 2 * EPOCH as i64),
+            Err(TokenError::EpochNotAccepted(_))
+        ));
+
+        // Requests for a stale key are refused
+        let stale = issuer.public_key(issuer.epoch_at(NOW) - 1);
+        let (request, _) = blind(&stale, "example.org", 1).unwrap();
+        assert!(issuer.issue(&request, NOW).is_err());
+
+        assert_ne!(issuer.public_key(1), issuer.public_key(2));
+    }
+
+    #[test]
+    fn test_forged_tokens_rejected() {
+        let issuer = issuer();
+        let mut token = issue(&issuer, "example.org", 1).remove(0);
+
+        token.nonce[0] ^= 1;
+        assert!(issuer.verify(&token, "example.org", NOW).is_err());
+
+        // A token from a different node's key
+        let other = TokenIssuer::new([8u8; 32], EPOCH);
+        let foreign = issue(&other, "example.org", 1).remove(0);
+        assert!(issuer.verify(&foreign, "example.org", NOW).is_err());
+    }
+
+    #[test]
+    fn test_dishonest_issuer_detected() {
+        let issuer = issuer();
+        let public_key = issuer.public_key(issuer.epoch_at(NOW));
+        let (request, state) = blind(&public_key, "example.org", 4).unwrap();
+        let mut response = issuer.issue(&request, NOW).unwrap();
+
+        // Evaluating one element under a different key would tag that client
+        let tagging_key = hash_to_scalar(&[b"tag"]);
+        let element = decode_element(&request.blinded[2]).unwrap();
+        response.evaluated[2] = (tagging_key * element).compress().to_bytes();
+
+        assert!(matches!(finalize(state, &response), Err(TokenError::InvalidProof)));
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let issuer = issuer();
+        let public_key = issuer.public_key(issuer.epoch_at(NOW));
+
+        let (mut request, _) = blind(&public_key, "example.org", 1).unwrap();
+        request.blinded[0] = [0xFF; 32];
+        assert!(issuer.issue(&request, NOW).is_err());
+
+        request.blinded.clear();
+        assert!(issuer.issue(&request, NOW).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Unsealing a share-sealed keyring
+//!
+//! A node whose keyring was split with `KeyManager::split_kek` starts sealed.
+//! Custodians hand in their shares one at a time, either at a terminal
+//! prompt or through the local unseal endpoint; each share is checked
+//! against the keyring as it arrives and the keyring opens once the
+//! threshold is reached. Collected shares are dropped after every attempt.
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::key_manager::{KeyManager, KeyShare, SealStatus},
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnsealProgress {
+    pub received: u8,
+    pub threshold: u8,
+    pub generation: u32,
+}
+
+pub struct UnsealSession {
+    dir: PathBuf,
+    status: SealStatus,
+    shares: Vec<KeyShare>,
+}
+
+impl UnsealSession {
+    /// Start collecting shares for the keyring in `dir`. Returns `None` if
+    /// the keyring is not sealed with key shares.
+    pub fn new(dir: &Path) -> Result<Option<Self>> {
+        Ok(KeyManager::seal_status(dir)?.map(|status| Self {
+            dir: dir.to_path_buf(),
+            status,
+            shares: Vec::new(),
+        }))
+    }
+
+    pub fn progress(&self) -> UnsealProgress {
+        UnsealProgress {
+            received: self.shares.len() as u8,
+            threshold: self.status.threshold,
+            generation: self.status.generation,
+        }
+    }
+
+    /// Add one share. Returns the unlocked keyring once `threshold` distinct
+    /// shares have been accepted.
+    pub fn submit(&mut self, share: KeyShare) -> Result<Option<KeyManager>> {
+        self.status.verify_share(&share)?;
+        if self.shares.iter().any(|existing| existing.index == share.index) {
+            return Err(NodeError::Crypto(format!("Share {} was already submitted", share.index)));
+        }
+
+        self.shares.push(share);
+        info!(
+            "Accepted key share {}/{}",
+            self.shares.len(),
+            self.status.threshold
+        );
+        if self.shares.len() < self.status.threshold as usize {
+            return Ok(None);
+        }
+
+        let shares = std::mem::take(&mut self.shares);
+        KeyManager::open_with_shares(&self.dir, &shares).map(Some)
+    }
+}
+
+/// Read shares line by line until the keyring unseals. Input is echoed, so
+/// custodians should enter shares on a terminal nobody else can see.
+pub fn prompt_for_shares(mut session: UnsealSession, input: impl BufRead) -> Result<KeyManager> {
+    let mut lines = input.lines();
+    loop {
+        let progress = session.progress();
+        eprint!(
+            "Key share {}/{} (generation {}): ",
+            progress.received + 1,
+            progress.threshold,
+            progress.generation
+        );
+        let _ = std::io::stderr().flush();
+
+        let line = lines
+            .next()
+            .ok_or_else(|| NodeError::Crypto("Input closed before the keyring was unsealed".into()))?
+            .map_err(|e| NodeError::Crypto(format!("Failed to read key share: {}", e)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match line.parse::<KeyShare>().and_then(|share| session.submit(share)) {
+            Ok(Some(key_manager)) => return Ok(key_manager),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Key share rejected: {}", e);
+                eprintln!("Rejected: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::crypto::key_manager::KdfParams;
+    use tempfile::tempdir;
+
+    fn sealed_keyring(dir: &Path) -> (Vec<u8>, Vec<KeyShare>) {
+        let params = KdfParams { memory_kib: 256, iterations: 1, parallelism: 1 };
+        let mut key_manager = KeyManager::open(dir, "test_key", &params).unwrap();
+        let encrypted = key_manager.encrypt(b"test data", b"").unwrap();
+        (encrypted, key_manager.split_kek(2, 3).unwrap())
+    }
+
+    #[test]
+    fn test_session_unseals_at_threshold() {
+        let dir = tempdir().unwrap();
+        let (encrypted, shares) = sealed_keyring(dir.path());
+        let mut session = UnsealSession::new(dir.path()).unwrap().unwrap();
+
+        assert!(session.submit(shares[2].clone()).unwrap().is_none());
+        assert!(session.submit(shares[2].clone()).is_err());
+        assert_eq!(session.progress().received, 1);
+
+        let key_manager = session.submit(shares[0].clone()).unwrap().unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+    }
+
+    #[test]
+    fn test_prompt_skips_bad_input() {
+        let dir = tempdir().unwrap();
+        let (encrypted, shares) = sealed_keyring(dir.path());
+        let session = UnsealSession::new(dir.path()).unwrap().unwrap();
+
+        let input = format!("not a share\n\n{}\n{}\n", shares[1], shares[0]);
+        let key_manager = prompt_for_shares(session, input.as_bytes()).unwrap();
+        assert_eq!(key_manager.decrypt(&encrypted, b"").unwrap(), b"test data".to_vec());
+
+        let session = UnsealSession::new(dir.path()).unwrap().unwrap();
+        let input = format!("{}\n", shares[1]);
+        assert!(prompt_for_shares(session, input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_unsealed_keyring_has_no_session() {
+        let dir = tempdir().unwrap();
+        let params = KdfParams { memory_kib: 256, iterations: 1, parallelism: 1 };
+        KeyManager::open(dir.path(), "test_key", &params).unwrap();
+        assert!(UnsealSession::new(dir.path()).unwrap().is_none());
+    }
+}
@@ -1,15 +1,42 @@
 // src/core/crypto/zkp.rs
+//
+// Zero-knowledge proof of biometric template possession.
+//
+// Enrollment turns a template into a public `TemplateCommitment` using a
+// code-offset fuzzy commitment: a random 256-bit key is encoded with a
+// repetition code and XORed with the template bits (the helper data), and
+// the key deterministically derives a short lattice witness `s` whose image
+// `t = A·s1 + s2` is published. Only a template within the code's correction
+// radius of the enrolled one recovers the key, and therefore `s`.
+//
+// A proof is the lattice sigma protocol from `sigma.rs` showing knowledge of
+// `s` for `t`, bound by Fiat-Shamir to the verifier's challenge and the
+// commitment digest. Verification uses the commitment and the proof only, so
+// any node can check it without secrets.
+//
+// The helper data leaks parity relations between template bits inside each
+// repetition block; templates should come from an extractor with enough
+// entropy per block.
 
 use super::quantum::{QuantumResistantProcessor, SecurityLevel};
 use super::types::{BiometricTemplate, TemplateType};
-use super::key_manager::{KeyManager, KeyUsage};
+use super::key_manager::KeyManager;
 use super::audit::{AuditSystem, AuditEventType};
+use super::sigma::{self, Statement, Witness, SigmaProof};
+use super::rng::SharedRng;
 use sha3::{Sha3_512, Digest};
 use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 
+/// Bits of key material protected by the fuzzy commitment
+const KEY_BITS: usize = 256;
+/// Smallest repetition factor that still corrects an error per block
+const MIN_REPETITION: usize = 3;
+/// Proofs older than this are rejected
+const PROOF_VALIDITY_SECS: i64 = 300;
+
 #[derive(Debug, Error)]
 pub enum ZKPError {
     #[error("Proof generation failed: {0}")]
@@ -18,6 +45,8 @@ pub enum ZKPError {
     VerificationFailed(String),
     #[error("Invalid parameters: {0}")]
     InvalidParameters(String),
+    #[error("Template does not match the commitment")]
+    TemplateMismatch,
     #[error("Quantum operation failed: {0}")]
     QuantumError(String),
     #[error("Key management error: {0}")]
@@ -32,21 +61,76 @@ pub struct ProofParameters {
     pub security_level: SecurityLevel,
     pub template_type: TemplateType,
     pub timestamp: i64,
+    /// Hamming distance from the enrolled template that is always accepted
+    pub max_distance: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZKProof {
     pub id: Uuid,
     pub proof_data: Vec<u8>,
+    /// Digest of the commitment the proof is about
     pub public_inputs: Vec<u8>,
     pub parameters: ProofParameters,
     pub created_at: i64,
 }
 
+/// Public enrollment record for a template. Safe to store and replicate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateCommitment {
+    pub id: Uuid,
+    pub template_type: TemplateType,
+    /// Serialized lattice statement (matrix seed and t = A·s1 + s2)
+    pub statement: Vec<u8>,
+    /// Repetition codeword of the key XOR the template bits
+    pub helper_data: Vec<u8>,
+    /// Repetition factor of the code; always odd
+    pub repetition: usize,
+    pub created_at: i64,
+}
+
+impl TemplateCommitment {
+    /// Commitment digest C, used as the proof's public input
+    pub fn digest(&self) -> Vec<u8> {
+        let mut hasher = Sha3_512::new();
+        hasher.update(b"freeghost-template-commitment-v1");
+        hasher.update(self.id.as_bytes());
+        hasher.update([self.template_type as u8]);
+        hasher.update((self.repetition as u64).to_le_bytes());
+        hasher.update(&self.statement);
+        hasher.update(&self.helper_data);
+        hasher.finalize().to_vec()
+    }
+
+    /// Any template within this Hamming distance of the enrolled one can
+    /// produce a proof. Errors are corrected per block, so some templates
+    /// farther away are accepted too if their errors are spread out.
+    pub fn max_distance(&self) -> usize {
+        self.repetition.saturating_sub(1) / 2
+    }
+
+    /// Check the code parameters before using a commitment received from
+    /// elsewhere: the repetition factor must be odd and at least
+    /// `MIN_REPETITION`, and the helper data must cover every key bit
+    pub fn validate(&self) -> Result<(), ZKPError> {
+        if self.repetition < MIN_REPETITION || self.repetition.is_multiple_of(2) {
+            return Err(ZKPError::InvalidParameters(format!(
+                "Repetition factor must be odd and at least {}",
+                MIN_REPETITION
+            )));
+        }
+        if self.helper_data.len() != helper_len(self.repetition) {
+            return Err(ZKPError::InvalidParameters("Helper data length mismatch".into()));
+        }
+        Ok(())
+    }
+}
+
 pub struct ZKProofGenerator {
     quantum_processor: Arc<QuantumResistantProcessor>,
     key_manager: Arc<KeyManager>,
     audit_system: Arc<AuditSystem>,
+    rng: SharedRng,
 }
 
 impl ZKProofGenerator {
@@ -55,49 +139,109 @@ impl ZKProofGenerator {
         key_manager: Arc<KeyManager>,
         audit_system: Arc<AuditSystem>,
     ) -> Self {
+        // Share the processor's provider so one injected rng covers both
+        let rng = quantum_processor.rng().clone();
         Self {
             quantum_processor,
             key_manager,
             audit_system,
+            rng,
         }
     }
 
+    /// Draw commitment keys and proof masks from `rng` instead
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Enroll a template, producing the public commitment proofs refer to
+    pub async fn commit_template(
+        &self,
+        template: &BiometricTemplate,
+    ) -> Result<TemplateCommitment, ZKPError> {
+        let repetition = repetition_factor(template.template_data.len() * 8)?;
+
+        let mut key = [0u8; KEY_BITS / 8];
+        let mut matrix_seed = [0u8; sigma::SEED_LEN];
+        self.rng.fill(&mut key)
+            .and_then(|_| self.rng.fill(&mut matrix_seed))
+            .map_err(|e| ZKPError::ProofGenerationFailed(e.to_string()))?;
+
+        let witness = Witness::derive(&key)
+            .map_err(|e| ZKPError::ProofGenerationFailed(e.to_string()))?;
+        let statement = witness.statement(matrix_seed);
+
+        let commitment = TemplateCommitment {
+            id: Uuid::new_v4(),
+            template_type: template.template_type,
+            statement: statement.to_bytes(),
+            helper_data: encode_helper(&key, &template.template_data, repetition),
+            repetition,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+
+        self.audit_system
+            .record_event(
+                AuditEventType::TemplateGeneration,
+                Some(commitment.id),
+                Some(serde_json::json!({
+                    "template_type": format!("{:?}", template.template_type),
+                    "max_distance": commitment.max_distance()
+                }))
+            )
+            .await
+            .map_err(|e| ZKPError::AuditError(e.to_string()))?;
+
+        Ok(commitment)
+    }
+
     pub async fn generate_proof(
         &self,
+        commitment: &TemplateCommitment,
         template: &BiometricTemplate,
         challenge: &[u8],
     ) -> Result<ZKProof, ZKPError> {
-        // Get proof generation key
-        let key_id = self.key_manager
-            .generate_key(KeyUsage::IdentityVerification)
-            .await
-            .map_err(|e| ZKPError::KeyError(e.to_string()))?;
+        if template.template_type as u8 != commitment.template_type as u8 {
+            return Err(ZKPError::InvalidParameters("Template type mismatch".into()));
+        }
+        commitment.validate()?;
+
+        // Recover the key; a template outside the correction radius yields a
+        // different key whose witness does not satisfy the statement
+        let key = decode_helper(&commitment.helper_data, &template.template_data, commitment.repetition)?;
+        let witness = Witness::derive(&key)
+            .map_err(|e| ZKPError::ProofGenerationFailed(e.to_string()))?;
+        let statement = Statement::from_bytes(&commitment.statement)
+            .map_err(|e| ZKPError::InvalidParameters(e.to_string()))?;
+
+        if !statement.is_satisfied_by(&witness) {
+            return Err(ZKPError::TemplateMismatch);
+        }
 
-        // Create parameters
+        let now = chrono::Utc::now().timestamp();
         let parameters = ProofParameters {
             challenge_size: challenge.len(),
             security_level: template.security_level,
             template_type: template.template_type,
-            timestamp: chrono::Utc::now().timestamp(),
+            timestamp: now,
+            max_distance: commitment.max_distance(),
         };
 
-        // Generate commitment
-        let commitment = self.generate_commitment(template, challenge)?;
+        let id = Uuid::new_v4();
+        let public_inputs = commitment.digest();
+        let context = proof_context(id, now, &parameters, challenge, &public_inputs)?;
 
-        // Generate proof using Fiat-Shamir transform
-        let (proof_data, public_inputs) = self.generate_fiat_shamir_proof(
-            &commitment,
-            template,
-            challenge,
-            &parameters,
-        )?;
+        let proof_data = sigma::prove_with_rng(&statement, &witness, &context, &*self.rng)
+            .map_err(|e| ZKPError::ProofGenerationFailed(e.to_string()))?
+            .to_bytes();
 
         let proof = ZKProof {
-            id: Uuid::new_v4(),
+            id,
             proof_data,
             public_inputs,
             parameters,
-            created_at: chrono::Utc::now().timestamp(),
+            created_at: now,
         };
 
         // Audit proof generation
@@ -119,27 +263,10 @@ impl ZKProofGenerator {
     pub async fn verify_proof(
         &self,
         proof: &ZKProof,
+        commitment: &TemplateCommitment,
         challenge: &[u8],
-        public_inputs: &[u8],
     ) -> Result<bool, ZKPError> {
-        // Verify parameters
-        if challenge.len() != proof.parameters.challenge_size {
-            return Err(ZKPError::InvalidParameters("Challenge size mismatch".into()));
-        }
-
-        // Verify timestamp is within acceptable range
-        let now = chrono::Utc::now().timestamp();
-        if (now - proof.created_at).abs() > 300 { // 5 minute window
-            return Err(ZKPError::VerificationFailed("Proof expired".into()));
-        }
-
-        // Verify using Fiat-Shamir verification
-        let is_valid = self.verify_fiat_shamir_proof(
-            &proof.proof_data,
-            public_inputs,
-            challenge,
-            &proof.parameters,
-        )?;
+        let is_valid = verify_template_proof(proof, commitment, challenge)?;
 
         // Audit verification
         self.audit_system
@@ -156,375 +283,281 @@ impl ZKProofGenerator {
 
         Ok(is_valid)
     }
+}
 
-    fn generate_commitment(
-        &self,
-        template: &BiometricTemplate,
-        challenge: &[u8],
-    ) -> Result<Vec<u8>, ZKPError> {
-        let mut hasher = Sha3_512::new();
-        
-        // Add template data
-        hasher.update(&template.template_data);
-        
-        // Add challenge
-        hasher.update(challenge);
-        
-        // Add randomness
-        let mut random_bytes = [0u8; 64];
-        getrandom::getrandom(&mut random_bytes)
-            .map_err(|e| ZKPError::ProofGenerationFailed(e.to_string()))?;
-        hasher.update(&random_bytes);
-
-        Ok(hasher.finalize().to_vec())
+/// Verify a template proof from public data only: the proof, the
+/// commitment it refers to, and the challenge the verifier issued.
+pub fn verify_template_proof(
+    proof: &ZKProof,
+    commitment: &TemplateCommitment,
+    challenge: &[u8],
+) -> Result<bool, ZKPError> {
+    // Verify parameters
+    if challenge.len() != proof.parameters.challenge_size {
+        return Err(ZKPError::InvalidParameters("Challenge size mismatch".into()));
     }
+    commitment.validate()?;
 
-    fn generate_fiat_shamir_proof(
-        &self,
-        commitment: &[u8],
-        template: &BiometricTemplate,
-        challenge: &[u8],
-        parameters: &ProofParameters,
-    ) -> Result<(Vec<u8>, Vec<u8>), ZKPError> {
-        // Initialize sigma protocol prover
-        let mut prover = SigmaProtocolProver::new(parameters.security_level);
-
-        // First message (commitment)
-        prover.set_commitment(commitment.to_vec());
-
-        // Generate challenge hash
-        let mut challenge_hasher = Sha3_512::new();
-        challenge_hasher.update(commitment);
-        challenge_hasher.update(challenge);
-        let challenge_hash = challenge_hasher.finalize();
-
-        // Generate proof using template private data
-        let proof_data = prover.generate_proof(
-            &template.template_data,
-            &challenge_hash,
-        )?;
-
-        // Generate public inputs
-        let public_inputs = self.generate_public_inputs(template, parameters)?;
-
-        Ok((proof_data, public_inputs))
+    // Verify timestamp is within acceptable range
+    let now = chrono::Utc::now().timestamp();
+    if (now - proof.created_at).abs() > PROOF_VALIDITY_SECS {
+        return Err(ZKPError::VerificationFailed("Proof expired".into()));
     }
 
-    fn verify_fiat_shamir_proof(
-        &self,
-        proof_data: &[u8],
-        public_inputs: &[u8],
-        challenge: &[u8],
-        parameters: &ProofParameters,
-    ) -> Result<bool, ZKPError> {
-        // Initialize sigma protocol verifier
-        let verifier = SigmaProtocolVerifier::new(parameters.security_level);
-
-        // Verify the proof
-        verifier.verify_proof(
-            proof_data,
-            public_inputs,
-            challenge,
-        )
+    let digest = commitment.digest();
+    if proof.public_inputs != digest
+        || proof.parameters.max_distance != commitment.max_distance()
+        || proof.parameters.template_type as u8 != commitment.template_type as u8
+    {
+        return Ok(false);
     }
 
-    fn generate_public_inputs(
-        &self,
-        template: &BiometricTemplate,
-        parameters: &ProofParameters,
-    ) -> Result<Vec<u8>, ZKPError> {
-        let mut hasher = Sha3_512::new();
-        
-        // Add template type
-        hasher.update(&[template.template_type as u8]);
-        
-        // Add security level
-        hasher.update(&[parameters.security_level as u8]);
-        
-        // Add timestamp
-        hasher.update(&parameters.timestamp.to_le_bytes());
-
-        Ok(hasher.finalize().to_vec())
-    }
+    let statement = Statement::from_bytes(&commitment.statement)
+        .map_err(|e| ZKPError::InvalidParameters(e.to_string()))?;
+    let sigma_proof = match SigmaProof::from_bytes(&proof.proof_data) {
+        Ok(p) => p,
+        Err(_) => return Ok(false),
+    };
+
+    let context = proof_context(
+        proof.id,
+        proof.created_at,
+        &proof.parameters,
+        challenge,
+        &digest,
+    )?;
+
+    Ok(sigma::verify(&statement, &sigma_proof, &context))
 }
 
-// Sigma Protocol implementation
-struct SigmaProtocolProver {
-    security_level: SecurityLevel,
-    commitment: Option<Vec<u8>>,
+/// Fiat-Shamir context binding the proof to everything the verifier checks
+fn proof_context(
+    id: Uuid,
+    created_at: i64,
+    parameters: &ProofParameters,
+    challenge: &[u8],
+    commitment_digest: &[u8],
+) -> Result<Vec<u8>, ZKPError> {
+    let parameters = serde_json::to_vec(parameters)
+        .map_err(|e| ZKPError::InvalidParameters(e.to_string()))?;
+
+    let mut hasher = Sha3_512::new();
+    hasher.update(b"freeghost-template-proof-v1");
+    hasher.update(id.as_bytes());
+    hasher.update(created_at.to_le_bytes());
+    hasher.update((parameters.len() as u64).to_le_bytes());
+    hasher.update(&parameters);
+    hasher.update((challenge.len() as u64).to_le_bytes());
+    hasher.update(challenge);
+    hasher.update(commitment_digest);
+    Ok(hasher.finalize().to_vec())
 }
 
-impl SigmaProtocolProver {
-    fn new(security_level: SecurityLevel) -> Self {
-        Self {
-            security_level,
-            commitment: None,
-        }
-    }
-
-    fn set_commitment(&mut self, commitment: Vec<u8>) {
-        self.commitment = Some(commitment);
-    }
-
-    fn generate_proof(
-        &self,
-        private_input: &[u8],
-        challenge_hash: &[u8],
-    ) -> Result<Vec<u8>, ZKPError> {
-        let commitment = self.commitment.as_ref()
-            .ok_or_else(|| ZKPError::ProofGenerationFailed("No commitment set".into()))?;
-
-        let mut proof = Vec::new();
-        
-        // Add commitment
-        proof.extend_from_slice(commitment);
-        
-        // Add response based on security level
-        match self.security_level {
-            SecurityLevel::Basic => {
-                // Basic Schnorr protocol
-                self.generate_schnorr_proof(&mut proof, private_input, challenge_hash)?;
-            }
-            SecurityLevel::Standard | SecurityLevel::High => {
-                // Enhanced protocol with additional security
-                self.generate_enhanced_proof(&mut proof, private_input, challenge_hash)?;
-            }
-        }
-
-        Ok(proof)
-    }
-
-    fn generate_schnorr_proof(
-        &self,
-        proof: &mut Vec<u8>,
-        private_input: &[u8],
-        challenge_hash: &[u8],
-    ) -> Result<(), ZKPError> {
-        let mut hasher = Sha3_512::new();
-        hasher.update(private_input);
-        hasher.update(challenge_hash);
-        let response = hasher.finalize();
-        proof.extend_from_slice(&response);
-        Ok(())
+/// Largest odd repetition factor that fits the template
+fn repetition_factor(template_bits: usize) -> Result<usize, ZKPError> {
+    let r = template_bits / KEY_BITS;
+    let r = if r.is_multiple_of(2) { r.saturating_sub(1) } else { r };
+    if r < MIN_REPETITION {
+        return Err(ZKPError::InvalidParameters(format!(
+            "Template must have at least {} bits",
+            KEY_BITS * MIN_REPETITION
+        )));
     }
+    Ok(r)
+}
 
-    fn generate_enhanced_proof(
-        &self,
-        proof: &mut Vec<u8>,
-        private_input: &[u8],
-        challenge_hash: &[u8],
-    ) -> Result<(), ZKPError> {
-        // Enhanced proof with multiple rounds
-        for i in 0..3 {
-            let mut hasher = Sha3_512::new();
-            hasher.update(private_input);
-            hasher.update(challenge_hash);
-            hasher.update(&[i]); // Round number
-            let response = hasher.finalize();
-            proof.extend_from_slice(&response);
-        }
-        Ok(())
-    }
+fn bit(bytes: &[u8], i: usize) -> u8 {
+    (bytes[i / 8] >> (i % 8)) & 1
 }
 
-struct SigmaProtocolVerifier {
-    security_level: SecurityLevel,
+/// Bytes of helper data for repetition factor `r`
+fn helper_len(r: usize) -> usize {
+    KEY_BITS * r / 8 + 1
 }
 
-impl SigmaProtocolVerifier {
-    fn new(security_level: SecurityLevel) -> Self {
-        Self { security_level }
+/// Helper data: each key bit repeated `r` times, XORed with the template
+fn encode_helper(key: &[u8], template: &[u8], r: usize) -> Vec<u8> {
+    let mut helper = vec![0u8; helper_len(r)];
+    for i in 0..KEY_BITS * r {
+        let h = bit(key, i / r) ^ bit(template, i);
+        helper[i / 8] |= h << (i % 8);
     }
+    helper
+}
 
-    fn verify_proof(
-        &self,
-        proof_data: &[u8],
-        public_inputs: &[u8],
-        challenge: &[u8],
-    ) -> Result<bool, ZKPError> {
-        match self.security_level {
-            SecurityLevel::Basic => {
-                self.verify_schnorr_proof(proof_data, public_inputs, challenge)
-            }
-            SecurityLevel::Standard | SecurityLevel::High => {
-                self.verify_enhanced_proof(proof_data, public_inputs, challenge)
-            }
-        }
+/// Majority-decode each block of `helper XOR template` back to a key bit.
+/// Runs over every bit regardless of the data.
+fn decode_helper(helper: &[u8], template: &[u8], r: usize) -> Result<[u8; KEY_BITS / 8], ZKPError> {
+    if r == 0 || helper.len() * 8 < KEY_BITS * r {
+        return Err(ZKPError::InvalidParameters("Helper data too short for commitment".into()));
     }
-
-    fn verify_schnorr_proof(
-        &self,
-        proof_data: &[u8],
-        public_inputs: &[u8],
-        challenge: &[u8],
-    ) -> Result<bool, ZKPError> {
-        let mut hasher = Sha3_512::new();
-        hasher.update(public_inputs);
-        hasher.update(challenge);
-        let expected = hasher.finalize();
-        
-        // Verify proof length
-        if proof_data.len() != expected.len() * 2 {
-            return Ok(false);
-        }
-        
-        // Verify commitment and response
-        let (commitment, response) = proof_data.split_at(expected.len());
-        let mut verification_hasher = Sha3_512::new();
-        verification_hasher.update(commitment);
-        verification_hasher.update(response);
-        
-        Ok(verification_hasher.finalize().as_slice() == expected.as_slice())
+    if template.len() * 8 < KEY_BITS * r {
+        return Err(ZKPError::InvalidParameters("Template too short for commitment".into()));
     }
 
-    fn verify_enhanced_proof(
-        &self,
-        proof_data: &[u8],
-        public_inputs: &[u8],
-        challenge: &[u8],
-    ) -> Result<bool, ZKPError> {
-        // Verify enhanced proof with multiple rounds
-        let round_size = 64; // SHA3-512 output size
-        let expected_size = round_size * 4; // Commitment + 3 rounds
-        
-        if proof_data.len() != expected_size {
-            return Ok(false);
-        }
-        
-        // Verify each round
-        let (commitment, rounds) = proof_data.split_at(round_size);
-        
-        for (i, round) in rounds.chunks(round_size).enumerate() {
-            let mut hasher = Sha3_512::new();
-            hasher.update(public_inputs);
-            hasher.update(challenge);
-            hasher.update(&[i as u8]);
-            let expected = hasher.finalize();
-            
-            if round != expected.as_slice() {
-                return Ok(false);
-            }
+    let mut key = [0u8; KEY_BITS / 8];
+    for k in 0..KEY_BITS {
+        let mut ones = 0usize;
+        for j in 0..r {
+            let i = k * r + j;
+            ones += (bit(helper, i) ^ bit(template, i)) as usize;
         }
-        
-        Ok(true)
+        let majority = ((r / 2).wrapping_sub(ones) >> (usize::BITS - 1)) as u8;
+        key[k / 8] |= majority << (k % 8);
     }
+    Ok(key)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::key_manager::KdfParams;
 
     async fn create_test_generator() -> ZKProofGenerator {
-        let quantum_processor = Arc::new(QuantumResistantProcessor::new(SecurityLevel::Standard).unwrap());
-        let secure_memory = Arc::new(super::super::secure_memory::SecureMemory::new(8192).unwrap());
+        let quantum_processor = Arc::new(QuantumResistantProcessor::new().unwrap());
         let audit_system = Arc::new(AuditSystem::new(30, SecurityLevel::Standard));
-        let key_manager = Arc::new(
-            KeyManager::new(
-                secure_memory,
-                quantum_processor.clone(),
-                audit_system.clone(),
-                SecurityLevel::Standard,
-            ).await.unwrap()
-        );
+        // Cheap KDF parameters so tests stay fast
+        let params = KdfParams { memory_kib: 256, iterations: 1, parallelism: 1 };
+        let key_manager = Arc::new(KeyManager::new_with_params("test_key", &params).unwrap());
 
         ZKProofGenerator::new(quantum_processor, key_manager, audit_system)
     }
 
+    fn test_template(fill: u8) -> BiometricTemplate {
+        BiometricTemplate::new(
+            vec![fill; 2048],
+            TemplateType::Combined,
+            SecurityLevel::Standard,
+        )
+    }
+
+    /// Flip `per_block` bits in every repetition block
+    fn add_noise(template: &BiometricTemplate, r: usize, per_block: usize) -> BiometricTemplate {
+        let mut noisy = template.clone();
+        for k in 0..KEY_BITS {
+            for j in 0..per_block {
+                let i = k * r + j;
+                noisy.template_data[i / 8] ^= 1 << (i % 8);
+            }
+        }
+        noisy
+    }
+
     #[tokio::test]
     async fn test_proof_generation_and_verification() {
         let generator = create_test_generator().await;
-        
-        let template = BiometricTemplate::new(
-            vec![0u8; 2048],
-            TemplateType::Combined,
-            SecurityLevel::Standard,
-        );
-        
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
         let challenge = b"test challenge";
-        
-        let proof = generator.generate_proof(&template, challenge)
+
+        let proof = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
-        let is_valid = generator.verify_proof(&proof, challenge, &proof.public_inputs)
+
+        let is_valid = generator.verify_proof(&proof, &commitment, challenge)
             .await
             .unwrap();
-            
+
         assert!(is_valid);
     }
 
+    #[tokio::test]
+    async fn test_noisy_template_within_distance() {
+        let generator = create_test_generator().await;
+        let template = test_template(0xA5);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
+        let noisy = add_noise(&template, commitment.repetition, commitment.max_distance());
+        let proof = generator.generate_proof(&commitment, &noisy, b"challenge")
+            .await
+            .unwrap();
+
+        assert!(verify_template_proof(&proof, &commitment, b"challenge").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_template_outside_distance_rejected() {
+        let generator = create_test_generator().await;
+        let template = test_template(0xA5);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
+        let far = add_noise(&template, commitment.repetition, commitment.max_distance() + 1);
+        let result = generator.generate_proof(&commitment, &far, b"challenge").await;
+
+        assert!(matches!(result, Err(ZKPError::TemplateMismatch)));
+    }
+
+    #[tokio::test]
+    async fn test_verification_needs_only_public_data() {
+        let generator = create_test_generator().await;
+        let template = test_template(0x3C);
+        let commitment = generator.commit_template(&template).await.unwrap();
+        let proof = generator.generate_proof(&commitment, &template, b"challenge")
+            .await
+            .unwrap();
+
+        // A different node only receives serialized public data
+        let commitment: TemplateCommitment =
+            serde_json::from_slice(&serde_json::to_vec(&commitment).unwrap()).unwrap();
+        let proof: ZKProof = serde_json::from_slice(&serde_json::to_vec(&proof).unwrap()).unwrap();
+
+        assert!(verify_template_proof(&proof, &commitment, b"challenge").unwrap());
+    }
+
     #[tokio::test]
     async fn test_proof_verification_with_wrong_challenge() {
         let generator = create_test_generator().await;
-        
-        let template = BiometricTemplate::new(
-            vec![0u8; 2048],
-            TemplateType::Combined,
-            SecurityLevel::Standard,
-        );
-        
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
         let challenge = b"test challenge";
-        let wrong_challenge = b"wrong challenge";
-        
-        let proof = generator.generate_proof(&template, challenge)
+        let wrong_challenge = b"fake challenge";
+
+        let proof = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
-        let is_valid = generator.verify_proof(&proof, wrong_challenge, &proof.public_inputs)
+
+        let is_valid = generator.verify_proof(&proof, &commitment, wrong_challenge)
             .await
             .unwrap();
-            
+
         assert!(!is_valid);
     }
 
+    #[tokio::test]
+    async fn test_proof_for_other_commitment_rejected() {
+        let generator = create_test_generator().await;
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+        let other = generator.commit_template(&template).await.unwrap();
+
+        let proof = generator.generate_proof(&commitment, &template, b"challenge")
+            .await
+            .unwrap();
+
+        assert!(!verify_template_proof(&proof, &other, b"challenge").unwrap());
+    }
+
     #[tokio::test]
     async fn test_proof_expiration() {
         let generator = create_test_generator().await;
-        
-        let template = BiometricTemplate::new(
-            vec![0u8; 2048],
-            TemplateType::Combined,
-            SecurityLevel::Standard,
-        );
-        
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
         let challenge = b"test challenge";
-        
-        let mut proof = generator.generate_proof(&template, challenge)
+
+        let mut proof = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
+
         // Modify timestamp to make proof expired
         proof.created_at = chrono::Utc::now().timestamp() - 600; // 10 minutes old
-        
-        let result = generator.verify_proof(&proof, challenge, &proof.public_inputs)
+
+        let result = generator.verify_proof(&proof, &commitment, challenge)
             .await;
-            
-        assert!(matches!(result, Err(ZKPError::VerificationFailed(_))));
-    }
 
-    #[tokio::test]
-    async fn test_different_security_levels() {
-        let generator = create_test_generator().await;
-        
-        for security_level in [SecurityLevel::Basic, SecurityLevel::Standard, SecurityLevel::High] {
-            let template = BiometricTemplate::new(
-                vec![0u8; 2048],
-                TemplateType::Combined,
-                security_level,
-            );
-            
-            let challenge = b"test challenge";
-            
-            let proof = generator.generate_proof(&template, challenge)
-                .await
-                .unwrap();
-                
-            let is_valid = generator.verify_proof(&proof, challenge, &proof.public_inputs)
-                .await
-                .unwrap();
-                
-            assert!(is_valid);
-        }
+        assert!(matches!(result, Err(ZKPError::VerificationFailed(_))));
     }
 
     #[tokio::test]
@@ -543,15 +576,16 @@ mod tests {
                 template_type,
                 SecurityLevel::Standard,
             );
-            
-            let proof = generator.generate_proof(&template, challenge)
+            let commitment = generator.commit_template(&template).await.unwrap();
+
+            let proof = generator.generate_proof(&commitment, &template, challenge)
                 .await
                 .unwrap();
-                
-            let is_valid = generator.verify_proof(&proof, challenge, &proof.public_inputs)
+
+            let is_valid = generator.verify_proof(&proof, &commitment, challenge)
                 .await
                 .unwrap();
-                
+
             assert!(is_valid);
         }
     }
@@ -560,29 +594,26 @@ mod tests {
     async fn test_concurrent_proof_generation() {
         let generator = Arc::new(create_test_generator().await);
         let mut handles = vec![];
-        
+
         for i in 0..10 {
             let generator_clone = generator.clone();
-            let template = BiometricTemplate::new(
-                vec![i as u8; 2048],
-                TemplateType::Combined,
-                SecurityLevel::Standard,
-            );
+            let template = test_template(i as u8);
             let challenge = format!("challenge {}", i).into_bytes();
-            
+
             handles.push(tokio::spawn(async move {
-                let proof = generator_clone.generate_proof(&template, &challenge)
+                let commitment = generator_clone.commit_template(&template).await.unwrap();
+                let proof = generator_clone.generate_proof(&commitment, &template, &challenge)
                     .await
                     .unwrap();
-                (proof, challenge)
+                (proof, commitment, challenge)
             }));
         }
-        
+
         let results = futures::future::join_all(handles).await;
-        
+
         for result in results {
-            let (proof, challenge) = result.unwrap();
-            let is_valid = generator.verify_proof(&proof, &challenge, &proof.public_inputs)
+            let (proof, commitment, challenge) = result.unwrap();
+            let is_valid = generator.verify_proof(&proof, &commitment, &challenge)
                 .await
                 .unwrap();
             assert!(is_valid);
@@ -592,73 +623,116 @@ mod tests {
     #[tokio::test]
     async fn test_invalid_parameters() {
         let generator = create_test_generator().await;
-        
-        let template = BiometricTemplate::new(
-            vec![0u8; 2048],
-            TemplateType::Combined,
-            SecurityLevel::Standard,
-        );
-        
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
         let challenge = b"test challenge";
-        let mut proof = generator.generate_proof(&template, challenge)
+        let mut proof = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
+
         // Modify challenge size in parameters
         proof.parameters.challenge_size += 1;
-        
-        let result = generator.verify_proof(&proof, challenge, &proof.public_inputs)
+
+        let result = generator.verify_proof(&proof, &commitment, challenge)
             .await;
-            
+
         assert!(matches!(result, Err(ZKPError::InvalidParameters(_))));
     }
 
+    #[tokio::test]
+    async fn test_malformed_commitment_rejected() {
+        let generator = create_test_generator().await;
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
+        let challenge = b"test challenge";
+        let proof = generator.generate_proof(&commitment, &template, challenge)
+            .await
+            .unwrap();
+
+        let mut zero = commitment.clone();
+        zero.repetition = 0;
+        let mut even = commitment.clone();
+        even.repetition += 1;
+        let mut truncated = commitment.clone();
+        truncated.helper_data.truncate(truncated.helper_data.len() / 2);
+
+        for bad in [zero, even, truncated] {
+            assert!(matches!(bad.validate(), Err(ZKPError::InvalidParameters(_))));
+            assert!(matches!(
+                verify_template_proof(&proof, &bad, challenge),
+                Err(ZKPError::InvalidParameters(_))
+            ));
+            assert!(matches!(
+                generator.generate_proof(&bad, &template, challenge).await,
+                Err(ZKPError::InvalidParameters(_))
+            ));
+        }
+    }
+
     #[tokio::test]
     async fn test_proof_data_tampering() {
         let generator = create_test_generator().await;
-        
-        let template = BiometricTemplate::new(
-            vec![0u8; 2048],
-            TemplateType::Combined,
-            SecurityLevel::Standard,
-        );
-        
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
         let challenge = b"test challenge";
-        let mut proof = generator.generate_proof(&template, challenge)
+        let mut proof = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
+
         // Tamper with proof data
         if let Some(byte) = proof.proof_data.get_mut(0) {
             *byte ^= 1;
         }
-        
-        let is_valid = generator.verify_proof(&proof, challenge, &proof.public_inputs)
+
+        let is_valid = generator.verify_proof(&proof, &commitment, challenge)
             .await
             .unwrap();
-            
+
         assert!(!is_valid);
     }
 
     #[tokio::test]
     async fn test_public_inputs_consistency() {
         let generator = create_test_generator().await;
-        let template = BiometricTemplate::new(
-            vec![0u8; 2048],
-            TemplateType::Combined,
-            SecurityLevel::Standard,
-        );
-        
+        let template = test_template(0);
+        let commitment = generator.commit_template(&template).await.unwrap();
+
         let challenge = b"test challenge";
-        let proof1 = generator.generate_proof(&template, challenge)
+        let proof1 = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
-        let proof2 = generator.generate_proof(&template, challenge)
+
+        let proof2 = generator.generate_proof(&commitment, &template, challenge)
             .await
             .unwrap();
-            
-        // Public inputs should be consistent for the same template and parameters
+
+        // Public inputs are the commitment digest and identical across proofs
         assert_eq!(proof1.public_inputs, proof2.public_inputs);
+        assert_eq!(proof1.public_inputs, commitment.digest());
+        // Proofs themselves are randomised
+        assert_ne!(proof1.proof_data, proof2.proof_data);
+    }
+
+    #[test]
+    fn test_helper_roundtrip() {
+        let key = [0x5Au8; KEY_BITS / 8];
+        let template = vec![0xC3u8; 1024];
+        let r = repetition_factor(template.len() * 8).unwrap();
+        assert_eq!(r % 2, 1);
+
+        let helper = encode_helper(&key, &template, r);
+        assert_eq!(decode_helper(&helper, &template, r).unwrap(), key);
+        assert!(decode_helper(&helper[..helper.len() / 2], &template, r).is_err());
+        assert!(decode_helper(&helper, &template[..KEY_BITS / 8], r).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_short_template_rejected() {
+        assert!(repetition_factor(KEY_BITS * 2).is_err());
+        assert_eq!(repetition_factor(KEY_BITS * 3).unwrap(), 3);
+        assert_eq!(repetition_factor(KEY_BITS * 8).unwrap(), 7);
+    }
+}
@@ -4,8 +4,9 @@ use uuid::Uuid;
 use tokio::sync::Mutex;
 use crate::utils::error::{Result, IdentityError};
 use crate::core::crypto::{
-    secure_memory::SecretData,
+    secure_memory::Secret,
     quantum::QuantumResistantProcessor,
+    rng::CryptoRng,
     key_manager::KeyManager,
     audit::{CryptoAuditor, AuditableOperation, AuditStatus},
 };
@@ -34,11 +35,17 @@ impl BiometricProcessor {
 
     pub async fn process_biometric_data(&self, data: BiometricData) -> Result<BiometricTemplate> {
         // Store biometric data in secure memory temporarily
-        let secure_data = SecretData::new(&data)
+        let secure_data = Secret::new(data)
             .map_err(|e| IdentityError::Processing(format!("Failed to secure data: {}", e)))?;
 
         // Extract features
-        let features = self.extract_features(secure_data.get()).await?;
+        let features = {
+            let data = secure_data.expose()
+                .map_err(|e| IdentityError::Processing(format!("Failed to access secure data: {}", e)))?;
+            self.extract_features(&data).await?
+        };
+        // The raw sample is not needed past this point
+        drop(secure_data);
 
         // Generate quantum-resistant template
         let template_id = Uuid::new_v4();
@@ -78,19 +85,20 @@ impl BiometricProcessor {
     async fn extract_features(&self, data: &BiometricData) -> Result<Vec<u8>> {
         // Feature extraction process
         // This is a critical security operation that must be done in secure memory
-        let secure_workspace = SecretData::new(&Vec::new())
+        let mut secure_workspace = Secret::new(Vec::new())
             .map_err(|e| IdentityError::Processing(format!("Failed to create secure workspace: {}", e)))?;
 
         // Perform feature extraction in secure memory
         let features = {
-            let mut workspace = secure_workspace.get_mut();
+            let mut workspace = secure_workspace.expose_mut()
+                .map_err(|e| IdentityError::Processing(format!("Failed to access secure workspace: {}", e)))?;
             workspace.clear();
             
             // Extract core biometric features
-            self.extract_core_features(data, workspace)?;
+            self.extract_core_features(data, &mut workspace)?;
             
             // Add noise for privacy
-            self.add_privacy_noise(workspace)?;
+            self.add_privacy_noise(&mut workspace)?;
             
             workspace.clone()
         };
@@ -191,13 +199,19 @@ impl BiometricProcessor {
 
     fn add_privacy_noise(&self, features: &mut Vec<u8>) -> Result<()> {
         // Add calibrated noise to prevent template reversal
-        use rand::{thread_rng, Rng};
-        let mut rng = thread_rng();
+        let rng = self.quantum_processor.rng();
+        let mut sample = [0u8; 1];
         
         for byte in features.iter_mut() {
-            // Add small random perturbation
-            let noise = rng.gen_range(-2..=2);
-            *byte = byte.saturating_add(noise as u8);
+            // Add small random perturbation, uniform over -2..=2
+            // (250 is the largest multiple of 5 that fits in a byte)
+            let noise = loop {
+                rng.fill(&mut sample)?;
+                if sample[0] < 250 {
+                    break (sample[0] % 5) as i8 - 2;
+                }
+            };
+            *byte = byte.saturating_add_signed(noise);
         }
         
         Ok(())
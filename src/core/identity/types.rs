@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::storage::{kv::Column, record::Record};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Identity {
     pub id: Uuid,
@@ -70,6 +72,11 @@ pub enum VerificationStatus {
     Revoked,
 }
 
+impl Record for Identity {
+    const COLUMN: Column = Column::Identities;
+    const VERSION: u8 = 1;
+}
+
 impl Identity {
     pub fn new(template: BiometricTemplate) -> Self {
         let now = SystemTime::now()
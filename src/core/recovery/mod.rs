@@ -3,13 +3,13 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use sha3::{Sha3_512, Digest};
-use ring::aead;
 use std::time::{SystemTime, Duration, UNIX_EPOCH};
 use tracing::{info, warn, error};
 use thiserror::Error;
 
 // Re-exports from other modules
 use crate::core::crypto::types::SecurityLevel;
+use crate::core::crypto::keystore::{KeyStore, MemoryKeyStore};
 use crate::core::biometrics::types::BiometricFactor;
 use crate::utils::error::Result;
 
@@ -28,9 +28,12 @@ pub struct RecoveryProof {
     verification_hash: [u8; 64],
 }
 
+/// Label of the recovery key inside its key store
+const RECOVERY_KEY_LABEL: &str = "freeghost-recovery";
+
 pub struct SecureRecoveryManager {
-    encryption_key: aead::SealingKey<aead::Aes256Gcm>,
-    decryption_key: aead::OpeningKey<aead::Aes256Gcm>,
+    keys: Arc<dyn KeyStore>,
+    key_label: String,
     active_tokens: Arc<RwLock<HashMap<[u8; 32], RecoveryToken>>>,
     epoch_manager: EpochManager,
 }
@@ -65,20 +68,34 @@ pub enum VerificationFactor {
 
 impl SecureRecoveryManager {
     pub fn new(encryption_key: &[u8; 32]) -> Result<Self, RecoveryError> {
-        let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, encryption_key)
+        let store = MemoryKeyStore::with_key(RECOVERY_KEY_LABEL, encryption_key);
+        Self::with_key_store(Arc::new(store), RECOVERY_KEY_LABEL)
+    }
+
+    /// Encrypt recovery data with the key `label` in `store`, creating it on
+    /// first use. The key itself stays in the store.
+    pub fn with_key_store(store: Arc<dyn KeyStore>, label: &str) -> Result<Self, RecoveryError> {
+        store.ensure_key(label)
             .map_err(|_| RecoveryError::KeyGenerationError)?;
-        
-        let sealing_key = aead::SealingKey::new(unbound_key.clone());
-        let opening_key = aead::OpeningKey::new(unbound_key);
 
         Ok(Self {
-            encryption_key: sealing_key,
-            decryption_key: opening_key,
+            keys: store,
+            key_label: label.to_string(),
             active_tokens: Arc::new(RwLock::new(HashMap::new())),
             epoch_manager: EpochManager::new(),
         })
     }
 
+    fn seal(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, RecoveryError> {
+        self.keys.encrypt(&self.key_label, data, aad)
+            .map_err(|_| RecoveryError::EncryptionError)
+    }
+
+    fn open(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, RecoveryError> {
+        self.keys.decrypt(&self.key_label, data, aad)
+            .map_err(|_| RecoveryError::DecryptionError)
+    }
+
     // ... [Previous methods remain the same] ...
 
     async fn verify_recovery_token(
@@ -1,7 +1,10 @@
 // src/core/security/permissions/privacy.rs
 use super::*;
-use ring::aead::{self, SealingKey, OpeningKey, Aad, NONCE_LEN};
 use sha3::{Sha3_512, Digest};
+use crate::core::crypto::keystore::{KeyStore, MemoryKeyStore};
+
+/// Label of the proof key inside its key store
+const PERMISSION_KEY_LABEL: &str = "freeghost-permissions";
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct PrivacyEnhancedPermission {
@@ -20,8 +23,8 @@ pub struct ZeroKnowledgeProof {
 
 pub struct PrivacyEnhancedPermissionManager {
     inner: PermissionManager,
-    encryption_key: SealingKey<aead::Aes256Gcm>,
-    decryption_key: OpeningKey<aead::Aes256Gcm>,
+    keys: Arc<dyn KeyStore>,
+    key_label: String,
     epoch_manager: EpochManager,
 }
 
@@ -33,16 +36,20 @@ struct EpochManager {
 
 impl PrivacyEnhancedPermissionManager {
     pub fn new(encryption_key: &[u8; 32]) -> Result<Self, PermissionError> {
-        let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, encryption_key)
+        let store = MemoryKeyStore::with_key(PERMISSION_KEY_LABEL, encryption_key);
+        Self::with_key_store(Arc::new(store), PERMISSION_KEY_LABEL)
+    }
+
+    /// Encrypt proofs with the key `label` in `store`, creating it on first
+    /// use. The key itself stays in the store.
+    pub fn with_key_store(store: Arc<dyn KeyStore>, label: &str) -> Result<Self, PermissionError> {
+        store.ensure_key(label)
             .map_err(|_| PermissionError::KeyGenerationError)?;
-        
-        let sealing_key = SealingKey::new(unbound_key.clone());
-        let opening_key = OpeningKey::new(unbound_key);
 
         Ok(Self {
             inner: PermissionManager::new(),
-            encryption_key: sealing_key,
-            decryption_key: opening_key,
+            keys: store,
+            key_label: label.to_string(),
             epoch_manager: EpochManager {
                 current_epoch: Arc::new(RwLock::new(0)),
                 epoch_duration: Duration::from_hours(24),
@@ -179,26 +186,30 @@ impl PrivacyEnhancedPermissionManager {
         proof_data.extend_from_slice(&current_epoch.to_le_bytes());
 
         // Encrypt proof data
-        let nonce = self.generate_nonce();
-        let aad = Aad::empty();
-        
-        let mut in_out = proof_data.clone();
-        self.encryption_key
-            .seal_in_place_append_tag(nonce, aad, &mut in_out)
+        let proof_data = self.keys
+            .encrypt(&self.key_label, &proof_data, &[])
             .map_err(|_| PermissionError::ProofGenerationError)?;
 
         Ok(ZeroKnowledgeProof {
-            proof_data: in_out,
+            proof_data,
             epoch: current_epoch,
         })
     }
 
-    fn generate_nonce(&self) -> ring::aead::Nonce {
-        let mut nonce = [0u8; NONCE_LEN];
-        ring::rand::SystemRandom::new()
-            .fill(&mut nonce)
-            .expect("Failed to generate nonce");
-        ring::aead::Nonce::assume_unique_for_key(nonce)
+    /// The proof must decrypt under our key and name this identifier and epoch
+    async fn verify_zero_knowledge_proof(
+        &self,
+        proof: &ZeroKnowledgeProof,
+        blinded_id: &[u8; 32],
+    ) -> Result<bool, PermissionError> {
+        let proof_data = match self.keys.decrypt(&self.key_label, &proof.proof_data, &[]) {
+            Ok(data) => data,
+            Err(_) => return Ok(false),
+        };
+
+        Ok(proof_data.len() == 32 * 3 + 8
+            && proof_data[..32] == blinded_id[..]
+            && proof_data[96..] == proof.epoch.to_le_bytes())
     }
 }
 
@@ -271,4 +282,31 @@ mod tests {
         let entity_token = [0u8; 32];
         assert!(manager.verify_permission(&entity_token, &permission, &context).await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_proofs_bound_to_key_store() {
+        let manager = PrivacyEnhancedPermissionManager::with_key_store(
+            Arc::new(MemoryKeyStore::new()),
+            "permissions",
+        ).unwrap();
+        let other = PrivacyEnhancedPermissionManager::with_key_store(
+            Arc::new(MemoryKeyStore::new()),
+            "permissions",
+        ).unwrap();
+
+        let permission = manager.create_private_permission(
+            PermissionScope::Storage,
+            PermissionLevel::User,
+            HashSet::new(),
+        ).await.unwrap();
+
+        let context = VerificationContext {
+            timestamp: SystemTime::now(),
+            session_id: [0u8; 32],
+            purpose: VerificationPurpose::Access,
+        };
+
+        assert!(manager.verify_permission(&[0u8; 32], &permission, &context).await.unwrap());
+        assert!(!other.verify_permission(&[0u8; 32], &permission, &context).await.unwrap());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    utils::{
+        config::Config,
+        error::{Result, NodeError},
+    },
+    core::{
+        identity::types::{Identity, VerificationStatus},
+        crypto::credentials::{
+            Attribute, AttributeValue, CredentialIssuer, IssuanceRequest,
+            IssuedInstance, IssuerPublicKey, Presentation, VerifiedClaims,
+            verify_presentation,
+        },
+    },
+    storage::kv::{KvStoreExt, SharedStore},
+};
+
+/// Day 0 for the `enrolled_day` attribute (2020-01-01T00:00:00Z)
+const ENROLLMENT_EPOCH: u64 = 1_577_836_800;
+const SECONDS_PER_DAY: u64 = 86_400;
+/// `enrolled_day` range; covers enrollments until 2064
+const MAX_ENROLLED_DAY: u32 = 16_383;
+/// Attribute naming the identity a credential was issued to. Holders only
+/// open it towards this node, e.g. to have tokens issued to that identity.
+pub const IDENTITY_ATTRIBUTE: &str = "identity";
+
+pub struct CredentialService {
+    storage: SharedStore,
+    issuer: CredentialIssuer,
+    validity_days: u64,
+}
+
+impl CredentialService {
+    /// `issuer_seed` must be secret and stable across restarts, e.g. derived
+    /// from the keyring, or credentials issued earlier stop verifying
+    pub async fn new(
+        config: &Config,
+        storage: SharedStore,
+        issuer_seed: &[u8],
+    ) -> Result<Self> {
+        let issuer = CredentialIssuer::from_seed(issuer_seed)
+            .map_err(|e| NodeError::Crypto(e.to_string()))?;
+
+        Ok(Self {
+            storage,
+            issuer,
+            validity_days: config.security.credential_validity_days,
+        })
+    }
+
+    pub fn issuer_public_key(&self) -> &IssuerPublicKey {
+        self.issuer.public_key()
+    }
+
+    /// Issue a batch of credential instances to a verified identity
+    pub async fn issue_credential(
+        &self,
+        id: Uuid,
+        request: IssuanceRequest,
+    ) -> Result<Vec<IssuedInstance>> {
+        let identity = self.storage
+            .get_identity(&id)
+            .map_err(|e| NodeError::Storage(e.to_string()))?
+            .ok_or_else(|| NodeError::Identity("Identity not found".into()))?;
+
+        if identity.verification_status != VerificationStatus::Verified {
+            warn!("Credential requested for unverified identity: {}", id);
+            return Err(NodeError::Identity("Identity is not verified".into()));
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let issued = self.issuer
+            .issue(&request, &Self::attributes(&identity), self.expiry(now))
+            .map_err(|e| NodeError::Crypto(e.to_string()))?;
+
+        info!("Issued {} credential instances for identity {}", issued.len(), id);
+        Ok(issued)
+    }
+
+    /// Verify a presentation made with `nonce`
+    pub fn verify_presentation(
+        &self,
+        presentation: &Presentation,
+        nonce: &[u8],
+    ) -> Result<VerifiedClaims> {
+        verify_presentation(
+            presentation,
+            self.issuer.public_key(),
+            nonce,
+            chrono::Utc::now().timestamp(),
+        )
+        .map_err(|e| NodeError::Crypto(e.to_string()))
+    }
+
+    fn attributes(identity: &Identity) -> Vec<Attribute> {
+        let enrolled_day = identity.metadata.created_at
+            .saturating_sub(ENROLLMENT_EPOCH) / SECONDS_PER_DAY;
+        let risk_percent = (identity.metadata.risk_score.clamp(0.0, 1.0) * 100.0).round() as u32;
+
+        vec![
+            Attribute {
+                name: "verified_human".into(),
+                value: AttributeValue::Bool(true),
+            },
+            Attribute {
+                name: "enrolled_day".into(),
+                value: AttributeValue::Integer {
+                    value: (enrolled_day as u32).min(MAX_ENROLLED_DAY),
+                    max: MAX_ENROLLED_DAY,
+                },
+            },
+            Attribute {
+                name: "risk_percent".into(),
+                value: AttributeValue::Integer { value: risk_percent, max: 100 },
+            },
+            Attribute {
+                name: IDENTITY_ATTRIBUTE.into(),
+                value: AttributeValue::Text(identity.id.to_string()),
+            },
+        ]
+    }
+
+    /// Expiry rounded up to a whole day, so it does not single out the holder
+    fn expiry(&self, now: i64) -> i64 {
+        let day = SECONDS_PER_DAY as i64;
+        (now / day + 1 + self.validity_days as i64) * day
+    }
+}
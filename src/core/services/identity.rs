@@ -1,5 +1,4 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{info, warn, error};
 use uuid::Uuid;
 
@@ -19,12 +18,12 @@ use crate::{
             zkp::ZeroKnowledgeProof,
         },
     },
-    storage::encrypted::EncryptedStore,
+    storage::kv::{KvStoreExt, SharedStore},
 };
 
 pub struct IdentityService {
     config: Arc<Config>,
-    storage: Arc<RwLock<EncryptedStore>>,
+    storage: SharedStore,
     key_manager: Arc<KeyManager>,
     quantum_processor: Arc<QuantumResistantProcessor>,
 }
@@ -32,7 +31,7 @@ pub struct IdentityService {
 impl IdentityService {
     pub async fn new(
         config: &Config,
-        storage: Arc<RwLock<EncryptedStore>>,
+        storage: SharedStore,
     ) -> Result<Self> {
         let key_manager = Arc::new(KeyManager::new(&config.security)?);
         let quantum_processor = Arc::new(QuantumResistantProcessor::new()?);
@@ -75,10 +74,7 @@ impl IdentityService {
 
         // Store identity
         self.storage
-            .write()
-            .await
             .store_identity(&identity)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?;
 
         info!("Created new identity: {}", identity.id);
@@ -93,10 +89,7 @@ impl IdentityService {
     ) -> Result<bool> {
         // Retrieve stored identity
         let mut identity = self.storage
-            .read()
-            .await
             .get_identity(&id)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?
             .ok_or_else(|| NodeError::Identity("Identity not found".into()))?;
 
@@ -126,10 +119,7 @@ impl IdentityService {
 
         // Store updated identity
         self.storage
-            .write()
-            .await
             .store_identity(&identity)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?;
 
         info!("Identity {} verification result: {}", id, verified);
@@ -142,10 +132,7 @@ impl IdentityService {
         pattern: BehaviorPattern,
     ) -> Result<()> {
         let mut identity = self.storage
-            .read()
-            .await
             .get_identity(&id)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?
             .ok_or_else(|| NodeError::Identity("Identity not found".into()))?;
 
@@ -157,10 +144,7 @@ impl IdentityService {
 
         // Store updated identity
         self.storage
-            .write()
-            .await
             .store_identity(&identity)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?;
 
         Ok(())
@@ -168,20 +152,14 @@ impl IdentityService {
 
     pub async fn revoke_identity(&self, id: Uuid) -> Result<()> {
         let mut identity = self.storage
-            .read()
-            .await
             .get_identity(&id)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?
             .ok_or_else(|| NodeError::Identity("Identity not found".into()))?;
 
         identity.verification_status = VerificationStatus::Revoked;
 
         self.storage
-            .write()
-            .await
             .store_identity(&identity)
-            .await
             .map_err(|e| NodeError::Storage(e.to_string()))?;
 
         info!("Identity {} has been revoked", id);
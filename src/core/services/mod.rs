@@ -1,4 +1,6 @@
 / src/core/services/mod.rs
+pub mod credentials;
 pub mod identity;
+pub mod tokens;
 pub mod verification;
 pub mod health;
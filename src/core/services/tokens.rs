@@ -0,0 +1,171 @@
+use serde::Serialize;
+use sha3::{Digest, Sha3_256};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    utils::{
+        config::Config,
+        error::{Result, NodeError},
+    },
+    core::{
+        identity::types::VerificationStatus,
+        crypto::{
+            credentials::{
+                verify_presentation, AttributeValue, IssuerPublicKey, Presentation,
+                PresentationRequest,
+            },
+            tokens::{Token, TokenIssuer, TokenPublicKey, TokenRequest, TokenResponse},
+        },
+        services::credentials::IDENTITY_ATTRIBUTE,
+    },
+    storage::kv::{KvStoreExt, SharedStore},
+};
+
+/// Outcome of a token redemption
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
+pub enum Redemption {
+    Accepted,
+    AlreadySpent,
+    Rejected(String),
+}
+
+pub struct TokenService {
+    storage: SharedStore,
+    // Serialises the quota check and update in `issue_tokens`
+    issuance: Mutex<()>,
+    issuer: TokenIssuer,
+    // Issuance requires a credential from this key
+    credential_issuer: IssuerPublicKey,
+    tokens_per_epoch: usize,
+}
+
+impl TokenService {
+    /// `master_seed` must be 32 secret bytes that stay the same across
+    /// restarts, e.g. derived from the keyring, or tokens issued earlier in
+    /// the epoch stop redeeming
+    pub async fn new(
+        config: &Config,
+        storage: SharedStore,
+        master_seed: &[u8],
+        credential_issuer: IssuerPublicKey,
+    ) -> Result<Self> {
+        let master_seed = <[u8; 32]>::try_from(master_seed)
+            .map_err(|_| NodeError::Crypto("Token key seed must be 32 bytes".into()))?;
+
+        Ok(Self {
+            storage,
+            issuance: Mutex::new(()),
+            issuer: TokenIssuer::new(master_seed, config.security.token_epoch_duration),
+            credential_issuer,
+            tokens_per_epoch: config.security.tokens_per_epoch,
+        })
+    }
+
+    /// What the credential presentation authorizing `request` for identity
+    /// `id` must show: that the holder is a verified human and that the
+    /// credential was issued to `id`. The nonce binds it to the blinded
+    /// elements, so it cannot be replayed for another batch.
+    pub fn presentation_request(id: Uuid, request: &TokenRequest) -> PresentationRequest {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"freeghost-token-issuance");
+        hasher.update(id.as_bytes());
+        hasher.update(request.key_id);
+        for element in &request.blinded {
+            hasher.update(element);
+        }
+
+        PresentationRequest {
+            nonce: hasher.finalize().to_vec(),
+            disclose: vec!["verified_human".into(), IDENTITY_ATTRIBUTE.into()],
+            predicates: Vec::new(),
+        }
+    }
+
+    /// Key clients must blind their requests against right now
+    pub fn current_public_key(&self) -> TokenPublicKey {
+        self.issuer.public_key(self.issuer.epoch_at(chrono::Utc::now().timestamp()))
+    }
+
+    /// Evaluate a blinded batch for a verified identity, authorized by a
+    /// presentation of its credential made for `presentation_request`. The
+    /// node learns how many tokens the identity holds, but not which tokens
+    /// they are.
+    pub async fn issue_tokens(
+        &self,
+        id: Uuid,
+        request: TokenRequest,
+        presentation: &Presentation,
+    ) -> Result<TokenResponse> {
+        let now = chrono::Utc::now().timestamp();
+        let epoch = self.issuer.epoch_at(now);
+
+        let expected = Self::presentation_request(id, &request);
+        let claims = verify_presentation(presentation, &self.credential_issuer, &expected.nonce, now)
+            .map_err(|e| {
+                warn!("Token issuance for identity {} with invalid credential: {}", id, e);
+                NodeError::Identity(format!("Credential presentation rejected: {}", e))
+            })?;
+        if !claims.satisfies(&expected)
+            || claims.disclosed.get("verified_human") != Some(&AttributeValue::Bool(true))
+        {
+            return Err(NodeError::Identity("Credential does not show a verified human".into()));
+        }
+        // Otherwise one credential could spend any identity's quota
+        if claims.disclosed.get(IDENTITY_ATTRIBUTE) != Some(&AttributeValue::Text(id.to_string())) {
+            warn!("Token issuance for identity {} with another identity's credential", id);
+            return Err(NodeError::Identity("Credential was not issued to this identity".into()));
+        }
+
+        let _issuance = self.issuance.lock().await;
+
+        let identity = self.storage
+            .get_identity(&id)?
+            .ok_or_else(|| NodeError::Identity("Identity not found".into()))?;
+        if identity.verification_status != VerificationStatus::Verified {
+            warn!("Tokens requested for unverified identity: {}", id);
+            return Err(NodeError::Identity("Identity is not verified".into()));
+        }
+
+        let issued = self.storage.issued_tokens(epoch, &id)? as usize;
+        if issued + request.blinded.len() > self.tokens_per_epoch {
+            return Err(NodeError::Identity(format!(
+                "Token quota of {} per epoch exceeded", self.tokens_per_epoch
+            )));
+        }
+
+        let response = self.issuer
+            .issue(&request, now)
+            .map_err(|e| NodeError::Crypto(e.to_string()))?;
+
+        self.storage.set_issued_tokens(epoch, &id, (issued + request.blinded.len()) as u64)?;
+
+        info!("Issued {} tokens in epoch {}", request.blinded.len(), epoch);
+        Ok(response)
+    }
+
+    /// Verify `token` for `scope` and record it as spent
+    pub async fn redeem_token(&self, token: &Token, scope: &str) -> Result<Redemption> {
+        if let Err(e) = self.issuer.verify(token, scope, chrono::Utc::now().timestamp()) {
+            return Ok(Redemption::Rejected(e.to_string()));
+        }
+
+        let fresh = self.storage.mark_token_spent(token.epoch, &token.id())?;
+
+        if fresh {
+            Ok(Redemption::Accepted)
+        } else {
+            warn!("Double spend attempt in epoch {}", token.epoch);
+            Ok(Redemption::AlreadySpent)
+        }
+    }
+
+    /// Forget spent tokens and issuance counters of epochs that can no
+    /// longer be redeemed. Returns the number of entries removed.
+    pub async fn prune_spent_tokens(&self) -> Result<usize> {
+        let current = self.issuer.epoch_at(chrono::Utc::now().timestamp());
+        self.storage.prune_spent_tokens(current.saturating_sub(1))
+    }
+}
@@ -6,14 +6,29 @@ pub mod storage;
 pub mod utils;
 
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{info, error};
 
 use crate::{
     utils::{config::Config, error::{Result, NodeError}},
-    core::services::{identity::IdentityService, verification::VerificationService},
+    core::services::{
+        credentials::CredentialService,
+        identity::IdentityService,
+        tokens::TokenService,
+        verification::VerificationService,
+    },
     network::p2p::P2PNetwork,
-    storage::encrypted::EncryptedStore,
+    core::crypto::{
+        audit::{AuditError, AuditSystem},
+        audit_export::AuditExporter,
+        key_manager::KeyManager,
+        poisoning::{AuditAlertHandler, MemoryPoisonDetector, PoisonResponse},
+        quantum::SecurityLevel,
+    },
+    storage::{
+        encrypted::{open_targets, BackupPolicy, BackupScheduler, EncryptedStore, KeyRotationScheduler, RotationPolicy},
+        kv::SharedStore,
+        migrations,
+    },
     plugins::manager::PluginManager,
 };
 
@@ -21,20 +36,61 @@ pub struct Application {
     config: Arc<Config>,
     identity_service: Arc<IdentityService>,
     verification_service: Arc<VerificationService>,
+    credential_service: Arc<CredentialService>,
+    token_service: Arc<TokenService>,
     network: Arc<P2PNetwork>,
-    storage: Arc<RwLock<EncryptedStore>>,
+    storage: Arc<EncryptedStore>,
+    audit: Arc<AuditSystem>,
+    memory_detector: Arc<MemoryPoisonDetector>,
     plugin_manager: Arc<PluginManager>,
 }
 
 impl Application {
     pub async fn new(config: Config) -> Result<Self> {
-        let config = Arc::new(config);
-        
         info!("Initializing storage...");
-        let storage = Arc::new(RwLock::new(
-            EncryptedStore::new(&config.storage).await
-                .map_err(|e| NodeError::Storage(e.to_string()))?
-        ));
+        let storage = EncryptedStore::new(&config.storage).await
+            .map_err(|e| NodeError::Storage(e.to_string()))?;
+
+        Self::with_storage(config, storage).await
+    }
+
+    /// Build the application around a keyring that was unsealed with key
+    /// shares instead of the configured passphrase
+    pub async fn unsealed(config: Config, key_manager: KeyManager) -> Result<Self> {
+        info!("Initializing storage...");
+        let storage = EncryptedStore::with_key_manager(&config.storage, key_manager).await
+            .map_err(|e| NodeError::Storage(e.to_string()))?;
+
+        Self::with_storage(config, storage).await
+    }
+
+    async fn with_storage(config: Config, storage: EncryptedStore) -> Result<Self> {
+        let config = Arc::new(config);
+
+        info!("Migrating storage schema...");
+        let schema_version = migrations::migrate(&storage)?;
+        info!("Storage schema at version {}", schema_version);
+
+        info!("Opening audit log...");
+        let audit = Arc::new(
+            storage.open_audit_system(
+                config.security.audit_retention_days,
+                config.security.audit_checkpoint_interval,
+                SecurityLevel::Standard,
+            ).await?
+        );
+        let storage = Arc::new(storage);
+        let store: SharedStore = storage.clone();
+
+        let memory_detector = Arc::new(
+            MemoryPoisonDetector::new(Box::new(AuditAlertHandler::new(audit.clone())))
+                .with_check_interval(std::time::Duration::from_millis(config.security.memory_scan_interval_ms))
+                .with_response(
+                    config.security.memory_poison_response.parse::<PoisonResponse>()
+                        .map_err(|e| NodeError::Config(e.to_string()))?
+                ),
+        );
+        storage.watch_memory(memory_detector.clone(), audit.get_current_session())?;
 
         info!("Initializing network...");
         let network = Arc::new(
@@ -43,8 +99,19 @@ impl Application {
         );
 
         info!("Initializing services...");
-        let identity_service = Arc::new(IdentityService::new(&config).await?);
+        let identity_service = Arc::new(IdentityService::new(&config, store.clone()).await?);
         let verification_service = Arc::new(VerificationService::new(&config).await?);
+        let credential_service = Arc::new(
+            CredentialService::new(&config, store.clone(), &storage.derive_key("credential-issuer")?).await?
+        );
+        let token_service = Arc::new(
+            TokenService::new(
+                &config,
+                store,
+                &storage.derive_key("token-epoch")?,
+                credential_service.issuer_public_key().clone(),
+            ).await?
+        );
 
         info!("Initializing plugin system...");
         let plugin_manager = Arc::new(
@@ -56,8 +123,12 @@ impl Application {
             config,
             identity_service,
             verification_service,
+            credential_service,
+            token_service,
             network,
             storage,
+            audit,
+            memory_detector,
             plugin_manager,
         })
     }
@@ -66,6 +137,36 @@ impl Application {
         info!("Starting network services...");
         self.network.start_network_maintenance().await;
 
+        info!("Starting key rotation scheduler...");
+        KeyRotationScheduler::new(
+            self.storage.clone(),
+            RotationPolicy::from(&self.config.storage),
+        ).start();
+
+        if self.config.storage.backup_interval > 0 {
+            info!("Starting backup scheduler...");
+            BackupScheduler::new(
+                self.storage.clone(),
+                open_targets(&self.config.storage)?,
+                BackupPolicy::from(&self.config.storage),
+            ).start();
+        }
+
+        info!("Starting memory integrity scanner...");
+        self.memory_detector.start();
+
+        if let Some(target) = &self.config.security.audit_export_target {
+            info!("Starting audit export to {}...", target);
+            let export_error = |e: AuditError| NodeError::Init(format!("Audit export: {}", e));
+            AuditExporter::connect(
+                target.parse().map_err(export_error)?,
+                self.config.security.audit_export_format.parse().map_err(export_error)?,
+                &self.config.node.id,
+            ).await
+                .map_err(export_error)?
+                .stream(self.audit.subscribe());
+        }
+
         info!("Loading plugins...");
         self.plugin_manager.load_plugins().await
             .map_err(|e| NodeError::Plugin(e.to_string()))?;
@@ -77,21 +178,47 @@ impl Application {
         Ok(())
     }
 
+    /// Detector that guarded key material should be registered with
+    pub fn memory_detector(&self) -> Arc<MemoryPoisonDetector> {
+        self.memory_detector.clone()
+    }
+
+    /// Resolves when the memory scanner asks for the node to stop
+    pub async fn shutdown_requested(&self) {
+        self.memory_detector.shutdown_requested().await
+    }
+
     async fn start_api_server(&self) -> Result<()> {
         use actix_web::{web, App, HttpServer};
-        use crate::api::handlers;
+        use crate::api::handlers::{self, audit::AuditApi, credentials::CredentialApi, session::SessionGuard};
 
         let identity_service = self.identity_service.clone();
         let verification_service = self.verification_service.clone();
-        let storage = self.storage.clone();
+        let credential_service = self.credential_service.clone();
+        let token_service = self.token_service.clone();
+        let storage: SharedStore = self.storage.clone();
+        let audit = self.audit.clone();
+        let audit_api = web::Data::new(AuditApi::from_config(&self.config));
+        let credential_api = web::Data::new(CredentialApi::from_config(&self.config));
+        let session_guard = SessionGuard::new(self.memory_detector.clone(), self.audit.get_current_session());
 
         HttpServer::new(move || {
+            let session_guard = session_guard.clone();
             App::new()
+                .wrap_fn(move |request, service| session_guard.filter(request, service))
                 .app_data(web::Data::new(identity_service.clone()))
                 .app_data(web::Data::new(verification_service.clone()))
+                .app_data(web::Data::from(credential_service.clone()))
+                .app_data(web::Data::from(token_service.clone()))
                 .app_data(web::Data::new(storage.clone()))
+                .app_data(web::Data::from(audit.clone()))
+                .app_data(audit_api.clone())
+                .app_data(credential_api.clone())
                 .service(handlers::identity::scope())
                 .service(handlers::verification::scope())
+                .service(handlers::credentials::scope())
+                .service(handlers::tokens::scope())
+                .service(handlers::audit::scope())
         })
         .bind((
             self.config.node.host.as_str(),
@@ -110,8 +237,12 @@ impl Application {
         self.plugin_manager.unload_plugins().await
             .map_err(|e| NodeError::Plugin(e.to_string()))?;
 
+        info!("Checkpointing audit log...");
+        self.audit.checkpoint().await
+            .map_err(|e| NodeError::Storage(e.to_string()))?;
+
         info!("Closing storage...");
-        self.storage.write().await.close().await
+        self.storage.close().await
             .map_err(|e| NodeError::Storage(e.to_string()))?;
 
         info!("Application shutdown complete");
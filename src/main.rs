@@ -1,4 +1,17 @@
-use secure_identity_node::{Application, utils::config::Config};
+use std::path::Path;
+use secure_identity_node::{
+    Application,
+    api::handlers,
+    core::crypto::{
+        deserialize_public_key, deserialize_secret_key,
+        key_manager::{KdfParams, KeyManager, KeyShare, SealedShare},
+        unseal::{self, UnsealSession},
+    },
+    storage::encrypted::{
+        self, BackupPolicy, BackupScheduler, EncryptedStore,
+    },
+    utils::{config::Config, error::{NodeError, Result as NodeResult}},
+};
 use tokio::signal;
 use tracing::{info, error};
 
@@ -21,8 +34,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         e
     })?;
     
+    // Key share administration: `keys <command> ...`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("keys") {
+        return run_key_command(&config, &args[1..]).map_err(Into::into);
+    }
+    if args.first().map(String::as_str) == Some("storage") {
+        return run_storage_command(&config, &args[1..]).await.map_err(Into::into);
+    }
+
+    // A keyring sealed with key shares has to be unsealed before anything
+    // can read storage
+    let app = match UnsealSession::new(Path::new(&config.storage.path))? {
+        Some(session) => {
+            let key_manager = if config.security.unseal_mode == "prompt" {
+                unseal::prompt_for_shares(session, std::io::stdin().lock())?
+            } else {
+                handlers::unseal::serve(&config.security.unseal_address, session).await?
+            };
+            Application::unsealed(config, key_manager).await
+        }
+        None => Application::new(config).await,
+    };
+
     // Initialize application
-    let app = Application::new(config).await.map_err(|e| {
+    let app = app.map_err(|e| {
         error!("Failed to initialize application: {}", e);
         e
     })?;
@@ -50,8 +86,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    // Wait for shutdown signal
-    let _ = shutdown_rx.await;
+    // Wait for shutdown signal, or for a poisoning response asking for one
+    tokio::select! {
+        _ = &mut shutdown_rx => {}
+        _ = app.shutdown_requested() => {
+            error!("Shutting down after memory poisoning was detected");
+        }
+    }
     
     // Perform graceful shutdown
     if let Err(e) = app.shutdown().await {
@@ -61,3 +102,189 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Application shutdown complete");
     Ok(())
 }
+
+const KEYS_USAGE: &str = "\
+usage: keys split <threshold> <count> [custodian-public-key-file...]
+       keys reshare <threshold> <count> [custodian-public-key-file...]
+       keys open-share <secret-key-file> <sealed-share-file>";
+
+/// `split` seals a passphrase keyring with new key shares, `reshare` issues
+/// a new set for a sealed one (after prompting for the current shares) and
+/// `open-share` lets a custodian decrypt a share sealed to their Kyber key.
+/// Key files hold hex; with custodian keys every share is printed sealed, as
+/// one JSON line per custodian.
+fn run_key_command(config: &Config, args: &[String]) -> NodeResult<()> {
+    let usage = || NodeError::Config(KEYS_USAGE.into());
+    let dir = Path::new(&config.storage.path);
+
+    match args.first().map(String::as_str) {
+        Some(command @ ("split" | "reshare")) if args.len() >= 3 => {
+            let threshold: u8 = args[1].parse().map_err(|_| usage())?;
+            let count: u8 = args[2].parse().map_err(|_| usage())?;
+            let custodians = args[3..]
+                .iter()
+                .map(|file| deserialize_public_key(&read_hex_file(file)?))
+                .collect::<NodeResult<Vec<_>>>()?;
+            if !custodians.is_empty() && custodians.len() != count as usize {
+                return Err(NodeError::Config("Need one custodian key per share".into()));
+            }
+
+            let shares = if command == "split" {
+                KeyManager::open(dir, &config.storage.encryption_key, &KdfParams::from(&config.storage))?
+                    .split_kek(threshold, count)?
+            } else {
+                let session = UnsealSession::new(dir)?
+                    .ok_or_else(|| NodeError::Config("Keyring is not sealed; use `keys split`".into()))?;
+                unseal::prompt_for_shares(session, std::io::stdin().lock())?
+                    .reshare(threshold, count)?
+            };
+
+            if custodians.is_empty() {
+                for share in &shares {
+                    println!("{}", share);
+                }
+            } else {
+                for (share, custodian) in shares.iter().zip(&custodians) {
+                    let sealed = share.seal(custodian)?;
+                    println!("{}", serde_json::to_string(&sealed).map_err(|e| NodeError::Crypto(e.to_string()))?);
+                }
+            }
+            Ok(())
+        }
+        Some("open-share") if args.len() == 3 => {
+            let secret_key = deserialize_secret_key(&read_hex_file(&args[1])?)?;
+            let contents = std::fs::read_to_string(&args[2])
+                .map_err(|e| NodeError::Config(format!("Failed to read {}: {}", args[2], e)))?;
+            let sealed: SealedShare = serde_json::from_str(&contents)
+                .map_err(|e| NodeError::Crypto(format!("Invalid sealed share: {}", e)))?;
+            let share: KeyShare = sealed.open(&secret_key)?;
+            println!("{}", share);
+            Ok(())
+        }
+        _ => Err(usage()),
+    }
+}
+
+const STORAGE_USAGE: &str = "\
+usage: storage migrate-aad [batch-size]
+       storage backup
+       storage list-backups
+       storage verify-backup [backup-id]
+       storage restore [backup-id]";
+
+/// Run these with the node stopped.
+///
+/// - `migrate-aad` rewrites records from before values were bound to their
+///   keys; it is safe to interrupt and rerun
+/// - `backup` takes a backup to every configured target like the scheduler
+///   does, test-restore and retention included
+/// - `list-backups` lists the backups of all targets together
+/// - `verify-backup` test-restores a backup (default: the newest) from each
+///   target holding it into a scratch directory; `restore` swaps it in for
+///   the current storage, from the first target where it is intact. Both
+///   first copy key files the storage directory lacks from a target.
+async fn run_storage_command(config: &Config, args: &[String]) -> NodeResult<()> {
+    let usage = || NodeError::Config(STORAGE_USAGE.into());
+    let storage_dir = Path::new(&config.storage.path);
+
+    match args.first().map(String::as_str) {
+        Some("migrate-aad") if args.len() <= 2 => {
+            let batch_size = match args.get(1) {
+                Some(size) => size.parse().map_err(|_| usage())?,
+                None => config.storage.reencrypt_batch_size,
+            };
+
+            let store = EncryptedStore::with_key_manager(&config.storage, unlock_keyring(config)?).await?;
+            let report = store.migrate_record_aad(batch_size)?;
+            println!(
+                "scanned {} records, migrated {}, failed {}",
+                report.scanned, report.migrated, report.failed,
+            );
+            if report.failed > 0 {
+                return Err(NodeError::Storage(format!("{} records could not be migrated", report.failed)));
+            }
+            Ok(())
+        }
+        Some("backup") if args.len() == 1 => {
+            let targets = encrypted::open_targets(&config.storage)?;
+            let store = EncryptedStore::with_key_manager(&config.storage, unlock_keyring(config)?).await?;
+            let summary = BackupScheduler::new(store.into(), targets, BackupPolicy::from(&config.storage))
+                .run_once()
+                .await?;
+            for upload in &summary.uploads {
+                println!(
+                    "backup {} to {}: {} files, {} new chunks ({} bytes), {} reused",
+                    summary.id, upload.target, summary.files, upload.chunks_written, upload.bytes_written,
+                    upload.chunks_reused,
+                );
+            }
+            Ok(())
+        }
+        Some("list-backups") if args.len() == 1 => {
+            for listing in encrypted::list_backups(&encrypted::open_targets(&config.storage)?)? {
+                let manifest = &listing.manifest;
+                let created_at = chrono::DateTime::from_timestamp(manifest.created_at, 0)
+                    .map_or_else(|| manifest.created_at.to_string(), |time| time.to_rfc3339());
+                println!(
+                    "{}  {}  {} files  schema {}  on {}",
+                    manifest.id, created_at, manifest.files.len(), manifest.schema_version,
+                    listing.targets.join(", "),
+                );
+            }
+            Ok(())
+        }
+        Some(command @ ("verify-backup" | "restore")) if args.len() <= 2 => {
+            let id = args.get(1).map(String::as_str);
+            let targets = encrypted::open_targets(&config.storage)?;
+            for name in encrypted::restore_key_files(storage_dir, &targets)? {
+                println!("copied {} from the backup", name);
+            }
+            let key_manager = unlock_keyring(config)?;
+
+            if command == "restore" {
+                let manifest = encrypted::restore_backup(storage_dir, &targets, id, &key_manager)?;
+                println!("backup {} restored", manifest.id);
+                return Ok(());
+            }
+
+            let listings = encrypted::list_backups(&targets)?;
+            let listing = match id {
+                Some(id) => listings.into_iter().find(|listing| listing.manifest.id == id),
+                None => listings.into_iter().last(),
+            }
+            .ok_or_else(|| NodeError::Storage("No such backup on any target".into()))?;
+            let mut failed = 0;
+            for target in targets.iter().filter(|target| listing.targets.contains(&target.describe())) {
+                match encrypted::verify_backup(target.as_ref(), Some(&listing.manifest.id), &key_manager) {
+                    Ok(_) => println!("backup {} verified on {}", listing.manifest.id, target.describe()),
+                    Err(e) => {
+                        println!("backup {} failed on {}: {}", listing.manifest.id, target.describe(), e);
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(NodeError::Storage(format!(
+                    "Backup {} failed verification on {} targets", listing.manifest.id, failed,
+                )));
+            }
+            Ok(())
+        }
+        _ => Err(usage()),
+    }
+}
+
+/// Unlock the storage keyring, prompting for key shares if it is sealed
+fn unlock_keyring(config: &Config) -> NodeResult<KeyManager> {
+    match UnsealSession::new(Path::new(&config.storage.path))? {
+        Some(session) => unseal::prompt_for_shares(session, std::io::stdin().lock()),
+        None => EncryptedStore::open_keyring(&config.storage),
+    }
+}
+
+fn read_hex_file(path: &str) -> NodeResult<Vec<u8>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| NodeError::Config(format!("Failed to read {}: {}", path, e)))?;
+    hex::decode(contents.trim())
+        .map_err(|e| NodeError::Config(format!("Invalid key file {}: {}", path, e)))
+}
@@ -1,22 +1,22 @@
 // src/network/state/mod.rs
 use super::{
-    types::{NetworkState, StateUpdate, SyncStatus},
+    types::{NetworkState, StateUpdate, SyncStatus, STATE_BACKUP_PREFIX, STATE_UPDATE_PREFIX},
     error::{NetworkError, Result},
 };
-use crate::storage::encrypted::EncryptedStore;
+use crate::storage::kv::{KvStoreExt, SharedStore};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::{RwLock, broadcast};
 use uuid::Uuid;
 
 pub struct StateManager {
-    store: Arc<EncryptedStore>,
+    store: SharedStore,
     current_state: RwLock<NetworkState>,
     sync_status: RwLock<HashMap<Uuid, SyncStatus>>,
     update_channel: broadcast::Sender<StateUpdate>,
 }
 
 impl StateManager {
-    pub fn new(store: Arc<EncryptedStore>) -> Self {
+    pub fn new(store: SharedStore) -> Self {
         let (tx, _) = broadcast::channel(1000);
         Self {
             store,
@@ -37,10 +37,9 @@ impl StateManager {
         }
 
         // Store update
-        self.store.store(
-            format!("state_update_{}", update.id).as_bytes(),
-            &update,
-        ).await?;
+        self.store
+            .put_record(update.storage_key().as_bytes(), &update)
+            .map_err(|e| NetworkError::StateError(e.to_string()))?;
 
         // Broadcast update
         let _ = self.update_channel.send(update);
@@ -79,15 +78,9 @@ impl StateManager {
     }
 
     async fn load_stored_updates(&self) -> Result<Vec<StateUpdate>> {
-        let mut updates = Vec::new();
-        let prefix = "state_update_".as_bytes();
-        
-        let stored_keys = self.store.get_keys_with_prefix(prefix).await?;
-        for key in stored_keys {
-            if let Some(update) = self.store.retrieve::<StateUpdate>(&key).await? {
-                updates.push(update);
-            }
-        }
+        let mut updates: Vec<StateUpdate> = self.store
+            .get_records(STATE_UPDATE_PREFIX.as_bytes())
+            .map_err(|e| NetworkError::StateError(e.to_string()))?;
 
         updates.sort_by_key(|u| u.timestamp);
         Ok(updates)
@@ -108,8 +101,10 @@ impl StateManager {
 
     async fn backup_current_state(&self) -> Result<()> {
         let state = self.current_state.read().await;
-        let backup_key = format!("state_backup_{}", chrono::Utc::now().timestamp());
-        self.store.store(backup_key.as_bytes(), &*state).await?;
+        let backup_key = format!("{}{:020}", STATE_BACKUP_PREFIX, chrono::Utc::now().timestamp());
+        self.store
+            .put_record(backup_key.as_bytes(), &*state)
+            .map_err(|e| NetworkError::StateError(e.to_string()))?;
         Ok(())
     }
 
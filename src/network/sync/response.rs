@@ -1,5 +1,7 @@
 // src/network/sync/response.rs
 use super::{NetworkMessage, NetworkState, StateManager};
+use crate::network::types::STATE_UPDATE_PREFIX;
+use crate::storage::kv::KvStoreExt;
 use tokio::sync::oneshot;
 use std::collections::HashMap;
 
@@ -124,15 +126,9 @@ impl StateManager {
     }
 
     async fn load_stored_updates(&self) -> Result<Vec<StateUpdate>> {
-        let mut updates = Vec::new();
-        let prefix = "state_update_".as_bytes();
-        
-        let stored_keys = self.store.get_keys_with_prefix(prefix).await?;
-        for key in stored_keys {
-            if let Some(update) = self.store.retrieve::<StateUpdate>(&key).await? {
-                updates.push(update);
-            }
-        }
+        let mut updates: Vec<StateUpdate> = self.store
+            .get_records(STATE_UPDATE_PREFIX.as_bytes())
+            .map_err(|e| NetworkError::StateError(e.to_string()))?;
 
         // Sort updates by timestamp
         updates.sort_by_key(|u| u.timestamp);
@@ -1,17 +1,17 @@
 // src/network/sync/state.rs
 use super::types::{NetworkState, StateUpdate, SyncStatus};
-use crate::storage::encrypted::EncryptedStore;
+use crate::storage::kv::{KvStoreExt, SharedStore};
 use std::collections::HashMap;
 
 pub struct StateManager {
-    store: Arc<EncryptedStore>,
+    store: SharedStore,
     current_state: RwLock<NetworkState>,
     sync_status: RwLock<HashMap<Uuid, SyncStatus>>,
     update_channel: broadcast::Sender<StateUpdate>,
 }
 
 impl StateManager {
-    pub fn new(store: Arc<EncryptedStore>) -> (Self, broadcast::Receiver<StateUpdate>) {
+    pub fn new(store: SharedStore) -> (Self, broadcast::Receiver<StateUpdate>) {
         let (tx, rx) = broadcast::channel(1000);
         
         (Self {
@@ -31,10 +31,9 @@ impl StateManager {
         state.apply_update(&update)?;
 
         // Store update
-        self.store.store(
-            format!("state_update_{}", update.id).as_bytes(),
-            &update,
-        ).await?;
+        self.store
+            .put_record(update.storage_key().as_bytes(), &update)
+            .map_err(|e| NetworkError::StateError(e.to_string()))?;
 
         // Broadcast update to subscribers
         let _ = self.update_channel.send(update);
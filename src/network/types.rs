@@ -3,6 +3,14 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::time::Duration;
 
+use crate::storage::{kv::Column, record::Record};
+
+/// Key prefix of applied updates in `Column::NetworkState`
+pub const STATE_UPDATE_PREFIX: &str = "update:";
+/// Key prefix of state backups in `Column::NetworkState`, followed by the
+/// zero-padded unix time
+pub const STATE_BACKUP_PREFIX: &str = "backup:";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageType {
     IdentityVerification,
@@ -51,6 +59,11 @@ pub struct NetworkState {
     pub state_hash: String,
 }
 
+impl Record for NetworkState {
+    const COLUMN: Column = Column::NetworkState;
+    const VERSION: u8 = 1;
+}
+
 impl NetworkState {
     pub fn hash(&self) -> String {
         use sha2::{Sha256, Digest};
@@ -125,7 +138,17 @@ pub enum StateChange {
     UpdatePeer(NetworkPeer),
 }
 
+impl Record for StateUpdate {
+    const COLUMN: Column = Column::NetworkState;
+    const VERSION: u8 = 1;
+}
+
 impl StateUpdate {
+    /// Key the update is stored under once applied
+    pub fn storage_key(&self) -> String {
+        format!("{}{}", STATE_UPDATE_PREFIX, self.id)
+    }
+
     pub fn verify_signature(&self) -> Result<()> {
         // In a real implementation, this would verify the cryptographic signature
         // For now, we'll assume all updates are valid
@@ -4,7 +4,7 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 use ring::rand::SystemRandom;
 use crate::core::crypto::{
-    secure_memory::SecretData,
+    secure_memory::Secret,
     audit::{CryptoAuditor, AuditableOperation, AuditStatus},
 };
 use crate::utils::error::{Result, AnalysisError};
@@ -41,12 +41,12 @@ pub struct BehaviorAnalyzer {
     auditor: Arc<CryptoAuditor>,
     rng: SystemRandom,
     // Store normalized baseline in secure memory
-    baseline: Arc<RwLock<SecretData<Vec<f64>>>>,
+    baseline: Arc<RwLock<Secret<Vec<f64>>>>,
 }
 
 impl BehaviorAnalyzer {
     pub fn new(auditor: Arc<CryptoAuditor>) -> Result<Self> {
-        let baseline = SecretData::new(&Vec::new())
+        let baseline = Secret::new(Vec::new())
             .map_err(|e| AnalysisError::Initialization(e.to_string()))?;
 
         Ok(Self {
@@ -126,10 +126,12 @@ impl BehaviorAnalyzer {
 
         // Get baseline for comparison
         let baseline = self.baseline.read().await;
-        let baseline_data = baseline.get();
+        let baseline_data = baseline.expose()
+            .map_err(|e| AnalysisError::Baseline(e.to_string()))?;
 
         // Calculate similarity score
-        let similarity = self.calculate_similarity(recent_patterns, baseline_data)?;
+        let similarity = self.calculate_similarity(recent_patterns, &baseline_data)?;
+        drop(baseline_data);
 
         // Audit verification attempt
         self.auditor
@@ -158,7 +160,7 @@ impl BehaviorAnalyzer {
 
         // Store new baseline in secure memory
         let mut baseline = self.baseline.write().await;
-        *baseline = SecretData::new(&baseline_metrics)
+        *baseline = Secret::new(baseline_metrics)
             .map_err(|e| AnalysisError::Baseline(e.to_string()))?;
 
         Ok(())
@@ -0,0 +1,613 @@
+//! Anti-entropy repair between replicas
+//!
+//! Each replica keeps its records in a Merkle tree (see `merkle`). A repair
+//! round with a peer starts from the roots and walks down the subtrees whose
+//! hashes differ, a level per request, until the peer's side of a subtree
+//! holds at most `leaf_threshold` records. Those are listed and compared key
+//! by key. Matching subtrees are never descended into, and only the records
+//! that differ are transferred: pulled when the peer's copy wins, pushed
+//! when ours does.
+//!
+//! Conflicts are settled by last writer wins: the higher version, then the
+//! greater value. Deletes leave tombstones, so a peer that still holds a
+//! deleted record does not bring it back.
+//!
+//! Every request and response of a round is charged to a token bucket of
+//! `bandwidth` bytes per second shared by all peers, with sizes estimated
+//! from the payloads. With the `metrics` feature, repaired keys and bytes
+//! exchanged are exported as counters.
+//!
+//! Peers are reached through `ReplicaPeer`. `LocalReplica` implements it
+//! directly, which is how in-process replicas and tests talk to each other;
+//! a remote peer implements it over its transport.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::merkle::{Hash, MerkleTree, NodePath, SubtreeSummary};
+use crate::utils::error::{Result, NodeError};
+
+/// Estimated wire size of a subtree path in a request
+const PATH_SIZE: usize = 34;
+/// Estimated wire size of a subtree summary in a response
+const SUMMARY_SIZE: usize = 40;
+/// Estimated wire size of a value hash or version
+const HASH_SIZE: usize = 32;
+
+/// A record version. `data` is `None` for a tombstone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Versioned {
+    pub version: u64,
+    pub data: Option<Vec<u8>>,
+}
+
+impl Versioned {
+    /// Whether this version wins over `other` under last writer wins
+    pub fn supersedes(&self, other: &Versioned) -> bool {
+        (self.version, &self.data) > (other.version, &other.data)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("versioned records serialize")
+    }
+
+    fn wire_size(&self) -> usize {
+        8 + self.data.as_ref().map_or(0, Vec::len)
+    }
+}
+
+/// Requests a replica answers during a repair round
+#[async_trait]
+pub trait ReplicaPeer: Send + Sync {
+    fn id(&self) -> String;
+
+    /// Summaries of the subtrees at `paths`, in order, counting leaves up
+    /// to `limit + 1`
+    async fn summaries(&self, paths: &[NodePath], limit: usize) -> Result<Vec<SubtreeSummary>>;
+
+    /// Keys and value hashes under `path`
+    async fn leaves(&self, path: NodePath) -> Result<Vec<(Vec<u8>, Hash)>>;
+
+    /// Current versions of `keys`; keys never written are left out
+    async fn fetch(&self, keys: &[Vec<u8>]) -> Result<Vec<(Vec<u8>, Versioned)>>;
+
+    /// Apply `records` where they win over the replica's own versions
+    async fn store(&self, records: Vec<(Vec<u8>, Versioned)>) -> Result<()>;
+
+    /// Drop `records`, copies held in another replica's place, where the
+    /// replica still has exactly that version
+    async fn discard(&self, records: Vec<(Vec<u8>, Versioned)>) -> Result<()>;
+}
+
+/// Versioned records held in memory, with their Merkle tree
+pub struct LocalReplica {
+    id: String,
+    records: RwLock<BTreeMap<Vec<u8>, Versioned>>,
+    tree: MerkleTree,
+}
+
+impl LocalReplica {
+    pub fn new(id: &str) -> Self {
+        Self { id: id.to_string(), records: RwLock::new(BTreeMap::new()), tree: MerkleTree::new() }
+    }
+
+    /// Write `data` under a version newer than the current one
+    pub fn put(&self, key: &[u8], data: &[u8]) -> Result<()> {
+        self.write(key, Some(data.to_vec()))
+    }
+
+    /// Replace the record with a tombstone
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.write(key, None)
+    }
+
+    fn write(&self, key: &[u8], data: Option<Vec<u8>>) -> Result<()> {
+        let mut records = self.records.write();
+        let now = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        let version = records.get(key).map_or(now, |current| now.max(current.version + 1));
+        let record = Versioned { version, data };
+        self.tree.insert(key, &record.encode())?;
+        records.insert(key.to_vec(), record);
+        Ok(())
+    }
+
+    /// Keep `record` if it wins over the current version. Returns whether
+    /// it did.
+    pub fn apply(&self, key: &[u8], record: Versioned) -> Result<bool> {
+        let mut records = self.records.write();
+        if records.get(key).is_some_and(|current| !record.supersedes(current)) {
+            return Ok(false);
+        }
+        self.tree.insert(key, &record.encode())?;
+        records.insert(key.to_vec(), record);
+        Ok(true)
+    }
+
+    /// Forget `key` if its current version is `record`, leaving no
+    /// tombstone. Returns whether it did.
+    pub fn remove(&self, key: &[u8], record: &Versioned) -> Result<bool> {
+        let mut records = self.records.write();
+        if records.get(key) != Some(record) {
+            return Ok(false);
+        }
+        self.tree.remove(key)?;
+        records.remove(key);
+        Ok(true)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.records.read().get(key).and_then(|record| record.data.clone())
+    }
+
+    /// Current version of `key`, tombstones included
+    pub fn record(&self, key: &[u8]) -> Option<Versioned> {
+        self.records.read().get(key).cloned()
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Check the records still hash to the tree's root
+    pub fn verify(&self) -> Result<()> {
+        let records = self.records.read();
+        let root = MerkleTree::compute_root(records.iter().map(|(key, record)| (key, record.encode())));
+        if root != self.tree.root() {
+            return Err(NodeError::Storage(format!("Replica {} does not match its Merkle root", self.id)));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReplicaPeer for LocalReplica {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn summaries(&self, paths: &[NodePath], limit: usize) -> Result<Vec<SubtreeSummary>> {
+        Ok(paths.iter().map(|path| self.tree.summary(path, limit)).collect())
+    }
+
+    async fn leaves(&self, path: NodePath) -> Result<Vec<(Vec<u8>, Hash)>> {
+        Ok(self.tree.leaves(&path))
+    }
+
+    async fn fetch(&self, keys: &[Vec<u8>]) -> Result<Vec<(Vec<u8>, Versioned)>> {
+        let records = self.records.read();
+        Ok(keys
+            .iter()
+            .filter_map(|key| records.get(key).map(|record| (key.clone(), record.clone())))
+            .collect())
+    }
+
+    async fn store(&self, records: Vec<(Vec<u8>, Versioned)>) -> Result<()> {
+        for (key, record) in records {
+            self.apply(&key, record)?;
+        }
+        Ok(())
+    }
+
+    async fn discard(&self, records: Vec<(Vec<u8>, Versioned)>) -> Result<()> {
+        for (key, record) in records {
+            self.remove(&key, &record)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AntiEntropyConfig {
+    /// Time between repair rounds
+    pub interval: Duration,
+    /// Bytes per second exchanged with peers; 0 for no limit
+    pub bandwidth: u64,
+    /// Largest subtree listed key by key instead of descended into
+    pub leaf_threshold: usize,
+    /// Most paths or keys per request
+    pub batch_size: usize,
+}
+
+impl Default for AntiEntropyConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            bandwidth: 1024 * 1024,
+            leaf_threshold: 16,
+            batch_size: 256,
+        }
+    }
+}
+
+/// Outcome of a repair round with one peer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub peer: String,
+    pub subtrees_compared: usize,
+    pub keys_pulled: usize,
+    pub keys_pushed: usize,
+    pub bytes: u64,
+}
+
+/// Token bucket holding up to a second's worth of bytes. A transfer that
+/// overdraws it waits until the debt is repaid.
+struct Bandwidth {
+    rate: u64,
+    // Bytes available and when that was computed
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Bandwidth {
+    fn new(rate: u64) -> Self {
+        Self { rate, state: Mutex::new((rate as f64, Instant::now())) }
+    }
+
+    async fn consume(&self, bytes: usize) {
+        if self.rate == 0 {
+            return;
+        }
+        let wait = {
+            let mut state = self.state.lock();
+            let (available, updated) = &mut *state;
+            let rate = self.rate as f64;
+            *available = (*available + updated.elapsed().as_secs_f64() * rate).min(rate);
+            *updated = Instant::now();
+            *available -= bytes as f64;
+            match *available < 0.0 {
+                true => Duration::from_secs_f64(-*available / rate),
+                false => Duration::ZERO,
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+pub struct AntiEntropy {
+    replica: Arc<LocalReplica>,
+    peers: RwLock<Vec<Arc<dyn ReplicaPeer>>>,
+    config: AntiEntropyConfig,
+    bandwidth: Bandwidth,
+}
+
+impl AntiEntropy {
+    pub fn new(replica: Arc<LocalReplica>, config: AntiEntropyConfig) -> Self {
+        Self {
+            replica,
+            peers: RwLock::new(Vec::new()),
+            bandwidth: Bandwidth::new(config.bandwidth),
+            config,
+        }
+    }
+
+    pub fn replica(&self) -> &Arc<LocalReplica> {
+        &self.replica
+    }
+
+    pub fn add_peer(&self, peer: Arc<dyn ReplicaPeer>) {
+        self.peers.write().push(peer);
+    }
+
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run_round().await {
+                    warn!("Anti-entropy round incomplete: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Repair against every peer in turn. Fails if any of them failed.
+    pub async fn run_round(&self) -> Result<Vec<RepairReport>> {
+        let peers = self.peers.read().clone();
+        let mut reports = Vec::new();
+        let mut failed = Vec::new();
+        for peer in peers {
+            match self.repair(peer.as_ref()).await {
+                Ok(report) => reports.push(report),
+                Err(e) => {
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("storage.anti_entropy.failed_repairs", 1);
+                    failed.push(format!("{}: {}", peer.id(), e));
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            return Err(NodeError::Storage(format!("Anti-entropy failed with {}", failed.join("; "))));
+        }
+        Ok(reports)
+    }
+
+    /// Bring this replica and `peer` to the same records
+    pub async fn repair(&self, peer: &dyn ReplicaPeer) -> Result<RepairReport> {
+        let mut report = RepairReport { peer: peer.id(), ..RepairReport::default() };
+        let divergent = self.find_divergent(peer, &mut report).await?;
+
+        for keys in divergent.chunks(self.config.batch_size.max(1)) {
+            self.transfer(&mut report, keys.iter().map(Vec::len).sum()).await;
+            let theirs: HashMap<_, _> = peer.fetch(keys).await?.into_iter().collect();
+            let received = theirs.iter().map(|(key, record)| key.len() + record.wire_size()).sum();
+            self.transfer(&mut report, received).await;
+
+            let mut push = Vec::new();
+            for key in keys {
+                match (self.replica.record(key), theirs.get(key)) {
+                    (ours, Some(theirs)) if ours.as_ref().is_none_or(|ours| theirs.supersedes(ours)) => {
+                        report.keys_pulled += self.replica.apply(key, theirs.clone())? as usize;
+                    }
+                    (Some(ours), theirs) if theirs.is_none_or(|theirs| ours.supersedes(theirs)) => {
+                        push.push((key.clone(), ours));
+                    }
+                    _ => {}
+                }
+            }
+
+            if !push.is_empty() {
+                let sent = push.iter().map(|(key, record)| key.len() + record.wire_size()).sum();
+                self.transfer(&mut report, sent).await;
+                report.keys_pushed += push.len();
+                peer.store(push).await?;
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("storage.anti_entropy.keys_pulled", report.keys_pulled as u64);
+            metrics::counter!("storage.anti_entropy.keys_pushed", report.keys_pushed as u64);
+            metrics::counter!("storage.anti_entropy.bytes", report.bytes);
+        }
+        match report.keys_pulled + report.keys_pushed {
+            0 => debug!(peer = %report.peer, "Replica in sync"),
+            repaired => info!(
+                peer = %report.peer,
+                pulled = report.keys_pulled,
+                pushed = report.keys_pushed,
+                bytes = report.bytes,
+                "Repaired {} keys",
+                repaired,
+            ),
+        }
+        Ok(report)
+    }
+
+    /// Keys whose value hashes differ from the peer's, found by descending
+    /// the subtrees whose hashes differ
+    async fn find_divergent(&self, peer: &dyn ReplicaPeer, report: &mut RepairReport) -> Result<Vec<Vec<u8>>> {
+        let tree = self.replica.tree();
+        let threshold = self.config.leaf_threshold;
+        let mut divergent = Vec::new();
+        let mut pending = vec![NodePath::root()];
+
+        while !pending.is_empty() {
+            let mut next = Vec::new();
+            for paths in pending.chunks(self.config.batch_size.max(1)) {
+                self.transfer(report, paths.len() * PATH_SIZE).await;
+                let summaries = peer.summaries(paths, threshold).await?;
+                if summaries.len() != paths.len() {
+                    return Err(NodeError::Storage(format!("Peer {} sent a short subtree summary", report.peer)));
+                }
+                self.transfer(report, summaries.len() * SUMMARY_SIZE).await;
+                report.subtrees_compared += paths.len();
+
+                for (path, theirs) in paths.iter().zip(summaries) {
+                    if tree.node_hash(path) == theirs.hash {
+                        continue;
+                    }
+                    match path.children() {
+                        Some((left, right)) if theirs.leaves > threshold => next.extend([left, right]),
+                        _ => {
+                            self.transfer(report, PATH_SIZE).await;
+                            let their_leaves = peer.leaves(*path).await?;
+                            let received = their_leaves.iter().map(|(key, _)| key.len() + HASH_SIZE).sum();
+                            self.transfer(report, received).await;
+                            divergent.extend(differing_keys(tree.leaves(path), their_leaves));
+                        }
+                    }
+                }
+            }
+            pending = next;
+        }
+        Ok(divergent)
+    }
+
+    async fn transfer(&self, report: &mut RepairReport, bytes: usize) {
+        report.bytes += bytes as u64;
+        self.bandwidth.consume(bytes).await;
+    }
+}
+
+/// Keys present on one side only or with different value hashes
+fn differing_keys(ours: Vec<(Vec<u8>, Hash)>, theirs: Vec<(Vec<u8>, Hash)>) -> Vec<Vec<u8>> {
+    let theirs: HashMap<_, _> = theirs.into_iter().collect();
+    let mut keys = Vec::new();
+    let mut matched = 0;
+    for (key, hash) in &ours {
+        match theirs.get(key) {
+            Some(theirs) if theirs == hash => matched += 1,
+            Some(_) => {
+                matched += 1;
+                keys.push(key.clone());
+            }
+            None => keys.push(key.clone()),
+        }
+    }
+    if matched < theirs.len() {
+        let ours: HashMap<_, _> = ours.into_iter().collect();
+        keys.extend(theirs.into_keys().filter(|key| !ours.contains_key(key)));
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versioned(version: u64, data: &str) -> Versioned {
+        Versioned { version, data: Some(data.as_bytes().to_vec()) }
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("record:{}", index).into_bytes()
+    }
+
+    fn unlimited() -> AntiEntropyConfig {
+        AntiEntropyConfig { bandwidth: 0, ..AntiEntropyConfig::default() }
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        let replica = LocalReplica::new("a");
+        assert!(replica.apply(b"k", versioned(2, "two")).unwrap());
+        assert!(!replica.apply(b"k", versioned(1, "one")).unwrap());
+        assert!(replica.apply(b"k", versioned(2, "zwei")).unwrap());
+        assert!(replica.apply(b"k", Versioned { version: 3, data: None }).unwrap());
+        assert_eq!(replica.get(b"k"), None);
+
+        // Local writes always supersede what is there
+        replica.put(b"k", b"again").unwrap();
+        assert_eq!(replica.get(b"k").unwrap(), b"again");
+        assert!(replica.record(b"k").unwrap().version > 3);
+        replica.verify().unwrap();
+    }
+
+    /// Several replicas start from the same records, diverge, then repair
+    /// in rounds until they all agree
+    #[tokio::test]
+    async fn test_replicas_converge() {
+        let replicas: Vec<_> = ["a", "b", "c", "d"].iter().map(|id| Arc::new(LocalReplica::new(id))).collect();
+        for index in 0..1000 {
+            for replica in &replicas {
+                replica.apply(&key(index), versioned(1, &format!("value {}", index))).unwrap();
+            }
+        }
+
+        // a updates, b deletes, c adds, c and d conflict
+        for index in 0..10 {
+            replicas[0].apply(&key(index), versioned(5, "updated by a")).unwrap();
+        }
+        replicas[1].apply(&key(500), Versioned { version: 5, data: None }).unwrap();
+        for index in 1000..1020 {
+            replicas[2].apply(&key(index), versioned(2, "new on c")).unwrap();
+        }
+        replicas[2].apply(&key(700), versioned(9, "c wins")).unwrap();
+        replicas[3].apply(&key(700), versioned(8, "d loses")).unwrap();
+        // Equal versions: the greater value wins everywhere
+        replicas[3].apply(&key(5), versioned(5, "updated by d")).unwrap();
+
+        let nodes: Vec<_> = replicas
+            .iter()
+            .map(|replica| {
+                let node = AntiEntropy::new(replica.clone(), unlimited());
+                for peer in replicas.iter().filter(|peer| !Arc::ptr_eq(peer, replica)) {
+                    node.add_peer(peer.clone());
+                }
+                node
+            })
+            .collect();
+
+        let mut repaired = 0;
+        let mut compared = 0;
+        for node in &nodes {
+            for report in node.run_round().await.unwrap() {
+                repaired += report.keys_pulled + report.keys_pushed;
+                compared += report.subtrees_compared;
+            }
+        }
+
+        let root = replicas[0].tree().root();
+        assert!(replicas.iter().all(|replica| replica.tree().root() == root));
+        for replica in &replicas {
+            replica.verify().unwrap();
+            assert_eq!(replica.get(&key(3)).unwrap(), b"updated by a");
+            assert_eq!(replica.get(&key(5)).unwrap(), b"updated by d");
+            assert_eq!(replica.get(&key(500)), None);
+            assert_eq!(replica.get(&key(700)).unwrap(), b"c wins");
+            assert_eq!(replica.get(&key(1010)).unwrap(), b"new on c");
+        }
+
+        // Only divergent records moved, and most of the trees were skipped
+        // Each of the 32 divergent keys reaches the other three replicas; a
+        // conflicting one may travel twice before the winner arrives
+        assert!(repaired < 2 * 3 * 32, "{} keys repaired", repaired);
+        assert!(compared < 1000, "{} subtrees compared", compared);
+
+        // A further round finds nothing to do
+        for node in &nodes {
+            for report in node.run_round().await.unwrap() {
+                assert_eq!(report.keys_pulled + report.keys_pushed, 0);
+                assert_eq!(report.subtrees_compared, 1);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_limited() {
+        let ours = Arc::new(LocalReplica::new("ours"));
+        let theirs = Arc::new(LocalReplica::new("theirs"));
+        for index in 0..100 {
+            theirs.apply(&key(index), versioned(1, &"x".repeat(200))).unwrap();
+        }
+
+        let rate = 20_000;
+        let node = AntiEntropy::new(ours.clone(), AntiEntropyConfig { bandwidth: rate, ..AntiEntropyConfig::default() });
+        let started = Instant::now();
+        let report = node.repair(theirs.as_ref()).await.unwrap();
+        assert_eq!(report.keys_pulled, 100);
+        assert_eq!(ours.tree().root(), theirs.tree().root());
+
+        // A second's worth of burst, the rest at the configured rate
+        assert!(report.bytes > rate);
+        let expected = Duration::from_secs_f64((report.bytes - rate) as f64 / rate as f64);
+        assert!(started.elapsed() >= expected.mul_f64(0.9), "{:?} for {} bytes", started.elapsed(), report.bytes);
+    }
+
+    #[tokio::test]
+    async fn test_failed_peer_reported() {
+        struct Unreachable;
+
+        #[async_trait]
+        impl ReplicaPeer for Unreachable {
+            fn id(&self) -> String {
+                "unreachable".to_string()
+            }
+            async fn summaries(&self, _: &[NodePath], _: usize) -> Result<Vec<SubtreeSummary>> {
+                Err(NodeError::Storage("connection refused".to_string()))
+            }
+            async fn leaves(&self, _: NodePath) -> Result<Vec<(Vec<u8>, Hash)>> {
+                unreachable!()
+            }
+            async fn fetch(&self, _: &[Vec<u8>]) -> Result<Vec<(Vec<u8>, Versioned)>> {
+                unreachable!()
+            }
+            async fn store(&self, _: Vec<(Vec<u8>, Versioned)>) -> Result<()> {
+                unreachable!()
+            }
+            async fn discard(&self, _: Vec<(Vec<u8>, Versioned)>) -> Result<()> {
+                unreachable!()
+            }
+        }
+
+        let replica = Arc::new(LocalReplica::new("a"));
+        let peer = Arc::new(LocalReplica::new("b"));
+        peer.put(b"k", b"v").unwrap();
+        let node = AntiEntropy::new(replica.clone(), unlimited());
+        node.add_peer(Arc::new(Unreachable));
+        node.add_peer(peer);
+
+        let error = node.run_round().await.unwrap_err().to_string();
+        assert!(error.contains("unreachable: "), "{}", error);
+        // The other peer was still repaired
+        assert_eq!(replica.get(b"k").unwrap(), b"v");
+    }
+}
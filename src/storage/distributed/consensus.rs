@@ -1,69 +1,956 @@
-use std::collections::HashMap;
+//! Raft consensus for replicated node metadata
+//!
+//! `RaftNode` is the Raft state machine of one node: leader election, log
+//! replication, snapshots and membership changes. It has no clock and does
+//! no I/O besides its storage: `tick` advances logical time, `step` handles a
+//! message from a peer and the messages it wants sent are collected with
+//! `take_messages`. Being deterministic, a cluster of them can be driven
+//! message by message through partitions and crashes.
+//!
+//! `Consensus` runs a node: it ticks it on a timer, feeds it what its
+//! `Transport` delivers and sends what it emits.
+//!
+//! Term, vote, log and snapshot are persisted (see `storage`) before any
+//! message depending on them leaves the node. Once `snapshot_threshold`
+//! entries have been applied past the last snapshot, the state machine is
+//! snapshotted and the log up to there is dropped; followers too far behind
+//! are sent the snapshot instead of entries.
+//!
+//! Membership changes add or remove one voter at a time. A configuration
+//! takes effect as soon as its entry is in the log, and the next change is
+//! refused until it is committed. A node joining a running cluster starts
+//! without members and learns the configuration from the leader; a removed
+//! leader steps down once its removal is committed. Nodes that have heard
+//! from a leader within an election timeout ignore vote requests, so a
+//! removed node cannot disrupt the cluster.
+
+mod metadata;
+mod storage;
+mod transport;
+
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{info, error};
-use crate::utils::error::Result;
+use std::time::Duration;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
 
-#[derive(Debug, Clone)]
-pub struct ConsensusState {
-    pub term: u64,
-    pub voted_for: Option<String>,
-    pub log: Vec<LogEntry>,
+pub use metadata::{MetadataCommand, MetadataStore};
+pub use storage::{ConsensusState, RaftStorage, Snapshot};
+pub use transport::{MemoryNetwork, Transport};
+use crate::utils::error::{Result, NodeError};
+
+pub type NodeId = String;
+
+/// Messages a `Consensus` buffers for its node
+const INBOX_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Payload {
+    /// Appended by a new leader so entries of earlier terms commit
+    Noop,
+    Command(Vec<u8>),
+    /// Voters from this entry on
+    Membership(Vec<NodeId>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LogEntry {
+    pub index: u64,
+    pub term: u64,
+    pub payload: Payload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Message {
+    RequestVote { last_log_index: u64, last_log_term: u64 },
+    Vote { granted: bool },
+    Append { prev_index: u64, prev_term: u64, entries: Vec<LogEntry>, commit: u64 },
+    /// On failure `match_index` is where the follower's log may diverge
+    AppendResult { success: bool, match_index: u64 },
+    InstallSnapshot { snapshot: Snapshot },
+    SnapshotInstalled { last_index: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub from: NodeId,
+    pub to: NodeId,
+    /// Sender's term
     pub term: u64,
-    pub command: Vec<u8>,
+    pub message: Message,
 }
 
-pub struct Consensus {
-    state: Arc<RwLock<ConsensusState>>,
-    peers: Arc<RwLock<HashMap<String, PeerInfo>>>,
+/// What the log replicates. Commands are applied in log order, once each,
+/// on every node.
+pub trait StateMachine: Send {
+    fn apply(&mut self, command: &[u8]);
+
+    fn snapshot(&self) -> Result<Vec<u8>>;
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct RaftConfig {
+    /// Ticks without a leader before standing for election, randomized up
+    /// to twice that
+    pub election_ticks: u32,
+    /// Ticks between the leader's heartbeats
+    pub heartbeat_ticks: u32,
+    /// Applied entries kept in the log before a snapshot replaces them
+    pub snapshot_threshold: u64,
+    /// Most entries sent in one message
+    pub max_append_entries: usize,
+    /// Length of a tick for `Consensus`
+    pub tick: Duration,
 }
 
-impl Consensus {
-    pub fn new() -> Self {
+impl Default for RaftConfig {
+    fn default() -> Self {
         Self {
-            state: Arc::new(RwLock::new(ConsensusState {
-                term: 0,
-                voted_for: None,
-                log: Vec::new(),
-            })),
-            peers: Arc::new(RwLock::new(HashMap::new())),
+            election_ticks: 10,
+            heartbeat_ticks: 2,
+            snapshot_threshold: 1000,
+            max_append_entries: 64,
+            tick: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Replication state of a follower, kept by the leader
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    next: u64,
+    matched: u64,
+}
+
+pub struct RaftNode<M: StateMachine> {
+    id: NodeId,
+    config: RaftConfig,
+    storage: RaftStorage,
+    machine: M,
+    role: Role,
+    leader: Option<NodeId>,
+    // Latest configuration in the log or snapshot
+    members: Vec<NodeId>,
+    commit_index: u64,
+    last_applied: u64,
+    // Ticks since the last heartbeat (leader) or since hearing from one
+    elapsed: u32,
+    timeout: u32,
+    rng: u64,
+    votes: HashSet<NodeId>,
+    progress: HashMap<NodeId, Progress>,
+    outbox: Vec<Envelope>,
+}
+
+impl<M: StateMachine> RaftNode<M> {
+    /// Node `id` on `storage`. A new cluster is bootstrapped by starting
+    /// each of its nodes with the same `members`; a node restarting or
+    /// joining a running cluster is started with none.
+    pub fn new(id: &str, members: &[NodeId], storage: RaftStorage, machine: M, config: RaftConfig) -> Result<Self> {
+        let seed: [u8; 32] = Sha3_256::digest(id.as_bytes()).into();
+        let mut node = Self {
+            id: id.to_string(),
+            config,
+            storage,
+            machine,
+            role: Role::Follower,
+            leader: None,
+            members: Vec::new(),
+            commit_index: 0,
+            last_applied: 0,
+            elapsed: 0,
+            timeout: 0,
+            rng: u64::from_le_bytes(seed[..8].try_into().expect("8 bytes")) | 1,
+            votes: HashSet::new(),
+            progress: HashMap::new(),
+            outbox: Vec::new(),
+        };
+
+        if let Some(snapshot) = node.storage.snapshot().cloned() {
+            node.machine.restore(&snapshot.data)?;
+            node.commit_index = snapshot.last_index;
+            node.last_applied = snapshot.last_index;
+        } else if node.storage.last_index() == 0 && !members.is_empty() {
+            let mut members = members.to_vec();
+            members.sort();
+            members.dedup();
+            node.storage.append(&[LogEntry { index: 1, term: 0, payload: Payload::Membership(members) }])?;
+        }
+        node.members = node.members_at(u64::MAX);
+        node.reset_timeout();
+        Ok(node)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.role == Role::Leader
+    }
+
+    pub fn leader(&self) -> Option<&str> {
+        self.leader.as_deref()
+    }
+
+    pub fn term(&self) -> u64 {
+        self.storage.state().term
+    }
+
+    pub fn commit_index(&self) -> u64 {
+        self.commit_index
+    }
+
+    pub fn members(&self) -> &[NodeId] {
+        &self.members
+    }
+
+    pub fn machine(&self) -> &M {
+        &self.machine
+    }
+
+    pub fn storage(&self) -> &RaftStorage {
+        &self.storage
+    }
+
+    /// Messages to send, oldest first
+    pub fn take_messages(&mut self) -> Vec<Envelope> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// Advance logical time by one tick
+    pub fn tick(&mut self) -> Result<()> {
+        self.elapsed += 1;
+        match self.role {
+            Role::Leader if self.elapsed >= self.config.heartbeat_ticks => {
+                self.elapsed = 0;
+                self.broadcast_append();
+            }
+            Role::Leader => {}
+            _ if self.elapsed >= self.timeout && self.is_voter() => self.campaign()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Append `command` to the log. Returns its index; it is applied once
+    /// committed.
+    pub fn propose(&mut self, command: Vec<u8>) -> Result<u64> {
+        self.append_as_leader(Payload::Command(command))
+    }
+
+    pub fn add_member(&mut self, id: &str) -> Result<u64> {
+        let mut members = self.members.clone();
+        if !members.iter().any(|member| member == id) {
+            members.push(id.to_string());
+            members.sort();
+        }
+        self.change_membership(members)
+    }
+
+    pub fn remove_member(&mut self, id: &str) -> Result<u64> {
+        let members = self.members.iter().filter(|member| *member != id).cloned().collect();
+        self.change_membership(members)
+    }
+
+    fn change_membership(&mut self, members: Vec<NodeId>) -> Result<u64> {
+        self.check_leader()?;
+        if members.is_empty() {
+            return Err(NodeError::Consensus("Cannot remove the last member".to_string()));
+        }
+        // The previous change must be committed, and so must an entry of
+        // this term, or two configurations could each elect a leader
+        let pending = (self.commit_index + 1..=self.storage.last_index())
+            .any(|index| matches!(self.storage.entry(index), Some(LogEntry { payload: Payload::Membership(_), .. })));
+        if pending || self.storage.term(self.commit_index) != Some(self.term()) {
+            return Err(NodeError::Consensus("A membership change is in progress".to_string()));
+        }
+        self.append_as_leader(Payload::Membership(members))
+    }
+
+    fn check_leader(&self) -> Result<()> {
+        match self.role {
+            Role::Leader => Ok(()),
+            _ => Err(NodeError::NotLeader { leader: self.leader.clone() }),
+        }
+    }
+
+    fn append_as_leader(&mut self, payload: Payload) -> Result<u64> {
+        self.check_leader()?;
+        let index = self.storage.last_index() + 1;
+        if let Payload::Membership(members) = &payload {
+            self.set_members(members.clone());
+        }
+        self.storage.append(&[LogEntry { index, term: self.term(), payload }])?;
+        self.broadcast_append();
+        self.advance_commit()?;
+        Ok(index)
+    }
+
+    /// Handle a message from a peer
+    pub fn step(&mut self, envelope: Envelope) -> Result<()> {
+        let Envelope { from, term, message, .. } = envelope;
+
+        if term > self.term() {
+            // Stay with a leader that is alive; see the module docs
+            let sticky = self.role == Role::Leader || (self.leader.is_some() && self.elapsed < self.config.election_ticks);
+            if matches!(message, Message::RequestVote { .. }) && sticky {
+                return Ok(());
+            }
+            let leader = match message {
+                Message::Append { .. } | Message::InstallSnapshot { .. } => Some(from.clone()),
+                _ => None,
+            };
+            self.become_follower(term, leader)?;
+        } else if term < self.term() {
+            // Tell a stale leader or candidate about the newer term
+            match message {
+                Message::RequestVote { .. } => self.send(&from, Message::Vote { granted: false }),
+                Message::Append { .. } | Message::InstallSnapshot { .. } => {
+                    self.send(&from, Message::AppendResult { success: false, match_index: 0 })
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match message {
+            Message::RequestVote { last_log_index, last_log_term } => {
+                self.handle_vote_request(&from, last_log_index, last_log_term)
+            }
+            Message::Vote { granted } => {
+                if self.role == Role::Candidate && granted && self.members.contains(&from) {
+                    self.votes.insert(from);
+                    if self.has_quorum(|member| self.votes.contains(member)) {
+                        self.become_leader()?;
+                    }
+                }
+                Ok(())
+            }
+            Message::Append { prev_index, prev_term, entries, commit } => {
+                self.handle_append(&from, prev_index, prev_term, entries, commit)
+            }
+            Message::AppendResult { success, match_index } => {
+                self.handle_append_result(&from, success, match_index)
+            }
+            Message::InstallSnapshot { snapshot } => self.handle_snapshot(&from, snapshot),
+            Message::SnapshotInstalled { last_index } => self.handle_append_result(&from, true, last_index),
+        }
+    }
+
+    fn handle_vote_request(&mut self, from: &str, last_log_index: u64, last_log_term: u64) -> Result<()> {
+        let state = self.storage.state().clone();
+        let up_to_date = (last_log_term, last_log_index) >= (self.storage.last_term(), self.storage.last_index());
+        let granted = up_to_date && state.voted_for.as_deref().is_none_or(|voted| voted == from);
+        if granted {
+            self.storage.set_state(ConsensusState { voted_for: Some(from.to_string()), ..state })?;
+            self.elapsed = 0;
+        }
+        self.send(from, Message::Vote { granted });
+        Ok(())
+    }
+
+    fn handle_append(&mut self, from: &str, prev_index: u64, prev_term: u64, entries: Vec<LogEntry>, commit: u64) -> Result<()> {
+        self.role = Role::Follower;
+        self.leader = Some(from.to_string());
+        self.elapsed = 0;
+
+        // Entries up to the snapshot are committed, so they match
+        let snapshot_index = self.storage.first_index() - 1;
+        let (prev_index, prev_term, entries) = match prev_index < snapshot_index {
+            true => (
+                snapshot_index,
+                self.storage.term(snapshot_index).unwrap_or(0),
+                entries.into_iter().filter(|entry| entry.index > snapshot_index).collect(),
+            ),
+            false => (prev_index, prev_term, entries),
+        };
+
+        if self.storage.term(prev_index) != Some(prev_term) {
+            let match_index = self.storage.last_index().min(prev_index.saturating_sub(1));
+            self.send(from, Message::AppendResult { success: false, match_index });
+            return Ok(());
+        }
+
+        let last_new = prev_index + entries.len() as u64;
+        self.storage.append(&entries)?;
+        if !entries.is_empty() {
+            self.members = self.members_at(u64::MAX);
+        }
+        if commit.min(last_new) > self.commit_index {
+            self.commit_index = commit.min(last_new);
+            self.apply_committed()?;
+        }
+        self.send(from, Message::AppendResult { success: true, match_index: last_new });
+        Ok(())
+    }
+
+    fn handle_append_result(&mut self, from: &str, success: bool, match_index: u64) -> Result<()> {
+        if self.role != Role::Leader {
+            return Ok(());
+        }
+        let Some(progress) = self.progress.get_mut(from) else {
+            return Ok(());
+        };
+        if success {
+            progress.matched = progress.matched.max(match_index);
+            progress.next = progress.matched + 1;
+            self.advance_commit()?;
+            if self.progress.get(from).is_some_and(|progress| progress.next <= self.storage.last_index()) {
+                self.send_append(from);
+            }
+        } else {
+            progress.next = (match_index + 1).min(progress.next.saturating_sub(1)).max(1);
+            self.send_append(from);
+        }
+        Ok(())
+    }
+
+    fn handle_snapshot(&mut self, from: &str, snapshot: Snapshot) -> Result<()> {
+        self.role = Role::Follower;
+        self.leader = Some(from.to_string());
+        self.elapsed = 0;
+
+        let last_index = snapshot.last_index;
+        if last_index > self.commit_index {
+            self.machine.restore(&snapshot.data)?;
+            self.storage.install(snapshot)?;
+            self.commit_index = last_index;
+            self.last_applied = last_index;
+            self.members = self.members_at(u64::MAX);
+            info!(node = %self.id, index = last_index, "Installed snapshot from {}", from);
+        }
+        self.send(from, Message::SnapshotInstalled { last_index: self.commit_index });
+        Ok(())
+    }
+
+    fn campaign(&mut self) -> Result<()> {
+        let term = self.term() + 1;
+        self.storage.set_state(ConsensusState { term, voted_for: Some(self.id.clone()) })?;
+        self.role = Role::Candidate;
+        self.leader = None;
+        self.votes = HashSet::from([self.id.clone()]);
+        self.reset_timeout();
+        debug!(node = %self.id, term, "Standing for election");
+
+        if self.has_quorum(|member| self.votes.contains(member)) {
+            return self.become_leader();
+        }
+        let (last_log_index, last_log_term) = (self.storage.last_index(), self.storage.last_term());
+        for member in self.members.clone() {
+            if member != self.id {
+                self.send(&member, Message::RequestVote { last_log_index, last_log_term });
+            }
         }
+        Ok(())
     }
 
-    pub async fn start(&self) -> Result<()> {
-        info!("Consensus mechanism started");
-        // Example implementation for consensus algorithm (e.g., Raft)
-        loop {
-            self.run_consensus_round().await?;
+    fn become_follower(&mut self, term: u64, leader: Option<NodeId>) -> Result<()> {
+        self.storage.set_state(ConsensusState { term, voted_for: None })?;
+        if self.role != Role::Follower {
+            debug!(node = %self.id, term, "Stepping down");
         }
+        self.role = Role::Follower;
+        self.leader = leader;
+        self.progress.clear();
+        self.reset_timeout();
+        Ok(())
+    }
+
+    fn become_leader(&mut self) -> Result<()> {
+        info!(node = %self.id, term = self.term(), "Elected leader");
+        self.role = Role::Leader;
+        self.leader = Some(self.id.clone());
+        self.elapsed = 0;
+        self.progress.clear();
+        self.set_members(self.members.clone());
+        self.append_as_leader(Payload::Noop)?;
+        Ok(())
+    }
+
+    /// Switch to `members`, tracking progress of new ones if leading
+    fn set_members(&mut self, members: Vec<NodeId>) {
+        if self.role == Role::Leader {
+            let next = self.storage.last_index() + 1;
+            self.progress.retain(|id, _| members.contains(id));
+            for member in members.iter().filter(|member| **member != self.id) {
+                self.progress.entry(member.clone()).or_insert(Progress { next, matched: 0 });
+            }
+        }
+        self.members = members;
+    }
+
+    /// Configuration in force at `index`
+    fn members_at(&self, index: u64) -> Vec<NodeId> {
+        let last = self.storage.last_index().min(index);
+        (self.storage.first_index()..=last)
+            .rev()
+            .find_map(|index| match self.storage.entry(index) {
+                Some(LogEntry { payload: Payload::Membership(members), .. }) => Some(members.clone()),
+                _ => None,
+            })
+            .or_else(|| self.storage.snapshot().map(|snapshot| snapshot.members.clone()))
+            .unwrap_or_default()
+    }
+
+    fn is_voter(&self) -> bool {
+        self.members.contains(&self.id)
+    }
+
+    fn has_quorum(&self, counted: impl Fn(&NodeId) -> bool) -> bool {
+        let votes = self.members.iter().filter(|member| counted(member)).count();
+        votes > self.members.len() / 2
     }
 
-    async fn run_consensus_round(&self) -> Result<()> {
-        // Placeholder for consensus round logic
-        // This would include leader election, log replication, etc.
+    /// Commit the newest entry of this term held by a majority
+    fn advance_commit(&mut self) -> Result<()> {
+        let term = self.term();
+        for index in (self.commit_index + 1..=self.storage.last_index()).rev() {
+            if self.storage.term(index) != Some(term) {
+                break;
+            }
+            let held = |member: &NodeId| {
+                *member == self.id || self.progress.get(member).is_some_and(|progress| progress.matched >= index)
+            };
+            if self.has_quorum(held) {
+                self.commit_index = index;
+                return self.apply_committed();
+            }
+        }
         Ok(())
     }
 
-    pub async fn add_peer(&self, peer_info: PeerInfo) -> Result<()> {
-        let mut peers = self.peers.write().await;
-        peers.insert(peer_info.id.clone(), peer_info);
+    fn apply_committed(&mut self) -> Result<()> {
+        while self.last_applied < self.commit_index {
+            let index = self.last_applied + 1;
+            let entry = self
+                .storage
+                .entry(index)
+                .ok_or_else(|| NodeError::Consensus(format!("Committed entry {} missing from the log", index)))?;
+            match &entry.payload {
+                Payload::Command(command) => self.machine.apply(command),
+                Payload::Membership(members) if self.role == Role::Leader && !members.contains(&self.id) => {
+                    info!(node = %self.id, "Removed from the cluster; stepping down");
+                    self.role = Role::Follower;
+                    self.leader = None;
+                    self.progress.clear();
+                }
+                _ => {}
+            }
+            self.last_applied = index;
+        }
+
+        let snapshot_index = self.storage.first_index() - 1;
+        if self.last_applied - snapshot_index >= self.config.snapshot_threshold {
+            let snapshot = Snapshot {
+                last_index: self.last_applied,
+                last_term: self.storage.term(self.last_applied).unwrap_or(0),
+                members: self.members_at(self.last_applied),
+                data: self.machine.snapshot()?,
+            };
+            self.storage.compact(snapshot)?;
+            debug!(node = %self.id, index = self.last_applied, "Took snapshot");
+        }
         Ok(())
     }
+
+    fn broadcast_append(&mut self) {
+        let followers: Vec<NodeId> = self.progress.keys().cloned().collect();
+        for follower in followers {
+            self.send_append(&follower);
+        }
+    }
+
+    fn send_append(&mut self, follower: &str) {
+        let Some(progress) = self.progress.get(follower).copied() else {
+            return;
+        };
+        if progress.next < self.storage.first_index() {
+            if let Some(snapshot) = self.storage.snapshot().cloned() {
+                self.send(follower, Message::InstallSnapshot { snapshot });
+                return;
+            }
+        }
+        let prev_index = progress.next - 1;
+        let message = Message::Append {
+            prev_index,
+            prev_term: self.storage.term(prev_index).unwrap_or(0),
+            entries: self.storage.entries(progress.next, self.config.max_append_entries).to_vec(),
+            commit: self.commit_index,
+        };
+        self.send(follower, message);
+    }
+
+    fn send(&mut self, to: &str, message: Message) {
+        self.outbox.push(Envelope { from: self.id.clone(), to: to.to_string(), term: self.term(), message });
+    }
+
+    fn reset_timeout(&mut self) {
+        // xorshift64: deterministic per node, enough to split votes
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let base = self.config.election_ticks.max(1);
+        self.timeout = base + (self.rng % base as u64) as u32;
+        self.elapsed = 0;
+    }
+}
+
+/// A `RaftNode` running on a timer, talking through a `Transport`
+pub struct Consensus<M: StateMachine> {
+    node: Mutex<RaftNode<M>>,
+    transport: Arc<dyn Transport>,
+    inbox: mpsc::Sender<Envelope>,
+    receiver: Mutex<Option<mpsc::Receiver<Envelope>>>,
+}
+
+impl<M: StateMachine + 'static> Consensus<M> {
+    pub fn new(node: RaftNode<M>, transport: Arc<dyn Transport>) -> Self {
+        let (inbox, receiver) = mpsc::channel(INBOX_SIZE);
+        Self { node: Mutex::new(node), transport, inbox, receiver: Mutex::new(Some(receiver)) }
+    }
+
+    /// Where the transport delivers messages addressed to this node
+    pub fn inbox(&self) -> mpsc::Sender<Envelope> {
+        self.inbox.clone()
+    }
+
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        let mut receiver = self.receiver.lock().take().expect("consensus started twice");
+        tokio::spawn(async move {
+            let tick = self.node.lock().config.tick;
+            let mut ticker = tokio::time::interval(tick);
+            loop {
+                let result = tokio::select! {
+                    _ = ticker.tick() => self.node.lock().tick(),
+                    envelope = receiver.recv() => match envelope {
+                        Some(envelope) => self.node.lock().step(envelope),
+                        None => return,
+                    },
+                };
+                if let Err(e) = result {
+                    warn!("Consensus step failed: {}", e);
+                }
+                self.flush().await;
+            }
+        })
+    }
+
+    pub async fn propose(&self, command: Vec<u8>) -> Result<u64> {
+        let index = self.node.lock().propose(command)?;
+        self.flush().await;
+        Ok(index)
+    }
+
+    pub async fn add_member(&self, id: &str) -> Result<u64> {
+        let index = self.node.lock().add_member(id)?;
+        self.flush().await;
+        Ok(index)
+    }
+
+    pub async fn remove_member(&self, id: &str) -> Result<u64> {
+        let index = self.node.lock().remove_member(id)?;
+        self.flush().await;
+        Ok(index)
+    }
+
+    /// Read the node's state
+    pub fn with_node<R>(&self, read: impl FnOnce(&RaftNode<M>) -> R) -> R {
+        read(&self.node.lock())
+    }
+
+    async fn flush(&self) {
+        let messages = self.node.lock().take_messages();
+        for envelope in messages {
+            let to = envelope.to.clone();
+            if let Err(e) = self.transport.send(envelope).await {
+                debug!("Cannot reach {}: {}", to, e);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::{BTreeMap, VecDeque};
+    use crate::storage::{kv::SharedStore, memory::MemoryStore};
+
+    /// Nodes exchanging messages in lockstep: each step ticks every live
+    /// node once and delivers messages until none are left
+    struct Cluster {
+        config: RaftConfig,
+        nodes: BTreeMap<NodeId, RaftNode<MetadataStore>>,
+        stores: HashMap<NodeId, SharedStore>,
+        // Pairs of nodes that cannot reach each other
+        cut: HashSet<(NodeId, NodeId)>,
+        queue: VecDeque<Envelope>,
+    }
+
+    impl Cluster {
+        fn new(ids: &[&str], config: RaftConfig) -> Self {
+            let mut cluster = Self {
+                config,
+                nodes: BTreeMap::new(),
+                stores: HashMap::new(),
+                cut: HashSet::new(),
+                queue: VecDeque::new(),
+            };
+            let members: Vec<NodeId> = ids.iter().map(|id| id.to_string()).collect();
+            for id in ids {
+                cluster.start(id, &members);
+            }
+            cluster
+        }
+
+        fn start(&mut self, id: &str, members: &[NodeId]) {
+            let store = self.stores.entry(id.to_string()).or_insert_with(|| Arc::new(MemoryStore::new())).clone();
+            let storage = RaftStorage::open(store).unwrap();
+            let node = RaftNode::new(id, members, storage, MetadataStore::default(), self.config.clone()).unwrap();
+            self.nodes.insert(id.to_string(), node);
+        }
+
+        fn crash(&mut self, id: &str) {
+            self.nodes.remove(id);
+        }
+
+        fn partition(&mut self, side: &[&str]) {
+            for a in side {
+                for b in self.stores.keys().filter(|b| !side.contains(&b.as_str())) {
+                    self.cut.insert((a.to_string(), b.clone()));
+                    self.cut.insert((b.clone(), a.to_string()));
+                }
+            }
+        }
+
+        fn heal(&mut self) {
+            self.cut.clear();
+        }
+
+        fn run(&mut self, steps: usize) {
+            for _ in 0..steps {
+                for node in self.nodes.values_mut() {
+                    node.tick().unwrap();
+                }
+                self.deliver();
+            }
+        }
+
+        fn deliver(&mut self) {
+            loop {
+                for node in self.nodes.values_mut() {
+                    self.queue.extend(node.take_messages());
+                }
+                let Some(envelope) = self.queue.pop_front() else {
+                    return;
+                };
+                if self.cut.contains(&(envelope.from.clone(), envelope.to.clone())) {
+                    continue;
+                }
+                if let Some(node) = self.nodes.get_mut(&envelope.to) {
+                    node.step(envelope).unwrap();
+                }
+            }
+        }
+
+        /// The leader of the highest term, if any
+        fn leader(&self) -> Option<NodeId> {
+            self.nodes
+                .values()
+                .filter(|node| node.is_leader())
+                .max_by_key(|node| node.term())
+                .map(|node| node.id().to_string())
+        }
+
+        fn node(&mut self, id: &str) -> &mut RaftNode<MetadataStore> {
+            self.nodes.get_mut(id).unwrap()
+        }
+
+        fn set(&mut self, leader: &str, key: &str, value: &str) -> Result<u64> {
+            let command = MetadataCommand::Set { key: key.to_string(), value: value.as_bytes().to_vec() };
+            let index = self.node(leader).propose(command.encode())?;
+            self.deliver();
+            Ok(index)
+        }
+
+        fn get(&self, id: &str, key: &str) -> Option<Vec<u8>> {
+            self.nodes[id].machine().get(key).map(<[u8]>::to_vec)
+        }
+
+        fn assert_converged(&self) {
+            let mut nodes = self.nodes.values();
+            let first = nodes.next().unwrap();
+            for node in nodes {
+                assert_eq!(node.machine(), first.machine(), "{} and {} differ", node.id(), first.id());
+                assert_eq!(node.commit_index(), first.commit_index());
+            }
+        }
+    }
+
+    fn config() -> RaftConfig {
+        RaftConfig { snapshot_threshold: 1000, ..RaftConfig::default() }
+    }
+
+    #[test]
+    fn test_leader_elected_and_log_replicated() {
+        let mut cluster = Cluster::new(&["a", "b", "c"], config());
+        cluster.run(40);
+        let leader = cluster.leader().unwrap();
+        assert_eq!(cluster.nodes.values().filter(|node| node.is_leader()).count(), 1);
+        for node in cluster.nodes.values() {
+            assert_eq!(node.leader(), Some(leader.as_str()));
+        }
+
+        let follower = cluster.nodes.keys().find(|id| **id != leader).unwrap().clone();
+        assert!(matches!(
+            cluster.node(&follower).propose(b"x".to_vec()),
+            Err(NodeError::NotLeader { leader: Some(hint) }) if hint == leader
+        ));
+
+        for index in 0..10 {
+            cluster.set(&leader, &format!("key{}", index), "value").unwrap();
+        }
+        cluster.run(5);
+        cluster.assert_converged();
+        assert_eq!(cluster.get("c", "key9").unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_leader_failure() {
+        let mut cluster = Cluster::new(&["a", "b", "c"], config());
+        cluster.run(40);
+        let old = cluster.leader().unwrap();
+        let old_term = cluster.node(&old).term();
+        cluster.set(&old, "before", "1").unwrap();
+
+        cluster.crash(&old);
+        cluster.run(40);
+        let new = cluster.leader().unwrap();
+        assert_ne!(new, old);
+        assert!(cluster.node(&new).term() > old_term);
+        cluster.set(&new, "after", "2").unwrap();
+
+        // The old leader comes back from its storage and catches up
+        cluster.start(&old, &[]);
+        cluster.run(10);
+        assert_eq!(cluster.leader().unwrap(), new);
+        cluster.assert_converged();
+        assert_eq!(cluster.get(&old, "before").unwrap(), b"1");
+        assert_eq!(cluster.get(&old, "after").unwrap(), b"2");
+    }
+
+    #[test]
+    fn test_partitioned_leader_overruled() {
+        let mut cluster = Cluster::new(&["a", "b", "c", "d", "e"], config());
+        cluster.run(40);
+        let old = cluster.leader().unwrap();
+        let peer = cluster.nodes.keys().find(|id| **id != old).unwrap().clone();
+
+        // The old leader keeps leading a minority; nothing it takes commits
+        cluster.partition(&[&old, &peer]);
+        let lost = cluster.set(&old, "lost", "minority").unwrap();
+        cluster.run(40);
+        assert!(cluster.node(&old).commit_index() < lost);
+
+        let new = cluster.leader().unwrap();
+        assert!(new != old && new != peer);
+        cluster.set(&new, "kept", "majority").unwrap();
+        cluster.run(5);
+
+        cluster.heal();
+        cluster.run(20);
+        assert_eq!(cluster.nodes.values().filter(|node| node.is_leader()).count(), 1);
+        cluster.assert_converged();
+        assert_eq!(cluster.get(&old, "kept").unwrap(), b"majority");
+        assert!(cluster.get(&old, "lost").is_none());
+    }
+
+    #[test]
+    fn test_state_persisted() {
+        let mut cluster = Cluster::new(&["a"], config());
+        cluster.run(40);
+        assert_eq!(cluster.leader().unwrap(), "a");
+        cluster.set("a", "k", "v").unwrap();
+        let term = cluster.node("a").term();
+
+        cluster.crash("a");
+        cluster.start("a", &[]);
+        let node = cluster.node("a");
+        assert_eq!(node.term(), term);
+        assert_eq!(node.storage().state().voted_for.as_deref(), Some("a"));
+        assert_eq!(node.members(), &["a".to_string()]);
+
+        // Re-elected, and its log applied again once committed
+        cluster.run(40);
+        assert!(cluster.node("a").term() > term);
+        assert_eq!(cluster.get("a", "k").unwrap(), b"v");
+    }
+
+    #[test]
+    fn test_lagging_follower_sent_snapshot() {
+        let mut cluster = Cluster::new(&["a", "b", "c"], RaftConfig { snapshot_threshold: 5, ..config() });
+        cluster.run(40);
+        let leader = cluster.leader().unwrap();
+        let lagging = cluster.nodes.keys().find(|id| **id != leader).unwrap().clone();
+
+        cluster.crash(&lagging);
+        for index in 0..20 {
+            cluster.set(&leader, &format!("key{}", index), "value").unwrap();
+        }
+        assert!(cluster.node(&leader).storage().first_index() > 10);
+
+        cluster.start(&lagging, &[]);
+        cluster.run(10);
+        cluster.assert_converged();
+        assert!(cluster.node(&lagging).storage().snapshot().is_some());
+        assert_eq!(cluster.get(&lagging, "key0").unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_membership_changes() {
+        let mut cluster = Cluster::new(&["a", "b", "c"], config());
+        cluster.run(40);
+        let leader = cluster.leader().unwrap();
+        cluster.set(&leader, "k", "v").unwrap();
+
+        // A new node joins empty and catches up
+        cluster.start("d", &[]);
+        cluster.node(&leader).add_member("d").unwrap();
+        assert!(matches!(cluster.node(&leader).add_member("e"), Err(NodeError::Consensus(_))));
+        cluster.run(5);
+        assert_eq!(cluster.node("d").members().len(), 4);
+        assert_eq!(cluster.get("d", "k").unwrap(), b"v");
+
+        // The leader removes itself and steps down once that commits
+        cluster.node(&leader).remove_member(&leader).unwrap();
+        cluster.run(40);
+        assert!(!cluster.node(&leader).is_leader());
+        let new = cluster.leader().unwrap();
+        assert_ne!(new, leader);
+        assert_eq!(cluster.node(&new).members().len(), 3);
 
-    #[tokio::test]
-    async fn test_consensus_initialization() {
-        let consensus = Consensus::new();
-        assert_eq!(consensus.state.read().await.term, 0);
-        assert!(consensus.state.read().await.voted_for.is_none());
-        assert!(consensus.state.read().await.log.is_empty());
+        // The removed node stays quiet and the rest carry on
+        cluster.crash(&leader);
+        cluster.set(&new, "after", "removal").unwrap();
+        cluster.run(5);
+        cluster.assert_converged();
+        assert_eq!(cluster.get("d", "after").unwrap(), b"removal");
     }
 }
@@ -0,0 +1,65 @@
+//! Node metadata replicated through Raft: a map of names to values, changed
+//! only by committed `MetadataCommand`s
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+use super::StateMachine;
+use crate::utils::error::{Result, NodeError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetadataCommand {
+    Set { key: String, value: Vec<u8> },
+    Delete { key: String },
+}
+
+impl MetadataCommand {
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("metadata commands serialize")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataStore {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl MetadataStore {
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl StateMachine for MetadataStore {
+    /// Commands that do not decode were proposed by a newer or broken node;
+    /// every node skips them alike
+    fn apply(&mut self, command: &[u8]) {
+        match bincode::deserialize(command) {
+            Ok(MetadataCommand::Set { key, value }) => {
+                self.entries.insert(key, value);
+            }
+            Ok(MetadataCommand::Delete { key }) => {
+                self.entries.remove(&key);
+            }
+            Err(e) => tracing::warn!("Skipping undecodable metadata command: {}", e),
+        }
+    }
+
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        bincode::serialize(&self.entries).map_err(|e| NodeError::Consensus(format!("Snapshot failed: {}", e)))
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        self.entries = bincode::deserialize(snapshot)
+            .map_err(|e| NodeError::Consensus(format!("Corrupt snapshot: {}", e)))?;
+        Ok(())
+    }
+}
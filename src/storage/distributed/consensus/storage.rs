@@ -0,0 +1,267 @@
+//! Durable Raft state
+//!
+//! Everything lives in `Column::Consensus`:
+//! - `state`: current term and vote
+//! - `snapshot`: the latest snapshot, replacing the log up to its index
+//! - `log:<index>`: entries after the snapshot, index big-endian so they
+//!   sort in log order
+//!
+//! Every change is one batch, written before the caller acts on it. The log
+//! is also kept in memory; it holds no more than the entries applied since
+//! the last snapshot plus those not yet applied.
+
+use serde::{Deserialize, Serialize};
+
+use super::{LogEntry, NodeId};
+use crate::{
+    storage::{
+        kv::{Column, KvBatch, KvStoreExt, SharedStore},
+        record::Record,
+    },
+    utils::error::{Result, NodeError},
+};
+
+const STATE_KEY: &[u8] = b"state";
+const SNAPSHOT_KEY: &[u8] = b"snapshot";
+const LOG_PREFIX: &[u8] = b"log:";
+
+/// Term and vote, persisted before any vote or message of the term
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsensusState {
+    pub term: u64,
+    pub voted_for: Option<NodeId>,
+}
+
+impl Record for ConsensusState {
+    const COLUMN: Column = Column::Consensus;
+    const VERSION: u8 = 1;
+}
+
+impl Record for LogEntry {
+    const COLUMN: Column = Column::Consensus;
+    const VERSION: u8 = 1;
+}
+
+/// State machine and configuration as of `last_index`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub last_index: u64,
+    pub last_term: u64,
+    pub members: Vec<NodeId>,
+    pub data: Vec<u8>,
+}
+
+impl Record for Snapshot {
+    const COLUMN: Column = Column::Consensus;
+    const VERSION: u8 = 1;
+}
+
+pub struct RaftStorage {
+    store: SharedStore,
+    state: ConsensusState,
+    snapshot: Option<Snapshot>,
+    // Entries after the snapshot, contiguous
+    entries: Vec<LogEntry>,
+}
+
+impl RaftStorage {
+    pub fn open(store: SharedStore) -> Result<Self> {
+        let state = store.get_record::<ConsensusState>(STATE_KEY)?.unwrap_or_default();
+        let snapshot = store.get_record::<Snapshot>(SNAPSHOT_KEY)?;
+        let entries = store.get_records::<LogEntry>(LOG_PREFIX)?;
+
+        let first = snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index) + 1;
+        for (expected, entry) in (first..).zip(&entries) {
+            if entry.index != expected {
+                return Err(NodeError::Storage(format!(
+                    "Raft log has entry {} where {} was expected", entry.index, expected,
+                )));
+            }
+        }
+        Ok(Self { store, state, snapshot, entries })
+    }
+
+    pub fn state(&self) -> &ConsensusState {
+        &self.state
+    }
+
+    pub fn set_state(&mut self, state: ConsensusState) -> Result<()> {
+        if state != self.state {
+            self.store.put_record(STATE_KEY, &state)?;
+            self.state = state;
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_ref()
+    }
+
+    /// Index of the first entry still in the log
+    pub fn first_index(&self) -> u64 {
+        self.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index) + 1
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.first_index() + self.entries.len() as u64 - 1
+    }
+
+    pub fn last_term(&self) -> u64 {
+        self.term(self.last_index()).unwrap_or(0)
+    }
+
+    /// Term of the entry at `index`, if it is in the log or the snapshot
+    pub fn term(&self, index: u64) -> Option<u64> {
+        match &self.snapshot {
+            Some(snapshot) if index == snapshot.last_index => Some(snapshot.last_term),
+            None if index == 0 => Some(0),
+            _ => self.entry(index).map(|entry| entry.term),
+        }
+    }
+
+    pub fn entry(&self, index: u64) -> Option<&LogEntry> {
+        let offset = index.checked_sub(self.first_index())?;
+        self.entries.get(usize::try_from(offset).ok()?)
+    }
+
+    /// Up to `max` entries from `from` on
+    pub fn entries(&self, from: u64, max: usize) -> &[LogEntry] {
+        let start = from.saturating_sub(self.first_index()).min(self.entries.len() as u64) as usize;
+        let end = self.entries.len().min(start.saturating_add(max));
+        &self.entries[start..end]
+    }
+
+    /// Add `entries`, which follow on from an entry already in the log or
+    /// the snapshot. Entries conflicting with them are removed along with
+    /// everything after; entries already present are skipped.
+    pub fn append(&mut self, entries: &[LogEntry]) -> Result<()> {
+        let mut batch = KvBatch::new();
+        let mut truncate_at = None;
+        let mut new = Vec::new();
+        for entry in entries {
+            if entry.index < self.first_index() {
+                continue;
+            }
+            match self.term(entry.index) {
+                Some(term) if term == entry.term && truncate_at.is_none() => continue,
+                Some(_) if truncate_at.is_none() => truncate_at = Some(entry.index),
+                _ => {}
+            }
+            batch.put_record(log_key(entry.index), entry)?;
+            new.push(entry.clone());
+        }
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(index) = truncate_at {
+            for stale in index + new.len() as u64..=self.last_index() {
+                batch.delete(Column::Consensus, log_key(stale));
+            }
+        }
+        let first = new[0].index;
+        if first > self.last_index() + 1 {
+            return Err(NodeError::Storage(format!(
+                "Raft entry {} does not follow the last entry {}", first, self.last_index(),
+            )));
+        }
+        self.store.write(batch)?;
+        self.entries.truncate((first - self.first_index()) as usize);
+        self.entries.extend(new);
+        Ok(())
+    }
+
+    /// Replace the log up to `snapshot.last_index`, which is in the log or
+    /// the current snapshot, with `snapshot`
+    pub fn compact(&mut self, snapshot: Snapshot) -> Result<()> {
+        if snapshot.last_index < self.first_index() {
+            return Ok(());
+        }
+        let removed = (snapshot.last_index + 1 - self.first_index()) as usize;
+        let mut batch = KvBatch::new();
+        batch.put_record(SNAPSHOT_KEY.to_vec(), &snapshot)?;
+        for entry in &self.entries[..removed.min(self.entries.len())] {
+            batch.delete(Column::Consensus, log_key(entry.index));
+        }
+        self.store.write(batch)?;
+        self.entries.drain(..removed.min(self.entries.len()));
+        self.snapshot = Some(snapshot);
+        Ok(())
+    }
+
+    /// Take a snapshot sent by the leader. Entries after it are kept if the
+    /// log agrees with it, dropped otherwise.
+    pub fn install(&mut self, snapshot: Snapshot) -> Result<()> {
+        if self.term(snapshot.last_index) == Some(snapshot.last_term) {
+            return self.compact(snapshot);
+        }
+        let mut batch = KvBatch::new();
+        batch.put_record(SNAPSHOT_KEY.to_vec(), &snapshot)?;
+        for entry in &self.entries {
+            batch.delete(Column::Consensus, log_key(entry.index));
+        }
+        self.store.write(batch)?;
+        self.entries.clear();
+        self.snapshot = Some(snapshot);
+        Ok(())
+    }
+}
+
+fn log_key(index: u64) -> Vec<u8> {
+    [LOG_PREFIX, &index.to_be_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::storage::memory::MemoryStore;
+    use super::super::Payload;
+
+    fn entry(index: u64, term: u64) -> LogEntry {
+        LogEntry { index, term, payload: Payload::Command(vec![index as u8]) }
+    }
+
+    #[test]
+    fn test_log_persisted() {
+        let store: SharedStore = Arc::new(MemoryStore::new());
+        let mut storage = RaftStorage::open(store.clone()).unwrap();
+        assert_eq!((storage.last_index(), storage.last_term()), (0, 0));
+
+        storage.set_state(ConsensusState { term: 3, voted_for: Some("b".into()) }).unwrap();
+        storage.append(&[entry(1, 1), entry(2, 1), entry(3, 2), entry(4, 2)]).unwrap();
+        // Already present, then conflicting from 3 on
+        storage.append(&[entry(2, 1), entry(3, 3)]).unwrap();
+        assert_eq!(storage.last_index(), 3);
+        assert_eq!(storage.term(3), Some(3));
+        assert!(storage.append(&[entry(6, 3)]).is_err());
+
+        storage.compact(Snapshot { last_index: 2, last_term: 1, members: vec!["a".into()], data: vec![7] }).unwrap();
+        assert_eq!(storage.first_index(), 3);
+        assert_eq!(storage.term(2), Some(1));
+        assert_eq!(storage.term(1), None);
+
+        let reopened = RaftStorage::open(store.clone()).unwrap();
+        assert_eq!(reopened.state(), storage.state());
+        assert_eq!(reopened.snapshot().unwrap().data, vec![7]);
+        assert_eq!(reopened.entries(0, 10), &[entry(3, 3)]);
+        assert_eq!(store.scan_prefix(Column::Consensus, LOG_PREFIX).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_installed() {
+        let store: SharedStore = Arc::new(MemoryStore::new());
+        let mut storage = RaftStorage::open(store.clone()).unwrap();
+        storage.append(&[entry(1, 1), entry(2, 1), entry(3, 1)]).unwrap();
+
+        // Agrees with the log: later entries stay
+        storage.install(Snapshot { last_index: 2, last_term: 1, members: Vec::new(), data: Vec::new() }).unwrap();
+        assert_eq!(storage.last_index(), 3);
+
+        // Does not: the log is dropped
+        storage.install(Snapshot { last_index: 5, last_term: 2, members: Vec::new(), data: Vec::new() }).unwrap();
+        assert_eq!((storage.first_index(), storage.last_index(), storage.last_term()), (6, 5, 2));
+        let reopened = RaftStorage::open(store).unwrap();
+        assert_eq!(reopened.last_index(), 5);
+    }
+}
@@ -0,0 +1,125 @@
+//! How Raft messages travel between nodes
+//!
+//! `Consensus` hands every outgoing `Envelope` to its `Transport` and takes
+//! incoming ones on its inbox. Delivery may fail, be delayed, duplicated or
+//! reordered; Raft copes with all of it. `MemoryNetwork` connects nodes in
+//! one process and can cut links between them.
+
+use std::collections::{HashMap, HashSet};
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use tokio::sync::mpsc;
+
+use super::{Envelope, NodeId};
+use crate::utils::error::{Result, NodeError};
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, envelope: Envelope) -> Result<()>;
+}
+
+/// In-process network between registered inboxes
+#[derive(Default)]
+pub struct MemoryNetwork {
+    inboxes: RwLock<HashMap<NodeId, mpsc::Sender<Envelope>>>,
+    // Links that drop messages, in both directions
+    cut: RwLock<HashSet<(NodeId, NodeId)>>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, id: &str, inbox: mpsc::Sender<Envelope>) {
+        self.inboxes.write().insert(id.to_string(), inbox);
+    }
+
+    /// Drop messages between `id` and every other node
+    pub fn isolate(&self, id: &str) {
+        let others: Vec<NodeId> = self.inboxes.read().keys().filter(|other| *other != id).cloned().collect();
+        let mut cut = self.cut.write();
+        for other in others {
+            cut.insert((id.to_string(), other.clone()));
+            cut.insert((other, id.to_string()));
+        }
+    }
+
+    pub fn heal(&self) {
+        self.cut.write().clear();
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryNetwork {
+    /// Messages on a cut link are lost without an error, as on a real
+    /// network
+    async fn send(&self, envelope: Envelope) -> Result<()> {
+        if self.cut.read().contains(&(envelope.from.clone(), envelope.to.clone())) {
+            return Ok(());
+        }
+        let inbox = self
+            .inboxes
+            .read()
+            .get(&envelope.to)
+            .cloned()
+            .ok_or_else(|| NodeError::Network(format!("Unknown node {}", envelope.to)))?;
+        inbox.send(envelope).await.map_err(|_| NodeError::Network("Node stopped".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::storage::{distributed::consensus::*, memory::MemoryStore};
+
+    async fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..500 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out");
+    }
+
+    #[tokio::test]
+    async fn test_consensus_over_memory_network() {
+        let network = Arc::new(MemoryNetwork::new());
+        let ids = ["a", "b", "c"];
+        let members: Vec<NodeId> = ids.iter().map(|id| id.to_string()).collect();
+        let config = RaftConfig { tick: Duration::from_millis(5), ..RaftConfig::default() };
+
+        let mut nodes = Vec::new();
+        for id in ids {
+            let storage = RaftStorage::open(Arc::new(MemoryStore::new())).unwrap();
+            let node = RaftNode::new(id, &members, storage, MetadataStore::default(), config.clone()).unwrap();
+            let consensus = Arc::new(Consensus::new(node, network.clone()));
+            network.register(id, consensus.inbox());
+            consensus.clone().start();
+            nodes.push(consensus);
+        }
+        let leader = |nodes: &[Arc<Consensus<MetadataStore>>]| {
+            nodes.iter().find(|node| node.with_node(|node| node.is_leader())).cloned()
+        };
+
+        wait_for(|| leader(&nodes).is_some()).await;
+        let first = leader(&nodes).unwrap();
+        let command = MetadataCommand::Set { key: "k".to_string(), value: b"1".to_vec() };
+        first.propose(command.encode()).await.unwrap();
+        wait_for(|| nodes.iter().all(|node| node.with_node(|node| node.machine().get("k") == Some(b"1")))).await;
+
+        // Cut off the leader; the others elect another and go on
+        let first_id = first.with_node(|node| node.id().to_string());
+        network.isolate(&first_id);
+        let others: Vec<_> = nodes.iter().filter(|node| !Arc::ptr_eq(node, &first)).cloned().collect();
+        wait_for(|| leader(&others).is_some()).await;
+        let command = MetadataCommand::Set { key: "k".to_string(), value: b"2".to_vec() };
+        leader(&others).unwrap().propose(command.encode()).await.unwrap();
+
+        network.heal();
+        wait_for(|| nodes.iter().all(|node| node.with_node(|node| node.machine().get("k") == Some(b"2")))).await;
+    }
+}
@@ -1,18 +1,643 @@
-// src/storage/distributed/merkle.rs
+//! Sparse Merkle tree over stored records
+//!
+//! Records sit at the leaves of a binary tree of depth 256, each at the path
+//! given by the SHA3-256 hash of its key. The tree is kept compact: an empty
+//! subtree hashes to `EMPTY` and a subtree holding a single record hashes to
+//! that record's leaf hash, at whatever depth it starts. Only subtrees with
+//! two or more records have an internal node, so a proof carries about
+//! `log2(n)` hashes.
+//!
+//! Hashes are domain separated:
+//! - leaf: `SHA3-256(0x00 || path || SHA3-256(value))`
+//! - internal node: `SHA3-256(0x01 || left || right)`
+//!
+//! A proof walks from the root towards a key's path until it reaches an empty
+//! subtree or a single leaf. The leaf is the record itself for an inclusion
+//! proof; an empty subtree or another record's leaf proves the key absent.
+//!
+//! A tree opened on a `KvStore` writes each leaf (record key and value hash)
+//! to `Column::Indexes` and its root to `Column::Default`, in one batch per
+//! update. On open the root is recomputed from the leaves and checked against
+//! the persisted one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use crate::{
+    storage::kv::{Column, KvBatch, SharedStore},
+    utils::error::{Result, NodeError},
+};
+
+pub type Hash = [u8; 32];
+
+/// Hash of an empty subtree
+pub const EMPTY: Hash = [0; 32];
+
+/// Depth of the leaves
+pub const DEPTH: u16 = 256;
+
+const LEAF_DOMAIN: u8 = 0x00;
+const NODE_DOMAIN: u8 = 0x01;
+
+/// Key prefix of persisted trees: `merkle:<name>:root` in `Column::Default`,
+/// `merkle:<name>:<record key>` in `Column::Indexes`
+const PERSIST_PREFIX: &str = "merkle:";
+
+/// Position of a subtree: its depth and the path bits leading to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodePath {
+    depth: u16,
+    // Bits past `depth` are zero
+    bits: Hash,
+}
+
+impl NodePath {
+    pub fn root() -> Self {
+        Self { depth: 0, bits: EMPTY }
+    }
+
+    /// Subtree at `depth` on the way to the leaf at `path`
+    pub fn towards(path: &Hash, depth: u16) -> Self {
+        let mut bits = *path;
+        for (index, byte) in bits.iter_mut().enumerate() {
+            let kept = (depth as usize).saturating_sub(index * 8).min(8);
+            *byte &= !(0xffu16 >> kept) as u8;
+        }
+        Self { depth, bits }
+    }
+
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.depth == DEPTH
+    }
+
+    /// Left and right subtrees; `None` at leaf depth
+    pub fn children(&self) -> Option<(Self, Self)> {
+        if self.is_leaf() {
+            return None;
+        }
+        let left = Self { depth: self.depth + 1, bits: self.bits };
+        let mut right = left;
+        right.bits[self.depth as usize / 8] |= 0x80 >> (self.depth % 8);
+        Some((left, right))
+    }
+
+    pub fn contains(&self, path: &Hash) -> bool {
+        Self::towards(path, self.depth) == *self
+    }
+
+    /// Leaf paths under this subtree
+    fn range(&self) -> RangeInclusive<Hash> {
+        let mut last = self.bits;
+        for (index, byte) in last.iter_mut().enumerate() {
+            let kept = (self.depth as usize).saturating_sub(index * 8).min(8);
+            *byte |= (0xffu16 >> kept) as u8;
+        }
+        self.bits..=last
+    }
+}
+
+/// A record of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Leaf {
+    key: Vec<u8>,
+    value_hash: Hash,
+    hash: Hash,
+}
+
+impl Leaf {
+    fn new(key: &[u8], value_hash: Hash) -> Self {
+        Self { key: key.to_vec(), value_hash, hash: leaf_hash(&key_path(key), &value_hash) }
+    }
+}
+
+/// What a proof's path ends in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofEnd {
+    Empty,
+    Leaf { path: Hash, value_hash: Hash },
+}
+
+/// Proof that a key holds a value, or holds none, under a root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Depth the path ends at
+    depth: u16,
+    /// Sibling hashes from the root down, `EMPTY` ones left out
+    siblings: Vec<Hash>,
+    /// Bit `i` is set if the sibling at depth `i + 1` is in `siblings`
+    present: Hash,
+    end: ProofEnd,
+}
+
+impl MerkleProof {
+    /// Whether the proof shows `key` holding `value` (`None`: no value)
+    /// in the tree with root `root`
+    pub fn verify(&self, root: &Hash, key: &[u8], value: Option<&[u8]>) -> bool {
+        let path = key_path(key);
+        let depth = self.depth as usize;
+        if depth > DEPTH as usize || !NodePath::towards(&path, self.depth).contains_end(&self.end) {
+            return false;
+        }
+
+        let claimed = match (&self.end, value) {
+            (ProofEnd::Leaf { path: end, value_hash }, Some(value)) => {
+                *end == path && *value_hash == value_hash_of(value)
+            }
+            (ProofEnd::Leaf { path: end, .. }, None) => *end != path,
+            (ProofEnd::Empty, None) => true,
+            (ProofEnd::Empty, Some(_)) => false,
+        };
+        if !claimed {
+            return false;
+        }
+
+        let mut hash = match &self.end {
+            ProofEnd::Empty => EMPTY,
+            ProofEnd::Leaf { path, value_hash } => leaf_hash(path, value_hash),
+        };
+        let mut siblings = self.siblings.iter().rev();
+        for depth in (0..depth).rev() {
+            let sibling = match bit(&self.present, depth) {
+                true => match siblings.next() {
+                    Some(sibling) => *sibling,
+                    None => return false,
+                },
+                false => EMPTY,
+            };
+            hash = match bit(&path, depth) {
+                false => node_hash(&hash, &sibling),
+                true => node_hash(&sibling, &hash),
+            };
+        }
+        siblings.next().is_none() && hash == *root
+    }
+}
+
+impl NodePath {
+    fn contains_end(&self, end: &ProofEnd) -> bool {
+        match end {
+            ProofEnd::Empty => true,
+            ProofEnd::Leaf { path, .. } => self.contains(path),
+        }
+    }
+}
+
+/// Hash of a subtree and how many leaves it holds, counted up to a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubtreeSummary {
+    pub hash: Hash,
+    pub leaves: usize,
+}
+
+/// Key whose value differs between two trees, with each side's value hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub key: Vec<u8>,
+    pub ours: Option<Hash>,
+    pub theirs: Option<Hash>,
+}
+
+#[derive(Default)]
+struct Nodes {
+    leaves: BTreeMap<Hash, Leaf>,
+    // Hashes of the subtrees holding two leaves or more
+    internal: HashMap<NodePath, Hash>,
+}
+
+impl Nodes {
+    /// Leaves under `path`, up to `limit`
+    fn leaves_under(&self, path: &NodePath, limit: usize) -> Vec<&Leaf> {
+        self.leaves.range(path.range()).map(|(_, leaf)| leaf).take(limit).collect()
+    }
+
+    fn hash(&self, path: &NodePath) -> Hash {
+        if let Some(hash) = self.internal.get(path) {
+            return *hash;
+        }
+        match self.leaves_under(path, 2).as_slice() {
+            [] => EMPTY,
+            [leaf] => leaf.hash,
+            _ => {
+                let (left, right) = path.children().expect("two leaves share a path");
+                node_hash(&self.hash(&left), &self.hash(&right))
+            }
+        }
+    }
+
+    /// Hash `path`, caching every internal node below it
+    fn fill(&mut self, path: NodePath) -> Hash {
+        if let Some(hash) = self.internal.get(&path) {
+            return *hash;
+        }
+        match self.leaves_under(&path, 2).as_slice() {
+            [] => return EMPTY,
+            [leaf] => return leaf.hash,
+            _ => {}
+        }
+        let (left, right) = path.children().expect("two leaves share a path");
+        let hash = node_hash(&self.fill(left), &self.fill(right));
+        self.internal.insert(path, hash);
+        hash
+    }
+
+    /// Set or clear the leaf at `path` and rehash up to the root
+    fn update(&mut self, path: Hash, leaf: Option<Leaf>) -> Option<Leaf> {
+        // Subtrees without a cached hash hold one leaf or none, and so do
+        // those below them
+        let mut depth = 0;
+        while self.internal.remove(&NodePath::towards(&path, depth)).is_some() && depth < DEPTH {
+            depth += 1;
+        }
+        let previous = match leaf {
+            Some(leaf) => self.leaves.insert(path, leaf),
+            None => self.leaves.remove(&path),
+        };
+        self.fill(NodePath::root());
+        previous
+    }
+
+    fn root(&self) -> Hash {
+        self.hash(&NodePath::root())
+    }
+}
+
 pub struct MerkleTree {
-    root: RwLock<Node>,
-    hasher: Sha3_256,
+    nodes: RwLock<Nodes>,
+    // Store and name the tree is persisted under
+    persist: Option<(SharedStore, String)>,
+}
+
+impl Default for MerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MerkleTree {
+    /// Empty tree in memory
+    pub fn new() -> Self {
+        Self { nodes: RwLock::new(Nodes::default()), persist: None }
+    }
+
+    /// Tree persisted in `store` under `name`, loaded from it if it exists
+    pub fn open(store: SharedStore, name: &str) -> Result<Self> {
+        let mut nodes = Nodes::default();
+        let prefix = leaf_prefix(name);
+        for entry in store.scan_prefix(Column::Indexes, &prefix)? {
+            let (key, value_hash) = entry?;
+            let value_hash: Hash = value_hash
+                .try_into()
+                .map_err(|_| NodeError::Storage(format!("Corrupt leaf in Merkle tree {}", name)))?;
+            let key = &key[prefix.len()..];
+            nodes.leaves.insert(key_path(key), Leaf::new(key, value_hash));
+        }
+        let root = nodes.fill(NodePath::root());
+
+        let persisted = store.get(Column::Default, &root_key(name))?;
+        if persisted.as_deref().unwrap_or(&EMPTY) != root {
+            return Err(NodeError::Storage(format!("Merkle tree {} does not match its persisted root", name)));
+        }
+        Ok(Self { nodes: RwLock::new(nodes), persist: Some((store, name.to_string())) })
+    }
+
+    /// Root of a tree holding `records`
+    pub fn compute_root<K, V>(records: impl IntoIterator<Item = (K, V)>) -> Hash
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut nodes = Nodes::default();
+        for (key, value) in records {
+            let key = key.as_ref();
+            nodes.leaves.insert(key_path(key), Leaf::new(key, value_hash_of(value.as_ref())));
+        }
+        nodes.root()
+    }
+
     pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        let mut root = self.root.write().await;
-        root.insert(key, value, &self.hasher)?;
+        self.set(key, Some(value_hash_of(value)))
+    }
+
+    pub fn remove(&self, key: &[u8]) -> Result<()> {
+        self.set(key, None)
+    }
+
+    fn set(&self, key: &[u8], value_hash: Option<Hash>) -> Result<()> {
+        let path = key_path(key);
+        let mut nodes = self.nodes.write();
+        let previous = nodes.update(path, value_hash.map(|value_hash| Leaf::new(key, value_hash)));
+
+        let Some((store, name)) = &self.persist else {
+            return Ok(());
+        };
+        let mut batch = KvBatch::new();
+        let leaf_key = [leaf_prefix(name), key.to_vec()].concat();
+        match value_hash {
+            Some(value_hash) => batch.put(Column::Indexes, leaf_key, value_hash.to_vec()),
+            None => batch.delete(Column::Indexes, leaf_key),
+        };
+        batch.put(Column::Default, root_key(name), nodes.root().to_vec());
+        if let Err(e) = store.write(batch) {
+            nodes.update(path, previous);
+            return Err(e);
+        }
         Ok(())
     }
 
-    pub fn verify(&self, proof: &MerkleProof) -> Result<bool> {
-        let root = self.root.read().await;
-        root.verify_proof(proof, &self.hasher)
+    pub fn root(&self) -> Hash {
+        self.nodes.read().root()
+    }
+
+    /// Hash of the subtree at `path`
+    pub fn node_hash(&self, path: &NodePath) -> Hash {
+        self.nodes.read().hash(path)
+    }
+
+    /// Hash of the value stored under `key`
+    pub fn get(&self, key: &[u8]) -> Option<Hash> {
+        self.nodes.read().leaves.get(&key_path(key)).map(|leaf| leaf.value_hash)
+    }
+
+    /// Summary of the subtree at `path`, counting at most `limit + 1` leaves
+    pub fn summary(&self, path: &NodePath, limit: usize) -> SubtreeSummary {
+        let nodes = self.nodes.read();
+        SubtreeSummary { hash: nodes.hash(path), leaves: nodes.leaves_under(path, limit.saturating_add(1)).len() }
+    }
+
+    /// Keys and value hashes of the leaves under `path`, in path order
+    pub fn leaves(&self, path: &NodePath) -> Vec<(Vec<u8>, Hash)> {
+        let nodes = self.nodes.read();
+        nodes.leaves_under(path, usize::MAX).into_iter().map(|leaf| (leaf.key.clone(), leaf.value_hash)).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.read().leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.read().leaves.is_empty()
+    }
+
+    /// Proof of what `key` holds in the tree as it is now
+    pub fn prove(&self, key: &[u8]) -> MerkleProof {
+        let target = key_path(key);
+        let nodes = self.nodes.read();
+        let mut proof = MerkleProof { depth: 0, siblings: Vec::new(), present: EMPTY, end: ProofEnd::Empty };
+        let mut path = NodePath::root();
+        loop {
+            proof.depth = path.depth;
+            match nodes.leaves_under(&path, 2).as_slice() {
+                [] => return proof,
+                [leaf] => {
+                    proof.end = ProofEnd::Leaf { path: key_path(&leaf.key), value_hash: leaf.value_hash };
+                    return proof;
+                }
+                _ => {}
+            }
+            let (left, right) = path.children().expect("two leaves share a path");
+            let (next, sibling) = match bit(&target, path.depth as usize) {
+                false => (left, right),
+                true => (right, left),
+            };
+            let sibling = nodes.hash(&sibling);
+            if sibling != EMPTY {
+                proof.present[path.depth as usize / 8] |= 0x80 >> (path.depth % 8);
+                proof.siblings.push(sibling);
+            }
+            path = next;
+        }
+    }
+
+    /// Whether `proof` shows `key` holding `value` under the current root
+    pub fn verify(&self, key: &[u8], value: Option<&[u8]>, proof: &MerkleProof) -> bool {
+        proof.verify(&self.root(), key, value)
+    }
+
+    /// Keys whose values differ from `other`, in path order. Subtrees with
+    /// equal hashes are skipped, so the cost grows with the differences, not
+    /// with the size of the trees.
+    pub fn diff(&self, other: &MerkleTree) -> Vec<Difference> {
+        if std::ptr::eq(self, other) {
+            return Vec::new();
+        }
+        let ours = self.nodes.read();
+        let theirs = other.nodes.read();
+        let mut differences = Vec::new();
+        diff_subtree(&ours, &theirs, NodePath::root(), &mut differences);
+        differences
+    }
+}
+
+fn diff_subtree(ours: &Nodes, theirs: &Nodes, path: NodePath, differences: &mut Vec<Difference>) {
+    if ours.hash(&path) == theirs.hash(&path) {
+        return;
+    }
+    let split = ours.internal.contains_key(&path) && theirs.internal.contains_key(&path);
+    if let (true, Some((left, right))) = (split, path.children()) {
+        diff_subtree(ours, theirs, left, differences);
+        diff_subtree(ours, theirs, right, differences);
+        return;
+    }
+
+    // One side holds a single leaf at most: compare leaf by leaf
+    let mut our_leaves = ours.leaves.range(path.range());
+    let mut their_leaves = theirs.leaves.range(path.range());
+    let (mut our_leaf, mut their_leaf) = (our_leaves.next(), their_leaves.next());
+    loop {
+        match (our_leaf, their_leaf) {
+            (None, None) => return,
+            (Some((ours, leaf)), Some((theirs, theirs_leaf))) if ours == theirs => {
+                if leaf.value_hash != theirs_leaf.value_hash {
+                    differences.push(Difference {
+                        key: leaf.key.clone(),
+                        ours: Some(leaf.value_hash),
+                        theirs: Some(theirs_leaf.value_hash),
+                    });
+                }
+                our_leaf = our_leaves.next();
+                their_leaf = their_leaves.next();
+            }
+            (Some((ours, leaf)), Some((theirs, _))) if ours < theirs => {
+                differences.push(Difference { key: leaf.key.clone(), ours: Some(leaf.value_hash), theirs: None });
+                our_leaf = our_leaves.next();
+            }
+            (Some((_, leaf)), None) => {
+                differences.push(Difference { key: leaf.key.clone(), ours: Some(leaf.value_hash), theirs: None });
+                our_leaf = our_leaves.next();
+            }
+            (_, Some((_, leaf))) => {
+                differences.push(Difference { key: leaf.key.clone(), ours: None, theirs: Some(leaf.value_hash) });
+                their_leaf = their_leaves.next();
+            }
+        }
+    }
+}
+
+/// Leaf path of a record key
+pub fn key_path(key: &[u8]) -> Hash {
+    Sha3_256::digest(key).into()
+}
+
+fn value_hash_of(value: &[u8]) -> Hash {
+    Sha3_256::digest(value).into()
+}
+
+fn leaf_hash(path: &Hash, value_hash: &Hash) -> Hash {
+    let mut hasher = Sha3_256::new();
+    hasher.update([LEAF_DOMAIN]);
+    hasher.update(path);
+    hasher.update(value_hash);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha3_256::new();
+    hasher.update([NODE_DOMAIN]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Bit `index` of `bits`, most significant first
+fn bit(bits: &Hash, index: usize) -> bool {
+    bits[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+fn root_key(name: &str) -> Vec<u8> {
+    format!("{}{}:root", PERSIST_PREFIX, name).into_bytes()
+}
+
+fn leaf_prefix(name: &str) -> Vec<u8> {
+    format!("{}{}:", PERSIST_PREFIX, name).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::storage::memory::MemoryStore;
+
+    fn record(index: u32) -> (Vec<u8>, Vec<u8>) {
+        (format!("record:{}", index).into_bytes(), format!("value {}", index).into_bytes())
+    }
+
+    #[test]
+    fn test_incremental_updates_match_rebuild() {
+        let tree = MerkleTree::new();
+        assert_eq!(tree.root(), EMPTY);
+        for index in 0..200 {
+            let (key, value) = record(index);
+            tree.insert(&key, &value).unwrap();
+        }
+        for index in (0..200).step_by(3) {
+            tree.remove(&record(index).0).unwrap();
+        }
+        tree.insert(b"record:1", b"changed").unwrap();
+
+        let mut records: Vec<_> = (0..200).filter(|index| index % 3 != 0).map(record).collect();
+        records[0].1 = b"changed".to_vec();
+        assert_eq!(tree.len(), records.len());
+        assert_eq!(tree.root(), MerkleTree::compute_root(records.iter().rev().cloned()));
+
+        for (key, _) in &records {
+            tree.remove(key).unwrap();
+        }
+        assert_eq!(tree.root(), EMPTY);
+    }
+
+    #[test]
+    fn test_inclusion_and_non_inclusion_proofs() {
+        let tree = MerkleTree::new();
+        assert!(tree.verify(b"record:0", None, &tree.prove(b"record:0")));
+
+        for index in 0..64 {
+            let (key, value) = record(index);
+            tree.insert(&key, &value).unwrap();
+        }
+        let root = tree.root();
+
+        let (key, value) = record(7);
+        let proof = tree.prove(&key);
+        assert!(proof.verify(&root, &key, Some(&value)));
+        assert!(!proof.verify(&root, &key, Some(b"other value")));
+        assert!(!proof.verify(&root, &key, None));
+        assert!(!proof.verify(&MerkleTree::compute_root([(&key, &value)]), &key, Some(&value)));
+        // Compact: about log2(64) hashes
+        assert!(proof.siblings.len() < 16);
+
+        for missing in 64..96 {
+            let (key, value) = record(missing);
+            let proof = tree.prove(&key);
+            assert!(proof.verify(&root, &key, None));
+            assert!(!proof.verify(&root, &key, Some(&value)));
+        }
+
+        // A proof is only good for its own key
+        assert!(!proof.verify(&root, b"record:8", Some(&value)));
+        let mut forged = proof.clone();
+        forged.siblings.pop();
+        assert!(!forged.verify(&root, &key, Some(&value)));
+
+        // Proofs survive serialization
+        let decoded: MerkleProof = bincode::deserialize(&bincode::serialize(&proof).unwrap()).unwrap();
+        assert!(decoded.verify(&root, &key, Some(&value)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_diff() {
+        let ours = MerkleTree::new();
+        let theirs = MerkleTree::new();
+        for index in 0..500 {
+            let (key, value) = record(index);
+            ours.insert(&key, &value).unwrap();
+            theirs.insert(&key, &value).unwrap();
+        }
+        assert!(ours.diff(&theirs).is_empty());
+
+        theirs.insert(b"record:10", b"newer").unwrap();
+        theirs.remove(b"record:20").unwrap();
+        ours.insert(b"record:600", b"only ours").unwrap();
+        theirs.insert(b"record:700", b"only theirs").unwrap();
+
+        let mut differences = ours.diff(&theirs);
+        differences.sort_by(|a, b| a.key.cmp(&b.key));
+        let hash = |value: &[u8]| Some(value_hash_of(value));
+        assert_eq!(differences, vec![
+            Difference { key: b"record:10".to_vec(), ours: hash(b"value 10"), theirs: hash(b"newer") },
+            Difference { key: b"record:20".to_vec(), ours: hash(b"value 20"), theirs: None },
+            Difference { key: b"record:600".to_vec(), ours: hash(b"only ours"), theirs: None },
+            Difference { key: b"record:700".to_vec(), ours: None, theirs: hash(b"only theirs") },
+        ]);
+        assert_eq!(MerkleTree::new().diff(&ours).len(), 501);
+    }
+
+    #[test]
+    fn test_root_persisted() {
+        let store: SharedStore = Arc::new(MemoryStore::new());
+        let tree = MerkleTree::open(store.clone(), "records").unwrap();
+        for index in 0..50 {
+            let (key, value) = record(index);
+            tree.insert(&key, &value).unwrap();
+        }
+        tree.remove(b"record:3").unwrap();
+        let root = tree.root();
+        drop(tree);
+
+        let tree = MerkleTree::open(store.clone(), "records").unwrap();
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.len(), 49);
+        assert_eq!(tree.get(b"record:4"), Some(value_hash_of(b"value 4")));
+        assert!(MerkleTree::open(store.clone(), "other").unwrap().is_empty());
+
+        // Leaves that no longer add up to the root are refused
+        store.delete(Column::Indexes, b"merkle:records:record:4").unwrap();
+        assert!(MerkleTree::open(store, "records").is_err());
+    }
+}
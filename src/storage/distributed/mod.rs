@@ -2,37 +2,56 @@
 pub mod replication;
 pub mod consensus;
 pub mod merkle;
+pub mod anti_entropy;
+pub mod placement;
+pub mod quorum;
 
-use tokio::sync::RwLock;
-use std::collections::HashMap;
+use std::sync::Arc;
 
+use crate::storage::kv::{Column, SharedStore};
+use crate::utils::error::{Result, NodeError};
+use self::merkle::MerkleTree;
+use self::quorum::QuorumStore;
+
+/// Name the record tree is persisted under
+const MERKLE_TREE: &str = "distributed";
+
+/// Records kept on their quorum replicas, with a local copy and Merkle tree
+///
+/// Writes and reads go through `QuorumStore`, so `get` sees the last `store`
+/// that reached its write quorum. The local copy is only written once the
+/// quorum has taken a record.
 pub struct DistributedStore {
-    local_store: EncryptedStore,
-    peers: RwLock<HashMap<PeerId, PeerStore>>,
+    local_store: SharedStore,
     merkle_tree: MerkleTree,
-    replication_factor: u8,
+    // Places each key on `replication_factor` replicas
+    quorum: Arc<QuorumStore>,
 }
 
 impl DistributedStore {
+    pub fn new(local_store: SharedStore, quorum: Arc<QuorumStore>) -> Result<Self> {
+        let merkle_tree = MerkleTree::open(local_store.clone(), MERKLE_TREE)?;
+        Ok(Self { local_store, merkle_tree, quorum })
+    }
+
     pub async fn store(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        // Local storage
-        self.local_store.store(key, value).await?;
+        // Replicate to the key's replicas
+        self.quorum.put(key, value).await?;
+
+        // Local storage, once the quorum has the record
+        self.local_store.put(Column::Default, key, value)?;
         
         // Update Merkle tree
         self.merkle_tree.insert(key, value)?;
         
-        // Replicate to peers
-        self.replicate(key, value).await?;
-        
         Ok(())
     }
 
     pub async fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
-        if let Ok(value) = self.local_store.get(key).await {
-            return Ok(value);
-        }
-        
-        // Try retrieving from peers
-        self.retrieve_from_peers(key).await
+        // Read from the key's replicas
+        self.quorum
+            .get(key)
+            .await?
+            .ok_or_else(|| NodeError::Storage(format!("Key {} not found", hex::encode(key))))
     }
 }
@@ -0,0 +1,173 @@
+//! Placement of keys on nodes by consistent hashing
+//!
+//! Every node owns `vnodes` points on a ring of 64-bit hashes. Walking
+//! clockwise from a key's hash, the distinct nodes met in order make up the
+//! key's preference list: the first `n` hold its replicas, the ones after
+//! stand in while one of those is down.
+//!
+//! A node joining takes over only the arcs ending at its points, so a
+//! preference list gains the new node and drops at most one other; a node
+//! leaving hands its arcs to the next nodes along. Either way about
+//! `1 / nodes` of the copies move, and none move between nodes that stayed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use sha3::{Digest, Sha3_256};
+
+use super::consensus::NodeId;
+
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    vnodes: usize,
+    points: BTreeMap<u64, NodeId>,
+    nodes: BTreeSet<NodeId>,
+}
+
+impl HashRing {
+    pub fn new(vnodes: usize) -> Self {
+        Self { vnodes: vnodes.max(1), points: BTreeMap::new(), nodes: BTreeSet::new() }
+    }
+
+    /// Returns whether the node was new
+    pub fn add_node(&mut self, id: &str) -> bool {
+        if !self.nodes.insert(id.to_string()) {
+            return false;
+        }
+        for vnode in 0..self.vnodes as u64 {
+            let point = position(&[id.as_bytes(), b"#", &vnode.to_be_bytes()].concat());
+            self.points.entry(point).or_insert_with(|| id.to_string());
+        }
+        true
+    }
+
+    /// Returns whether the node was there
+    pub fn remove_node(&mut self, id: &str) -> bool {
+        if !self.nodes.remove(id) {
+            return false;
+        }
+        self.points.retain(|_, owner| owner != id);
+        true
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.nodes.contains(id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeId> {
+        self.nodes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The first `n` distinct nodes clockwise from the key's hash, or all
+    /// of them if there are fewer
+    pub fn preference_list(&self, key: &[u8], n: usize) -> Vec<NodeId> {
+        let n = n.min(self.nodes.len());
+        let start = position(key);
+        let mut list: Vec<NodeId> = Vec::with_capacity(n);
+        for owner in self.points.range(start..).chain(self.points.range(..start)).map(|(_, owner)| owner) {
+            if list.len() == n {
+                break;
+            }
+            if !list.contains(owner) {
+                list.push(owner.clone());
+            }
+        }
+        list
+    }
+
+    /// Nodes among the first `n` for `key` here but not in `before`, which
+    /// need a copy of it
+    pub fn gained(&self, before: &HashRing, key: &[u8], n: usize) -> Vec<NodeId> {
+        let previous = before.preference_list(key, n);
+        self.preference_list(key, n).into_iter().filter(|id| !previous.contains(id)).collect()
+    }
+}
+
+fn position(bytes: &[u8]) -> u64 {
+    let digest = Sha3_256::digest(bytes);
+    u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(ids: &[&str]) -> HashRing {
+        let mut ring = HashRing::new(128);
+        for id in ids {
+            ring.add_node(id);
+        }
+        ring
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("record:{}", index).into_bytes()
+    }
+
+    #[test]
+    fn test_preference_list() {
+        let ring = ring(&["a", "b", "c", "d", "e"]);
+        let list = ring.preference_list(b"k", 3);
+        assert_eq!(list.len(), 3);
+        assert!(list.iter().all(|id| list.iter().filter(|other| *other == id).count() == 1));
+        assert_eq!(ring.preference_list(b"k", 5)[..3], list[..]);
+        assert_eq!(ring.preference_list(b"k", 10).len(), 5);
+        assert!(HashRing::new(128).preference_list(b"k", 3).is_empty());
+
+        // Same members, same placement, whatever the order they joined in
+        assert_eq!(self::ring(&["e", "d", "c", "b", "a"]).preference_list(b"k", 3), list);
+    }
+
+    #[test]
+    fn test_keys_spread_evenly() {
+        let ids = ["a", "b", "c", "d", "e"];
+        let ring = ring(&ids);
+        let mut primaries = BTreeMap::new();
+        for index in 0..10_000 {
+            *primaries.entry(ring.preference_list(&key(index), 1).remove(0)).or_insert(0) += 1;
+        }
+        assert_eq!(primaries.len(), ids.len());
+        assert!(primaries.values().all(|count| (1_000..3_000).contains(count)), "{:?}", primaries);
+    }
+
+    #[test]
+    fn test_minimal_movement_on_join_and_leave() {
+        let before = ring(&["a", "b", "c", "d"]);
+        let mut joined = before.clone();
+        joined.add_node("e");
+        let mut left = before.clone();
+        left.remove_node("b");
+
+        let (keys, n) = (2_000, 3);
+        let mut moved_on_join = 0;
+        let mut moved_on_leave = 0;
+        for index in 0..keys {
+            let key = key(index);
+            let old = before.preference_list(&key, n);
+
+            // Only the new node gains copies, and it takes them from one
+            // node at most
+            let gained = joined.gained(&before, &key, n);
+            assert!(gained.is_empty() || gained == ["e"]);
+            let new = joined.preference_list(&key, n);
+            assert!(old.iter().filter(|id| !new.contains(id)).count() <= gained.len());
+            moved_on_join += gained.len();
+
+            // Only copies held by the leaving node move
+            let gained = left.gained(&before, &key, n);
+            assert_eq!(gained.len(), old.contains(&"b".to_string()) as usize);
+            moved_on_leave += gained.len();
+        }
+
+        // A fifth and a quarter of the copies, with some slack
+        let copies = (keys * n) as f64;
+        assert!((0.1..0.3).contains(&(moved_on_join as f64 / copies)), "{} moved on join", moved_on_join);
+        assert!((0.15..0.35).contains(&(moved_on_leave as f64 / copies)), "{} moved on leave", moved_on_leave);
+    }
+}
@@ -0,0 +1,721 @@
+//! Quorum reads and writes over the replicas `HashRing` picks
+//!
+//! A key lives on the first `replication_factor` nodes of its preference
+//! list. A write goes to all of them and succeeds once `write_quorum` have
+//! taken it. A read asks all of them and succeeds once `read_quorum` have
+//! answered; it returns the newest version under last writer wins and
+//! writes that back to the replicas that answered with an older one.
+//! `QuorumConfig::validate` requires `write_quorum + read_quorum >
+//! replication_factor`, so a read sees the last successful write.
+//!
+//! Versions are the coordinator's wall clock in milliseconds. Clocks are not
+//! synchronized between coordinators: a write coordinated by a node whose
+//! clock runs ahead wins over later writes from other nodes until their
+//! clocks pass it, so keep node clocks in sync (NTP) or route writes to a
+//! key through one coordinator.
+//!
+//! A replica that fails a request is marked down and skipped until it
+//! answers again. Writes meant for it go to the next node along the
+//! preference list and count towards the quorum, while the coordinator
+//! keeps a hint: the record and the replica it belongs on. `handoff`, run
+//! every `handoff_interval` by `start`, probes the replicas marked down and
+//! hands their hinted records over once they are back; the stand-ins then
+//! drop their copies, unless a newer version replaced them meanwhile. Reads
+//! that lack answers fall back to the same nodes the writes did. A write
+//! taken by stand-ins need not overlap a later read quorum of the actual
+//! replicas, so read-your-writes only holds while no replica is down.
+//!
+//! When a node joins or leaves, each replica that became responsible for a
+//! key gets a copy of it from a previous replica, and nothing else moves
+//! (see `placement`). A write that misses its quorum is not undone on the
+//! replicas that took it; anti-entropy settles those like any divergence.
+//!
+//! With the `metrics` feature, hinted writes, handoffs, read repairs and
+//! missed quorums are exported as counters.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::join_all;
+use parking_lot::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::anti_entropy::{ReplicaPeer, Versioned};
+use super::consensus::NodeId;
+use super::merkle::NodePath;
+use super::placement::HashRing;
+use crate::utils::error::{Result, NodeError};
+
+/// Most records per request when moving data between nodes
+const BATCH_SIZE: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct QuorumConfig {
+    /// Replicas of each key
+    pub replication_factor: usize,
+    /// Replicas that must take a write
+    pub write_quorum: usize,
+    /// Replicas that must answer a read
+    pub read_quorum: usize,
+    /// Points each node owns on the ring
+    pub vnodes: usize,
+    /// Time between hinted handoff attempts
+    pub handoff_interval: Duration,
+}
+
+impl Default for QuorumConfig {
+    fn default() -> Self {
+        Self {
+            replication_factor: 3,
+            write_quorum: 2,
+            read_quorum: 2,
+            vnodes: 128,
+            handoff_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl QuorumConfig {
+    /// Requires `write_quorum + read_quorum > replication_factor`. Writes
+    /// that count stand-ins towards the quorum do not keep that overlap, so
+    /// while replicas are down a read may miss the last write until handoff.
+    pub fn validate(&self) -> Result<()> {
+        let n = self.replication_factor;
+        if n == 0 {
+            return Err(NodeError::Config("Replication factor must be at least 1".to_string()));
+        }
+        for (name, quorum) in [("Write", self.write_quorum), ("Read", self.read_quorum)] {
+            if quorum == 0 || quorum > n {
+                return Err(NodeError::Config(format!(
+                    "{} quorum must be between 1 and the replication factor {}, not {}", name, n, quorum,
+                )));
+            }
+        }
+        if self.write_quorum + self.read_quorum <= n {
+            return Err(NodeError::Config(format!(
+                "Write quorum {} plus read quorum {} must exceed the replication factor {}",
+                self.write_quorum, self.read_quorum, n,
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A record waiting for a replica that was down
+#[derive(Debug, Clone, PartialEq)]
+struct Hint {
+    record: Versioned,
+    // Nodes that took a version of the record in the replica's place
+    stand_ins: BTreeSet<NodeId>,
+}
+
+pub struct QuorumStore {
+    config: QuorumConfig,
+    ring: RwLock<HashRing>,
+    peers: RwLock<HashMap<NodeId, Arc<dyn ReplicaPeer>>>,
+    down: RwLock<HashSet<NodeId>>,
+    // Records waiting for replicas that were down, by replica
+    hints: Mutex<HashMap<NodeId, BTreeMap<Vec<u8>, Hint>>>,
+    // Copies stand-ins still hold of handed-off records, by stand-in
+    stale: Mutex<HashMap<NodeId, BTreeMap<Vec<u8>, Versioned>>>,
+    last_version: Mutex<u64>,
+}
+
+impl QuorumStore {
+    pub fn new(config: QuorumConfig) -> Result<Self> {
+        Self::with_nodes(config, Vec::new())
+    }
+
+    /// A store whose ring starts with `peers`, which hold nothing yet
+    pub fn with_nodes(config: QuorumConfig, peers: Vec<Arc<dyn ReplicaPeer>>) -> Result<Self> {
+        config.validate()?;
+        let mut ring = HashRing::new(config.vnodes);
+        let peers: HashMap<_, _> = peers.into_iter().map(|peer| (peer.id(), peer)).collect();
+        for id in peers.keys() {
+            ring.add_node(id);
+        }
+        Ok(Self {
+            config,
+            ring: RwLock::new(ring),
+            peers: RwLock::new(peers),
+            down: RwLock::new(HashSet::new()),
+            hints: Mutex::new(HashMap::new()),
+            stale: Mutex::new(HashMap::new()),
+            last_version: Mutex::new(0),
+        })
+    }
+
+    pub fn config(&self) -> &QuorumConfig {
+        &self.config
+    }
+
+    /// Nodes holding the replicas of `key`
+    pub fn replicas(&self, key: &[u8]) -> Vec<NodeId> {
+        self.ring.read().preference_list(key, self.config.replication_factor)
+    }
+
+    pub fn is_down(&self, id: &str) -> bool {
+        self.down.read().contains(id)
+    }
+
+    /// Records waiting for a replica to come back
+    pub fn pending_hints(&self) -> usize {
+        self.hints.lock().values().map(BTreeMap::len).sum()
+    }
+
+    /// Add `peer` to the ring and copy it the records it is now a replica
+    /// of. Returns the number of records copied.
+    pub async fn add_node(&self, peer: Arc<dyn ReplicaPeer>) -> Result<usize> {
+        let id = peer.id();
+        self.peers.write().insert(id.clone(), peer);
+        let before = {
+            let mut ring = self.ring.write();
+            let before = ring.clone();
+            if !ring.add_node(&id) {
+                return Ok(0);
+            }
+            before
+        };
+        info!(node = %id, "Node joined the ring");
+        self.rebalance(&before).await
+    }
+
+    /// Take `id` off the ring and copy its records to the replicas that
+    /// take over from it. Returns the number of records copied.
+    pub async fn remove_node(&self, id: &str) -> Result<usize> {
+        let before = {
+            let mut ring = self.ring.write();
+            let before = ring.clone();
+            if !ring.remove_node(id) {
+                return Ok(0);
+            }
+            before
+        };
+        info!(node = %id, "Node left the ring");
+        // A node leaving cleanly is still a source for what it held
+        let copied = self.rebalance(&before).await;
+        self.peers.write().remove(id);
+        self.down.write().remove(id);
+        self.hints.lock().remove(id);
+        self.stale.lock().remove(id);
+        copied
+    }
+
+    pub async fn put(&self, key: &[u8], data: &[u8]) -> Result<()> {
+        self.write(key, Some(data.to_vec())).await
+    }
+
+    /// Replace the record with a tombstone on its replicas
+    pub async fn delete(&self, key: &[u8]) -> Result<()> {
+        self.write(key, None).await
+    }
+
+    async fn write(&self, key: &[u8], data: Option<Vec<u8>>) -> Result<()> {
+        let record = Versioned { version: self.next_version(), data };
+        let (replicas, mut fallbacks) = self.placement(key);
+        let sent = join_all(replicas.iter().map(|id| self.send(id, key, &record))).await;
+
+        let mut acked = 0;
+        for (intended, stored) in replicas.iter().zip(sent) {
+            if stored {
+                acked += 1;
+                continue;
+            }
+            let mut stand_in = None;
+            for fallback in fallbacks.by_ref() {
+                if self.send(&fallback, key, &record).await {
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("storage.quorum.hinted_writes", 1);
+                    debug!(intended = %intended, fallback = %fallback, "Hinted write");
+                    acked += 1;
+                    stand_in = Some(fallback);
+                    break;
+                }
+            }
+            self.hint(intended, key, &record, stand_in.as_deref());
+        }
+
+        if acked < self.config.write_quorum {
+            #[cfg(feature = "metrics")]
+            metrics::counter!("storage.quorum.missed_writes", 1);
+            return Err(NodeError::QuorumNotReached { required: self.config.write_quorum, acked });
+        }
+        Ok(())
+    }
+
+    /// The newest version of `key` among `read_quorum` replicas or more;
+    /// `None` if it was never written or was deleted
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (replicas, fallbacks) = self.placement(key);
+        let keys = [key.to_vec()];
+        let fetched = join_all(replicas.iter().map(|id| self.fetch(id, &keys))).await;
+        let mut answers: Vec<_> = replicas
+            .iter()
+            .zip(fetched)
+            .filter_map(|(id, answer)| answer.map(|record| (id.clone(), record)))
+            .collect();
+        for fallback in fallbacks {
+            if answers.len() >= self.config.read_quorum {
+                break;
+            }
+            if let Some(record) = self.fetch(&fallback, &keys).await {
+                answers.push((fallback, record));
+            }
+        }
+
+        if answers.len() < self.config.read_quorum {
+            #[cfg(feature = "metrics")]
+            metrics::counter!("storage.quorum.missed_reads", 1);
+            return Err(NodeError::QuorumNotReached { required: self.config.read_quorum, acked: answers.len() });
+        }
+
+        let newest = answers
+            .iter()
+            .filter_map(|(_, record)| record.as_ref())
+            .fold(None, |newest: Option<&Versioned>, record| match newest {
+                Some(newest) if !record.supersedes(newest) => Some(newest),
+                _ => Some(record),
+            })
+            .cloned();
+        if let Some(newest) = &newest {
+            for (id, record) in &answers {
+                let stale = record.as_ref().is_none_or(|record| newest.supersedes(record));
+                if stale && replicas.contains(id) && self.send(id, key, newest).await {
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("storage.quorum.read_repairs", 1);
+                    debug!(node = %id, "Read repair");
+                }
+            }
+        }
+        Ok(newest.and_then(|record| record.data))
+    }
+
+    /// Probe the replicas marked down and hand their hinted records to the
+    /// ones that are back, then have the stand-ins drop their copies.
+    /// Returns the number of records handed over.
+    pub async fn handoff(&self) -> usize {
+        let down: Vec<NodeId> = self.down.read().iter().cloned().collect();
+        let mut delivered = 0;
+        for id in down {
+            let Some(peer) = self.peers.read().get(&id).cloned() else {
+                continue;
+            };
+            let records: Vec<_> = self
+                .hints
+                .lock()
+                .get(&id)
+                .map(|hints| hints.iter().map(|(key, hint)| (key.clone(), hint.clone())).collect())
+                .unwrap_or_default();
+
+            // Replicas without hints are probed with an empty fetch
+            let result = match records.is_empty() {
+                true => peer.fetch(&[]).await.map(|_| ()),
+                false => peer.store(records.iter().map(|(key, hint)| (key.clone(), hint.record.clone())).collect()).await,
+            };
+            if let Err(e) = result {
+                debug!(node = %id, "Replica still down: {}", e);
+                continue;
+            }
+
+            self.down.write().remove(&id);
+            let mut hints = self.hints.lock();
+            if let Some(pending) = hints.get_mut(&id) {
+                // Hints written meanwhile stay for the next round
+                for (key, hint) in &records {
+                    if pending.get(key) == Some(hint) {
+                        pending.remove(key);
+                    }
+                }
+                if pending.is_empty() {
+                    hints.remove(&id);
+                }
+            }
+            drop(hints);
+            let mut stale = self.stale.lock();
+            for (key, hint) in &records {
+                for stand_in in &hint.stand_ins {
+                    stale.entry(stand_in.clone()).or_default().insert(key.clone(), hint.record.clone());
+                }
+            }
+            drop(stale);
+            info!(node = %id, "Replica back, {} hinted records handed off", records.len());
+            delivered += records.len();
+        }
+
+        self.discard_stale().await;
+        #[cfg(feature = "metrics")]
+        metrics::counter!("storage.quorum.handoffs", delivered as u64);
+        delivered
+    }
+
+    /// Have stand-ins drop the copies they took for replicas that have
+    /// their records now. Stand-ins that are down keep theirs until a later
+    /// round, and keys a stand-in replicates by now are left alone.
+    async fn discard_stale(&self) {
+        let stale: Vec<_> = self.stale.lock().iter().map(|(id, records)| (id.clone(), records.clone())).collect();
+        for (id, records) in stale {
+            let Some(peer) = self.available(&id) else {
+                continue;
+            };
+            let copies: Vec<_> = records
+                .iter()
+                .filter(|(key, _)| !self.replicas(key).contains(&id))
+                .map(|(key, record)| (key.clone(), record.clone()))
+                .collect();
+            if let Err(e) = peer.discard(copies).await {
+                self.mark_down(&id, &e);
+                continue;
+            }
+            debug!(node = %id, "Stand-in dropped {} handed-off records", records.len());
+
+            let mut stale = self.stale.lock();
+            if let Some(pending) = stale.get_mut(&id) {
+                for (key, record) in &records {
+                    if pending.get(key) == Some(record) {
+                        pending.remove(key);
+                    }
+                }
+                if pending.is_empty() {
+                    stale.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Run `handoff` every `QuorumConfig::handoff_interval`
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.handoff_interval);
+            loop {
+                ticker.tick().await;
+                self.handoff().await;
+            }
+        })
+    }
+
+    /// The replicas of `key`, then the nodes that stand in for them
+    fn placement(&self, key: &[u8]) -> (Vec<NodeId>, std::vec::IntoIter<NodeId>) {
+        let ring = self.ring.read();
+        let mut replicas = ring.preference_list(key, ring.len());
+        let fallbacks = replicas.split_off(self.config.replication_factor.min(replicas.len()));
+        (replicas, fallbacks.into_iter())
+    }
+
+    fn available(&self, id: &str) -> Option<Arc<dyn ReplicaPeer>> {
+        if self.is_down(id) {
+            return None;
+        }
+        self.peers.read().get(id).cloned()
+    }
+
+    fn mark_down(&self, id: &str, error: &NodeError) {
+        if self.down.write().insert(id.to_string()) {
+            warn!(node = %id, "Replica marked down: {}", error);
+        }
+    }
+
+    /// Store `record` on `id`. Returns whether it was taken.
+    async fn send(&self, id: &str, key: &[u8], record: &Versioned) -> bool {
+        let Some(peer) = self.available(id) else {
+            return false;
+        };
+        match peer.store(vec![(key.to_vec(), record.clone())]).await {
+            Ok(()) => true,
+            Err(e) => {
+                self.mark_down(id, &e);
+                false
+            }
+        }
+    }
+
+    /// The version `id` holds of the one key in `keys`; `None` if it did
+    /// not answer
+    async fn fetch(&self, id: &str, keys: &[Vec<u8>]) -> Option<Option<Versioned>> {
+        let peer = self.available(id)?;
+        match peer.fetch(keys).await {
+            Ok(records) => Some(records.into_iter().next().map(|(_, record)| record)),
+            Err(e) => {
+                self.mark_down(id, &e);
+                None
+            }
+        }
+    }
+
+    /// Keep `record` for `id`, noting `stand_in` if one took it instead
+    fn hint(&self, id: &str, key: &[u8], record: &Versioned, stand_in: Option<&str>) {
+        let mut hints = self.hints.lock();
+        let hint = hints
+            .entry(id.to_string())
+            .or_default()
+            .entry(key.to_vec())
+            .or_insert_with(|| Hint { record: record.clone(), stand_ins: BTreeSet::new() });
+        if record.supersedes(&hint.record) {
+            hint.record = record.clone();
+        }
+        hint.stand_ins.extend(stand_in.map(str::to_string));
+    }
+
+    /// Copy each key to the replicas it gained between `before` and the
+    /// current ring, from one of its replicas in `before`
+    async fn rebalance(&self, before: &HashRing) -> Result<usize> {
+        let after = self.ring.read().clone();
+        let n = self.config.replication_factor;
+        let mut moves: HashMap<(NodeId, NodeId), Vec<Vec<u8>>> = HashMap::new();
+        let mut planned = HashSet::new();
+        let mut failed = Vec::new();
+
+        for source in before.nodes() {
+            let Some(peer) = self.available(source) else {
+                continue;
+            };
+            let keys = match peer.leaves(NodePath::root()).await {
+                Ok(leaves) => leaves.into_iter().map(|(key, _)| key),
+                Err(e) => {
+                    self.mark_down(source, &e);
+                    continue;
+                }
+            };
+            for key in keys {
+                // Hinted copies on stand-ins are not a source
+                if !before.preference_list(&key, n).contains(source) || planned.contains(&key) {
+                    continue;
+                }
+                for target in after.gained(before, &key, n) {
+                    moves.entry((source.clone(), target)).or_default().push(key.clone());
+                }
+                planned.insert(key);
+            }
+        }
+
+        let mut copied = 0;
+        for ((source, target), keys) in moves {
+            let (Some(from), Some(to)) = (self.available(&source), self.peers.read().get(&target).cloned()) else {
+                continue;
+            };
+            for keys in keys.chunks(BATCH_SIZE) {
+                let records = match from.fetch(keys).await {
+                    Ok(records) => records,
+                    Err(e) => {
+                        self.mark_down(&source, &e);
+                        failed.push(format!("{} to {}: {}", source, target, e));
+                        break;
+                    }
+                };
+                copied += records.len();
+                if let Err(e) = to.store(records.clone()).await {
+                    // Delivered by the handoff instead
+                    self.mark_down(&target, &e);
+                    for (key, record) in &records {
+                        self.hint(&target, key, record, None);
+                    }
+                }
+            }
+        }
+
+        info!("Rebalanced {} records across {} nodes", copied, after.len());
+        if !failed.is_empty() {
+            return Err(NodeError::Storage(format!("Rebalance incomplete: {}", failed.join("; "))));
+        }
+        Ok(copied)
+    }
+
+    /// Millisecond timestamps, strictly increasing for this coordinator but
+    /// only as consistent across coordinators as their clocks
+    fn next_version(&self) -> u64 {
+        let mut last = self.last_version.lock();
+        let now = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        *last = now.max(*last + 1);
+        *last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use async_trait::async_trait;
+    use crate::storage::distributed::anti_entropy::LocalReplica;
+    use crate::storage::distributed::merkle::{Hash, SubtreeSummary};
+
+    /// A replica that can be taken down
+    struct Node {
+        replica: LocalReplica,
+        down: AtomicBool,
+    }
+
+    impl Node {
+        fn new(id: &str) -> Arc<Self> {
+            Arc::new(Self { replica: LocalReplica::new(id), down: AtomicBool::new(false) })
+        }
+
+        fn set_down(&self, down: bool) {
+            self.down.store(down, Ordering::SeqCst);
+        }
+
+        fn reachable(&self) -> Result<()> {
+            match self.down.load(Ordering::SeqCst) {
+                true => Err(NodeError::Network("connection refused".to_string())),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ReplicaPeer for Node {
+        fn id(&self) -> String {
+            self.replica.id()
+        }
+        async fn summaries(&self, paths: &[NodePath], limit: usize) -> Result<Vec<SubtreeSummary>> {
+            self.reachable()?;
+            self.replica.summaries(paths, limit).await
+        }
+        async fn leaves(&self, path: NodePath) -> Result<Vec<(Vec<u8>, Hash)>> {
+            self.reachable()?;
+            self.replica.leaves(path).await
+        }
+        async fn fetch(&self, keys: &[Vec<u8>]) -> Result<Vec<(Vec<u8>, Versioned)>> {
+            self.reachable()?;
+            self.replica.fetch(keys).await
+        }
+        async fn store(&self, records: Vec<(Vec<u8>, Versioned)>) -> Result<()> {
+            self.reachable()?;
+            self.replica.store(records).await
+        }
+        async fn discard(&self, records: Vec<(Vec<u8>, Versioned)>) -> Result<()> {
+            self.reachable()?;
+            self.replica.discard(records).await
+        }
+    }
+
+    fn cluster(ids: &[&str], config: QuorumConfig) -> (QuorumStore, HashMap<String, Arc<Node>>) {
+        let nodes: HashMap<_, _> = ids.iter().map(|id| (id.to_string(), Node::new(id))).collect();
+        let peers = nodes.values().map(|node| node.clone() as Arc<dyn ReplicaPeer>).collect();
+        (QuorumStore::with_nodes(config, peers).unwrap(), nodes)
+    }
+
+    fn holders(nodes: &HashMap<String, Arc<Node>>, key: &[u8]) -> Vec<String> {
+        let mut holders: Vec<_> = nodes.iter().filter(|(_, node)| node.replica.get(key).is_some()).map(|(id, _)| id.clone()).collect();
+        holders.sort();
+        holders
+    }
+
+    fn sorted(mut ids: Vec<String>) -> Vec<String> {
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_config_validated() {
+        assert!(QuorumConfig::default().validate().is_ok());
+        for (n, w, r) in [(0, 1, 1), (3, 4, 2), (3, 2, 0), (3, 1, 2)] {
+            let config = QuorumConfig { replication_factor: n, write_quorum: w, read_quorum: r, ..QuorumConfig::default() };
+            assert!(matches!(QuorumStore::new(config), Err(NodeError::Config(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_placed_on_replicas() {
+        let (store, nodes) = cluster(&["a", "b", "c", "d", "e"], QuorumConfig::default());
+        for index in 0..50 {
+            let key = format!("record:{}", index).into_bytes();
+            store.put(&key, b"v1").await.unwrap();
+            assert_eq!(holders(&nodes, &key), sorted(store.replicas(&key)));
+            assert_eq!(store.get(&key).await.unwrap().unwrap(), b"v1");
+        }
+
+        store.put(b"record:0", b"v2").await.unwrap();
+        assert_eq!(store.get(b"record:0").await.unwrap().unwrap(), b"v2");
+        store.delete(b"record:0").await.unwrap();
+        assert_eq!(store.get(b"record:0").await.unwrap(), None);
+        assert_eq!(store.get(b"never written").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_repairs_stale_replicas() {
+        let (store, nodes) = cluster(&["a", "b", "c", "d", "e"], QuorumConfig::default());
+        let replicas = store.replicas(b"k");
+        store.put(b"k", b"old").await.unwrap();
+
+        // A newer version that reached one replica only
+        nodes[&replicas[0]].replica.put(b"k", b"new").unwrap();
+        assert_eq!(store.get(b"k").await.unwrap().unwrap(), b"new");
+        for id in &replicas {
+            assert_eq!(nodes[id].replica.get(b"k").unwrap(), b"new");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hinted_handoff() {
+        let (store, nodes) = cluster(&["a", "b", "c", "d", "e"], QuorumConfig::default());
+        let replicas = store.replicas(b"k");
+        nodes[&replicas[0]].set_down(true);
+        nodes[&replicas[1]].set_down(true);
+
+        // One replica and two stand-ins make the quorum
+        store.put(b"k", b"v").await.unwrap();
+        assert!(store.is_down(&replicas[0]) && store.is_down(&replicas[1]));
+        assert_eq!(store.pending_hints(), 2);
+        let stand_ins: Vec<_> = holders(&nodes, b"k").into_iter().filter(|id| !replicas.contains(id)).collect();
+        assert_eq!(stand_ins.len(), 2);
+        assert_eq!(store.get(b"k").await.unwrap().unwrap(), b"v");
+
+        // Nothing to hand over while they are still down
+        assert_eq!(store.handoff().await, 0);
+        nodes[&replicas[0]].set_down(false);
+        nodes[&replicas[1]].set_down(false);
+        assert_eq!(store.handoff().await, 2);
+        assert_eq!(store.pending_hints(), 0);
+        assert!(!store.is_down(&replicas[0]));
+        for id in &replicas {
+            assert_eq!(nodes[id].replica.get(b"k").unwrap(), b"v");
+        }
+
+        // The stand-ins no longer hold their copies
+        assert_eq!(holders(&nodes, b"k"), sorted(replicas.clone()));
+        for id in &stand_ins {
+            assert!(nodes[id].replica.record(b"k").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quorum_not_reached() {
+        let (store, nodes) = cluster(&["a", "b", "c"], QuorumConfig::default());
+        store.put(b"k", b"v").await.unwrap();
+        nodes["a"].set_down(true);
+        nodes["b"].set_down(true);
+
+        let error = store.put(b"k", b"w").await.unwrap_err();
+        assert!(matches!(error, NodeError::QuorumNotReached { required: 2, acked: 1 }), "{}", error);
+        let error = store.get(b"k").await.unwrap_err();
+        assert!(matches!(error, NodeError::QuorumNotReached { required: 2, acked: 1 }), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_on_join_and_leave() {
+        let (store, mut nodes) = cluster(&["a", "b", "c", "d"], QuorumConfig::default());
+        let keys: Vec<_> = (0..300).map(|index| format!("record:{}", index).into_bytes()).collect();
+        for key in &keys {
+            store.put(key, key).await.unwrap();
+        }
+
+        // The new node receives exactly the keys it now replicates
+        let node = Node::new("e");
+        nodes.insert("e".to_string(), node.clone());
+        let copied = store.add_node(node.clone()).await.unwrap();
+        let gained = keys.iter().filter(|key| store.replicas(key).contains(&"e".to_string())).count();
+        assert_eq!(copied, gained);
+        assert_eq!(node.replica.tree().len(), gained);
+
+        // Every replica under the new ring holds its keys
+        store.remove_node("b").await.unwrap();
+        nodes["b"].set_down(true);
+        for key in &keys {
+            for id in store.replicas(key) {
+                assert_eq!(nodes[&id].replica.get(key).unwrap(), *key, "{} lacks a key", id);
+            }
+            assert_eq!(store.get(key).await.unwrap().unwrap(), *key);
+        }
+        assert!(!store.replicas(&keys[0]).contains(&"b".to_string()));
+    }
+}
@@ -1,21 +1,24 @@
-use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{info, error};
 use crate::utils::error::Result;
 use crate::network::p2p::P2PNetwork;
+use super::anti_entropy::{AntiEntropy, AntiEntropyConfig, LocalReplica};
 
 pub struct RecoveryManager {
     network: Arc<P2PNetwork>,
-    data_store: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    anti_entropy: Arc<AntiEntropy>,
 }
 
 impl RecoveryManager {
     pub fn new(network: Arc<P2PNetwork>) -> Self {
-        Self {
-            network,
-            data_store: Arc::new(RwLock::new(HashMap::new())),
-        }
+        let replica = Arc::new(LocalReplica::new("local"));
+        Self::with_anti_entropy(network, Arc::new(AntiEntropy::new(replica, AntiEntropyConfig::default())))
+    }
+
+    /// Recovery over the replica and peers of `anti_entropy`, usually those
+    /// of the `ReplicationManager`
+    pub fn with_anti_entropy(network: Arc<P2PNetwork>, anti_entropy: Arc<AntiEntropy>) -> Self {
+        Self { network, anti_entropy }
     }
 
     pub async fn recover_data(&self, key: String) -> Result<Option<Vec<u8>>> {
@@ -23,18 +26,19 @@ impl RecoveryManager {
         // Example recovery logic: attempt to fetch data from peers
         let data = self.network.fetch_data_from_peers(&key).await?;
         if let Some(data) = data {
-            let mut store = self.data_store.write().await;
-            store.insert(key.clone(), data.clone());
+            self.anti_entropy.replica().put(key.as_bytes(), &data)?;
             Ok(Some(data))
         } else {
             Ok(None)
         }
     }
 
+    /// Check local records against their Merkle root, then repair
+    /// whatever diverged from the peers
     pub async fn verify_integrity(&self) -> Result<()> {
         info!("Verifying data integrity");
-        // Placeholder for integrity verification logic
-        // This would include checking data hashes and repairing corrupted data
+        self.anti_entropy.replica().verify().inspect_err(|e| error!("{}", e))?;
+        self.anti_entropy.run_round().await.inspect_err(|e| error!("{}", e))?;
         Ok(())
     }
 }
@@ -52,7 +56,7 @@ mod tests {
         let data = manager.recover_data("key1".to_string()).await.unwrap();
         assert!(data.is_none());
 
-        // Test integrity verification (this is a placeholder, actual test would require data setup)
+        // Empty replica, no peers
         assert!(manager.verify_integrity().await.is_ok());
     }
 }
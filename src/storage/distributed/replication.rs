@@ -1,44 +1,87 @@
-use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use tracing::{info, error};
 use crate::utils::error::Result;
-use crate::network::p2p::P2PNetwork;
+use super::anti_entropy::{AntiEntropy, AntiEntropyConfig, LocalReplica, ReplicaPeer};
+use super::quorum::{QuorumConfig, QuorumStore};
 
 pub struct ReplicationManager {
-    network: Arc<P2PNetwork>,
-    data_store: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    quorum: Arc<QuorumStore>,
+    anti_entropy: Arc<AntiEntropy>,
 }
 
 impl ReplicationManager {
-    pub fn new(network: Arc<P2PNetwork>) -> Self {
-        Self {
-            network,
-            data_store: Arc::new(RwLock::new(HashMap::new())),
-        }
+    /// A single local replica until nodes join with `add_node`
+    pub fn new() -> Self {
+        let replica = Arc::new(LocalReplica::new("local"));
+        let config = QuorumConfig { replication_factor: 1, write_quorum: 1, read_quorum: 1, ..QuorumConfig::default() };
+        let quorum = QuorumStore::with_nodes(config, vec![replica.clone()]).expect("single replica quorum is valid");
+        Self::with_quorum(Arc::new(quorum), Arc::new(AntiEntropy::new(replica, AntiEntropyConfig::default())))
+    }
+
+    /// Replication through `quorum`, whose ring should include the replica
+    /// of `anti_entropy`
+    pub fn with_quorum(quorum: Arc<QuorumStore>, anti_entropy: Arc<AntiEntropy>) -> Self {
+        Self { quorum, anti_entropy }
+    }
+
+    pub fn quorum(&self) -> &Arc<QuorumStore> {
+        &self.quorum
+    }
+
+    pub fn anti_entropy(&self) -> &Arc<AntiEntropy> {
+        &self.anti_entropy
+    }
+
+    /// Put `node` on the placement ring, moving it the keys it now
+    /// replicates
+    pub async fn add_node(&self, node: Arc<dyn ReplicaPeer>) -> Result<usize> {
+        self.quorum.add_node(node).await
+    }
+
+    pub async fn remove_node(&self, id: &str) -> Result<usize> {
+        self.quorum.remove_node(id).await
+    }
+
+    /// Replica to compare with in consistency checks
+    pub fn add_peer(&self, peer: Arc<dyn ReplicaPeer>) {
+        self.anti_entropy.add_peer(peer);
     }
 
     pub async fn replicate_data(&self, key: String, data: Vec<u8>) -> Result<()> {
         info!("Replicating data for key: {}", key);
-        let mut store = self.data_store.write().await;
-        store.insert(key.clone(), data.clone());
-
-        // Example replication logic: broadcast data to peers
-        self.network.broadcast(&data).await?;
-        Ok(())
+        self.quorum.put(key.as_bytes(), &data).await
     }
 
     pub async fn get_data(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        let store = self.data_store.read().await;
-        Ok(store.get(key).cloned())
+        self.quorum.get(key.as_bytes()).await
     }
 
+    /// Run an anti-entropy round with every peer, repairing what diverged
     pub async fn check_consistency(&self) -> Result<()> {
         info!("Checking data consistency");
-        // Placeholder for consistency check logic
-        // This would include verifying data integrity across replicas
+        let reports = self.anti_entropy.run_round().await.inspect_err(|e| error!("{}", e))?;
+        let repaired: usize = reports.iter().map(|report| report.keys_pulled + report.keys_pushed).sum();
+        info!("Consistency check repaired {} keys across {} peers", repaired, reports.len());
         Ok(())
     }
+
+    /// Run consistency checks every `AntiEntropyConfig::interval`
+    pub fn start_anti_entropy(&self) -> JoinHandle<()> {
+        self.anti_entropy.clone().start()
+    }
+
+    /// Hand hinted writes to replicas as they come back, every
+    /// `QuorumConfig::handoff_interval`
+    pub fn start_handoff(&self) -> JoinHandle<()> {
+        self.quorum.clone().start()
+    }
+}
+
+impl Default for ReplicationManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -47,15 +90,22 @@ mod tests {
 
     #[tokio::test]
     async fn test_replication_manager() {
-        let network = Arc::new(P2PNetwork::new());
-        let manager = ReplicationManager::new(network);
+        let manager = ReplicationManager::new();
 
         // Test data replication
         manager.replicate_data("key1".to_string(), vec![1, 2, 3]).await.unwrap();
         let data = manager.get_data("key1").await.unwrap();
         assert_eq!(data, Some(vec![1, 2, 3]));
+        assert_eq!(manager.anti_entropy().replica().get(b"key1"), Some(vec![1, 2, 3]));
+
+        // A second node takes over the keys that hash to it
+        let other = Arc::new(LocalReplica::new("other"));
+        manager.add_node(other.clone()).await.unwrap();
+        let moved = manager.quorum().replicas(b"key1") == ["other"];
+        assert_eq!(other.get(b"key1").is_some(), moved);
+        assert_eq!(manager.get_data("key1").await.unwrap(), Some(vec![1, 2, 3]));
 
-        // Test consistency check (this is a placeholder, actual test would require a network setup)
+        // No peers yet: nothing to repair
         assert!(manager.check_consistency().await.is_ok());
     }
 }
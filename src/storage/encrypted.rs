@@ -1,24 +1,111 @@
+mod backup;
+mod compression;
+mod migration;
+mod quota;
+mod rotation;
+
 use std::path::Path;
-use rocksdb::{DB, Options, WriteBatch};
-use serde::{Serialize, de::DeserializeOwned};
-use uuid::Uuid;
+use std::sync::Arc;
+use parking_lot::{Mutex, RwLock};
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, DB, Direction, IteratorMode, Options, Snapshot, WriteBatch};
+use serde::{Serialize, Deserialize};
 use tracing::{info, warn, error};
+use uuid::Uuid;
 
 use crate::{
     utils::error::{Result, NodeError},
-    core::{
-        identity::types::Identity,
-        crypto::key_manager::KeyManager,
+    core::crypto::{
+        audit::{AuditSystem, AUDIT_CF},
+        dilithium::{Dilithium, SecretKey as DilithiumSecretKey},
+        key_manager::{KdfParams, KeyManager},
+        keystore,
+        poisoning::MemoryPoisonDetector,
+        quantum::SecurityLevel,
     },
 };
+use super::{
+    kv::{BatchOp, Column, KvBatch, KvIter, KvSnapshot, KvStore, KvStoreExt},
+    record::Record,
+};
+
+pub use backup::{
+    list_backups, open_targets, prune_backups, restore_backup, restore_key_files, verify_backup,
+    BackupFile, BackupListing, BackupManifest, BackupPolicy, BackupScheduler, BackupSummary,
+    BackupTarget, LocalTarget, PruneSummary, TargetUpload,
+};
+#[cfg(feature = "backup")]
+pub use backup::{S3Target, SftpTarget};
+pub use compression::{Codec, ColumnCodecs};
+pub use migration::AadMigration;
+pub use quota::{QuotaUsage, SizeQuota};
+pub use rotation::{KeyRotationScheduler, ReencryptJob, ReencryptProgress, RotationPolicy};
+
+/// Dilithium key signing audit checkpoints
+pub(crate) const AUDIT_SIGNING_KEY: &str = "audit:signing-key";
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct AuditSigningKey {
+    pub secret: Vec<u8>,
+}
+
+impl Record for AuditSigningKey {
+    const COLUMN: Column = Column::Default;
+    const VERSION: u8 = 1;
+}
 
+/// Layout of stored records. It is part of every value's associated data,
+/// so bumping it makes old values unreadable until they are migrated.
+pub const RECORD_SCHEMA_VERSION: u16 = 1;
+
+/// `KvStore` on RocksDB. Values are encrypted under the keyring's active
+/// data key; keys are stored in the clear so they keep their order.
+///
+/// Each `Column` is a RocksDB column family. Each value is bound to its
+/// column, key, the record schema version and (through the ciphertext
+/// header) the data key version, so a value copied under another key fails
+/// to decrypt instead of being read as that record.
+///
+/// Puts are held to the `max_size_gb` quota; see `quota`.
 pub struct EncryptedStore {
-    db: DB,
+    // Shared with the audit log, which lives in its own column family
+    db: Arc<DB>,
     key_manager: KeyManager,
+    // Serialises `put_if_absent`
+    inserts: Mutex<()>,
+    // Writers share it; a re-encryption batch takes it exclusively so no
+    // write lands between reading a record and rewriting it
+    writes: RwLock<()>,
+    quota: SizeQuota,
 }
 
 impl EncryptedStore {
     pub async fn new(config: &crate::utils::config::StorageConfig) -> Result<Self> {
+        let key_manager = Self::open_keyring(config)?;
+        Self::with_key_manager(config, key_manager).await
+    }
+
+    /// Unlock the persistent keyring kept alongside the database, without
+    /// opening the database itself
+    pub fn open_keyring(config: &crate::utils::config::StorageConfig) -> Result<KeyManager> {
+        let path = Path::new(&config.path);
+        Ok(match keystore::from_config(config)? {
+            None => KeyManager::open(path, &config.encryption_key, &KdfParams::from(config))?,
+            // Keyring still protected by the passphrase: move its KEK over
+            Some(store) if KeyManager::uses_passphrase(path)? => {
+                let mut key_manager = KeyManager::open(path, &config.encryption_key, &KdfParams::from(config))?;
+                key_manager.move_kek_to_store(store)?;
+                key_manager
+            }
+            Some(store) => KeyManager::open_with_store(path, store)?,
+        })
+    }
+
+    /// Open the store with a keyring that is already unlocked, e.g. one
+    /// unsealed with key shares
+    pub async fn with_key_manager(
+        config: &crate::utils::config::StorageConfig,
+        key_manager: KeyManager,
+    ) -> Result<Self> {
         let path = Path::new(&config.path);
         
         // Create directory if it doesn't exist
@@ -27,165 +114,250 @@ impl EncryptedStore {
                 .map_err(|e| NodeError::Storage(format!("Failed to create storage directory: {}", e)))?;
         }
 
-        // Configure RocksDB options
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.set_max_total_wal_size(1024 * 1024 * 1024); // 1GB WAL
-        opts.set_keep_log_file_num(10);
-        opts.set_max_open_files(1000);
-        opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
-        
-        // Open database
-        let db = DB::open(&opts, path)
-            .map_err(|e| NodeError::Storage(format!("Failed to open database: {}", e)))?;
-
-        // Initialize key manager
-        let key_manager = KeyManager::new(&config.encryption_key)?;
+        let db = open_db(path, &ColumnCodecs::from_config(config)?)?;
 
-        Ok(Self {
-            db,
+        let store = Self {
+            db: Arc::new(db),
             key_manager,
-        })
-    }
+            inserts: Mutex::new(()),
+            writes: RwLock::new(()),
+            quota: SizeQuota::from_config(config),
+        };
+        store.restore_key_uses()?;
 
-    pub async fn store_identity(&self, identity: &Identity) -> Result<()> {
-        let key = format!("identity:{}", identity.id);
-        self.store(&key, identity).await
+        Ok(store)
     }
 
-    pub async fn get_identity(&self, id: &Uuid) -> Result<Option<Identity>> {
-        let key = format!("identity:{}", id);
-        self.get(&key).await
-    }
+    /// Audit log kept in this database. Its signing key is stored here
+    /// encrypted, so checkpoints keep verifying across restarts.
+    pub async fn open_audit_system(
+        &self,
+        retention_days: i64,
+        checkpoint_interval: u64,
+        security_level: SecurityLevel,
+    ) -> Result<AuditSystem> {
+        let signing_key = match self.get_record::<AuditSigningKey>(AUDIT_SIGNING_KEY.as_bytes())? {
+            Some(stored) => DilithiumSecretKey::from_bytes(&stored.secret)?,
+            None => {
+                let (_, signing_key) = Dilithium::keygen()?;
+                self.put_record(AUDIT_SIGNING_KEY.as_bytes(), &AuditSigningKey { secret: signing_key.to_bytes() })?;
+                signing_key
+            }
+        };
 
-    pub async fn delete_identity(&self, id: &Uuid) -> Result<()> {
-        let key = format!("identity:{}", id);
-        self.delete(&key).await
+        AuditSystem::open(self.db.clone(), signing_key, retention_days, checkpoint_interval, security_level)
+            .map_err(|e| NodeError::Storage(format!("Failed to open audit log: {}", e)))
     }
 
-    pub async fn store<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
-        // Serialize value
-        let serialized = serde_json::to_vec(value)
-            .map_err(|e| NodeError::Storage(format!("Serialization failed: {}", e)))?;
-
-        // Encrypt serialized data
-        let encrypted = self.key_manager.encrypt(&serialized)?;
+    /// Key for `purpose` derived from the keyring's root secret. It stays
+    /// the same across restarts and data key rotations.
+    pub fn derive_key(&self, purpose: &str) -> Result<Vec<u8>> {
+        self.key_manager.derive_key(purpose)
+    }
 
-        // Store encrypted data
-        self.db
-            .put(key.as_bytes(), encrypted)
-            .map_err(|e| NodeError::Storage(format!("Database write failed: {}", e)))?;
+    /// Have `detector` watch the keyring's keys, suspending `session` on an
+    /// alert
+    pub fn watch_memory(&self, detector: Arc<MemoryPoisonDetector>, session: Uuid) -> Result<()> {
+        self.key_manager.watch_memory(detector, session)
+    }
 
+    pub async fn close(&self) -> Result<()> {
+        // RocksDB will be closed when dropped
         Ok(())
     }
 
-    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
-        // Retrieve encrypted data
-        let encrypted = match self.db.get(key.as_bytes())
-            .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))? {
-            Some(data) => data,
-            None => return Ok(None),
-        };
-
-        // Decrypt data
-        let decrypted = self.key_manager.decrypt(&encrypted)?;
+    /// Live size of the database against its quota, measured now
+    pub fn usage(&self) -> Result<QuotaUsage> {
+        self.quota.usage(|| quota::live_size(&self.db))
+    }
 
-        // Deserialize decrypted data
-        let value = serde_json::from_slice(&decrypted)
-            .map_err(|e| NodeError::Storage(format!("Deserialization failed: {}", e)))?;
+    fn cf(&self, column: Column) -> Result<&ColumnFamily> {
+        column_family(&self.db, column)
+    }
 
-        Ok(Some(value))
+    /// Refuse a write of `incoming` bytes that would exceed the quota
+    fn admit(&self, incoming: usize) -> Result<()> {
+        self.quota.admit(incoming as u64, || quota::live_size(&self.db))
     }
+}
 
-    pub async fn delete(&self, key: &str) -> Result<()> {
+impl KvStore for EncryptedStore {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
         self.db
-            .delete(key.as_bytes())
-            .map_err(|e| NodeError::Storage(format!("Database delete failed: {}", e)))?;
-
-        Ok(())
+            .get_cf(self.cf(column)?, key)
+            .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?
+            .map(|encrypted| decrypt_record(&self.key_manager, column, key, &encrypted))
+            .transpose()
     }
 
-    pub async fn batch_operation<F>(&self, operation: F) -> Result<()>
-    where
-        F: FnOnce(&mut WriteBatch) -> Result<()>,
-    {
-        let mut batch = WriteBatch::default();
-        operation(&mut batch)?;
-
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()> {
+        let _writes = self.writes.read();
+        let encrypted = encrypt_record(&self.key_manager, column, key, value)?;
+        self.admit(key.len() + encrypted.len())?;
         self.db
-            .write(batch)
-            .map_err(|e| NodeError::Storage(format!("Batch operation failed: {}", e)))?;
+            .put_cf(self.cf(column)?, key, encrypted)
+            .map_err(|e| NodeError::Storage(format!("Database write failed: {}", e)))
+    }
 
-        Ok(())
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()> {
+        let _writes = self.writes.read();
+        self.db
+            .delete_cf(self.cf(column)?, key)
+            .map_err(|e| NodeError::Storage(format!("Database delete failed: {}", e)))
     }
 
-    pub async fn backup(&self, backup_path: &Path) -> Result<()> {
-        // Ensure backup directory exists
-        if !backup_path.exists() {
-            std::fs::create_dir_all(backup_path)
-                .map_err(|e| NodeError::Storage(format!("Failed to create backup directory: {}", e)))?;
+    fn put_if_absent(&self, column: Column, key: &[u8], value: &[u8]) -> Result<bool> {
+        let _guard = self.inserts.lock();
+        let exists = self.db
+            .get_pinned_cf(self.cf(column)?, key)
+            .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?
+            .is_some();
+        if exists {
+            return Ok(false);
         }
 
-        // Create backup
-        let backup_engine = rocksdb::backup::BackupEngine::open(
-            &rocksdb::backup::BackupEngineOptions::default(),
-            backup_path,
-        ).map_err(|e| NodeError::Storage(format!("Failed to create backup engine: {}", e)))?;
+        self.put(column, key, value)?;
+        Ok(true)
+    }
 
-        backup_engine
-            .create_new_backup(&self.db)
-            .map_err(|e| NodeError::Storage(format!("Backup creation failed: {}", e)))?;
+    fn write(&self, batch: KvBatch) -> Result<()> {
+        let _writes = self.writes.read();
+        let mut encrypted = WriteBatch::default();
+        let mut incoming = 0;
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Put(column, key, value) => {
+                    let value = encrypt_record(&self.key_manager, column, &key, &value)?;
+                    incoming += key.len() + value.len();
+                    encrypted.put_cf(self.cf(column)?, key, value);
+                }
+                BatchOp::Delete(column, key) => encrypted.delete_cf(self.cf(column)?, key),
+            }
+        }
+        if incoming > 0 {
+            self.admit(incoming)?;
+        }
 
-        info!("Created backup at {:?}", backup_path);
-        Ok(())
+        self.db
+            .write(encrypted)
+            .map_err(|e| NodeError::Storage(format!("Batch operation failed: {}", e)))
     }
 
-    pub async fn restore(&self, backup_path: &Path) -> Result<()> {
-        // Verify backup exists
-        if !backup_path.exists() {
-            return Err(NodeError::Storage("Backup path does not exist".into()));
-        }
+    fn iter_from(&self, column: Column, start: &[u8]) -> Result<KvIter<'_>> {
+        let iter = self.db.iterator_cf(self.cf(column)?, IteratorMode::From(start, Direction::Forward));
+        Ok(decrypting(&self.key_manager, column, iter))
+    }
 
-        // Open backup engine
-        let backup_engine = rocksdb::backup::BackupEngine::open(
-            &rocksdb::backup::BackupEngineOptions::default(),
-            backup_path,
-        ).map_err(|e| NodeError::Storage(format!("Failed to open backup engine: {}", e)))?;
+    fn snapshot(&self) -> Result<Box<dyn KvSnapshot + '_>> {
+        Ok(Box::new(EncryptedSnapshot {
+            snapshot: self.db.snapshot(),
+            db: &self.db,
+            key_manager: &self.key_manager,
+        }))
+    }
+}
 
-        // Close current database
-        drop(&self.db);
+struct EncryptedSnapshot<'a> {
+    snapshot: Snapshot<'a>,
+    db: &'a DB,
+    key_manager: &'a KeyManager,
+}
 
-        // Restore from backup
-        backup_engine
-            .restore_latest_backup(&self.db.path(), &self.db.path(), &rocksdb::backup::RestoreOptions::default())
-            .map_err(|e| NodeError::Storage(format!("Restore failed: {}", e)))?;
+impl KvSnapshot for EncryptedSnapshot<'_> {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.snapshot
+            .get_cf(column_family(self.db, column)?, key)
+            .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?
+            .map(|encrypted| decrypt_record(self.key_manager, column, key, &encrypted))
+            .transpose()
+    }
 
-        info!("Restored from backup at {:?}", backup_path);
-        Ok(())
+    fn iter_from(&self, column: Column, start: &[u8]) -> Result<KvIter<'_>> {
+        let mode = IteratorMode::From(start, Direction::Forward);
+        let iter = self.snapshot.iterator_cf(column_family(self.db, column)?, mode);
+        Ok(decrypting(self.key_manager, column, iter))
     }
+}
 
-    pub async fn close(&self) -> Result<()> {
-        // RocksDB will be closed when dropped
-        Ok(())
+/// Open the RocksDB database at `path` with a column family per record
+/// kind, each compressed with its codec from `codecs`
+fn open_db(path: &Path, codecs: &ColumnCodecs) -> Result<DB> {
+    // Configure RocksDB options
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_max_total_wal_size(1024 * 1024 * 1024); // 1GB WAL
+    opts.set_keep_log_file_num(10);
+    opts.set_max_open_files(1000);
+
+    let column_families = compression::column_families().map(|name| {
+        let mut cf_opts = Options::default();
+        cf_opts.set_compression_type(codecs.codec(name).compression_type());
+        ColumnFamilyDescriptor::new(name, cf_opts)
+    });
+    DB::open_cf_descriptors(&opts, path, column_families)
+        .map_err(|e| NodeError::Storage(format!("Failed to open database: {}", e)))
+}
+
+fn column_family(db: &DB, column: Column) -> Result<&ColumnFamily> {
+    db.cf_handle(column.name())
+        .ok_or_else(|| NodeError::Storage(format!("Missing column family {}", column.name())))
+}
+
+fn decrypting<'a, I>(key_manager: &'a KeyManager, column: Column, iter: I) -> KvIter<'a>
+where
+    I: Iterator<Item = std::result::Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>> + 'a,
+{
+    Box::new(iter.map(move |item| {
+        let (key, value) = item
+            .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?;
+        let value = decrypt_record(key_manager, column, &key, &value)?;
+        Ok((key.into_vec(), value))
+    }))
+}
+
+/// Associated data of the value stored under `key` in `column`: a label
+/// naming the column, the schema version, then the key. No label followed by
+/// a schema version is a prefix of another label, so the encoding is
+/// unambiguous. The default column keeps the label it had before there were
+/// column families.
+fn record_aad(column: Column, key: &[u8]) -> Vec<u8> {
+    let mut aad = b"freeghost-record".to_vec();
+    if column != Column::Default {
+        aad.push(b':');
+        aad.extend_from_slice(column.name().as_bytes());
     }
+    aad.extend_from_slice(&RECORD_SCHEMA_VERSION.to_be_bytes());
+    aad.extend_from_slice(key);
+    aad
+}
+
+fn encrypt_record(key_manager: &KeyManager, column: Column, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    key_manager.encrypt(value, &record_aad(column, key))
+}
+
+fn decrypt_record(key_manager: &KeyManager, column: Column, key: &[u8], encrypted: &[u8]) -> Result<Vec<u8>> {
+    key_manager
+        .decrypt(encrypted, &record_aad(column, key))
+        .map_err(|e| NodeError::Storage(format!(
+            "Record {} failed to decrypt: {}",
+            String::from_utf8_lossy(key),
+            e,
+        )))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
     use tempfile::tempdir;
-    use crate::core::identity::types::BiometricTemplate;
+    use crate::core::identity::types::{BiometricTemplate, Identity};
+    use crate::core::crypto::audit::AuditEventType;
 
     #[tokio::test]
     async fn test_identity_storage() {
         let temp_dir = tempdir().unwrap();
-        let config = crate::utils::config::StorageConfig {
-            path: temp_dir.path().to_str().unwrap().to_string(),
-            encryption_key: "test_key".to_string(),
-            max_size_gb: 1,
-            backup_interval: 3600,
-            compression_enabled: true,
-        };
+        let config = crate::utils::config::StorageConfig::for_test(temp_dir.path());
 
         let store = EncryptedStore::new(&config).await.unwrap();
         
@@ -199,45 +371,130 @@ mod tests {
         let id = identity.id;
 
         // Store identity
-        store.store_identity(&identity).await.unwrap();
+        store.store_identity(&identity).unwrap();
 
         // Retrieve identity
-        let retrieved = store.get_identity(&id).await.unwrap().unwrap();
+        let retrieved = store.get_identity(&id).unwrap().unwrap();
         assert_eq!(retrieved.id, id);
 
         // Delete identity
-        store.delete_identity(&id).await.unwrap();
-        assert!(store.get_identity(&id).await.unwrap().is_none());
+        store.delete_identity(&id).unwrap();
+        assert!(store.get_identity(&id).unwrap().is_none());
     }
 
     #[tokio::test]
-    async fn test_backup_restore() {
+    async fn test_audit_log_persists() {
         let temp_dir = tempdir().unwrap();
-        let backup_dir = tempdir().unwrap();
-        let config = crate::utils::config::StorageConfig {
-            path: temp_dir.path().to_str().unwrap().to_string(),
-            encryption_key: "test_key".to_string(),
-            max_size_gb: 1,
-            backup_interval: 3600,
-            compression_enabled: true,
-        };
+        let config = crate::utils::config::StorageConfig::for_test(temp_dir.path());
+
+        {
+            let store = EncryptedStore::new(&config).await.unwrap();
+            let audit = store.open_audit_system(30, 2, SecurityLevel::Standard).await.unwrap();
+            audit.record_event(AuditEventType::KeyRotation, None, None).await.unwrap();
+        }
 
+        // Same signing key after a restart, so old checkpoints still verify
         let store = EncryptedStore::new(&config).await.unwrap();
-        
-        // Create and store test data
-        store.store("test_key", &"test_value").await.unwrap();
+        let audit = store.open_audit_system(30, 2, SecurityLevel::Standard).await.unwrap();
+        let verification = audit.verify_chain().await.unwrap();
+        assert_eq!(verification.records, 4);
+        assert!(verification.checkpoints >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_kv_store() {
+        let temp_dir = tempdir().unwrap();
+        let config = crate::utils::config::StorageConfig::for_test(temp_dir.path());
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        store.put(Column::Default, b"k:1", b"one").unwrap();
+
+        // Values never reach the database in the clear
+        let raw = store.db.get(b"k:1").unwrap().unwrap();
+        assert!(!raw.windows(3).any(|w| w == b"one"));
+
+        let mut batch = KvBatch::new();
+        batch
+            .put(Column::Default, b"k:2".to_vec(), b"two".to_vec())
+            .put(Column::Tokens, b"k:3".to_vec(), b"three".to_vec())
+            .delete(Column::Default, b"k:1".to_vec());
+        let snapshot = store.snapshot().unwrap();
+        store.write(batch).unwrap();
+
+        let entries: Vec<_> = store.scan_prefix(Column::Default, b"k:").unwrap().map(|item| item.unwrap()).collect();
+        assert_eq!(entries, vec![(b"k:2".to_vec(), b"two".to_vec())]);
+        assert_eq!(store.get(Column::Tokens, b"k:3").unwrap(), Some(b"three".to_vec()));
+
+        // The snapshot still sees the state before the batch
+        assert_eq!(snapshot.get(Column::Default, b"k:1").unwrap(), Some(b"one".to_vec()));
+        assert_eq!(snapshot.get(Column::Default, b"k:2").unwrap(), None);
+        assert_eq!(snapshot.get(Column::Tokens, b"k:3").unwrap(), None);
+
+        // A value copied under another key or column does not decrypt there
+        store.put(Column::Identities, b"a", b"alice").unwrap();
+        store.put(Column::Identities, b"b", b"bob").unwrap();
+        let identities = store.cf(Column::Identities).unwrap();
+        let raw = store.db.get_cf(identities, b"a").unwrap().unwrap();
+        store.db.put_cf(identities, b"b", &raw).unwrap();
+        assert!(matches!(store.get(Column::Identities, b"b"), Err(NodeError::Storage(_))));
+        assert!(store.scan_prefix(Column::Identities, b"").unwrap().any(|item| item.is_err()));
+        assert_eq!(store.get(Column::Identities, b"a").unwrap(), Some(b"alice".to_vec()));
+
+        store.db.put_cf(store.cf(Column::Indexes).unwrap(), b"a", &raw).unwrap();
+        assert!(store.get(Column::Indexes, b"a").is_err());
+    }
 
-        // Create backup
-        store.backup(backup_dir.path()).await.unwrap();
+    #[tokio::test]
+    async fn test_spent_token_set() {
+        let temp_dir = tempdir().unwrap();
+        let config = crate::utils::config::StorageConfig::for_test(temp_dir.path());
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+
+        // First redemption succeeds, the second is a double spend
+        assert!(store.mark_token_spent(7, &[1u8; 32]).unwrap());
+        assert!(!store.mark_token_spent(7, &[1u8; 32]).unwrap());
+        assert!(store.is_token_spent(7, &[1u8; 32]).unwrap());
 
-        // Modify data
-        store.store("test_key", &"modified_value").await.unwrap();
+        // The same id under another epoch is a different token
+        assert!(store.mark_token_spent(8, &[1u8; 32]).unwrap());
 
-        // Restore from backup
-        store.restore(backup_dir.path()).await.unwrap();
+        // Pruning only removes older epochs
+        assert_eq!(store.prune_spent_tokens(8).unwrap(), 1);
+        assert!(!store.is_token_spent(7, &[1u8; 32]).unwrap());
+        assert!(store.is_token_spent(8, &[1u8; 32]).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_quota_enforced() {
+        let temp_dir = tempdir().unwrap();
+        let config = crate::utils::config::StorageConfig {
+            quota_watermarks: vec![80],
+            compression_codec: "zstd".to_string(),
+            column_compression: HashMap::from([(AUDIT_CF.to_string(), "none".to_string())]),
+            ..crate::utils::config::StorageConfig::for_test(temp_dir.path())
+        };
 
-        // Verify restored data
-        let value: String = store.get("test_key").await.unwrap().unwrap();
-        assert_eq!(value, "test_value");
+        let mut store = EncryptedStore::new(&config).await.unwrap();
+        assert_eq!(store.usage().unwrap().limit, 1024 * 1024 * 1024);
+
+        // Room for 2 KiB more than the store holds now
+        store.quota = SizeQuota::new(store.usage().unwrap().used + 2048, &[80]);
+        store.put(Column::Identities, b"a", &[0u8; 512]).unwrap();
+
+        let big = vec![0u8; 4096];
+        let refused = store.put(Column::Identities, b"b", &big);
+        assert!(matches!(refused, Err(NodeError::QuotaExceeded { requested, .. }) if requested > 4096));
+        let mut batch = KvBatch::new();
+        batch.put(Column::Tokens, b"t".to_vec(), big.clone()).delete(Column::Identities, b"a".to_vec());
+        assert!(matches!(store.write(batch), Err(NodeError::QuotaExceeded { .. })));
+        assert_eq!(store.get(Column::Identities, b"b").unwrap(), None);
+        assert!(store.get(Column::Identities, b"a").unwrap().is_some());
+
+        // Deletes always go through
+        store.delete(Column::Identities, b"a").unwrap();
+        store.put(Column::Identities, b"b", &[0u8; 1024]).unwrap();
+        let usage = store.usage().unwrap();
+        assert!(usage.used <= usage.limit);
     }
 }
@@ -0,0 +1,1110 @@
+//! Encrypted, incremental backups
+//!
+//! A backup is a RocksDB checkpoint of the whole database, every column
+//! family and the audit log included. Its files are cut into chunks of up to
+//! `CHUNK_SIZE` bytes, each named by a MAC of its contents and encrypted
+//! under a key derived from the keyring root for backups only. A chunk that
+//! a target already holds is not uploaded again, and SST files never change
+//! once written, so a backup only adds the chunks of files created since the
+//! previous one.
+//!
+//! Each backup has a manifest listing its files with their SHA-256 checksums
+//! and chunks. The manifest is signed with HMAC-SHA256 under another key
+//! derived from the root, so it cannot be edited or forged without the
+//! keyring. The root survives key rotation, and rotation keeps old data keys,
+//! so every backup stays readable with the current keyring.
+//!
+//! Backups go to one or more `BackupTarget`s: the local `backup_path` and
+//! the URLs in `backup_targets`. One checkpoint is uploaded to every target
+//! under the same backup id, so listings merge across targets and a restore
+//! can use whichever target still has the backup. Objects on a target:
+//!
+//! - `manifests/<id>.json`, one per backup; ids sort in the order taken
+//! - `chunks/<chunk id>`, shared by all backups
+//! - `keys/`, the keyring and key file as of the newest backup. They are
+//!   wrapped under the KEK already and only used to restore onto a storage
+//!   directory that lost them.
+//!
+//! Restoring extracts a backup into a fresh directory next to the storage
+//! directory, checks every file against the manifest and every record
+//! against the keyring, and only then swaps it in by renaming. The replaced
+//! directory is kept beside it. `verify_backup` runs the same drill into a
+//! temporary directory and throws the result away.
+//!
+//! Only one process may write to a target at a time: pruning removes chunks
+//! no manifest refers to yet.
+
+mod local;
+#[cfg(feature = "backup")]
+mod s3;
+#[cfg(feature = "backup")]
+mod sftp;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use chrono::Utc;
+use ring::{digest, hmac};
+use rocksdb::{checkpoint::Checkpoint, IteratorMode};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinHandle;
+use tracing::{info, warn, error};
+
+use super::{ColumnCodecs, EncryptedStore, column_family, decrypt_record, open_db};
+use crate::{
+    utils::{
+        config::StorageConfig,
+        error::{Result, NodeError},
+    },
+    core::crypto::{
+        key_manager::{KeyManager, KEYRING_FILE, write_key_file},
+        keystore::DEFAULT_KEY_FILE,
+    },
+    storage::{kv::Column, migrations},
+};
+
+pub use local::LocalTarget;
+#[cfg(feature = "backup")]
+pub use s3::S3Target;
+#[cfg(feature = "backup")]
+pub use sftp::SftpTarget;
+
+/// Largest piece a file is cut into. Large enough that a chunk of a big SST
+/// file goes to object storage as a multipart upload.
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+const NONCE_LEN: usize = 12;
+const MANIFEST_FORMAT: u32 = 1;
+
+const MANIFESTS_DIR: &str = "manifests";
+const CHUNKS_DIR: &str = "chunks";
+const KEYS_DIR: &str = "keys";
+
+/// Files of the storage directory that are not part of the database
+const KEY_FILES: [&str; 2] = [KEYRING_FILE, DEFAULT_KEY_FILE];
+
+/// Where backups are stored. Keys are `/`-separated paths such as
+/// `chunks/<id>`. `put` has to replace an object atomically, so a reader
+/// sees either the old contents or all of the new ones.
+pub trait BackupTarget: Send + Sync {
+    /// For logs and listings; never includes credentials
+    fn describe(&self) -> String;
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// `None` if there is no object under `key`
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    fn exists(&self, key: &str) -> Result<bool>;
+
+    /// Names of the objects directly under `dir`, without the `dir/` prefix
+    fn list(&self, dir: &str) -> Result<Vec<String>>;
+
+    /// Deleting an object that does not exist succeeds
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// The targets configured in `storage`: `backup_path`, if set, then every
+/// URL in `backup_targets`
+pub fn open_targets(config: &StorageConfig) -> Result<Vec<Arc<dyn BackupTarget>>> {
+    let mut targets: Vec<Arc<dyn BackupTarget>> = Vec::new();
+    if !config.backup_path.is_empty() {
+        targets.push(Arc::new(LocalTarget::new(&config.backup_path)));
+    }
+    for url in &config.backup_targets {
+        targets.push(open_target(url, config)?);
+    }
+
+    if targets.is_empty() {
+        return Err(NodeError::Config("No backup targets configured".into()));
+    }
+    Ok(targets)
+}
+
+#[cfg_attr(not(feature = "backup"), allow(unused_variables))]
+fn open_target(url: &str, config: &StorageConfig) -> Result<Arc<dyn BackupTarget>> {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    match scheme {
+        "file" => Ok(Arc::new(LocalTarget::new(&url["file://".len()..]))),
+        #[cfg(feature = "backup")]
+        "sftp" => Ok(Arc::new(SftpTarget::from_url(url)?)),
+        #[cfg(feature = "backup")]
+        "s3" => {
+            let required = |value: &Option<String>, name: &str| {
+                value.clone().ok_or_else(|| NodeError::Config(format!("{} must be set for S3 backup targets", name)))
+            };
+            Ok(Arc::new(S3Target::from_url(
+                url,
+                &required(&config.backup_s3_access_key, "backup_s3_access_key")?,
+                &required(&config.backup_s3_secret_key, "backup_s3_secret_key")?,
+            )?))
+        }
+        _ => Err(NodeError::Config(format!("Unsupported backup target {}", url))),
+    }
+}
+
+/// One database file of a backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// Name inside the database directory
+    pub name: String,
+    pub size: u64,
+    /// Hex SHA-256 of the contents
+    pub sha256: String,
+    /// Ids of the chunks the contents are cut into, in order
+    pub chunks: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: u32,
+    pub id: String,
+    pub created_at: i64,
+    /// Storage schema version of the backed up records
+    pub schema_version: u32,
+    pub files: Vec<BackupFile>,
+    /// Hex HMAC-SHA256 of the manifest with this field empty
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// A backup and the targets holding it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupListing {
+    pub manifest: BackupManifest,
+    pub targets: Vec<String>,
+}
+
+/// Outcome of one `EncryptedStore::backup`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupSummary {
+    pub id: String,
+    pub files: usize,
+    /// Targets that now hold the backup
+    pub uploads: Vec<TargetUpload>,
+    /// Targets that do not, with the error that stopped them
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetUpload {
+    pub target: String,
+    pub chunks_written: u64,
+    pub chunks_reused: u64,
+    /// Encrypted bytes added to the target
+    pub bytes_written: u64,
+}
+
+/// Outcome of one `prune_backups`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneSummary {
+    pub backups_removed: usize,
+    pub chunks_removed: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupPolicy {
+    pub interval: Duration,
+    /// Newest backups kept on each target; 0 keeps all of them
+    pub retention: usize,
+    /// Test-restore each backup from every target right after taking it
+    pub verify: bool,
+}
+
+impl From<&StorageConfig> for BackupPolicy {
+    fn from(config: &StorageConfig) -> Self {
+        Self {
+            interval: Duration::from_secs(config.backup_interval),
+            retention: config.backup_retention,
+            verify: config.backup_verify,
+        }
+    }
+}
+
+/// Keys of every backup, derived from the keyring root
+struct BackupKeys {
+    cipher: Aes256Gcm,
+    chunk_ids: hmac::Key,
+    manifests: hmac::Key,
+}
+
+impl BackupKeys {
+    fn derive(key_manager: &KeyManager) -> Result<Self> {
+        let cipher = Aes256Gcm::new_from_slice(&key_manager.derive_key("freeghost-backup-encryption")?)
+            .map_err(|e| NodeError::Crypto(format!("Failed to initialize cipher: {}", e)))?;
+        Ok(Self {
+            cipher,
+            chunk_ids: hmac::Key::new(hmac::HMAC_SHA256, &key_manager.derive_key("freeghost-backup-chunk-id")?),
+            manifests: hmac::Key::new(hmac::HMAC_SHA256, &key_manager.derive_key("freeghost-backup-manifest")?),
+        })
+    }
+
+    /// Keyed, so chunk names do not reveal hashes of the data
+    fn chunk_id(&self, data: &[u8]) -> String {
+        hex::encode(hmac::sign(&self.chunk_ids, data))
+    }
+
+    /// The nonce is taken from the chunk id, a MAC of the contents, so the
+    /// same contents always seal to the same bytes (as in SIV) and an
+    /// interrupted upload can be resumed with the parts already sent.
+    /// Different contents get different nonces but for a MAC collision.
+    fn seal_chunk(&self, id: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let tag = hex::decode(id).map_err(|_| NodeError::Crypto(format!("Invalid chunk id {}", id)))?;
+        let nonce = &tag[..NONCE_LEN];
+        let ciphertext = self.cipher
+            .encrypt(Nonce::from_slice(nonce), Payload { msg: data, aad: id.as_bytes() })
+            .map_err(|e| NodeError::Crypto(format!("Chunk encryption failed: {}", e)))?;
+        Ok([nonce, &ciphertext].concat())
+    }
+
+    /// Decrypt a chunk and check that it is the one named `id`
+    fn open_chunk(&self, id: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        let corrupt = || NodeError::Storage(format!("Backup chunk {} is corrupted", id));
+        if sealed.len() < NONCE_LEN {
+            return Err(corrupt());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let data = self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: id.as_bytes() })
+            .map_err(|_| corrupt())?;
+        let tag = hex::decode(id).map_err(|_| corrupt())?;
+        hmac::verify(&self.chunk_ids, &data, &tag).map_err(|_| corrupt())?;
+        Ok(data)
+    }
+
+    fn sign(&self, manifest: &BackupManifest) -> Result<String> {
+        Ok(hex::encode(hmac::sign(&self.manifests, &signed_bytes(manifest)?)))
+    }
+
+    fn verify(&self, manifest: &BackupManifest) -> Result<()> {
+        let invalid = || NodeError::Storage(format!("Backup {} has an invalid signature", manifest.id));
+        let signature = hex::decode(&manifest.signature).map_err(|_| invalid())?;
+        hmac::verify(&self.manifests, &signed_bytes(manifest)?, &signature).map_err(|_| invalid())
+    }
+}
+
+fn signed_bytes(manifest: &BackupManifest) -> Result<Vec<u8>> {
+    let unsigned = BackupManifest { signature: String::new(), ..manifest.clone() };
+    serde_json::to_vec(&unsigned)
+        .map_err(|e| NodeError::Storage(format!("Serialization failed: {}", e)))
+}
+
+/// A target a backup is being uploaded to, until it fails
+struct Upload<'a> {
+    target: &'a dyn BackupTarget,
+    stats: TargetUpload,
+    error: Option<NodeError>,
+}
+
+impl Upload<'_> {
+    fn run(&mut self, step: impl FnOnce(&dyn BackupTarget, &mut TargetUpload) -> Result<()>) {
+        if self.error.is_none() {
+            if let Err(e) = step(self.target, &mut self.stats) {
+                warn!("Backup to {} failed: {}", self.stats.target, e);
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl EncryptedStore {
+    /// Back the whole database up to every target, uploading only chunks a
+    /// target does not hold yet. Fails only if no target took the backup.
+    ///
+    /// Reads the whole checkpoint and uploads with blocking I/O; call it
+    /// from `spawn_blocking` inside a runtime, as `BackupScheduler` does.
+    pub fn backup(&self, targets: &[Arc<dyn BackupTarget>]) -> Result<BackupSummary> {
+        let keys = BackupKeys::derive(&self.key_manager)?;
+
+        // Left behind by an interrupted backup
+        let checkpoint = sibling(self.db.path(), "checkpoint");
+        remove_dir(&checkpoint)?;
+
+        let id = Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+        let created_at = Utc::now().timestamp();
+        Checkpoint::new(&self.db)
+            .and_then(|engine| engine.create_checkpoint(&checkpoint))
+            .map_err(|e| NodeError::Storage(format!("Checkpoint failed: {}", e)))?;
+
+        let mut uploads: Vec<Upload> = targets
+            .iter()
+            .map(|target| Upload {
+                target: target.as_ref(),
+                stats: TargetUpload { target: target.describe(), ..Default::default() },
+                error: None,
+            })
+            .collect();
+        let files = upload_chunks(&checkpoint, &keys, &mut uploads);
+        remove_dir(&checkpoint)?;
+        let files = files?;
+
+        let mut manifest = BackupManifest {
+            format: MANIFEST_FORMAT,
+            id: id.clone(),
+            created_at,
+            schema_version: migrations::schema_version(self)?,
+            files,
+            signature: String::new(),
+        };
+        manifest.signature = keys.sign(&manifest)?;
+        let contents = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| NodeError::Storage(format!("Serialization failed: {}", e)))?;
+
+        let mut key_files = Vec::new();
+        for name in KEY_FILES {
+            let source = self.db.path().join(name);
+            if source.exists() {
+                key_files.push((name, fs::read(&source).map_err(io_error("read key file"))?));
+            }
+        }
+
+        for upload in &mut uploads {
+            // Keys before the manifest, so a backup never lacks the keys it was taken with
+            upload.run(|target, _| {
+                for (name, contents) in &key_files {
+                    target.put(&format!("{}/{}", KEYS_DIR, name), contents)?;
+                }
+                target.put(&manifest_key(&id), &contents)
+            });
+        }
+
+        let mut summary = BackupSummary { id, files: manifest.files.len(), ..Default::default() };
+        for upload in uploads {
+            match upload.error {
+                None => summary.uploads.push(upload.stats),
+                Some(e) => summary.failed.push(format!("{}: {}", upload.stats.target, e)),
+            }
+        }
+        if summary.uploads.is_empty() {
+            return Err(NodeError::Storage(format!("Backup failed on every target: {}", summary.failed.join("; "))));
+        }
+
+        for upload in &summary.uploads {
+            info!(
+                "Backup {} written to {}: {} files, {} new chunks ({} bytes), {} reused",
+                summary.id, upload.target, summary.files, upload.chunks_written, upload.bytes_written, upload.chunks_reused,
+            );
+        }
+        Ok(summary)
+    }
+
+    /// `verify_backup` with this store's keyring, e.g. as a restore drill
+    /// on a running node
+    pub fn test_restore(&self, target: &dyn BackupTarget, id: Option<&str>) -> Result<BackupManifest> {
+        verify_backup(target, id, &self.key_manager)
+    }
+}
+
+/// Cut every file of `checkpoint` into chunks and upload the ones each
+/// target lacks
+fn upload_chunks(checkpoint: &Path, keys: &BackupKeys, uploads: &mut [Upload]) -> Result<Vec<BackupFile>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(checkpoint).map_err(io_error("read checkpoint"))? {
+        let entry = entry.map_err(io_error("read checkpoint"))?;
+        if entry.file_type().map_err(io_error("read checkpoint"))?.is_file() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+
+    let mut files = Vec::with_capacity(names.len());
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    for name in names {
+        let mut file = fs::File::open(checkpoint.join(&name)).map_err(io_error("read checkpoint"))?;
+        let mut hash = digest::Context::new(&digest::SHA256);
+        let mut backup_file = BackupFile { name, size: 0, sha256: String::new(), chunks: Vec::new() };
+
+        loop {
+            let len = read_full(&mut file, &mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            let data = &buffer[..len];
+            hash.update(data);
+            backup_file.size += len as u64;
+
+            let id = keys.chunk_id(data);
+            let key = format!("{}/{}", CHUNKS_DIR, id);
+            let mut sealed = None;
+            for upload in uploads.iter_mut() {
+                upload.run(|target, stats| {
+                    if target.exists(&key)? {
+                        stats.chunks_reused += 1;
+                        return Ok(());
+                    }
+                    if sealed.is_none() {
+                        sealed = Some(keys.seal_chunk(&id, data)?);
+                    }
+                    let sealed = sealed.as_deref().unwrap_or_default();
+                    target.put(&key, sealed)?;
+                    stats.chunks_written += 1;
+                    stats.bytes_written += sealed.len() as u64;
+                    Ok(())
+                });
+            }
+            backup_file.chunks.push(id);
+        }
+
+        backup_file.sha256 = hex::encode(hash.finish());
+        files.push(backup_file);
+    }
+    Ok(files)
+}
+
+/// Every backup on `target`, oldest first. Signatures are not checked here;
+/// restoring and verifying do.
+fn target_backups(target: &dyn BackupTarget) -> Result<Vec<BackupManifest>> {
+    let mut backups = Vec::new();
+    for name in target.list(MANIFESTS_DIR)? {
+        let Some(id) = name.strip_suffix(".json") else { continue };
+        let Some(contents) = target.get(&manifest_key(id))? else { continue };
+        let manifest: BackupManifest = serde_json::from_slice(&contents).map_err(|e| {
+            NodeError::Storage(format!("Invalid backup manifest {} on {}: {}", id, target.describe(), e))
+        })?;
+        backups.push(manifest);
+    }
+    backups.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(backups)
+}
+
+/// Every backup on any of `targets`, oldest first, with the targets that
+/// hold it. A target that cannot be listed is skipped with a warning.
+/// Signatures are not checked here; restoring and verifying do.
+pub fn list_backups(targets: &[Arc<dyn BackupTarget>]) -> Result<Vec<BackupListing>> {
+    let mut listings: BTreeMap<String, BackupListing> = BTreeMap::new();
+    let mut listed = 0;
+    for target in targets {
+        let backups = match target_backups(target.as_ref()) {
+            Ok(backups) => backups,
+            Err(e) => {
+                warn!("Cannot list backups on {}: {}", target.describe(), e);
+                continue;
+            }
+        };
+        listed += 1;
+        for manifest in backups {
+            listings
+                .entry(manifest.id.clone())
+                .or_insert_with(|| BackupListing { manifest, targets: Vec::new() })
+                .targets
+                .push(target.describe());
+        }
+    }
+
+    if listed == 0 && !targets.is_empty() {
+        return Err(NodeError::Storage("No backup target could be listed".into()));
+    }
+    Ok(listings.into_values().collect())
+}
+
+/// Keep the newest `retention` backups on `target` (all of them for 0) and
+/// remove chunks no remaining backup refers to
+pub fn prune_backups(target: &dyn BackupTarget, retention: usize) -> Result<PruneSummary> {
+    let backups = target_backups(target)?;
+    let expired = match retention {
+        0 => 0,
+        keep => backups.len().saturating_sub(keep),
+    };
+
+    let mut summary = PruneSummary::default();
+    for manifest in &backups[..expired] {
+        target.delete(&manifest_key(&manifest.id))?;
+        summary.backups_removed += 1;
+    }
+
+    let referenced: HashSet<&str> = backups[expired..]
+        .iter()
+        .flat_map(|manifest| &manifest.files)
+        .flat_map(|file| file.chunks.iter().map(String::as_str))
+        .collect();
+    for name in target.list(CHUNKS_DIR)? {
+        if !referenced.contains(name.as_str()) {
+            target.delete(&format!("{}/{}", CHUNKS_DIR, name))?;
+            summary.chunks_removed += 1;
+        }
+    }
+
+    if summary != PruneSummary::default() {
+        info!(
+            "Pruned {} backups and {} chunks from {}",
+            summary.backups_removed, summary.chunks_removed, target.describe(),
+        );
+    }
+    Ok(summary)
+}
+
+/// Restore a backup on `target` (the newest for `None`) into a temporary
+/// directory, check it completely, then delete it again. Returns the checked
+/// manifest.
+pub fn verify_backup(target: &dyn BackupTarget, id: Option<&str>, key_manager: &KeyManager) -> Result<BackupManifest> {
+    let keys = BackupKeys::derive(key_manager)?;
+    let manifest = load_manifest(target, id, &keys)?;
+
+    let scratch = std::env::temp_dir().join(format!("freeghost-verify-{}-{}", std::process::id(), manifest.id));
+    remove_dir(&scratch)?;
+    let checked = extract(target, &manifest, &keys, &scratch)
+        .and_then(|_| check_database(&scratch, key_manager));
+    remove_dir(&scratch)?;
+
+    let records = checked?;
+    info!(
+        "Backup {} on {} verified: {} files, {} records",
+        manifest.id, target.describe(), manifest.files.len(), records,
+    );
+    Ok(manifest)
+}
+
+/// Replace the database in `storage_dir` with a backup (the newest for
+/// `None`), taken from the first target that holds it intact. Run it with
+/// the node stopped.
+///
+/// The backup is extracted and checked next to `storage_dir` first; the
+/// current directory is only renamed away once that succeeded, and is kept
+/// as `<storage_dir>.replaced-<time>`. Key files come from the current
+/// directory, which holds every key the backup can need, or from the backup
+/// if the directory lost them.
+pub fn restore_backup(
+    storage_dir: &Path,
+    targets: &[Arc<dyn BackupTarget>],
+    id: Option<&str>,
+    key_manager: &KeyManager,
+) -> Result<BackupManifest> {
+    let listings = list_backups(targets)?;
+    let listing = match id {
+        Some(id) => listings
+            .into_iter()
+            .find(|listing| listing.manifest.id == id)
+            .ok_or_else(|| NodeError::Storage(format!("No backup {} on any target", id)))?,
+        None => listings
+            .into_iter()
+            .last()
+            .ok_or_else(|| NodeError::Storage("No backups on any target".into()))?,
+    };
+
+    let mut last_error = None;
+    for target in targets.iter().filter(|target| listing.targets.contains(&target.describe())) {
+        match restore_from(storage_dir, target.as_ref(), &listing.manifest.id, key_manager) {
+            Ok(manifest) => return Ok(manifest),
+            Err(e) => {
+                warn!("Cannot restore backup {} from {}: {}", listing.manifest.id, target.describe(), e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| NodeError::Storage(format!("Backup {} is on no target", listing.manifest.id))))
+}
+
+fn restore_from(
+    storage_dir: &Path,
+    target: &dyn BackupTarget,
+    id: &str,
+    key_manager: &KeyManager,
+) -> Result<BackupManifest> {
+    let keys = BackupKeys::derive(key_manager)?;
+    let manifest = load_manifest(target, Some(id), &keys)?;
+
+    let staging = sibling(storage_dir, "restore");
+    remove_dir(&staging)?;
+    let staged = extract(target, &manifest, &keys, &staging)
+        .and_then(|_| check_database(&staging, key_manager))
+        .and_then(|records| {
+            for name in KEY_FILES {
+                let current = storage_dir.join(name);
+                let contents = match current.exists() {
+                    true => Some(fs::read(&current).map_err(io_error("read key file"))?),
+                    false => target.get(&format!("{}/{}", KEYS_DIR, name))?,
+                };
+                if let Some(contents) = contents {
+                    write_key_file(&staging.join(name), &contents)?;
+                }
+            }
+            Ok(records)
+        });
+    let records = match staged {
+        Ok(records) => records,
+        Err(e) => {
+            remove_dir(&staging)?;
+            return Err(e);
+        }
+    };
+
+    if storage_dir.exists() {
+        let replaced = sibling(storage_dir, &format!("replaced-{}", Utc::now().format("%Y%m%dT%H%M%SZ")));
+        fs::rename(storage_dir, &replaced).map_err(io_error("move storage directory aside"))?;
+        if let Err(e) = fs::rename(&staging, storage_dir) {
+            if let Err(undo) = fs::rename(&replaced, storage_dir) {
+                error!("Failed to move {:?} back after a failed restore: {}", replaced, undo);
+            }
+            return Err(NodeError::Storage(format!("Failed to swap in restored storage: {}", e)));
+        }
+        info!("Previous storage kept at {:?}", replaced);
+    } else {
+        fs::rename(&staging, storage_dir).map_err(io_error("swap in restored storage"))?;
+    }
+
+    info!(
+        "Restored backup {} ({} records) from {} into {:?}",
+        manifest.id, records, target.describe(), storage_dir,
+    );
+    Ok(manifest)
+}
+
+/// Copy key files `storage_dir` lacks from the first target that has them,
+/// so a node that lost its storage directory can unlock the keyring to
+/// restore. Returns the names of the files copied.
+pub fn restore_key_files(storage_dir: &Path, targets: &[Arc<dyn BackupTarget>]) -> Result<Vec<String>> {
+    let mut restored = Vec::new();
+    for name in KEY_FILES {
+        let target_path = storage_dir.join(name);
+        if target_path.exists() {
+            continue;
+        }
+        for target in targets {
+            match target.get(&format!("{}/{}", KEYS_DIR, name)) {
+                Ok(Some(contents)) => {
+                    fs::create_dir_all(storage_dir).map_err(io_error("create storage directory"))?;
+                    write_key_file(&target_path, &contents)?;
+                    restored.push(name.to_string());
+                    break;
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Cannot read {} from {}: {}", name, target.describe(), e),
+            }
+        }
+    }
+    Ok(restored)
+}
+
+/// Backup `id` on `target`, or its newest one, with its signature checked
+fn load_manifest(target: &dyn BackupTarget, id: Option<&str>, keys: &BackupKeys) -> Result<BackupManifest> {
+    let backups = target_backups(target)?;
+    let manifest = match id {
+        Some(id) => backups
+            .into_iter()
+            .find(|manifest| manifest.id == id)
+            .ok_or_else(|| NodeError::Storage(format!("No backup {} on {}", id, target.describe())))?,
+        None => backups
+            .into_iter()
+            .last()
+            .ok_or_else(|| NodeError::Storage(format!("No backups on {}", target.describe())))?,
+    };
+
+    if manifest.format != MANIFEST_FORMAT {
+        return Err(NodeError::Storage(format!(
+            "Backup {} has unsupported format {}", manifest.id, manifest.format,
+        )));
+    }
+    keys.verify(&manifest)?;
+    Ok(manifest)
+}
+
+/// Reassemble the files of `manifest` in `dir`, checking each against its
+/// size and checksum
+fn extract(target: &dyn BackupTarget, manifest: &BackupManifest, keys: &BackupKeys, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).map_err(io_error("create restore directory"))?;
+
+    for backup_file in &manifest.files {
+        let mut file = fs::File::create(dir.join(&backup_file.name)).map_err(io_error("write restored file"))?;
+        let mut hash = digest::Context::new(&digest::SHA256);
+        let mut size = 0u64;
+
+        for id in &backup_file.chunks {
+            let sealed = target
+                .get(&format!("{}/{}", CHUNKS_DIR, id))?
+                .ok_or_else(|| NodeError::Storage(format!("Backup chunk {} is missing", id)))?;
+            let data = keys.open_chunk(id, &sealed)?;
+            hash.update(&data);
+            size += data.len() as u64;
+            file.write_all(&data).map_err(io_error("write restored file"))?;
+        }
+        file.sync_all().map_err(io_error("write restored file"))?;
+
+        if size != backup_file.size || hex::encode(hash.finish()) != backup_file.sha256 {
+            return Err(NodeError::Storage(format!(
+                "Restored {} does not match its checksum in backup {}", backup_file.name, manifest.id,
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Open the database in `path` and decrypt every record. Returns how many
+/// there are. The audit log is not read; it verifies its own checkpoints.
+fn check_database(path: &Path, key_manager: &KeyManager) -> Result<u64> {
+    let db = open_db(path, &ColumnCodecs::default())?;
+    let mut records = 0;
+
+    for column in Column::ALL {
+        for item in db.iterator_cf(column_family(&db, column)?, IteratorMode::Start) {
+            let (key, value) = item
+                .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?;
+            if column == Column::Default && KeyManager::is_legacy(&value) {
+                // Written before values were bound to their keys; see `migrate_record_aad`
+                key_manager.decrypt_legacy(&value)?;
+            } else {
+                decrypt_record(key_manager, column, &key, &value)?;
+            }
+            records += 1;
+        }
+    }
+    Ok(records)
+}
+
+fn manifest_key(id: &str) -> String {
+    format!("{}/{}.json", MANIFESTS_DIR, id)
+}
+
+/// `<path>.<suffix>`, on the same file system as `path` so it can be renamed
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Fill `buffer` as far as the reader allows; returns the bytes read
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).map_err(io_error("read checkpoint"))? {
+            0 => break,
+            len => filled += len,
+        }
+    }
+    Ok(filled)
+}
+
+fn remove_dir(path: &Path) -> Result<()> {
+    if path.exists() {
+        fs::remove_dir_all(path).map_err(io_error("remove directory"))?;
+    }
+    Ok(())
+}
+
+fn io_error(action: &'static str) -> impl Fn(std::io::Error) -> NodeError {
+    move |e| NodeError::Storage(format!("Failed to {}: {}", action, e))
+}
+
+/// Takes a backup every `interval`, counted from the newest one on any
+/// target, so restarts neither skip nor repeat backups. Backups and target
+/// listings run on the blocking pool, off the async workers.
+#[derive(Clone)]
+pub struct BackupScheduler {
+    storage: Arc<EncryptedStore>,
+    targets: Vec<Arc<dyn BackupTarget>>,
+    policy: BackupPolicy,
+}
+
+impl BackupScheduler {
+    pub fn new(storage: Arc<EncryptedStore>, targets: Vec<Arc<dyn BackupTarget>>, policy: BackupPolicy) -> Self {
+        Self { storage, targets, policy }
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait = self.blocking(Self::until_due).await.unwrap_or_else(|e| {
+                    warn!("Cannot read existing backups: {}", e);
+                    self.policy.interval
+                });
+                tokio::time::sleep(wait).await;
+
+                if let Err(e) = self.run_once().await {
+                    error!("Backup failed: {}", e);
+                    tokio::time::sleep(self.policy.interval).await;
+                }
+            }
+        })
+    }
+
+    /// Time until the next backup is due
+    pub fn until_due(&self) -> Result<Duration> {
+        let Some(newest) = list_backups(&self.targets)?.pop() else {
+            return Ok(Duration::ZERO);
+        };
+        let age = Utc::now().timestamp().saturating_sub(newest.manifest.created_at).max(0) as u64;
+        Ok(self.policy.interval.saturating_sub(Duration::from_secs(age)))
+    }
+
+    /// Take a backup, test-restore it from every target that took it if
+    /// configured, then apply retention. Fails if any target missed out.
+    pub async fn run_once(&self) -> Result<BackupSummary> {
+        self.blocking(Self::take_backup).await
+    }
+
+    fn take_backup(&self) -> Result<BackupSummary> {
+        let mut summary = self.storage.backup(&self.targets)?;
+
+        for target in &self.targets {
+            let description = target.describe();
+            if !summary.uploads.iter().any(|upload| upload.target == description) {
+                continue;
+            }
+            let checked = match self.policy.verify {
+                true => self.storage.test_restore(target.as_ref(), Some(&summary.id)).map(|_| ()),
+                false => Ok(()),
+            };
+            if let Err(e) = checked.and_then(|_| prune_backups(target.as_ref(), self.policy.retention).map(|_| ())) {
+                summary.failed.push(format!("{}: {}", description, e));
+            }
+        }
+
+        if !summary.failed.is_empty() {
+            return Err(NodeError::Storage(format!(
+                "Backup {} incomplete: {}", summary.id, summary.failed.join("; "),
+            )));
+        }
+        Ok(summary)
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Self) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let scheduler = self.clone();
+        tokio::task::spawn_blocking(move || f(&scheduler))
+            .await
+            .map_err(|e| NodeError::Storage(format!("Backup task failed: {}", e)))?
+    }
+}
+
+/// Behaviour every target has to share; run by each target's tests
+#[cfg(test)]
+pub(crate) fn exercise(target: &dyn BackupTarget) {
+    assert_eq!(target.get("chunks/a").unwrap(), None);
+    assert!(!target.exists("chunks/a").unwrap());
+    assert!(target.list("chunks").unwrap().is_empty());
+
+    target.put("chunks/a", b"first").unwrap();
+    target.put("chunks/b", b"").unwrap();
+    target.put("manifests/1.json", b"{}").unwrap();
+    assert_eq!(target.get("chunks/a").unwrap(), Some(b"first".to_vec()));
+    assert_eq!(target.get("chunks/b").unwrap(), Some(Vec::new()));
+    assert!(target.exists("chunks/a").unwrap());
+
+    let mut names = target.list("chunks").unwrap();
+    names.sort();
+    assert_eq!(names, vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(target.list("manifests").unwrap(), vec!["1.json".to_string()]);
+
+    // Replacing keeps a single object
+    target.put("chunks/a", b"second").unwrap();
+    assert_eq!(target.get("chunks/a").unwrap(), Some(b"second".to_vec()));
+    assert_eq!(target.list("chunks").unwrap().len(), 2);
+
+    target.delete("chunks/a").unwrap();
+    target.delete("chunks/a").unwrap();
+    assert!(!target.exists("chunks/a").unwrap());
+    assert_eq!(target.list("chunks").unwrap(), vec!["b".to_string()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::crypto::key_manager::KdfParams;
+    use crate::storage::kv::KvStore;
+    use tempfile::tempdir;
+
+    fn test_config(path: &Path, backup_path: &Path) -> StorageConfig {
+        StorageConfig {
+            backup_path: backup_path.to_str().unwrap().to_string(),
+            backup_retention: 2,
+            backup_verify: true,
+            ..StorageConfig::for_test(path)
+        }
+    }
+
+    fn local(dir: &Path) -> Vec<Arc<dyn BackupTarget>> {
+        vec![Arc::new(LocalTarget::new(dir))]
+    }
+
+    #[tokio::test]
+    async fn test_backup_restore_swaps_in() {
+        let root = tempdir().unwrap();
+        let storage_dir = root.path().join("storage");
+        let targets = local(&root.path().join("backups"));
+        let config = test_config(&storage_dir, &root.path().join("backups"));
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        store.put(Column::Default, b"test_key", b"test_value").unwrap();
+        store.put(Column::Tokens, b"token", b"spent").unwrap();
+        let first = store.backup(&targets).unwrap();
+        assert!(first.uploads[0].chunks_written > 0);
+
+        store.put(Column::Default, b"test_key", b"modified_value").unwrap();
+        store.rotate_encryption_key().await.unwrap();
+        store.put(Column::Default, b"later", b"after the backup").unwrap();
+        let key_manager = EncryptedStore::open_keyring(&config).unwrap();
+        drop(store);
+
+        let manifest = restore_backup(&storage_dir, &targets, Some(&first.id), &key_manager).unwrap();
+        assert_eq!(manifest.id, first.id);
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        assert_eq!(store.get(Column::Default, b"test_key").unwrap(), Some(b"test_value".to_vec()));
+        assert_eq!(store.get(Column::Tokens, b"token").unwrap(), Some(b"spent".to_vec()));
+        assert_eq!(store.get(Column::Default, b"later").unwrap(), None);
+        // The keyring was kept, so data keys added after the backup survive
+        assert!(store.key_manager.versions().len() > 1);
+
+        // The replaced directory is kept beside the restored one
+        let replaced = fs::read_dir(root.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with("storage.replaced-"))
+            .count();
+        assert_eq!(replaced, 1);
+        assert!(!sibling(&storage_dir, "restore").exists());
+    }
+
+    #[tokio::test]
+    async fn test_backups_are_incremental_and_pruned() {
+        let root = tempdir().unwrap();
+        let backup_dir = root.path().join("backups");
+        let config = test_config(&root.path().join("storage"), &backup_dir);
+        let store = Arc::new(EncryptedStore::new(&config).await.unwrap());
+        let targets = open_targets(&config).unwrap();
+        let scheduler = BackupScheduler::new(store.clone(), targets.clone(), BackupPolicy::from(&config));
+        assert_eq!(scheduler.until_due().unwrap(), Duration::ZERO);
+
+        store.put(Column::Default, b"a", b"1").unwrap();
+        let first = scheduler.run_once().await.unwrap();
+        let second = scheduler.run_once().await.unwrap();
+        assert_ne!(first.id, second.id);
+        // Nothing changed in between: files the first backup stored are not stored again
+        assert!(second.uploads[0].chunks_reused > 0);
+        assert!(second.uploads[0].chunks_written < first.uploads[0].chunks_written);
+        assert!(scheduler.until_due().unwrap() > Duration::from_secs(3500));
+
+        store.put(Column::Default, b"b", b"2").unwrap();
+        let third = scheduler.run_once().await.unwrap();
+
+        // Retention keeps the newest two backups and the chunks they use
+        let backups = target_backups(targets[0].as_ref()).unwrap();
+        let ids: Vec<_> = backups.iter().map(|manifest| manifest.id.clone()).collect();
+        assert_eq!(ids, vec![second.id, third.id.clone()]);
+        let referenced: HashSet<String> = backups
+            .into_iter()
+            .flat_map(|manifest| manifest.files)
+            .flat_map(|file| file.chunks)
+            .collect();
+        assert_eq!(targets[0].list(CHUNKS_DIR).unwrap().len(), referenced.len());
+
+        let manifest = store.test_restore(targets[0].as_ref(), None).unwrap();
+        assert_eq!(manifest.id, third.id);
+    }
+
+    #[tokio::test]
+    async fn test_backups_listed_across_targets() {
+        let root = tempdir().unwrap();
+        let storage_dir = root.path().join("storage");
+        let mut config = test_config(&storage_dir, &root.path().join("primary"));
+        config.backup_targets = vec![format!("file://{}", root.path().join("secondary").display())];
+        let targets = open_targets(&config).unwrap();
+        assert_eq!(targets.len(), 2);
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        store.put(Column::Identities, b"id", b"alice").unwrap();
+        let both = store.backup(&targets).unwrap();
+        assert_eq!(both.uploads.len(), 2);
+        let only_secondary = store.backup(&targets[1..]).unwrap();
+
+        let listings = list_backups(&targets).unwrap();
+        assert_eq!(listings.len(), 2);
+        assert_eq!(listings[0].manifest.id, both.id);
+        assert_eq!(listings[0].targets, vec![targets[0].describe(), targets[1].describe()]);
+        assert_eq!(listings[1].manifest.id, only_secondary.id);
+        assert_eq!(listings[1].targets, vec![targets[1].describe()]);
+
+        // A backup whose first target lost a chunk is restored from the next one
+        let chunk = targets[0].list(CHUNKS_DIR).unwrap().remove(0);
+        targets[0].delete(&format!("{}/{}", CHUNKS_DIR, chunk)).unwrap();
+        assert!(verify_backup(targets[0].as_ref(), Some(&both.id), &store.key_manager).is_err());
+        let key_manager = EncryptedStore::open_keyring(&config).unwrap();
+        drop(store);
+        restore_backup(&storage_dir, &targets, Some(&both.id), &key_manager).unwrap();
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        assert_eq!(store.get(Column::Identities, b"id").unwrap(), Some(b"alice".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_tampering_is_detected() {
+        let root = tempdir().unwrap();
+        let backup_dir = root.path().join("backups");
+        let target = LocalTarget::new(&backup_dir);
+        let config = test_config(&root.path().join("storage"), &backup_dir);
+        let store = EncryptedStore::new(&config).await.unwrap();
+        store.put(Column::Identities, b"id", b"alice").unwrap();
+        let summary = store.backup(&local(&backup_dir)).unwrap();
+        verify_backup(&target, None, &store.key_manager).unwrap();
+
+        // An edited manifest no longer matches its signature
+        let key = manifest_key(&summary.id);
+        let original = target.get(&key).unwrap().unwrap();
+        let mut manifest: BackupManifest = serde_json::from_slice(&original).unwrap();
+        manifest.files[0].size += 1;
+        target.put(&key, &serde_json::to_vec(&manifest).unwrap()).unwrap();
+        assert!(verify_backup(&target, None, &store.key_manager).is_err());
+        target.put(&key, &original).unwrap();
+
+        // A flipped bit in a chunk fails its authentication
+        let chunk = format!("{}/{}", CHUNKS_DIR, target.list(CHUNKS_DIR).unwrap().remove(0));
+        let sealed = target.get(&chunk).unwrap().unwrap();
+        let mut flipped = sealed.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        target.put(&chunk, &flipped).unwrap();
+        assert!(verify_backup(&target, None, &store.key_manager).is_err());
+        target.put(&chunk, &sealed).unwrap();
+        verify_backup(&target, None, &store.key_manager).unwrap();
+
+        // Another keyring cannot read the backup at all
+        let other = tempdir().unwrap();
+        let other_keys = EncryptedStore::open_keyring(&test_config(other.path(), &backup_dir)).unwrap();
+        assert!(verify_backup(&target, None, &other_keys).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_key_files_restored_onto_empty_directory() {
+        let root = tempdir().unwrap();
+        let storage_dir = root.path().join("storage");
+        let targets = local(&root.path().join("backups"));
+        let config = test_config(&storage_dir, &root.path().join("backups"));
+        let store = EncryptedStore::new(&config).await.unwrap();
+        store.put(Column::Identities, b"id", b"alice").unwrap();
+        store.backup(&targets).unwrap();
+        drop(store);
+
+        fs::remove_dir_all(&storage_dir).unwrap();
+        assert_eq!(restore_key_files(&storage_dir, &targets).unwrap(), vec![KEYRING_FILE.to_string()]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(storage_dir.join(KEYRING_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let key_manager = EncryptedStore::open_keyring(&config).unwrap();
+        restore_backup(&storage_dir, &targets, None, &key_manager).unwrap();
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        assert_eq!(store.get(Column::Identities, b"id").unwrap(), Some(b"alice".to_vec()));
+    }
+
+    #[test]
+    fn test_chunks_seal_deterministically() {
+        let key_manager = KeyManager::new_with_params("test_key", &KdfParams { memory_kib: 256, iterations: 1, parallelism: 1 }).unwrap();
+        let keys = BackupKeys::derive(&key_manager).unwrap();
+        let id = keys.chunk_id(b"contents");
+        let sealed = keys.seal_chunk(&id, b"contents").unwrap();
+        assert_eq!(keys.seal_chunk(&id, b"contents").unwrap(), sealed);
+        assert_ne!(keys.seal_chunk(&keys.chunk_id(b"other"), b"other").unwrap()[..NONCE_LEN], sealed[..NONCE_LEN]);
+        assert_eq!(keys.open_chunk(&id, &sealed).unwrap(), b"contents".to_vec());
+        // Sealed contents do not pass as another chunk
+        assert!(keys.open_chunk(&keys.chunk_id(b"other"), &sealed).is_err());
+    }
+}
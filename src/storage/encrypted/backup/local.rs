@@ -0,0 +1,105 @@
+//! Backups in a directory on a local or mounted file system
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::{BackupTarget, io_error};
+use crate::utils::error::Result;
+
+/// Suffix of files being written; `list` skips them
+const PARTIAL_SUFFIX: &str = ".tmp";
+
+pub struct LocalTarget {
+    dir: PathBuf,
+}
+
+impl LocalTarget {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self { dir: dir.as_ref().to_path_buf() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        key.split('/').fold(self.dir.clone(), |path, part| path.join(part))
+    }
+}
+
+impl BackupTarget for LocalTarget {
+    fn describe(&self) -> String {
+        format!("file://{}", self.dir.display())
+    }
+
+    /// Written to a temporary file first and renamed over the old one
+    fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error("create backup directory"))?;
+        }
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(PARTIAL_SUFFIX);
+
+        let mut file = fs::File::create(&tmp).map_err(io_error("write backup file"))?;
+        file.write_all(data)
+            .and_then(|_| file.sync_all())
+            .map_err(io_error("write backup file"))?;
+        fs::rename(&tmp, &path).map_err(io_error("write backup file"))
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error("read backup file")(e)),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.path(key).is_file())
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(self.path(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error("list backup directory")(e)),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(io_error("list backup directory"))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().map_err(io_error("list backup directory"))?.is_file()
+                && !name.ends_with(PARTIAL_SUFFIX)
+            {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(io_error("remove backup file")(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::encrypted::backup::exercise;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_local_target() {
+        let dir = tempdir().unwrap();
+        let target = LocalTarget::new(dir.path().join("backups"));
+        exercise(&target);
+
+        // Leftovers of an interrupted write are not objects
+        fs::write(dir.path().join("backups/chunks/c.tmp"), b"partial").unwrap();
+        assert_eq!(target.list("chunks").unwrap(), vec!["b".to_string()]);
+    }
+}
@@ -0,0 +1,295 @@
+//! Backups on an SFTP server
+//!
+//! URL: `sftp://[user@]host[:port]/path[?identity=<private key file>]`.
+//! Each operation runs the OpenSSH `sftp` client in batch mode, so host keys
+//! and credentials come from the usual SSH configuration: `known_hosts`, the
+//! agent or the identity file given. Password prompts are disabled.
+//!
+//! Objects are uploaded under a temporary name and renamed into place, which
+//! is atomic on servers supporting the `posix-rename` extension (OpenSSH
+//! does). Locally they pass through a private temporary file, created fresh
+//! and readable by the owner only, since key files are among them.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use tempfile::NamedTempFile;
+
+use super::{BackupTarget, io_error};
+use crate::utils::error::{Result, NodeError};
+
+/// Suffix of objects being uploaded; `list` skips them
+const PARTIAL_SUFFIX: &str = ".tmp";
+
+pub struct SftpTarget {
+    // `[user@]host`
+    destination: String,
+    port: Option<u16>,
+    dir: String,
+    identity: Option<String>,
+    program: String,
+}
+
+impl SftpTarget {
+    pub fn from_url(url: &str) -> Result<Self> {
+        let invalid = || NodeError::Config(format!("Invalid SFTP backup target {}", url));
+        let rest = url.strip_prefix("sftp://").ok_or_else(invalid)?;
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (authority, dir) = rest.split_once('/').ok_or_else(invalid)?;
+
+        let (destination, port) = match authority.rsplit_once(':') {
+            Some((destination, port)) => (destination, Some(port.parse().map_err(|_| invalid())?)),
+            None => (authority, None),
+        };
+        // A leading `-` would make sftp read it as an option
+        if destination.is_empty() || destination.ends_with('@') || destination.starts_with('-') {
+            return Err(invalid());
+        }
+
+        let mut identity = None;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            match pair.split_once('=').ok_or_else(invalid)? {
+                ("identity", value) if !value.starts_with('-') => identity = Some(value.to_string()),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let dir = format!("/{}", dir.trim_end_matches('/'));
+        check_path(&dir)?;
+        Ok(Self {
+            destination: destination.to_string(),
+            port,
+            dir,
+            identity,
+            program: "sftp".to_string(),
+        })
+    }
+
+    fn remote(&self, key: &str) -> Result<String> {
+        let path = format!("{}/{}", self.dir.trim_end_matches('/'), key);
+        check_path(&path)?;
+        Ok(path)
+    }
+
+    /// Run `commands` in one session. `Ok(Err(stderr))` if one failed; a
+    /// command prefixed with `-` may fail without ending the session.
+    fn batch(&self, commands: &[String]) -> Result<std::result::Result<String, String>> {
+        let mut command = Command::new(&self.program);
+        command.args(["-b", "-", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command.arg("-P").arg(port.to_string());
+        }
+        if let Some(identity) = &self.identity {
+            command.arg("-i").arg(identity);
+        }
+        let mut child = command
+            .arg("--")
+            .arg(&self.destination)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(io_error("run sftp"))?;
+
+        let script = commands.iter().map(|command| format!("{}\n", command)).collect::<String>();
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes()).map_err(io_error("run sftp"))?;
+        }
+        let output = child.wait_with_output().map_err(io_error("run sftp"))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        match output.status.success() {
+            true => Ok(Ok(stdout)),
+            false => Ok(Err(String::from_utf8_lossy(&output.stderr).trim().to_string())),
+        }
+    }
+
+    /// `batch`, failing unless every command succeeded
+    fn run(&self, commands: &[String]) -> Result<String> {
+        self.batch(commands)?.map_err(|stderr| self.failed(&stderr))
+    }
+
+    fn failed(&self, stderr: &str) -> NodeError {
+        NodeError::Storage(format!("SFTP on {} failed: {}", self.describe(), stderr))
+    }
+}
+
+/// New owner-only file in the temporary directory, removed on drop
+fn staging_file() -> Result<NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix("freeghost-sftp-")
+        .tempfile()
+        .map_err(io_error("create staging file"))
+}
+
+impl BackupTarget for SftpTarget {
+    fn describe(&self) -> String {
+        match self.port {
+            Some(port) => format!("sftp://{}:{}{}", self.destination, port, self.dir),
+            None => format!("sftp://{}{}", self.destination, self.dir),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let remote = self.remote(key)?;
+        let mut staging = staging_file()?;
+        staging
+            .write_all(data)
+            .and_then(|_| staging.flush())
+            .map_err(io_error("stage backup file"))?;
+
+        // Create missing parents; existing ones make `mkdir` fail harmlessly
+        let mut commands = Vec::new();
+        let mut parent = self.dir.clone();
+        commands.push(format!("-mkdir \"{}\"", parent));
+        for part in key.split('/').rev().skip(1).collect::<Vec<_>>().into_iter().rev() {
+            parent = format!("{}/{}", parent.trim_end_matches('/'), part);
+            commands.push(format!("-mkdir \"{}\"", parent));
+        }
+        let partial = format!("{}{}", remote, PARTIAL_SUFFIX);
+        commands.push(format!("put \"{}\" \"{}\"", staging.path().display(), partial));
+        commands.push(format!("rename \"{}\" \"{}\"", partial, remote));
+
+        self.run(&commands).map(|_| ())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let staging = staging_file()?;
+        match self.batch(&[format!("get \"{}\" \"{}\"", self.remote(key)?, staging.path().display())])? {
+            Ok(_) => fs::read(staging.path()).map(Some).map_err(io_error("read staged backup file")),
+            Err(stderr) if is_missing(&stderr) => Ok(None),
+            Err(stderr) => Err(self.failed(&stderr)),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        match self.batch(&[format!("ls \"{}\"", self.remote(key)?)])? {
+            Ok(_) => Ok(true),
+            Err(stderr) if is_missing(&stderr) => Ok(false),
+            Err(stderr) => Err(self.failed(&stderr)),
+        }
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>> {
+        match self.batch(&[format!("ls -1 \"{}\"", self.remote(dir)?)])? {
+            Ok(stdout) => Ok(parse_listing(&stdout)),
+            Err(stderr) if is_missing(&stderr) => Ok(Vec::new()),
+            Err(stderr) => Err(self.failed(&stderr)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match self.batch(&[format!("rm \"{}\"", self.remote(key)?)])? {
+            Err(stderr) if !is_missing(&stderr) => Err(self.failed(&stderr)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Paths go into batch commands in double quotes
+fn check_path(path: &str) -> Result<()> {
+    if path.contains(['"', '\\', '\n', '\r']) {
+        return Err(NodeError::Storage(format!("Unsupported SFTP path {:?}", path)));
+    }
+    Ok(())
+}
+
+/// How OpenSSH reports a missing file for `get`, `ls` and `rm`
+fn is_missing(stderr: &str) -> bool {
+    stderr.contains("not found") || stderr.contains("No such file")
+}
+
+/// Object names from `ls -1` output. In batch mode sftp echoes each command
+/// as `sftp> ...`, and lists entries with the directory in front.
+fn parse_listing(stdout: &str) -> Vec<String> {
+    let mut names: Vec<String> = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("sftp>"))
+        .map(|line| line.rsplit('/').next().unwrap_or(line).to_string())
+        .filter(|name| name != "." && name != ".." && !name.ends_with(PARTIAL_SUFFIX))
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_url_parsing() {
+        let target = SftpTarget::from_url("sftp://backup@vault.example:2222/srv/backups/?identity=/etc/freeghost/id_ed25519").unwrap();
+        assert_eq!(target.destination, "backup@vault.example");
+        assert_eq!(target.port, Some(2222));
+        assert_eq!(target.dir, "/srv/backups");
+        assert_eq!(target.identity.as_deref(), Some("/etc/freeghost/id_ed25519"));
+        assert_eq!(target.describe(), "sftp://backup@vault.example:2222/srv/backups");
+
+        let target = SftpTarget::from_url("sftp://vault.example/backups").unwrap();
+        assert_eq!(target.port, None);
+        assert_eq!(target.remote("chunks/a").unwrap(), "/backups/chunks/a");
+
+        assert!(SftpTarget::from_url("sftp://vault.example").is_err());
+        assert!(SftpTarget::from_url("sftp://vault.example:ssh/backups").is_err());
+        assert!(SftpTarget::from_url("sftp://vault.example/back\"ups").is_err());
+        // Neither may pass as an sftp option
+        assert!(SftpTarget::from_url("sftp://-oProxyCommand=touch%20pwned/backups").is_err());
+        assert!(SftpTarget::from_url("sftp://vault.example/backups?identity=-oProxyCommand=x").is_err());
+    }
+
+    #[test]
+    fn test_listing_parsed() {
+        let stdout = "sftp> ls -1 \"/srv/backups/chunks\"\n/srv/backups/chunks/a1\n/srv/backups/chunks/b2.tmp\n/srv/backups/chunks/0f\n";
+        assert_eq!(parse_listing(stdout), vec!["0f".to_string(), "a1".to_string()]);
+    }
+
+    /// Stands in for `sftp -b -`: runs the batch commands against the local
+    /// file system, with OpenSSH's error messages
+    #[cfg(unix)]
+    const FAKE_SFTP: &str = r#"#!/bin/sh
+fail() { echo "$1" >&2; $ignore || exit 1; }
+while IFS= read -r line; do
+    echo "sftp> $line"
+    ignore=false
+    case "$line" in -*) ignore=true; line="${line#-}" ;; esac
+    eval "set -- $line"
+    command="$1"; shift
+    case "$command" in
+        mkdir) mkdir "$1" 2>/dev/null || fail "Couldn't create directory: Failure" ;;
+        put) cp "$1" "$2" || fail "Couldn't write $2" ;;
+        rename) mv -f "$1" "$2" || fail "Couldn't rename file" ;;
+        get) if [ -f "$1" ]; then cp "$1" "$2"; else fail "File \"$1\" not found."; fi ;;
+        ls)
+            [ "$1" = "-1" ] && shift
+            if [ -d "$1" ]; then
+                for entry in "$1"/*; do [ -e "$entry" ] && echo "$entry"; done
+            elif [ -e "$1" ]; then
+                echo "$1"
+            else
+                fail "Can't ls: \"$1\" not found"
+            fi ;;
+        rm) if [ -f "$1" ]; then rm -f "$1"; else fail "Couldn't delete file: No such file or directory"; fi ;;
+        *) fail "Invalid command." ;;
+    esac
+done
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sftp_target() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let program = dir.path().join("sftp");
+        fs::write(&program, FAKE_SFTP).unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let url = format!("sftp://backup@localhost{}/backups", dir.path().display());
+        let mut target = SftpTarget::from_url(&url).unwrap();
+        target.program = program.to_str().unwrap().to_string();
+        crate::storage::encrypted::backup::exercise(&target);
+    }
+}
@@ -0,0 +1,119 @@
+//! Block compression per column family
+//!
+//! `compression_codec` picks the codec of every column family and
+//! `column_compression` overrides it for single ones, by column family name
+//! (`audit` included). `compression_enabled = false` turns compression off
+//! everywhere. A codec change applies to files RocksDB writes from then on;
+//! existing files keep theirs until compaction rewrites them.
+//!
+//! Values are encrypted before they reach RocksDB, so only keys and block
+//! metadata compress. Encrypted columns gain little from a codec; the audit
+//! log, which stores its entries in the clear, gains the most.
+
+use std::collections::HashMap;
+use rocksdb::DBCompressionType;
+
+use super::AUDIT_CF;
+use crate::{
+    utils::{
+        config::StorageConfig,
+        error::{Result, NodeError},
+    },
+    storage::kv::Column,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Codec {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Ok(Codec::None),
+            "lz4" => Ok(Codec::Lz4),
+            "zstd" => Ok(Codec::Zstd),
+            _ => Err(NodeError::Config(format!("Unknown compression codec {}; use lz4, zstd or none", name))),
+        }
+    }
+
+    pub(super) fn compression_type(self) -> DBCompressionType {
+        match self {
+            Codec::None => DBCompressionType::None,
+            Codec::Lz4 => DBCompressionType::Lz4,
+            Codec::Zstd => DBCompressionType::Zstd,
+        }
+    }
+}
+
+/// Codec of each column family
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnCodecs {
+    default: Codec,
+    columns: HashMap<String, Codec>,
+}
+
+impl Default for ColumnCodecs {
+    /// LZ4 everywhere
+    fn default() -> Self {
+        Self { default: Codec::Lz4, columns: HashMap::new() }
+    }
+}
+
+impl ColumnCodecs {
+    pub fn from_config(config: &StorageConfig) -> Result<Self> {
+        Self::new(config.compression_enabled, &config.compression_codec, &config.column_compression)
+    }
+
+    /// `codec` for every column family but those named in `columns`
+    pub fn new(enabled: bool, codec: &str, columns: &HashMap<String, String>) -> Result<Self> {
+        let default = Codec::parse(codec)?;
+        let mut overrides = HashMap::new();
+        for (name, codec) in columns {
+            if !column_families().any(|column| column == name) {
+                return Err(NodeError::Config(format!("Unknown column family {} in column_compression", name)));
+            }
+            overrides.insert(name.clone(), Codec::parse(codec)?);
+        }
+
+        if !enabled {
+            return Ok(Self { default: Codec::None, columns: HashMap::new() });
+        }
+        Ok(Self { default, columns: overrides })
+    }
+
+    pub fn codec(&self, column_family: &str) -> Codec {
+        self.columns.get(column_family).copied().unwrap_or(self.default)
+    }
+}
+
+/// Names of every column family of the database
+pub(super) fn column_families() -> impl Iterator<Item = &'static str> {
+    Column::ALL.iter().map(|column| column.name()).chain([AUDIT_CF])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codecs_per_column_family() {
+        let mut columns = HashMap::from([("identities".to_string(), "none".to_string())]);
+        let codecs = ColumnCodecs::new(true, "zstd", &columns).unwrap();
+        assert_eq!(codecs.codec("identities"), Codec::None);
+        assert_eq!(codecs.codec("audit"), Codec::Zstd);
+        assert_eq!(codecs.codec("default"), Codec::Zstd);
+
+        let codecs = ColumnCodecs::new(false, "zstd", &columns).unwrap();
+        assert!(column_families().all(|name| codecs.codec(name) == Codec::None));
+
+        // Mistakes are reported even with compression off
+        columns.insert("tokens".to_string(), "snappy".to_string());
+        assert!(ColumnCodecs::new(false, "lz4", &columns).is_err());
+        columns = HashMap::from([("blobs".to_string(), "lz4".to_string())]);
+        assert!(ColumnCodecs::new(true, "lz4", &columns).is_err());
+        assert!(ColumnCodecs::new(true, "brotli", &HashMap::new()).is_err());
+    }
+}
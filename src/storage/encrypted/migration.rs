@@ -0,0 +1,134 @@
+//! Migration of records written before values were bound to their keys
+//!
+//! Such records are in the legacy ciphertext format: encrypted without
+//! associated data, so they decrypt wherever they are copied. The regular
+//! read path refuses them. `migrate_record_aad` walks the default column in
+//! key order and rewrites each one under the active data key, bound to its
+//! key and the current record schema version. Legacy records predate column
+//! families, so no other column can hold one.
+//!
+//! Each batch holds the store's write lock, like a re-encryption batch.
+//! Migrated records are recognised by their format, so an interrupted run
+//! is finished by running it again.
+
+use rocksdb::{Direction, IteratorMode, WriteBatch};
+use tracing::{info, warn};
+
+use super::{EncryptedStore, encrypt_record};
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::key_manager::KeyManager,
+    storage::kv::Column,
+};
+
+/// Counts from one `migrate_record_aad` run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AadMigration {
+    pub scanned: u64,
+    pub migrated: u64,
+    /// Legacy records that failed to decrypt; they are left in place
+    pub failed: u64,
+}
+
+impl EncryptedStore {
+    /// Rewrite every legacy record, `batch_size` records at a time
+    pub fn migrate_record_aad(&self, batch_size: usize) -> Result<AadMigration> {
+        let batch_size = batch_size.max(1);
+        let mut report = AadMigration::default();
+        let mut cursor: Option<Vec<u8>> = None;
+
+        loop {
+            let _writes = self.writes.write();
+
+            let mode = match &cursor {
+                Some(key) => IteratorMode::From(key, Direction::Forward),
+                None => IteratorMode::Start,
+            };
+
+            let mut batch = WriteBatch::default();
+            let mut last = None;
+            let mut count = 0;
+            let mut finished = true;
+
+            for item in self.db.iterator(mode) {
+                let (key, value) = item
+                    .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?;
+                if cursor.as_deref() == Some(key.as_ref()) {
+                    continue;
+                }
+                if count == batch_size {
+                    finished = false;
+                    break;
+                }
+                count += 1;
+
+                if KeyManager::is_legacy(&value) {
+                    match self.key_manager.decrypt_legacy(&value) {
+                        Ok(plaintext) => {
+                            batch.put(&key, encrypt_record(&self.key_manager, Column::Default, &key, &plaintext)?);
+                            report.migrated += 1;
+                        }
+                        Err(e) => {
+                            warn!("Cannot migrate record {}: {}", String::from_utf8_lossy(&key), e);
+                            report.failed += 1;
+                        }
+                    }
+                }
+                last = Some(key);
+            }
+
+            report.scanned += count as u64;
+            self.db
+                .write(batch)
+                .map_err(|e| NodeError::Storage(format!("Batch operation failed: {}", e)))?;
+
+            if finished {
+                break;
+            }
+            cursor = last.map(Vec::from);
+        }
+
+        info!(
+            "Record migration finished: {} scanned, {} migrated, {} failed",
+            report.scanned, report.migrated, report.failed,
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::kv::KvStore;
+    use crate::utils::config::StorageConfig;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_legacy_records_migrated() {
+        let dir = tempdir().unwrap();
+        let store = EncryptedStore::new(&StorageConfig::for_test(dir.path())).await.unwrap();
+
+        // As an older release wrote them
+        for i in 0..5u8 {
+            let legacy = store.key_manager.encrypt_legacy(&[i]).unwrap();
+            store.db.put(format!("record:{}", i), legacy).unwrap();
+        }
+        store.db.put("record:corrupt", [1u8, 0, 0, 0, 1, 0xff]).unwrap();
+        store.put(Column::Default, b"record:current", &[42]).unwrap();
+
+        assert!(store.get(Column::Default, b"record:0").is_err());
+
+        let report = store.migrate_record_aad(2).unwrap();
+        assert_eq!(report, AadMigration { scanned: 7, migrated: 5, failed: 1 });
+        for i in 0..5u8 {
+            let key = format!("record:{}", i);
+            assert_eq!(store.get(Column::Default, key.as_bytes()).unwrap(), Some(vec![i]));
+        }
+        assert_eq!(store.get(Column::Default, b"record:current").unwrap(), Some(vec![42]));
+
+        // Nothing left to do on a second run
+        let report = store.migrate_record_aad(2).unwrap();
+        assert_eq!(report.migrated, 0);
+        assert_eq!(report.failed, 1);
+    }
+}
@@ -0,0 +1,198 @@
+//! Storage quota
+//!
+//! `max_size_gb` caps the live size of the database: the SST files RocksDB
+//! still uses plus its memtables, summed over every column family. Files
+//! waiting for deletion after a compaction and the WAL are not counted.
+//!
+//! The size is read from RocksDB properties at most every
+//! `MEASURE_INTERVAL` and extrapolated with the bytes written in between.
+//! A write that would take the store past the quota by that estimate is
+//! measured again before it is refused with `NodeError::QuotaExceeded`, so
+//! space freed by compaction is picked up at once.
+//!
+//! Only puts through `KvStore` are held to the quota. Deletes always go
+//! through, so a full store can be cleaned up. Re-encryption and migrations
+//! rewrite records that are already counted, and the audit log must not
+//! lose entries, so those write directly.
+//!
+//! Crossing one of the `quota_watermarks` (percentages of the quota) logs a
+//! warning once, until usage drops below it again. With the `metrics`
+//! feature the size, the quota and the watermark reached are also exported
+//! as gauges.
+
+use std::time::{Duration, Instant};
+use parking_lot::Mutex;
+use rocksdb::DB;
+use tracing::{info, warn};
+
+use super::compression::column_families;
+use crate::utils::{
+    config::StorageConfig,
+    error::{Result, NodeError},
+};
+
+/// Longest time a size estimate is trusted without reading it from RocksDB
+const MEASURE_INTERVAL: Duration = Duration::from_secs(5);
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Size of the store as of the last measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub used: u64,
+    pub limit: u64,
+    /// Highest watermark reached, if any
+    pub watermark: Option<u8>,
+}
+
+pub struct SizeQuota {
+    limit: u64,
+    // Ascending percentages
+    watermarks: Vec<u8>,
+    state: Mutex<QuotaState>,
+}
+
+struct QuotaState {
+    // Live size as last measured, plus what was admitted since
+    used: u64,
+    measured_at: Option<Instant>,
+    // Number of watermarks reached
+    level: usize,
+}
+
+impl SizeQuota {
+    pub fn new(limit: u64, watermarks: &[u8]) -> Self {
+        let mut watermarks = watermarks.to_vec();
+        watermarks.sort_unstable();
+        watermarks.dedup();
+        Self {
+            limit,
+            watermarks,
+            state: Mutex::new(QuotaState { used: 0, measured_at: None, level: 0 }),
+        }
+    }
+
+    pub fn from_config(config: &StorageConfig) -> Self {
+        Self::new(config.max_size_gb.saturating_mul(GIB), &config.quota_watermarks)
+    }
+
+    /// Let a write of `incoming` bytes through, or refuse it if it does not
+    /// fit. `measure` returns the live size; it is called when the estimate
+    /// is stale or the write does not fit by it.
+    pub fn admit(&self, incoming: u64, measure: impl FnOnce() -> Result<u64>) -> Result<()> {
+        let mut state = self.state.lock();
+        let stale = state.measured_at.is_none_or(|at| at.elapsed() >= MEASURE_INTERVAL);
+        if stale || state.used.saturating_add(incoming) > self.limit {
+            self.record(&mut state, measure()?);
+        }
+
+        if state.used.saturating_add(incoming) > self.limit {
+            #[cfg(feature = "metrics")]
+            metrics::counter!("storage.quota.rejected_writes", 1);
+            return Err(NodeError::QuotaExceeded { used: state.used, limit: self.limit, requested: incoming });
+        }
+        state.used += incoming;
+        Ok(())
+    }
+
+    /// Measure now and return the result
+    pub fn usage(&self, measure: impl FnOnce() -> Result<u64>) -> Result<QuotaUsage> {
+        let mut state = self.state.lock();
+        self.record(&mut state, measure()?);
+        Ok(QuotaUsage {
+            used: state.used,
+            limit: self.limit,
+            watermark: state.level.checked_sub(1).map(|index| self.watermarks[index]),
+        })
+    }
+
+    fn record(&self, state: &mut QuotaState, used: u64) {
+        state.used = used;
+        state.measured_at = Some(Instant::now());
+
+        let percent = used.saturating_mul(100) / self.limit.max(1);
+        let level = self.watermarks.iter().filter(|&&watermark| percent >= watermark as u64).count();
+        if level > state.level {
+            warn!(
+                used_bytes = used,
+                limit_bytes = self.limit,
+                watermark = self.watermarks[level - 1],
+                "Storage is at {}% of its quota",
+                percent,
+            );
+        } else if level < state.level {
+            info!(used_bytes = used, limit_bytes = self.limit, "Storage dropped to {}% of its quota", percent);
+        }
+        state.level = level;
+
+        #[cfg(feature = "metrics")]
+        {
+            metrics::gauge!("storage.size_bytes", used as f64);
+            metrics::gauge!("storage.quota_bytes", self.limit as f64);
+            let watermark = level.checked_sub(1).map_or(0, |index| self.watermarks[index]);
+            metrics::gauge!("storage.quota.watermark", watermark as f64);
+        }
+    }
+}
+
+/// Live size of `db`: SST files in use plus memtables, over every column
+/// family
+pub(super) fn live_size(db: &DB) -> Result<u64> {
+    let mut size = 0;
+    for name in column_families() {
+        let cf = db
+            .cf_handle(name)
+            .ok_or_else(|| NodeError::Storage(format!("Missing column family {}", name)))?;
+        for property in ["rocksdb.live-sst-files-size", "rocksdb.size-all-mem-tables"] {
+            size += db
+                .property_int_value_cf(cf, property)
+                .map_err(|e| NodeError::Storage(format!("Failed to read {}: {}", property, e)))?
+                .unwrap_or(0);
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_writes_refused_beyond_quota() {
+        let quota = SizeQuota::new(1000, &[]);
+        let measured = Cell::new(0);
+        let measure = || {
+            measured.set(measured.get() + 1);
+            Ok(600)
+        };
+
+        quota.admit(100, measure).unwrap();
+        assert_eq!(measured.get(), 1);
+        // Fresh estimate: no measurement while writes fit
+        quota.admit(200, measure).unwrap();
+        assert_eq!(measured.get(), 1);
+
+        // 900 estimated; remeasured before refusing
+        let refused = quota.admit(500, measure).unwrap_err();
+        assert_eq!(measured.get(), 2);
+        assert!(matches!(refused, NodeError::QuotaExceeded { used: 600, limit: 1000, requested: 500 }));
+
+        // Compaction freed space
+        quota.admit(200, || Ok(100)).unwrap();
+        assert_eq!(quota.usage(|| Ok(300)).unwrap().used, 300);
+    }
+
+    #[test]
+    fn test_watermarks_reached_and_left() {
+        let quota = SizeQuota::new(1000, &[90, 75, 90]);
+        let usage = |used| quota.usage(move || Ok(used)).unwrap().watermark;
+
+        assert_eq!(usage(700), None);
+        assert_eq!(usage(800), Some(75));
+        assert_eq!(usage(950), Some(90));
+        assert_eq!(usage(760), Some(75));
+        assert_eq!(usage(10), None);
+        assert_eq!(usage(1200), Some(90));
+    }
+}
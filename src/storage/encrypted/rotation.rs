@@ -0,0 +1,509 @@
+//! Scheduled data key rotation
+//!
+//! A `RotationPolicy` decides when the active DEK has been in use long
+//! enough, by age or by number of encryptions. Rotating only adds a key
+//! version; a `ReencryptJob` then walks the columns one after another, each
+//! in key order, and rewrites records still under older versions, one
+//! bounded batch at a time.
+//! Each batch commits in the same write as the job's checkpoint, so a
+//! restart resumes after the last committed batch, and the scheduler paces
+//! batches to a configured record rate.
+//!
+//! Older versions stay in the keyring once a job finishes: backups taken
+//! before the rotation still need them.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rocksdb::{Direction, IteratorMode, WriteBatch};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinHandle;
+use tracing::{info, warn, error};
+
+use super::{EncryptedStore, decrypt_record, encrypt_record};
+use crate::storage::kv::Column;
+use crate::{
+    utils::{
+        config::StorageConfig,
+        error::{Result, NodeError},
+    },
+    core::crypto::{
+        key_manager::KeyManager,
+        quantum::SecurityLevel,
+        types::CryptoMetadata,
+    },
+};
+
+/// Rotation bookkeeping, stored encrypted like any other record
+const STATE_KEY: &str = "rotation:state";
+
+#[derive(Debug, Clone)]
+pub struct RotationPolicy {
+    pub max_age: Option<Duration>,
+    pub max_uses: Option<u64>,
+    pub check_interval: Duration,
+    pub batch_size: usize,
+    /// Records per second; `None` runs batches back to back
+    pub rate: Option<u64>,
+}
+
+impl From<&StorageConfig> for RotationPolicy {
+    fn from(config: &StorageConfig) -> Self {
+        Self {
+            max_age: Some(config.key_rotation_max_age)
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            max_uses: Some(config.key_rotation_max_uses).filter(|&uses| uses > 0),
+            check_interval: Duration::from_secs(config.key_rotation_check_interval),
+            batch_size: config.reencrypt_batch_size.max(1),
+            rate: Some(config.reencrypt_rate).filter(|&rate| rate > 0),
+        }
+    }
+}
+
+impl RotationPolicy {
+    pub fn is_due(&self, key_age: Duration, uses: u64) -> bool {
+        self.max_age.is_some_and(|max| key_age >= max)
+            || self.max_uses.is_some_and(|max| uses >= max)
+    }
+
+    /// Minimum time a batch of `records` should take at the configured rate
+    fn pace(&self, records: u64) -> Duration {
+        self.rate
+            .map(|rate| Duration::from_secs_f64(records as f64 / rate as f64))
+            .unwrap_or_default()
+    }
+}
+
+/// Progress of re-encrypting the store under `target_version`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReencryptJob {
+    pub target_version: u32,
+    /// Column `cursor` is in
+    #[serde(default = "default_column")]
+    pub column: Column,
+    /// Last key processed, hex encoded; the next batch starts after it
+    pub cursor: Option<String>,
+    pub scanned: u64,
+    pub rewritten: u64,
+    /// Records that are not ciphertexts of this store or fail to decrypt
+    pub skipped: u64,
+    pub started_at: i64,
+}
+
+impl ReencryptJob {
+    fn new(target_version: u32) -> Self {
+        Self {
+            target_version,
+            column: Column::Default,
+            cursor: None,
+            scanned: 0,
+            rewritten: 0,
+            skipped: 0,
+            started_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+/// Jobs saved before column families only covered the default column
+fn default_column() -> Column {
+    Column::Default
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReencryptProgress {
+    Idle,
+    Running(ReencryptJob),
+    Finished(ReencryptJob),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RotationState {
+    metadata: CryptoMetadata,
+    /// Active version when `uses` was recorded
+    version: u32,
+    uses: u64,
+    job: Option<ReencryptJob>,
+}
+
+impl EncryptedStore {
+    /// Add a data key version, make it active and queue re-encryption of
+    /// everything written under older versions. Returns the new version.
+    pub async fn rotate_encryption_key(&self) -> Result<u32> {
+        let version = self.key_manager.rotate_keys()?;
+
+        let mut state = self.rotation_state()?;
+        state.metadata.record_key_rotation();
+        state.version = version;
+        state.uses = 0;
+        // A job for an older target has to start over: records it already
+        // passed are behind the new version too
+        state.job = Some(ReencryptJob::new(version));
+        self.put_rotation_state(&state)?;
+
+        info!("Rotated data key to version {}", version);
+        Ok(version)
+    }
+
+    /// Time since the active data key was created
+    pub fn key_age(&self) -> Duration {
+        let created_at = self.key_manager.active_created_at();
+        let age = chrono::Utc::now().timestamp().saturating_sub(created_at);
+        Duration::from_secs(age.max(0) as u64)
+    }
+
+    pub fn key_uses(&self) -> u64 {
+        self.key_manager.active_uses()
+    }
+
+    /// Current re-encryption job, if one is pending
+    pub fn reencrypt_job(&self) -> Result<Option<ReencryptJob>> {
+        Ok(self.rotation_state()?.job)
+    }
+
+    /// Re-encrypt up to `batch_size` records after the job's cursor and
+    /// commit them together with the advanced cursor
+    pub async fn reencrypt_batch(&self, batch_size: usize) -> Result<ReencryptProgress> {
+        // Held for one batch; see `writes`
+        let _writes = self.writes.write();
+
+        let mut state = self.rotation_state()?;
+        let Some(mut job) = state.job.take() else {
+            return Ok(ReencryptProgress::Idle);
+        };
+
+        let mut start = job.cursor
+            .as_deref()
+            .map(hex::decode)
+            .transpose()
+            .map_err(|e| NodeError::Storage(format!("Invalid re-encryption cursor: {}", e)))?;
+
+        let mut batch = WriteBatch::default();
+        let mut last = None;
+        let mut count = 0;
+        let mut finished = true;
+
+        let first = Column::ALL.iter().position(|&column| column == job.column).unwrap_or(0);
+        'columns: for &column in &Column::ALL[first..] {
+            let mode = match &start {
+                Some(key) => IteratorMode::From(key, Direction::Forward),
+                None => IteratorMode::Start,
+            };
+
+            for item in self.db.iterator_cf(self.cf(column)?, mode) {
+                let (key, value) = item
+                    .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?;
+                if start.as_deref() == Some(key.as_ref()) {
+                    continue;
+                }
+                if count == batch_size {
+                    finished = false;
+                    break 'columns;
+                }
+                count += 1;
+                last = Some((column, key.clone()));
+
+                if column == Column::Default && key.as_ref() == STATE_KEY.as_bytes() {
+                    continue;
+                }
+                match KeyManager::key_version(&value) {
+                    Ok(version) if version < job.target_version => {
+                        match decrypt_record(&self.key_manager, column, &key, &value) {
+                            Ok(plaintext) => {
+                                let encrypted = encrypt_record(&self.key_manager, column, &key, &plaintext)?;
+                                batch.put_cf(self.cf(column)?, &key, encrypted);
+                                job.rewritten += 1;
+                            }
+                            Err(e) => {
+                                // Leave it for an operator rather than stall the job
+                                warn!("Skipping record {} during re-encryption: {}", String::from_utf8_lossy(&key), e);
+                                job.skipped += 1;
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(_) => job.skipped += 1,
+                }
+            }
+            // The cursor only applies to the column it was taken in
+            start = None;
+        }
+
+        job.scanned += count as u64;
+        if let Some((column, key)) = last {
+            job.column = column;
+            job.cursor = Some(hex::encode(key));
+        }
+        state.job = (!finished).then(|| job.clone());
+
+        batch.put(STATE_KEY.as_bytes(), self.encrypt_state(&state)?);
+        self.db
+            .write(batch)
+            .map_err(|e| NodeError::Storage(format!("Batch operation failed: {}", e)))?;
+
+        Ok(if finished {
+            ReencryptProgress::Finished(job)
+        } else {
+            ReencryptProgress::Running(job)
+        })
+    }
+
+    /// Record the active key's usage count so it survives a restart
+    pub fn checkpoint_key_uses(&self) -> Result<()> {
+        let mut state = self.rotation_state()?;
+        let uses = self.key_manager.active_uses();
+        if state.uses != uses {
+            state.uses = uses;
+            self.put_rotation_state(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Called on open: carry the persisted usage count over to the keyring
+    pub(super) fn restore_key_uses(&self) -> Result<()> {
+        let state = self.rotation_state()?;
+        if state.version == self.key_manager.active_version() {
+            self.key_manager.add_uses(state.uses);
+        }
+        Ok(())
+    }
+
+    fn rotation_state(&self) -> Result<RotationState> {
+        let active = self.key_manager.active_version();
+        let stored = self.db
+            .get(STATE_KEY.as_bytes())
+            .map_err(|e| NodeError::Storage(format!("Database read failed: {}", e)))?
+            .map(|encrypted| -> Result<RotationState> {
+                // Written before values were bound to their keys; read it so
+                // the store still opens for `migrate_record_aad`
+                let decrypted = if KeyManager::is_legacy(&encrypted) {
+                    self.key_manager.decrypt_legacy(&encrypted)?
+                } else {
+                    decrypt_record(&self.key_manager, Column::Default, STATE_KEY.as_bytes(), &encrypted)?
+                };
+                serde_json::from_slice(&decrypted)
+                    .map_err(|e| NodeError::Storage(format!("Invalid rotation state: {}", e)))
+            })
+            .transpose()?;
+
+        Ok(match stored {
+            Some(state) if state.version == active => state,
+            // The keyring moved on without us, e.g. a crash between rotating
+            // and writing the state, or a rotation through the key manager
+            Some(mut state) => {
+                state.metadata.record_key_rotation();
+                state.version = active;
+                state.uses = 0;
+                state.job = Some(ReencryptJob::new(active));
+                state
+            }
+            None => {
+                let oldest = self.key_manager.versions().first().copied().unwrap_or(active);
+                RotationState {
+                    metadata: CryptoMetadata::new(SecurityLevel::High),
+                    version: active,
+                    uses: 0,
+                    job: (oldest < active).then(|| ReencryptJob::new(active)),
+                }
+            }
+        })
+    }
+
+    fn put_rotation_state(&self, state: &RotationState) -> Result<()> {
+        let _writes = self.writes.read();
+        self.db
+            .put(STATE_KEY.as_bytes(), self.encrypt_state(state)?)
+            .map_err(|e| NodeError::Storage(format!("Database write failed: {}", e)))
+    }
+
+    fn encrypt_state(&self, state: &RotationState) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(state)
+            .map_err(|e| NodeError::Storage(format!("Serialization failed: {}", e)))?;
+        encrypt_record(&self.key_manager, Column::Default, STATE_KEY.as_bytes(), &serialized)
+    }
+}
+
+/// Background task applying a `RotationPolicy` to a store
+pub struct KeyRotationScheduler {
+    storage: Arc<EncryptedStore>,
+    policy: RotationPolicy,
+}
+
+impl KeyRotationScheduler {
+    pub fn new(storage: Arc<EncryptedStore>, policy: RotationPolicy) -> Self {
+        Self { storage, policy }
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.policy.check_interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run_once().await {
+                    error!("Key rotation failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Rotate if the policy says so, then drive any pending job to the end
+    pub async fn run_once(&self) -> Result<()> {
+        self.storage.checkpoint_key_uses()?;
+        if self.policy.is_due(self.storage.key_age(), self.storage.key_uses()) {
+            self.storage.rotate_encryption_key().await?;
+        }
+
+        loop {
+            let started = Instant::now();
+            let progress = self.storage
+                .reencrypt_batch(self.policy.batch_size)
+                .await?;
+
+            match progress {
+                ReencryptProgress::Idle => return Ok(()),
+                ReencryptProgress::Finished(job) => {
+                    info!(
+                        "Re-encryption to key version {} finished: {} scanned, {} rewritten, {} skipped",
+                        job.target_version, job.scanned, job.rewritten, job.skipped,
+                    );
+                    return Ok(());
+                }
+                ReencryptProgress::Running(_) => {
+                    let pace = self.policy.pace(self.policy.batch_size as u64);
+                    tokio::time::sleep(pace.saturating_sub(started.elapsed())).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::kv::KvStore;
+    use tempfile::tempdir;
+
+    fn test_config(path: &std::path::Path) -> StorageConfig {
+        StorageConfig {
+            reencrypt_batch_size: 4,
+            ..StorageConfig::for_test(path)
+        }
+    }
+
+    /// Spread over the columns, so jobs have to move from one to the next
+    fn put_test_record(store: &EncryptedStore, i: usize) {
+        let column = Column::ALL[i % Column::ALL.len()];
+        store.put(column, format!("record:{}", i).as_bytes(), &[i as u8]).unwrap();
+    }
+
+    fn get_test_record(store: &EncryptedStore, i: usize) -> Option<u8> {
+        let column = Column::ALL[i % Column::ALL.len()];
+        store.get(column, format!("record:{}", i).as_bytes()).unwrap().map(|value| value[0])
+    }
+
+    async fn versions_in_use(store: &EncryptedStore) -> Vec<u32> {
+        let mut versions: Vec<u32> = Column::ALL
+            .iter()
+            .flat_map(|&column| store.db.iterator_cf(store.cf(column).unwrap(), IteratorMode::Start))
+            .map(|item| KeyManager::key_version(&item.unwrap().1).unwrap())
+            .collect();
+        versions.sort();
+        versions.dedup();
+        versions
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut config = test_config(std::path::Path::new("."));
+        assert!(!RotationPolicy::from(&config).is_due(Duration::from_secs(u32::MAX as u64), u64::MAX));
+
+        config.key_rotation_max_age = 60;
+        config.key_rotation_max_uses = 10;
+        let policy = RotationPolicy::from(&config);
+        assert!(!policy.is_due(Duration::from_secs(59), 9));
+        assert!(policy.is_due(Duration::from_secs(60), 0));
+        assert!(policy.is_due(Duration::ZERO, 10));
+    }
+
+    #[tokio::test]
+    async fn test_incremental_reencryption() {
+        let dir = tempdir().unwrap();
+        let store = EncryptedStore::new(&test_config(dir.path())).await.unwrap();
+        for i in 0..10 {
+            put_test_record(&store, i);
+        }
+        assert_eq!(store.reencrypt_batch(4).await.unwrap(), ReencryptProgress::Idle);
+
+        let version = store.rotate_encryption_key().await.unwrap();
+        assert_eq!(version, 2);
+
+        // Ten records plus the state record, four at a time
+        let mut batches = 0;
+        loop {
+            batches += 1;
+            match store.reencrypt_batch(4).await.unwrap() {
+                ReencryptProgress::Running(job) => assert!(job.scanned < 11),
+                ReencryptProgress::Finished(job) => {
+                    assert_eq!(job.rewritten, 10);
+                    assert_eq!(job.skipped, 0);
+                    break;
+                }
+                ReencryptProgress::Idle => panic!("job disappeared"),
+            }
+        }
+        assert_eq!(batches, 3);
+        assert_eq!(versions_in_use(&store).await, vec![2]);
+
+        for i in 0..10 {
+            assert_eq!(get_test_record(&store, i), Some(i as u8));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_resumes_after_restart() {
+        let dir = tempdir().unwrap();
+        let config = test_config(dir.path());
+
+        {
+            let store = EncryptedStore::new(&config).await.unwrap();
+            for i in 0..10 {
+                put_test_record(&store, i);
+            }
+            store.rotate_encryption_key().await.unwrap();
+            assert!(matches!(store.reencrypt_batch(4).await.unwrap(), ReencryptProgress::Running(_)));
+        }
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        let job = store.reencrypt_job().unwrap().unwrap();
+        assert_eq!(job.scanned, 4);
+        assert!(job.cursor.is_some());
+
+        // Written while the job runs: already under the new version
+        put_test_record(&store, 99);
+
+        while let ReencryptProgress::Running(_) = store.reencrypt_batch(4).await.unwrap() {}
+        assert_eq!(versions_in_use(&store).await, vec![2]);
+        assert_eq!(get_test_record(&store, 99), Some(99));
+        assert!(store.reencrypt_job().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_rotates_by_usage() {
+        let dir = tempdir().unwrap();
+        let mut config = test_config(dir.path());
+        config.key_rotation_max_uses = 5;
+
+        let store = EncryptedStore::new(&config).await.unwrap();
+        for i in 0..5 {
+            put_test_record(&store, i);
+        }
+
+        let store = Arc::new(store);
+        let scheduler = KeyRotationScheduler::new(store.clone(), RotationPolicy::from(&config));
+        scheduler.run_once().await.unwrap();
+
+        assert_eq!(store.key_manager.active_version(), 2);
+        assert_eq!(versions_in_use(&store).await, vec![2]);
+        assert!(store.reencrypt_job().unwrap().is_none());
+        assert_eq!(get_test_record(&store, 4), Some(4));
+    }
+}
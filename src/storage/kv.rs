@@ -0,0 +1,359 @@
+//! Storage interface shared by every subsystem
+//!
+//! A `KvStore` is a set of ordered maps of byte keys to byte values, one per
+//! `Column`, with atomic batches, forward iteration and point-in-time
+//! snapshots. Services hold a `SharedStore` and never name a backend;
+//! `KvStoreExt` layers the typed records and key layouts they use on top of
+//! any store.
+//!
+//! Backends:
+//! - `EncryptedStore`: RocksDB, every value encrypted under the keyring
+//! - `MemoryStore`: a `BTreeMap` in process memory, for tests
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::{
+    utils::error::Result,
+    core::identity::types::Identity,
+};
+use super::record::{self, Record};
+
+/// Forward iterator over `(key, value)` pairs in key order
+pub type KvIter<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+pub type SharedStore = Arc<dyn KvStore>;
+
+/// Key prefix of spent tokens in `Column::Tokens`: `spent:<epoch>:<id>`
+const SPENT_TOKEN_PREFIX: &str = "spent:";
+/// Key prefix of issuance counters: `issued:<epoch>:<identity>`
+const ISSUED_TOKENS_PREFIX: &str = "issued:";
+/// Key prefix of the template hash index in `Column::Indexes`
+const TEMPLATE_INDEX_PREFIX: &str = "template:";
+
+/// Column families of the node database, one per kind of record. The audit
+/// log has a column family of its own, written by `AuditSystem`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Column {
+    /// Node bookkeeping: schema version, rotation state, signing keys
+    Default,
+    Identities,
+    NetworkState,
+    /// Spent tokens and issuance quotas
+    Tokens,
+    /// Secondary indexes into the other columns
+    Indexes,
+    /// Raft term, vote, log and snapshot
+    Consensus,
+}
+
+impl Column {
+    pub const ALL: [Column; 6] = [
+        Column::Default,
+        Column::Identities,
+        Column::NetworkState,
+        Column::Tokens,
+        Column::Indexes,
+        Column::Consensus,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Default => "default",
+            Column::Identities => "identities",
+            Column::NetworkState => "network_state",
+            Column::Tokens => "tokens",
+            Column::Indexes => "indexes",
+            Column::Consensus => "consensus",
+        }
+    }
+}
+
+pub trait KvStore: Send + Sync {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()>;
+
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()>;
+
+    /// Insert unless `key` exists, atomically. Returns whether it inserted.
+    fn put_if_absent(&self, column: Column, key: &[u8], value: &[u8]) -> Result<bool>;
+
+    /// Apply every operation in `batch` or none of them
+    fn write(&self, batch: KvBatch) -> Result<()>;
+
+    /// Entries of `column` with keys at or after `start`
+    fn iter_from(&self, column: Column, start: &[u8]) -> Result<KvIter<'_>>;
+
+    /// Read-only view of the store as it is now; later writes do not show
+    fn snapshot(&self) -> Result<Box<dyn KvSnapshot + '_>>;
+
+    /// Entries of `column` whose key starts with `prefix`
+    fn scan_prefix(&self, column: Column, prefix: &[u8]) -> Result<KvIter<'_>> {
+        let prefix = prefix.to_vec();
+        let iter = self.iter_from(column, &prefix)?;
+        Ok(Box::new(iter.take_while(move |item| match item {
+            Ok((key, _)) => key.starts_with(&prefix),
+            Err(_) => true,
+        })))
+    }
+}
+
+pub trait KvSnapshot {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn iter_from(&self, column: Column, start: &[u8]) -> Result<KvIter<'_>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    Put(Column, Vec<u8>, Vec<u8>),
+    Delete(Column, Vec<u8>),
+}
+
+/// Writes applied atomically by `KvStore::write`
+#[derive(Debug, Clone, Default)]
+pub struct KvBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl KvBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, column: Column, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.ops.push(BatchOp::Put(column, key.into(), value.into()));
+        self
+    }
+
+    pub fn delete(&mut self, column: Column, key: impl Into<Vec<u8>>) -> &mut Self {
+        self.ops.push(BatchOp::Delete(column, key.into()));
+        self
+    }
+
+    /// Put `record` under `key` in its column
+    pub fn put_record<R: Record>(&mut self, key: impl Into<Vec<u8>>, record: &R) -> Result<&mut Self> {
+        Ok(self.put(R::COLUMN, key, record::encode(record)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+}
+
+/// Typed records and the key layouts services share, on any `KvStore`
+pub trait KvStoreExt: KvStore {
+    fn put_record<R: Record>(&self, key: &[u8], record: &R) -> Result<()> {
+        self.put(R::COLUMN, key, &record::encode(record)?)
+    }
+
+    fn get_record<R: Record>(&self, key: &[u8]) -> Result<Option<R>> {
+        self.get(R::COLUMN, key)?
+            .map(|bytes| record::decode(&bytes))
+            .transpose()
+    }
+
+    /// Every record under `prefix` in the record's column, in key order
+    fn get_records<R: Record>(&self, prefix: &[u8]) -> Result<Vec<R>> {
+        self.scan_prefix(R::COLUMN, prefix)?
+            .map(|item| item.and_then(|(_, value)| record::decode(&value)))
+            .collect()
+    }
+
+    /// Store `identity` and point its template hash at it, in one write
+    fn store_identity(&self, identity: &Identity) -> Result<()> {
+        let mut batch = KvBatch::new();
+        batch
+            .put_record(identity.id.as_bytes().to_vec(), identity)?
+            .put_record(template_index_key(&identity.template.hash), &IdentityRef { id: identity.id })?;
+        self.write(batch)
+    }
+
+    fn get_identity(&self, id: &Uuid) -> Result<Option<Identity>> {
+        self.get_record(id.as_bytes())
+    }
+
+    /// Identity enrolled with the template of hash `template_hash`
+    fn find_identity_by_template(&self, template_hash: &str) -> Result<Option<Identity>> {
+        match self.get_record::<IdentityRef>(&template_index_key(template_hash))? {
+            Some(entry) => self.get_identity(&entry.id),
+            None => Ok(None),
+        }
+    }
+
+    fn delete_identity(&self, id: &Uuid) -> Result<()> {
+        let mut batch = KvBatch::new();
+        if let Some(identity) = self.get_identity(id)? {
+            batch.delete(Column::Indexes, template_index_key(&identity.template.hash));
+        }
+        batch.delete(Column::Identities, id.as_bytes().to_vec());
+        self.write(batch)
+    }
+
+    /// Record a redeemed token. Returns `false` if it was already spent.
+    fn mark_token_spent(&self, epoch: u64, token_id: &[u8]) -> Result<bool> {
+        let spent = record::encode(&SpentToken { spent_at: chrono::Utc::now().timestamp() })?;
+        self.put_if_absent(Column::Tokens, &spent_token_key(epoch, token_id), &spent)
+    }
+
+    fn is_token_spent(&self, epoch: u64, token_id: &[u8]) -> Result<bool> {
+        Ok(self.get(Column::Tokens, &spent_token_key(epoch, token_id))?.is_some())
+    }
+
+    /// Drop spent-token entries and issuance counters of epochs before
+    /// `epoch`; their tokens can no longer be redeemed anyway. Returns the
+    /// number of entries removed.
+    fn prune_spent_tokens(&self, epoch: u64) -> Result<usize> {
+        let mut batch = KvBatch::new();
+
+        for prefix in [SPENT_TOKEN_PREFIX, ISSUED_TOKENS_PREFIX] {
+            let end = format!("{}{:020}:", prefix, epoch).into_bytes();
+            for item in self.scan_prefix(Column::Tokens, prefix.as_bytes())? {
+                let (key, _) = item?;
+                if key >= end {
+                    break;
+                }
+                batch.delete(Column::Tokens, key);
+            }
+        }
+
+        let removed = batch.len();
+        self.write(batch)?;
+        Ok(removed)
+    }
+
+    /// Tokens issued to `identity` in `epoch`
+    fn issued_tokens(&self, epoch: u64, identity: &Uuid) -> Result<u64> {
+        Ok(self
+            .get_record::<IssuedTokens>(&issued_tokens_key(epoch, identity))?
+            .map_or(0, |issued| issued.count))
+    }
+
+    fn set_issued_tokens(&self, epoch: u64, identity: &Uuid, count: u64) -> Result<()> {
+        self.put_record(&issued_tokens_key(epoch, identity), &IssuedTokens { count })
+    }
+}
+
+impl<S: KvStore + ?Sized> KvStoreExt for S {}
+
+/// Value of a spent-token entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SpentToken {
+    pub spent_at: i64,
+}
+
+impl Record for SpentToken {
+    const COLUMN: Column = Column::Tokens;
+    const VERSION: u8 = 1;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IssuedTokens {
+    pub count: u64,
+}
+
+impl Record for IssuedTokens {
+    const COLUMN: Column = Column::Tokens;
+    const VERSION: u8 = 1;
+}
+
+/// Index entry pointing at an identity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IdentityRef {
+    pub id: Uuid,
+}
+
+impl Record for IdentityRef {
+    const COLUMN: Column = Column::Indexes;
+    const VERSION: u8 = 1;
+}
+
+pub(crate) fn template_index_key(template_hash: &str) -> Vec<u8> {
+    format!("{}{}", TEMPLATE_INDEX_PREFIX, template_hash).into_bytes()
+}
+
+/// Epochs are zero-padded so keys sort by epoch
+pub(crate) fn spent_token_key(epoch: u64, token_id: &[u8]) -> Vec<u8> {
+    format!("{}{:020}:{}", SPENT_TOKEN_PREFIX, epoch, hex::encode(token_id)).into_bytes()
+}
+
+pub(crate) fn issued_tokens_key(epoch: u64, identity: &Uuid) -> Vec<u8> {
+    format!("{}{:020}:{}", ISSUED_TOKENS_PREFIX, epoch, identity).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::identity::types::BiometricTemplate;
+    use crate::storage::memory::MemoryStore;
+
+    #[test]
+    fn test_records() {
+        let store: SharedStore = Arc::new(MemoryStore::new());
+        store.put_record(b"issued:2", &IssuedTokens { count: 2 }).unwrap();
+        store.put_record(b"issued:1", &IssuedTokens { count: 1 }).unwrap();
+        store.put_record(b"other", &IssuedTokens { count: 3 }).unwrap();
+
+        // Records land in their own column
+        assert!(store.get(Column::Default, b"issued:1").unwrap().is_none());
+        assert_eq!(store.get_record::<IssuedTokens>(b"issued:1").unwrap().unwrap().count, 1);
+        assert!(store.get_record::<IssuedTokens>(b"missing").unwrap().is_none());
+
+        let counts: Vec<u64> = store
+            .get_records::<IssuedTokens>(b"issued:")
+            .unwrap()
+            .into_iter()
+            .map(|issued| issued.count)
+            .collect();
+        assert_eq!(counts, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_template_index() {
+        let store = MemoryStore::new();
+        let template = BiometricTemplate::new(vec![0.1, 0.2], 0.9, "hash-a".to_string());
+        let identity = Identity::new(template);
+
+        store.store_identity(&identity).unwrap();
+        let found = store.find_identity_by_template("hash-a").unwrap().unwrap();
+        assert_eq!(found.id, identity.id);
+        assert!(store.find_identity_by_template("hash-b").unwrap().is_none());
+
+        store.delete_identity(&identity.id).unwrap();
+        assert!(store.get_identity(&identity.id).unwrap().is_none());
+        assert!(store.get(Column::Indexes, &template_index_key("hash-a")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_spent_token_set() {
+        let store = MemoryStore::new();
+
+        // First redemption succeeds, the second is a double spend
+        assert!(store.mark_token_spent(7, &[1u8; 32]).unwrap());
+        assert!(!store.mark_token_spent(7, &[1u8; 32]).unwrap());
+        assert!(store.is_token_spent(7, &[1u8; 32]).unwrap());
+
+        // The same id under another epoch is a different token
+        assert!(store.mark_token_spent(8, &[1u8; 32]).unwrap());
+
+        // Pruning only removes older epochs, issuance counters included
+        let identity = Uuid::new_v4();
+        store.set_issued_tokens(7, &identity, 3).unwrap();
+        store.set_issued_tokens(8, &identity, 1).unwrap();
+        assert_eq!(store.prune_spent_tokens(8).unwrap(), 2);
+        assert!(!store.is_token_spent(7, &[1u8; 32]).unwrap());
+        assert!(store.is_token_spent(8, &[1u8; 32]).unwrap());
+        assert_eq!(store.issued_tokens(7, &identity).unwrap(), 0);
+        assert_eq!(store.issued_tokens(8, &identity).unwrap(), 1);
+    }
+}
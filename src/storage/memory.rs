@@ -0,0 +1,163 @@
+//! In-memory `KvStore` for tests and volatile setups. Nothing is encrypted
+//! or persisted.
+
+use std::collections::BTreeMap;
+use parking_lot::RwLock;
+
+use crate::utils::error::Result;
+use super::kv::{BatchOp, Column, KvBatch, KvIter, KvSnapshot, KvStore};
+
+/// Entries of every column, keyed by column first so each one stays ordered
+type Entries = BTreeMap<(Column, Vec<u8>), Vec<u8>>;
+
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: RwLock<Entries>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().is_empty()
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.read().get(&(column, key.to_vec())).cloned())
+    }
+
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()> {
+        self.entries.write().insert((column, key.to_vec()), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()> {
+        self.entries.write().remove(&(column, key.to_vec()));
+        Ok(())
+    }
+
+    fn put_if_absent(&self, column: Column, key: &[u8], value: &[u8]) -> Result<bool> {
+        let mut entries = self.entries.write();
+        let key = (column, key.to_vec());
+        if entries.contains_key(&key) {
+            return Ok(false);
+        }
+        entries.insert(key, value.to_vec());
+        Ok(true)
+    }
+
+    fn write(&self, batch: KvBatch) -> Result<()> {
+        let mut entries = self.entries.write();
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Put(column, key, value) => {
+                    entries.insert((column, key), value);
+                }
+                BatchOp::Delete(column, key) => {
+                    entries.remove(&(column, key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn iter_from(&self, column: Column, start: &[u8]) -> Result<KvIter<'_>> {
+        // Copied out so no lock is held while the caller iterates
+        Ok(range_from(&self.entries.read(), column, start))
+    }
+
+    fn snapshot(&self) -> Result<Box<dyn KvSnapshot + '_>> {
+        Ok(Box::new(MemorySnapshot {
+            entries: self.entries.read().clone(),
+        }))
+    }
+}
+
+struct MemorySnapshot {
+    entries: Entries,
+}
+
+impl KvSnapshot for MemorySnapshot {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.get(&(column, key.to_vec())).cloned())
+    }
+
+    fn iter_from(&self, column: Column, start: &[u8]) -> Result<KvIter<'_>> {
+        Ok(range_from(&self.entries, column, start))
+    }
+}
+
+fn range_from<'a>(entries: &Entries, column: Column, start: &[u8]) -> KvIter<'a> {
+    let items: Vec<_> = entries
+        .range((column, start.to_vec())..)
+        .take_while(|((entry_column, _), _)| *entry_column == column)
+        .map(|((_, key), value)| Ok((key.clone(), value.clone())))
+        .collect();
+    Box::new(items.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_delete() {
+        let store = MemoryStore::new();
+        store.put(Column::Default, b"a", b"1").unwrap();
+        assert_eq!(store.get(Column::Default, b"a").unwrap(), Some(b"1".to_vec()));
+
+        assert!(!store.put_if_absent(Column::Default, b"a", b"2").unwrap());
+        assert!(store.put_if_absent(Column::Default, b"b", b"2").unwrap());
+
+        store.delete(Column::Default, b"a").unwrap();
+        assert_eq!(store.get(Column::Default, b"a").unwrap(), None);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_batch_and_iteration() {
+        let store = MemoryStore::new();
+        store.put(Column::Default, b"stale", b"x").unwrap();
+
+        let mut batch = KvBatch::new();
+        batch.put(Column::Default, b"k:2".to_vec(), b"2".to_vec())
+            .put(Column::Default, b"k:1".to_vec(), b"1".to_vec())
+            .put(Column::Default, b"l:1".to_vec(), b"3".to_vec())
+            .put(Column::Tokens, b"k:3".to_vec(), b"4".to_vec())
+            .delete(Column::Default, b"stale".to_vec());
+        store.write(batch).unwrap();
+
+        let keys: Vec<_> = store.scan_prefix(Column::Default, b"k:").unwrap().map(|item| item.unwrap().0).collect();
+        assert_eq!(keys, vec![b"k:1".to_vec(), b"k:2".to_vec()]);
+
+        let from: Vec<_> = store.iter_from(Column::Default, b"k:2").unwrap().map(|item| item.unwrap().0).collect();
+        assert_eq!(from, vec![b"k:2".to_vec(), b"l:1".to_vec()]);
+        assert_eq!(store.get(Column::Default, b"stale").unwrap(), None);
+
+        // Columns do not see each other's keys
+        assert_eq!(store.get(Column::Default, b"k:3").unwrap(), None);
+        assert_eq!(store.scan_prefix(Column::Tokens, b"k:").unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let store = MemoryStore::new();
+        store.put(Column::Default, b"a", b"1").unwrap();
+
+        let snapshot = store.snapshot().unwrap();
+        store.put(Column::Default, b"a", b"2").unwrap();
+        store.put(Column::Default, b"b", b"3").unwrap();
+
+        assert_eq!(snapshot.get(Column::Default, b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(snapshot.iter_from(Column::Default, b"").unwrap().count(), 1);
+        assert_eq!(store.get(Column::Default, b"a").unwrap(), Some(b"2".to_vec()));
+    }
+}
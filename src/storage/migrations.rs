@@ -0,0 +1,279 @@
+//! Ordered schema migrations
+//!
+//! The store records the schema version it is at in the default column.
+//! `migrate` runs at startup and applies every migration newer than that,
+//! oldest first, recording the new version after each one. A crash between
+//! applying a migration and recording its version runs it again, so every
+//! migration has to be idempotent.
+
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    utils::error::{Result, NodeError},
+    core::identity::types::Identity,
+    network::types::{NetworkState, StateUpdate, STATE_BACKUP_PREFIX, STATE_UPDATE_PREFIX},
+};
+use super::{
+    encrypted::{AuditSigningKey, AUDIT_SIGNING_KEY},
+    kv::{self, Column, IdentityRef, IssuedTokens, KvBatch, KvStore, KvStoreExt, SpentToken},
+    record::Record,
+};
+
+/// Records moved per write while migrating
+const MIGRATION_BATCH: usize = 500;
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema:version";
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&dyn KvStore) -> Result<()>,
+}
+
+/// Every migration, oldest first. Append only: once released, a version
+/// has to keep meaning the same change.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "move prefixed JSON records into column families",
+        apply: split_into_columns,
+    },
+];
+
+#[derive(Serialize, Deserialize)]
+struct SchemaVersion {
+    version: u32,
+}
+
+impl Record for SchemaVersion {
+    const COLUMN: Column = Column::Default;
+    const VERSION: u8 = 1;
+}
+
+/// Schema version `store` is at; 0 for a store that never ran a migration
+pub fn schema_version(store: &dyn KvStore) -> Result<u32> {
+    Ok(store
+        .get_record::<SchemaVersion>(SCHEMA_VERSION_KEY)?
+        .map_or(0, |stored| stored.version))
+}
+
+/// Bring `store` up to the newest schema. Returns the version it is at.
+pub fn migrate(store: &dyn KvStore) -> Result<u32> {
+    run(store, MIGRATIONS)
+}
+
+pub fn run(store: &dyn KvStore, migrations: &[Migration]) -> Result<u32> {
+    if !migrations.windows(2).all(|pair| pair[0].version < pair[1].version) {
+        return Err(NodeError::Storage("Migrations are not in version order".into()));
+    }
+
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    let mut current = schema_version(store)?;
+    if current > latest {
+        return Err(NodeError::Storage(format!(
+            "Store schema version {} is newer than this node supports ({})", current, latest,
+        )));
+    }
+
+    for migration in migrations {
+        if migration.version <= current {
+            continue;
+        }
+        info!("Applying storage migration {}: {}", migration.version, migration.description);
+        (migration.apply)(store)
+            .map_err(|e| NodeError::Storage(format!("Migration {} failed: {}", migration.version, e)))?;
+        store.put_record(SCHEMA_VERSION_KEY, &SchemaVersion { version: migration.version })?;
+        current = migration.version;
+    }
+
+    Ok(current)
+}
+
+/// Version 1: records used to share the default column under ad-hoc key
+/// prefixes, serialized as JSON. Each one moves to its column as a typed
+/// record, deleted from the old place in the same write.
+fn split_into_columns(store: &dyn KvStore) -> Result<()> {
+    move_records(store, "identity:", |_, value, batch| {
+        let identity: Identity = from_json(value)?;
+        batch
+            .put_record(identity.id.as_bytes().to_vec(), &identity)?
+            .put_record(kv::template_index_key(&identity.template.hash), &IdentityRef { id: identity.id })?;
+        Ok(())
+    })?;
+
+    move_records(store, "state_update_", |id, value, batch| {
+        let update: StateUpdate = from_json(value)?;
+        batch.put_record(format!("{}{}", STATE_UPDATE_PREFIX, id), &update)?;
+        Ok(())
+    })?;
+
+    move_records(store, "state_backup_", |timestamp, value, batch| {
+        let state: NetworkState = from_json(value)?;
+        let timestamp: i64 = timestamp.parse().map_err(|_| invalid_key("state_backup_", timestamp))?;
+        batch.put_record(format!("{}{:020}", STATE_BACKUP_PREFIX, timestamp), &state)?;
+        Ok(())
+    })?;
+
+    // Already `<zero-padded epoch>:<hex id>`
+    move_records(store, "token:spent:", |suffix, value, batch| {
+        let spent_at: i64 = from_json(value)?;
+        batch.put_record(format!("spent:{}", suffix), &SpentToken { spent_at })?;
+        Ok(())
+    })?;
+
+    move_records(store, "token:issued:", |suffix, value, batch| {
+        let count: u64 = from_json(value)?;
+        let (epoch, identity) = suffix
+            .split_once(':')
+            .and_then(|(epoch, identity)| Some((epoch.parse().ok()?, Uuid::parse_str(identity).ok()?)))
+            .ok_or_else(|| invalid_key("token:issued:", suffix))?;
+        batch.put_record(kv::issued_tokens_key(epoch, &identity), &IssuedTokens { count })?;
+        Ok(())
+    })?;
+
+    // Stays where it is; only the encoding changes. A record starts with its
+    // version byte, never with the quote of a JSON string.
+    if let Some(value) = store.get(Column::Default, AUDIT_SIGNING_KEY.as_bytes())? {
+        if value.first() == Some(&b'"') {
+            let encoded: String = from_json(&value)?;
+            let secret = hex::decode(encoded)
+                .map_err(|e| NodeError::Storage(format!("Invalid audit signing key: {}", e)))?;
+            store.put_record(AUDIT_SIGNING_KEY.as_bytes(), &AuditSigningKey { secret })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Move every default-column record under `prefix`. `convert` gets the key
+/// without the prefix and the old value, and adds the new record to the
+/// batch that also deletes the old one.
+fn move_records<F>(store: &dyn KvStore, prefix: &str, convert: F) -> Result<u64>
+where
+    F: Fn(&str, &[u8], &mut KvBatch) -> Result<()>,
+{
+    let mut moved = 0;
+    loop {
+        // Moved records are gone from the prefix, so each pass starts over
+        let chunk: Vec<_> = store
+            .scan_prefix(Column::Default, prefix.as_bytes())?
+            .take(MIGRATION_BATCH)
+            .collect::<Result<_>>()?;
+        if chunk.is_empty() {
+            return Ok(moved);
+        }
+
+        let mut batch = KvBatch::new();
+        for (key, value) in &chunk {
+            let suffix = std::str::from_utf8(&key[prefix.len()..])
+                .map_err(|_| invalid_key(prefix, &String::from_utf8_lossy(key)))?;
+            convert(suffix, value, &mut batch)?;
+            batch.delete(Column::Default, key.clone());
+        }
+        store.write(batch)?;
+        moved += chunk.len() as u64;
+    }
+}
+
+fn from_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    serde_json::from_slice(bytes)
+        .map_err(|e| NodeError::Storage(format!("Deserialization failed: {}", e)))
+}
+
+fn invalid_key(prefix: &str, rest: &str) -> NodeError {
+    NodeError::Storage(format!("Unexpected key {}{}", prefix, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::identity::types::BiometricTemplate,
+        storage::memory::MemoryStore,
+    };
+
+    fn mark_one(store: &dyn KvStore) -> Result<()> {
+        store.put(Column::Default, b"applied:1", b"")
+    }
+
+    fn mark_two(store: &dyn KvStore) -> Result<()> {
+        store.put(Column::Default, b"applied:2", b"")
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration { version: 1, description: "one", apply: mark_one },
+        Migration { version: 2, description: "two", apply: mark_two },
+    ];
+
+    #[test]
+    fn test_migrations_run_once_in_order() {
+        let store = MemoryStore::new();
+        assert_eq!(schema_version(&store).unwrap(), 0);
+
+        assert_eq!(run(&store, &TEST_MIGRATIONS[..1]).unwrap(), 1);
+        assert!(store.get(Column::Default, b"applied:1").unwrap().is_some());
+        assert!(store.get(Column::Default, b"applied:2").unwrap().is_none());
+
+        // Only the newer migration runs on the next start
+        store.delete(Column::Default, b"applied:1").unwrap();
+        assert_eq!(run(&store, TEST_MIGRATIONS).unwrap(), 2);
+        assert!(store.get(Column::Default, b"applied:1").unwrap().is_none());
+        assert!(store.get(Column::Default, b"applied:2").unwrap().is_some());
+        assert_eq!(schema_version(&store).unwrap(), 2);
+
+        // A node that only knows older migrations refuses the store
+        assert!(run(&store, &TEST_MIGRATIONS[..1]).is_err());
+
+        let unordered = [
+            Migration { version: 2, description: "two", apply: mark_two },
+            Migration { version: 1, description: "one", apply: mark_one },
+        ];
+        assert!(run(&MemoryStore::new(), &unordered).is_err());
+    }
+
+    #[test]
+    fn test_split_into_columns() {
+        let store = MemoryStore::new();
+        let identity = Identity::new(BiometricTemplate::new(vec![0.5], 0.9, "hash".to_string()));
+        let update = StateUpdate {
+            id: Uuid::new_v4(),
+            base_version: 0,
+            changes: Vec::new(),
+            timestamp: 1000,
+            signature: Vec::new(),
+        };
+        let token_owner = Uuid::new_v4();
+
+        let legacy = |key: String, value: Vec<u8>| store.put(Column::Default, key.as_bytes(), &value).unwrap();
+        legacy(format!("identity:{}", identity.id), serde_json::to_vec(&identity).unwrap());
+        legacy(format!("state_update_{}", update.id), serde_json::to_vec(&update).unwrap());
+        legacy(format!("token:spent:{:020}:{}", 7, hex::encode([1u8; 32])), b"1000".to_vec());
+        legacy(format!("token:issued:7:{}", token_owner), b"3".to_vec());
+        legacy(AUDIT_SIGNING_KEY.to_string(), serde_json::to_vec(&hex::encode([9u8; 4])).unwrap());
+
+        assert_eq!(migrate(&store).unwrap(), 1);
+
+        assert_eq!(store.get_identity(&identity.id).unwrap().unwrap().id, identity.id);
+        assert_eq!(store.find_identity_by_template("hash").unwrap().unwrap().id, identity.id);
+        let updates: Vec<StateUpdate> = store.get_records(STATE_UPDATE_PREFIX.as_bytes()).unwrap();
+        assert_eq!(updates[0].id, update.id);
+        assert!(store.is_token_spent(7, &[1u8; 32]).unwrap());
+        assert_eq!(store.issued_tokens(7, &token_owner).unwrap(), 3);
+        let signing_key: AuditSigningKey = store.get_record(AUDIT_SIGNING_KEY.as_bytes()).unwrap().unwrap();
+        assert_eq!(signing_key.secret, vec![9u8; 4]);
+
+        // Nothing is left behind under the old layout
+        let remaining: Vec<Vec<u8>> = store
+            .scan_prefix(Column::Default, b"")
+            .unwrap()
+            .map(|item| item.unwrap().0)
+            .collect();
+        assert_eq!(remaining, vec![AUDIT_SIGNING_KEY.as_bytes().to_vec(), SCHEMA_VERSION_KEY.to_vec()]);
+
+        // Running it again changes nothing
+        split_into_columns(&store).unwrap();
+        assert_eq!(store.issued_tokens(7, &token_owner).unwrap(), 3);
+    }
+}
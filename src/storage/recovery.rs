@@ -1,4 +1,4 @@
-use crate::storage::types::{Block, Node, RecoveryState};
+use crate::storage::types::{Block, RecoveryState};
 use crate::storage::distributed::merkle::MerkleTree;
 use crate::utils::error::Result;
 use std::collections::HashMap;
@@ -104,7 +104,7 @@ impl RecoveryManager {
 
     fn verify_node_state(&self, state: &RecoveryState) -> bool {
         // Verify state integrity using merkle tree
-        let computed_root = MerkleTree::compute_root(&state.blocks);
+        let computed_root = MerkleTree::compute_root(state.blocks.iter().map(|block| (&block.key, &block.data)));
         computed_root == state.merkle_root
     }
 
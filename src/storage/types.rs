@@ -9,3 +9,17 @@ pub struct StorageConfig {
     pub max_size: usize,
 }
 
+/// A stored record as exchanged during recovery
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    pub key: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Records a node holds and the Merkle root they hash to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryState {
+    pub blocks: Vec<Block>,
+    pub merkle_root: [u8; 32],
+}
+
@@ -1,7 +1,13 @@
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::time::Duration;
 use config::{Config as ConfigLib, ConfigError, Environment, File};
-use crate::utils::error::{Result, NodeError};
+use crate::{
+    utils::error::{Result, NodeError},
+    core::crypto::key_manager::KeyManager,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -37,9 +43,56 @@ pub struct NetworkConfig {
 pub struct StorageConfig {
     pub path: String,
     pub encryption_key: String,
+    /// Live database size writes are refused beyond
     pub max_size_gb: u64,
+    /// Percentages of `max_size_gb` that log a warning when reached
+    pub quota_watermarks: Vec<u8>,
+    /// Seconds between scheduled backups; 0 disables them
     pub backup_interval: u64,
+    /// Directory backups are written to; empty for remote targets only
+    pub backup_path: String,
+    /// Further backup targets, as `file://`, `sftp://` or `s3://` URLs.
+    /// SFTP and S3 need the `backup` feature. S3 speaks plain HTTP, so its
+    /// host must be a loopback address, e.g. a local TLS gateway.
+    pub backup_targets: Vec<String>,
+    pub backup_s3_access_key: Option<String>,
+    pub backup_s3_secret_key: Option<String>,
+    /// Newest backups kept on each target; 0 keeps all of them
+    pub backup_retention: usize,
+    /// Test-restore every scheduled backup right after taking it
+    pub backup_verify: bool,
     pub compression_enabled: bool,
+    /// `lz4`, `zstd` or `none`, for every column family not in `column_compression`
+    pub compression_codec: String,
+    /// Codec by column family name
+    pub column_compression: HashMap<String, String>,
+    /// Argon2id memory cost for the keyring KEK, in KiB
+    pub kdf_memory_kib: u32,
+    /// Argon2id passes
+    pub kdf_iterations: u32,
+    /// Argon2id lanes
+    pub kdf_parallelism: u32,
+    /// Where the keyring KEK lives: "passphrase", "file", "pkcs11" or "kms"
+    pub key_store: String,
+    /// Key file for the "file" store; defaults to `<path>/keys.json`
+    pub key_store_path: Option<String>,
+    pub pkcs11_module: Option<String>,
+    pub pkcs11_token: Option<String>,
+    pub pkcs11_pin: Option<String>,
+    /// `http://` URL of the KMS gateway; must be a loopback address, since
+    /// key material is sent unencrypted
+    pub kms_url: Option<String>,
+    pub kms_token: Option<String>,
+    /// Rotate the data key after this many seconds; 0 disables
+    pub key_rotation_max_age: u64,
+    /// Rotate the data key after this many encryptions; 0 disables
+    pub key_rotation_max_uses: u64,
+    /// How often the rotation policy is checked, in seconds
+    pub key_rotation_check_interval: u64,
+    /// Records rewritten per re-encryption batch
+    pub reencrypt_batch_size: usize,
+    /// Records re-encrypted per second; 0 runs unthrottled
+    pub reencrypt_rate: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,6 +112,32 @@ pub struct SecurityConfig {
     pub max_request_size: usize,
     pub rate_limit_requests: u32,
     pub rate_limit_window: u64,
+    pub credential_validity_days: u64,
+    /// Bearer token for `POST /credentials/{id}/issue`; unset disables
+    /// issuance
+    pub credential_api_token: Option<String>,
+    pub token_epoch_duration: u64,
+    pub tokens_per_epoch: usize,
+    /// How a share-sealed keyring is unsealed: "prompt" or "endpoint"
+    pub unseal_mode: String,
+    /// Loopback address of the unseal endpoint
+    pub unseal_address: String,
+    /// Days audit records are kept, counted from the checkpoint covering them
+    pub audit_retention_days: i64,
+    /// Audit events between signed checkpoints
+    pub audit_checkpoint_interval: u64,
+    /// Bearer token for the `/audit` query endpoint; unset disables it
+    pub audit_api_token: Option<String>,
+    /// Format of exported audit events: "jsonl", "cef" or "syslog"
+    pub audit_export_format: String,
+    /// Stream audit events to `file:<path>`, `udp://host:port` or
+    /// `tcp://host:port`
+    pub audit_export_target: Option<String>,
+    /// Milliseconds between integrity scans of guarded memory
+    pub memory_scan_interval_ms: u64,
+    /// Response to detected memory poisoning: "alert", "zeroize",
+    /// "suspend" or "shutdown"
+    pub memory_poison_response: String,
 }
 
 impl Config {
@@ -72,7 +151,20 @@ impl Config {
             .set_default("network.peer_cleanup_interval", 300)?
             .set_default("storage.max_size_gb", 10)?
             .set_default("storage.backup_interval", 86400)?
+            .set_default("storage.backup_path", "./data/backups")?
+            .set_default("storage.backup_retention", 14)?
+            .set_default("storage.backup_verify", true)?
             .set_default("storage.compression_enabled", true)?
+            .set_default("storage.compression_codec", "lz4")?
+            .set_default("storage.kdf_memory_kib", 65536)?
+            .set_default("storage.kdf_iterations", 3)?
+            .set_default("storage.kdf_parallelism", 1)?
+            .set_default("storage.key_store", "passphrase")?
+            .set_default("storage.key_rotation_max_age", 7_776_000)?  // 90 days
+            .set_default("storage.key_rotation_max_uses", 2_147_483_648u64)?
+            .set_default("storage.key_rotation_check_interval", 3600)?
+            .set_default("storage.reencrypt_batch_size", 500)?
+            .set_default("storage.reencrypt_rate", 2000)?
             .set_default("plugins.enabled", true)?
             .set_default("plugins.auto_update", false)?
             .set_default("plugins.sandbox_enabled", true)?
@@ -80,6 +172,16 @@ impl Config {
             .set_default("security.max_request_size", 10_485_760)?  // 10MB
             .set_default("security.rate_limit_requests", 100)?
             .set_default("security.rate_limit_window", 60)?
+            .set_default("security.credential_validity_days", 30)?
+            .set_default("security.token_epoch_duration", 86400)?
+            .set_default("security.tokens_per_epoch", 32)?
+            .set_default("security.unseal_mode", "endpoint")?
+            .set_default("security.unseal_address", "127.0.0.1:8201")?
+            .set_default("security.audit_retention_days", 365)?
+            .set_default("security.audit_checkpoint_interval", 256)?
+            .set_default("security.audit_export_format", "jsonl")?
+            .set_default("security.memory_scan_interval_ms", 1000)?
+            .set_default("security.memory_poison_response", "zeroize")?
             
             // Load from config file
             .add_source(File::with_name("config/default"))
@@ -114,11 +216,59 @@ impl Config {
         if self.storage.max_size_gb == 0 {
             return Err(NodeError::Config("max_size_gb must be greater than 0".into()));
         }
-        if self.storage.encryption_key.is_empty() {
+        if self.storage.quota_watermarks.iter().any(|&percent| percent == 0 || percent > 100) {
+            return Err(NodeError::Config("quota_watermarks must be percentages from 1 to 100".into()));
+        }
+        // A keyring sealed with key shares does not use the passphrase
+        let sealed = KeyManager::seal_status(Path::new(&self.storage.path))
+            .map(|status| status.is_some())
+            .unwrap_or(false);
+        let external = ["pkcs11", "kms"].contains(&self.storage.key_store.as_str());
+        if self.storage.encryption_key.is_empty() && !sealed && !external {
             return Err(NodeError::Config("encryption_key must be set".into()));
         }
+        if self.storage.kdf_iterations == 0 || self.storage.kdf_parallelism == 0 {
+            return Err(NodeError::Config("kdf_iterations and kdf_parallelism must be greater than 0".into()));
+        }
+        if self.storage.kdf_memory_kib < 8 * self.storage.kdf_parallelism {
+            return Err(NodeError::Config("kdf_memory_kib must be at least 8 KiB per lane".into()));
+        }
+        if self.storage.key_rotation_check_interval == 0 || self.storage.reencrypt_batch_size == 0 {
+            return Err(NodeError::Config("key_rotation_check_interval and reencrypt_batch_size must be greater than 0".into()));
+        }
 
         // Validate security configuration
+        if !["prompt", "endpoint"].contains(&self.security.unseal_mode.as_str()) {
+            return Err(NodeError::Config("unseal_mode must be \"prompt\" or \"endpoint\"".into()));
+        }
+        let unseal_address: SocketAddr = self.security.unseal_address.parse()
+            .map_err(|_| NodeError::Config("Invalid unseal_address".into()))?;
+        if !unseal_address.ip().is_loopback() {
+            return Err(NodeError::Config("unseal_address must be a loopback address".into()));
+        }
+        if self.security.audit_retention_days <= 0 || self.security.audit_checkpoint_interval == 0 {
+            return Err(NodeError::Config("audit_retention_days and audit_checkpoint_interval must be greater than 0".into()));
+        }
+        if !["jsonl", "cef", "syslog"].contains(&self.security.audit_export_format.as_str()) {
+            return Err(NodeError::Config("audit_export_format must be \"jsonl\", \"cef\" or \"syslog\"".into()));
+        }
+        if let Some(target) = &self.security.audit_export_target {
+            if !["file:", "udp://", "tcp://"].iter().any(|scheme| target.starts_with(scheme)) {
+                return Err(NodeError::Config("audit_export_target must start with file:, udp:// or tcp://".into()));
+            }
+        }
+        if self.security.memory_scan_interval_ms == 0 {
+            return Err(NodeError::Config("memory_scan_interval_ms must be greater than 0".into()));
+        }
+        if !["alert", "zeroize", "suspend", "shutdown"].contains(&self.security.memory_poison_response.as_str()) {
+            return Err(NodeError::Config("memory_poison_response must be \"alert\", \"zeroize\", \"suspend\" or \"shutdown\"".into()));
+        }
+        if matches!(&self.security.audit_api_token, Some(token) if token.len() < 16) {
+            return Err(NodeError::Config("audit_api_token must be at least 16 characters".into()));
+        }
+        if matches!(&self.security.credential_api_token, Some(token) if token.len() < 16) {
+            return Err(NodeError::Config("credential_api_token must be at least 16 characters".into()));
+        }
         if self.security.tls_enabled {
             if self.security.tls_cert_path.is_none() || self.security.tls_key_path.is_none() {
                 return Err(NodeError::Config("TLS cert and key paths must be set when TLS is enabled".into()));
@@ -145,6 +295,45 @@ impl Config {
     }
 }
 
+impl StorageConfig {
+    /// Store in `dir` with cheap KDF parameters and nothing scheduled, for
+    /// tests. Override fields with struct update syntax.
+    #[cfg(any(test, feature = "test-config"))]
+    pub fn for_test(dir: &Path) -> Self {
+        Self {
+            path: dir.to_str().unwrap().to_string(),
+            encryption_key: "test_key".to_string(),
+            max_size_gb: 1,
+            quota_watermarks: Vec::new(),
+            backup_interval: 3600,
+            backup_path: String::new(),
+            backup_targets: Vec::new(),
+            backup_s3_access_key: None,
+            backup_s3_secret_key: None,
+            backup_retention: 0,
+            backup_verify: false,
+            compression_enabled: true,
+            compression_codec: "lz4".to_string(),
+            column_compression: HashMap::new(),
+            kdf_memory_kib: 256,
+            kdf_iterations: 1,
+            kdf_parallelism: 1,
+            key_store: "passphrase".to_string(),
+            key_store_path: None,
+            pkcs11_module: None,
+            pkcs11_token: None,
+            pkcs11_pin: None,
+            kms_url: None,
+            kms_token: None,
+            key_rotation_max_age: 0,
+            key_rotation_max_uses: 0,
+            key_rotation_check_interval: 3600,
+            reencrypt_batch_size: 500,
+            reencrypt_rate: 0,
+        }
+    }
+}
+
 impl From<ConfigError> for NodeError {
     fn from(error: ConfigError) -> Self {
         NodeError::Config(error.to_string())
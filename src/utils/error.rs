@@ -14,6 +14,18 @@ pub enum NodeError {
     #[error("Storage error: {0}")]
     Storage(String),
 
+    #[error("Storage quota exceeded: {used} of {limit} bytes used, {requested} more requested")]
+    QuotaExceeded { used: u64, limit: u64, requested: u64 },
+
+    #[error("Consensus error: {0}")]
+    Consensus(String),
+
+    #[error("Not the consensus leader; leader is {leader:?}")]
+    NotLeader { leader: Option<String> },
+
+    #[error("Quorum not reached: {acked} of {required} replicas answered")]
+    QuorumNotReached { required: usize, acked: usize },
+
     #[error("Plugin error: {0}")]
     Plugin(String),
 
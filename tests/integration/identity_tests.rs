@@ -1,30 +1,24 @@
+// Needs `--features test-config` for `StorageConfig::for_test`
 use freeghost::{
     core::{
         identity::types::{Identity, BiometricTemplate, DeviceInfo, BehaviorPattern, PatternType},
         services::identity::IdentityService,
         crypto::quantum::ZeroKnowledgeProof,
     },
-    storage::encrypted::EncryptedStore,
+    storage::{encrypted::EncryptedStore, kv::SharedStore},
     utils::config::{Config, StorageConfig, SecurityConfig},
 };
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tempfile::tempdir;
 use uuid::Uuid;
 
-async fn setup_test_environment() -> (IdentityService, Arc<RwLock<EncryptedStore>>) {
+async fn setup_test_environment() -> (IdentityService, SharedStore) {
     let temp_dir = tempdir().unwrap();
     
     let config = Config {
         node: Default::default(),
         network: Default::default(),
-        storage: StorageConfig {
-            path: temp_dir.path().to_str().unwrap().to_string(),
-            encryption_key: "test_key".to_string(),
-            max_size_gb: 1,
-            backup_interval: 3600,
-            compression_enabled: true,
-        },
+        storage: StorageConfig::for_test(temp_dir.path()),
         plugins: Default::default(),
         security: SecurityConfig {
             tls_enabled: false,
@@ -36,9 +30,9 @@ async fn setup_test_environment() -> (IdentityService, Arc<RwLock<EncryptedStore
         },
     };
 
-    let storage = Arc::new(RwLock::new(
+    let storage: SharedStore = Arc::new(
         EncryptedStore::new(&config.storage).await.unwrap()
-    ));
+    );
     
     let service = IdentityService::new(&config, storage.clone()).await.unwrap();
     
@@ -215,17 +209,11 @@ async fn test_invalid_operations() {
 #[tokio::test]
 async fn test_storage_persistence() {
     let temp_dir = tempdir().unwrap();
-    let config = StorageConfig {
-        path: temp_dir.path().to_str().unwrap().to_string(),
-        encryption_key: "test_key".to_string(),
-        max_size_gb: 1,
-        backup_interval: 3600,
-        compression_enabled: true,
-    };
+    let config = StorageConfig::for_test(temp_dir.path());
 
     // Create and store an identity
     let identity = {
-        let storage = Arc::new(RwLock::new(EncryptedStore::new(&config).await.unwrap()));
+        let storage: SharedStore = Arc::new(EncryptedStore::new(&config).await.unwrap());
         let service = IdentityService::new(
             &Config {
                 storage: config.clone(),
@@ -245,7 +233,7 @@ async fn test_storage_persistence() {
     };
 
     // Create new service instance and verify persistence
-    let storage = Arc::new(RwLock::new(EncryptedStore::new(&config).await.unwrap()));
+    let storage: SharedStore = Arc::new(EncryptedStore::new(&config).await.unwrap());
     let service = IdentityService::new(
         &Config {
             storage: config,
@@ -1,12 +1,18 @@
 // tests/integration/storage/encrypted_store_tests.rs
-use secure_identity_node::{
-    core::crypto::key_manager::KeyManager,
-    storage::encrypted::{EncryptedStore, StorageError},
+// Needs `--features test-config` for `StorageConfig::for_test`
+use freeghost::{
+    storage::{
+        encrypted::{EncryptedStore, ReencryptProgress},
+        kv::{Column, KvStore, KvStoreExt, SharedStore},
+        memory::MemoryStore,
+        migrations,
+        record::Record,
+    },
+    utils::{config::StorageConfig, error::NodeError},
 };
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::sync::Arc;
 use tempfile::tempdir;
-use tokio::time::sleep;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 struct TestIdentity {
@@ -22,51 +28,57 @@ struct TestMetadata {
     version: u32,
 }
 
-async fn setup_test_store() -> (EncryptedStore, tempfile::TempDir) {
+impl Record for TestIdentity {
+    const COLUMN: Column = Column::Identities;
+    const VERSION: u8 = 1;
+}
+
+fn test_identity(i: usize) -> TestIdentity {
+    TestIdentity {
+        id: format!("test_{}", i),
+        template: vec![1, 2, 3, 4],
+        metadata: TestMetadata {
+            created_at: 1000,
+            updated_at: 1000,
+            version: 1,
+        },
+    }
+}
+
+async fn setup_test_store() -> (Arc<EncryptedStore>, tempfile::TempDir) {
     let temp_dir = tempdir().unwrap();
-    let key_manager = KeyManager::new_test_instance().await;
-    let store = EncryptedStore::new(temp_dir.path(), key_manager)
+    let store = EncryptedStore::new(&StorageConfig::for_test(temp_dir.path()))
         .await
         .unwrap();
-    (store, temp_dir)
+    (Arc::new(store), temp_dir)
 }
 
 #[tokio::test]
 async fn test_concurrent_access() {
     let (store, _temp_dir) = setup_test_store().await;
-    let store = std::sync::Arc::new(store);
-    
+
     let mut handles = Vec::new();
-    
+
     // Spawn multiple tasks doing simultaneous reads and writes
     for i in 0..10 {
-        let store = store.clone();
+        let store: SharedStore = store.clone();
         let handle = tokio::spawn(async move {
-            let test_data = TestIdentity {
-                id: format!("test_{}", i),
-                template: vec![1, 2, 3, 4],
-                metadata: TestMetadata {
-                    created_at: 1000,
-                    updated_at: 1000,
-                    version: 1,
-                },
-            };
-            
+            let test_data = test_identity(i);
+
             // Write data
-            store.store(test_data.id.as_bytes(), &test_data).await.unwrap();
-            
+            store.put_record(test_data.id.as_bytes(), &test_data).unwrap();
+
             // Read data back
             let retrieved: TestIdentity = store
-                .retrieve(test_data.id.as_bytes())
-                .await
+                .get_record(test_data.id.as_bytes())
                 .unwrap()
                 .unwrap();
-                
+
             assert_eq!(test_data, retrieved);
         });
         handles.push(handle);
     }
-    
+
     // Wait for all tasks to complete
     for handle in handles {
         handle.await.unwrap();
@@ -76,32 +88,22 @@ async fn test_concurrent_access() {
 #[tokio::test]
 async fn test_key_rotation_durability() {
     let (store, _temp_dir) = setup_test_store().await;
-    
+
     // Store multiple items
-    let items: Vec<TestIdentity> = (0..5)
-        .map(|i| TestIdentity {
-            id: format!("test_{}", i),
-            template: vec![1, 2, 3, 4],
-            metadata: TestMetadata {
-                created_at: 1000,
-                updated_at: 1000,
-                version: 1,
-            },
-        })
-        .collect();
-        
+    let items: Vec<TestIdentity> = (0..5).map(test_identity).collect();
+
     for item in &items {
-        store.store(item.id.as_bytes(), item).await.unwrap();
+        store.put_record(item.id.as_bytes(), item).unwrap();
     }
-    
-    // Perform key rotation
+
+    // Perform key rotation and re-encrypt everything
     store.rotate_encryption_key().await.unwrap();
-    
+    while let ReencryptProgress::Running(_) = store.reencrypt_batch(2).await.unwrap() {}
+
     // Verify all items are still accessible
     for item in &items {
         let retrieved: TestIdentity = store
-            .retrieve(item.id.as_bytes())
-            .await
+            .get_record(item.id.as_bytes())
             .unwrap()
             .unwrap();
         assert_eq!(item, &retrieved);
@@ -111,12 +113,41 @@ async fn test_key_rotation_durability() {
 #[tokio::test]
 async fn test_error_conditions() {
     let (store, _temp_dir) = setup_test_store().await;
-    
+
     // Test retrieving non-existent key
-    let result: Option<TestIdentity> = store.retrieve(b"nonexistent").await.unwrap();
+    let result: Option<TestIdentity> = store.get_record(b"nonexistent").unwrap();
     assert!(result.is_none());
-    
-    // Test storing invalid data
-    let result = store.store(b"test", &vec![0xff, 0xff]).await;
-    assert!(matches!(result, Err(StorageError::InvalidFormat(_))));
-}
\ No newline at end of file
+
+    // Test reading a value that is not a record of this type
+    store.put(Column::Identities, b"test", &[1, 0xff, 0xff]).unwrap();
+    let result = store.get_record::<TestIdentity>(b"test");
+    assert!(matches!(result, Err(NodeError::Storage(_))));
+
+    // Test reading a record of another schema version
+    store.put(Column::Identities, b"test", &[2]).unwrap();
+    let result = store.get_record::<TestIdentity>(b"test");
+    assert!(matches!(result, Err(NodeError::Storage(_))));
+}
+
+#[tokio::test]
+async fn test_backends_agree() {
+    let (encrypted, _temp_dir) = setup_test_store().await;
+    let backends: Vec<SharedStore> = vec![encrypted as SharedStore, Arc::new(MemoryStore::new())];
+
+    for store in backends {
+        for i in 0..3 {
+            store.put_record(format!("test_{}", i).as_bytes(), &test_identity(i)).unwrap();
+        }
+        store.put(Column::Default, b"test_9", b"value").unwrap();
+
+        let snapshot = store.snapshot().unwrap();
+        store.delete(Column::Identities, b"test_0").unwrap();
+
+        let loaded: Vec<TestIdentity> = store.get_records(b"test_").unwrap();
+        assert_eq!(loaded, vec![test_identity(1), test_identity(2)]);
+        assert!(snapshot.get(Column::Identities, b"test_0").unwrap().is_some());
+
+        assert_eq!(migrations::migrate(&*store).unwrap(), 1);
+        assert_eq!(migrations::schema_version(&*store).unwrap(), 1);
+    }
+}